@@ -0,0 +1,102 @@
+#ifndef WEBP_CONVERTER_H
+#define WEBP_CONVERTER_H
+
+/* Generated by cbindgen from src/ffi.rs - do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Success.
+ */
+#define WEBPCONV_OK 0
+
+/**
+ * A required pointer was null or a string was not valid UTF-8.
+ */
+#define WEBPCONV_INVALID_ARGUMENT -1
+
+/**
+ * The conversion failed; see `webpconv_last_error`.
+ */
+#define WEBPCONV_CONVERSION_FAILED -2
+
+/**
+ * An internal error (a panic) was caught; see `webpconv_last_error`.
+ */
+#define WEBPCONV_PANIC -3
+
+/**
+ * Encoder settings passed from C. A null pointer selects the defaults.
+ */
+typedef struct WebpconvOptions {
+  /**
+   * Lossy quality from 1 to 100 (default 80).
+   */
+  uint8_t quality;
+  /**
+   * Non-zero for lossless compression.
+   */
+  uint8_t lossless;
+  /**
+   * Compression method from 0 (fastest) to 6 (best compression, default 4).
+   */
+  uint8_t method;
+} WebpconvOptions;
+
+
+
+
+
+
+
+/**
+ * Convert an image file to WebP.
+ *
+ * `output_path` may be null to write `<input>.webp` next to the input.
+ * Returns `WEBPCONV_OK` or a negative error code.
+ *
+ * # Safety
+ *
+ * `input_path` must be a valid NUL-terminated string, `output_path` null or a
+ * valid NUL-terminated string, and `options` null or a valid pointer.
+ */
+int webpconv_convert_file(const char *input_path,
+                          const char *output_path,
+                          const struct WebpconvOptions *options);
+
+/**
+ * Convert an encoded image held in memory to WebP.
+ *
+ * On success `*output` receives a buffer of `*output_len` bytes that must be
+ * released with `webpconv_free_buffer`.
+ *
+ * # Safety
+ *
+ * `input` must point to `input_len` readable bytes, `output` and
+ * `output_len` must be valid for writes, and `options` null or a valid pointer.
+ */
+int webpconv_convert_buffer(const uint8_t *input,
+                            size_t input_len,
+                            const struct WebpconvOptions *options,
+                            uint8_t **output,
+                            size_t *output_len);
+
+/**
+ * Release a buffer returned by `webpconv_convert_buffer`.
+ *
+ * # Safety
+ *
+ * `buffer` and `len` must come from one successful `webpconv_convert_buffer`
+ * call, and the buffer must not be freed twice. Null is ignored.
+ */
+void webpconv_free_buffer(uint8_t *buffer, size_t len);
+
+/**
+ * Message of the last error on the calling thread, or null.
+ *
+ * The string stays valid until the next failing call on the same thread.
+ */
+const char *webpconv_last_error(void);
+
+#endif  /* WEBP_CONVERTER_H */
@@ -0,0 +1,176 @@
+//! Content analysis used to pick encoder settings per image.
+
+use crate::encoder::Preset;
+use image::{DynamicImage, Rgba};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Most distinct colors an image may have to count as a graphic.
+const MAX_PALETTE: usize = 256;
+/// Distinct colors tracked at most; later new colors are not counted.
+const MAX_TRACKED_COLORS: usize = 4096;
+/// Luma jump between neighbours that counts as a hard edge.
+const EDGE_THRESHOLD: i32 = 96;
+/// Rows sampled at most; taller images are analyzed every n-th row.
+const MAX_ROWS: u32 = 512;
+
+/// Broad kind of image content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    /// Continuous-tone content (photos, renders); best encoded lossy.
+    Photo,
+    /// Flat colors and hard edges (logos, icons, screenshots); best encoded lossless.
+    Graphic,
+    /// Mostly two colors with many hard edges (documents, code, captions).
+    Text,
+}
+
+impl ContentKind {
+    /// Whether lossless encoding suits this content.
+    pub fn prefers_lossless(self) -> bool {
+        matches!(self, ContentKind::Graphic | ContentKind::Text)
+    }
+
+    /// Encoder preset tuned for this content.
+    pub fn preset(self) -> Preset {
+        match self {
+            ContentKind::Photo => Preset::Photo,
+            ContentKind::Graphic => Preset::Drawing,
+            ContentKind::Text => Preset::Text,
+        }
+    }
+}
+
+impl fmt::Display for ContentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContentKind::Photo => "photo",
+            ContentKind::Graphic => "graphic",
+            ContentKind::Text => "text",
+        })
+    }
+}
+
+/// Simple statistics describing an image's content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageAnalysis {
+    /// Distinct RGBA colors, counted up to one past the palette limit.
+    pub colors: usize,
+    /// Whether any pixel is not fully opaque.
+    pub translucent: bool,
+    /// Share of pixels identical to their left neighbour (0-1); high for
+    /// flat fills and screenshots, low for photos.
+    pub flat_ratio: f64,
+    /// Share of pixels covered by the two most common colors (0-1).
+    pub dominant_ratio: f64,
+    /// Share of pixels with a hard luma edge to their left neighbour (0-1).
+    pub edge_ratio: f64,
+}
+
+impl ImageAnalysis {
+    /// Analyze an image, sampling rows of large images.
+    pub fn of(img: &DynamicImage) -> Self {
+        let rgba = img.to_rgba8();
+        let row_step = (rgba.height() / MAX_ROWS).max(1) as usize;
+
+        let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+        let mut translucent = false;
+        let (mut sampled, mut flat, mut edges, mut compared) = (0usize, 0usize, 0usize, 0usize);
+        for row in rgba.rows().step_by(row_step) {
+            let mut previous: Option<&Rgba<u8>> = None;
+            for pixel in row {
+                sampled += 1;
+                if let Some(count) = counts.get_mut(&pixel.0) {
+                    *count += 1;
+                } else if counts.len() < MAX_TRACKED_COLORS {
+                    counts.insert(pixel.0, 1);
+                }
+                translucent |= pixel[3] < 255;
+                if let Some(previous) = previous {
+                    compared += 1;
+                    if previous == pixel {
+                        flat += 1;
+                    } else if (luma(previous) - luma(pixel)).abs() >= EDGE_THRESHOLD {
+                        edges += 1;
+                    }
+                }
+                previous = Some(pixel);
+            }
+        }
+
+        let mut frequencies: Vec<usize> = counts.values().copied().collect();
+        frequencies.sort_unstable_by(|a, b| b.cmp(a));
+        let dominant: usize = frequencies.iter().take(2).sum();
+        let ratio = |part: usize, whole: usize| if whole == 0 { 1.0 } else { part as f64 / whole as f64 };
+
+        Self {
+            colors: counts.len().min(MAX_PALETTE + 1),
+            translucent,
+            flat_ratio: ratio(flat, compared),
+            dominant_ratio: ratio(dominant, sampled),
+            edge_ratio: if compared == 0 { 0.0 } else { ratio(edges, compared) },
+        }
+    }
+
+    /// Classify the content from the statistics.
+    pub fn content(&self) -> ContentKind {
+        // Glyphs: ink on a plain background, switching between them often
+        if self.dominant_ratio >= 0.75 && self.edge_ratio >= 0.04 {
+            return ContentKind::Text;
+        }
+
+        let few_colors = self.colors <= MAX_PALETTE;
+        let screenshot = self.flat_ratio >= 0.7;
+        let cutout = self.translucent && self.flat_ratio >= 0.5;
+        if few_colors || screenshot || cutout {
+            ContentKind::Graphic
+        } else {
+            ContentKind::Photo
+        }
+    }
+}
+
+/// Integer Rec. 601 luma of a pixel.
+fn luma(pixel: &Rgba<u8>) -> i32 {
+    (299 * i32::from(pixel[0]) + 587 * i32::from(pixel[1]) + 114 * i32::from(pixel[2])) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graphic_content() {
+        let logo = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| {
+            if x < 32 { image::Rgb([200, 30, 30]) } else { image::Rgb([255, 255, 255]) }
+        }));
+        let analysis = ImageAnalysis::of(&logo);
+        assert_eq!(analysis.colors, 2);
+        assert!(!analysis.translucent);
+        assert_eq!(analysis.content(), ContentKind::Graphic);
+    }
+
+    #[test]
+    fn test_photo_content() {
+        let photo = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4 + y % 3) as u8, (y * 4 + x % 5) as u8, ((x * y) % 256) as u8])
+        }));
+        let analysis = ImageAnalysis::of(&photo);
+        assert!(analysis.colors > MAX_PALETTE);
+        assert!(analysis.flat_ratio < 0.1);
+        assert_eq!(analysis.content(), ContentKind::Photo);
+        assert_eq!(analysis.content().preset(), Preset::Photo);
+    }
+
+    #[test]
+    fn test_text_content() {
+        // Rows of thin dark strokes on white, like lines of glyphs
+        let page = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            if x % 4 == 1 && y % 8 < 6 { image::Rgb([20, 20, 20]) } else { image::Rgb([255, 255, 255]) }
+        }));
+        let analysis = ImageAnalysis::of(&page);
+        assert_eq!(analysis.dominant_ratio, 1.0);
+        assert_eq!(analysis.content(), ContentKind::Text);
+        assert_eq!(analysis.content().preset(), Preset::Text);
+    }
+}
@@ -0,0 +1,132 @@
+//! Heuristic detection of visible lossy compression artifacts.
+//!
+//! Smooth regions of the source (skies, walls, gradients) show artifacts
+//! first, so the detector compares the luma variance of each smooth block
+//! before and after encoding: a gradient crushed flat points to banding,
+//! structure appearing in a flat area points to blocking.
+
+use image::{DynamicImage, GrayImage};
+use std::fmt;
+
+/// Side length of the compared blocks.
+const BLOCK: u32 = 8;
+/// Luma variance below which a source block counts as smooth.
+const SMOOTH_VARIANCE: f64 = 30.0;
+/// Luma variance above which a smooth block is a gradient rather than flat.
+const GRADIENT_VARIANCE: f64 = 1.0;
+/// Share of smooth blocks that must be affected before warning.
+const WARN_SHARE: f64 = 0.05;
+/// Fewest smooth blocks worth judging; tiny or busy images are skipped.
+const MIN_SMOOTH_BLOCKS: usize = 8;
+
+/// Kind of compression artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    /// Smooth gradients collapsed into visible steps.
+    Banding,
+    /// Block edges or noise appearing in flat areas.
+    Blocking,
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArtifactKind::Banding => "banding",
+            ArtifactKind::Blocking => "blocking",
+        })
+    }
+}
+
+/// A likely visible artifact in an encoded image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Artifact {
+    pub kind: ArtifactKind,
+    /// Share of the source's smooth blocks (gradient blocks for banding)
+    /// affected, from 0 to 1.
+    pub share: f64,
+}
+
+/// Compare an encoded image with its source and list the artifacts that
+/// affect enough smooth regions to be likely visible.
+pub fn detect(reference: &DynamicImage, encoded: &DynamicImage) -> Vec<Artifact> {
+    let (reference, encoded) = (reference.to_luma8(), encoded.to_luma8());
+    let (mut smooth, mut gradients, mut banded, mut blocky) = (0usize, 0usize, 0usize, 0usize);
+
+    for y in (0..reference.height().saturating_sub(BLOCK - 1)).step_by(BLOCK as usize) {
+        for x in (0..reference.width().saturating_sub(BLOCK - 1)).step_by(BLOCK as usize) {
+            let before = block_variance(&reference, x, y);
+            if before >= SMOOTH_VARIANCE {
+                continue;
+            }
+            smooth += 1;
+            let after = block_variance(&encoded, x, y);
+            if before > GRADIENT_VARIANCE {
+                gradients += 1;
+                if after < before * 0.25 {
+                    banded += 1;
+                }
+            }
+            if after > before * 2.0 + 4.0 {
+                blocky += 1;
+            }
+        }
+    }
+
+    if smooth < MIN_SMOOTH_BLOCKS {
+        return Vec::new();
+    }
+    let mut artifacts = Vec::new();
+    if gradients >= MIN_SMOOTH_BLOCKS && banded as f64 / gradients as f64 >= WARN_SHARE {
+        artifacts.push(Artifact { kind: ArtifactKind::Banding, share: banded as f64 / gradients as f64 });
+    }
+    if blocky as f64 / smooth as f64 >= WARN_SHARE {
+        artifacts.push(Artifact { kind: ArtifactKind::Blocking, share: blocky as f64 / smooth as f64 });
+    }
+    artifacts
+}
+
+/// Luma variance of one block.
+fn block_variance(img: &GrayImage, x0: u32, y0: u32) -> f64 {
+    let n = f64::from(BLOCK * BLOCK);
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for y in y0..y0 + BLOCK {
+        for x in x0..x0 + BLOCK {
+            let value = f64::from(img.get_pixel(x, y)[0]);
+            sum += value;
+            sum_sq += value * value;
+        }
+    }
+    let mean = sum / n;
+    sum_sq / n - mean * mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(128, 64, |x, _| image::Luma([60 + (x / 2) as u8])))
+    }
+
+    #[test]
+    fn test_clean_copy_has_no_artifacts() {
+        assert!(detect(&gradient(), &gradient()).is_empty());
+    }
+
+    #[test]
+    fn test_detects_banding_and_blocking() {
+        // Quantize the gradient into wide steps
+        let banded = DynamicImage::ImageLuma8(GrayImage::from_fn(128, 64, |x, _| image::Luma([60 + (x / 32 * 16) as u8])));
+        let artifacts = detect(&gradient(), &banded);
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].kind, ArtifactKind::Banding);
+
+        // A checkerboard of 4x4 blocks over a flat area
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(64, 64, image::Luma([128])));
+        let blocky = DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, y| {
+            image::Luma([if (x / 4 + y / 4) % 2 == 0 { 120 } else { 136 }])
+        }));
+        let artifacts = detect(&flat, &blocky);
+        assert_eq!(artifacts, vec![Artifact { kind: ArtifactKind::Blocking, share: 1.0 }]);
+    }
+}
@@ -0,0 +1,159 @@
+//! Baseline comparisons: the files of a run set against those of an earlier
+//! `--report`, so recurring conversion jobs notice files that newly fail,
+//! grow or otherwise change.
+
+use webp_converter::error::{WebPError, WebPResult};
+use crate::run_report::{FileRecord, FileStatus};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The part of a JSON run report a comparison needs.
+#[derive(Deserialize)]
+struct BaselineReport {
+    files: Vec<FileRecord>,
+}
+
+/// Read the file records of a JSON report written by `--report`.
+pub fn load_baseline(path: &Path) -> WebPResult<Vec<FileRecord>> {
+    let contents = std::fs::read_to_string(path)?;
+    let report: BaselineReport = serde_json::from_str(&contents)
+        .map_err(|e| WebPError::InvalidSettings(format!("{} is not a run report: {}", path.display(), e)))?;
+    Ok(report.files)
+}
+
+/// An output whose size changed against the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeChange {
+    pub input: PathBuf,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl SizeChange {
+    /// Change in percent of the baseline size; positive when it grew.
+    pub fn percent(&self) -> f64 {
+        (self.after as f64 - self.before as f64) / self.before.max(1) as f64 * 100.0
+    }
+}
+
+/// A file whose outcome changed against the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusChange {
+    pub input: PathBuf,
+    /// `None` for a file the baseline did not have.
+    pub before: Option<FileStatus>,
+    pub after: FileStatus,
+    /// The error or skip reason of the current run.
+    pub detail: Option<String>,
+}
+
+/// What changed between a baseline and the current run, by input path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BaselineComparison {
+    /// Failed now, but not in the baseline (including files it did not have).
+    pub newly_failed: Vec<StatusChange>,
+    /// Failed in the baseline, converted or skipped now.
+    pub fixed: Vec<StatusChange>,
+    /// Other outcome changes, e.g. converted before and skipped now.
+    pub status_changed: Vec<StatusChange>,
+    /// Converted both times, more than the threshold larger now.
+    pub grew: Vec<SizeChange>,
+    /// Converted both times, more than the threshold smaller now.
+    pub shrank: Vec<SizeChange>,
+    /// Files of the current run the baseline does not have.
+    pub new_files: usize,
+    /// Files of the baseline the current run does not have.
+    pub missing_files: usize,
+}
+
+impl BaselineComparison {
+    /// Compare `current` with `baseline`; output sizes count as changed when
+    /// they differ by more than `threshold` percent.
+    pub fn new(baseline: &[FileRecord], current: &[FileRecord], threshold: f64) -> Self {
+        let before: HashMap<&Path, &FileRecord> = baseline.iter().map(|record| (record.input.as_path(), record)).collect();
+        let mut comparison = Self::default();
+        for record in current {
+            let previous = before.get(record.input.as_path());
+            let change = |before: Option<FileStatus>| StatusChange {
+                input: record.input.clone(),
+                before,
+                after: record.status,
+                detail: record.error.clone().or_else(|| record.reason.clone()),
+            };
+            let Some(previous) = previous else {
+                comparison.new_files += 1;
+                if record.status == FileStatus::Failed {
+                    comparison.newly_failed.push(change(None));
+                }
+                continue;
+            };
+            match (previous.status, record.status) {
+                (FileStatus::Failed, FileStatus::Failed) => {}
+                (before, FileStatus::Failed) => comparison.newly_failed.push(change(Some(before))),
+                (FileStatus::Failed, _) => comparison.fixed.push(change(Some(FileStatus::Failed))),
+                (FileStatus::Converted, FileStatus::Converted) => {
+                    let (Some(before), Some(after)) = (previous.compressed_size, record.compressed_size) else { continue };
+                    let size_change = SizeChange { input: record.input.clone(), before, after };
+                    if size_change.percent() > threshold {
+                        comparison.grew.push(size_change);
+                    } else if size_change.percent() < -threshold {
+                        comparison.shrank.push(size_change);
+                    }
+                }
+                (before, after) if before != after => comparison.status_changed.push(change(Some(before))),
+                _ => {}
+            }
+        }
+        let current: HashSet<&Path> = current.iter().map(|record| record.input.as_path()).collect();
+        comparison.missing_files = baseline.iter().filter(|record| !current.contains(record.input.as_path())).count();
+        comparison.grew.sort_by(|a, b| b.percent().total_cmp(&a.percent()));
+        comparison.shrank.sort_by(|a, b| a.percent().total_cmp(&b.percent()));
+        comparison
+    }
+
+    /// Whether anything got worse: files that newly fail or grew.
+    pub fn has_regressions(&self) -> bool {
+        !self.newly_failed.is_empty() || !self.grew.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(input: &str, status: FileStatus, compressed_size: Option<u64>) -> FileRecord {
+        serde_json::from_value(serde_json::json!({ "input": input, "status": status, "compressed_size": compressed_size }))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compare_with_baseline() {
+        let baseline = [
+            record("a.png", FileStatus::Converted, Some(1000)),
+            record("b.png", FileStatus::Converted, Some(1000)),
+            record("c.png", FileStatus::Converted, Some(1000)),
+            record("d.png", FileStatus::Failed, None),
+            record("e.png", FileStatus::Converted, Some(1000)),
+            record("gone.png", FileStatus::Converted, Some(1000)),
+        ];
+        let current = [
+            record("a.png", FileStatus::Converted, Some(1050)),
+            record("b.png", FileStatus::Converted, Some(1400)),
+            record("c.png", FileStatus::Failed, None),
+            record("d.png", FileStatus::Converted, Some(500)),
+            record("e.png", FileStatus::Skipped, None),
+            record("new.png", FileStatus::Converted, Some(10)),
+        ];
+        let comparison = BaselineComparison::new(&baseline, &current, 10.0);
+        assert!(comparison.has_regressions());
+        assert_eq!(comparison.grew, [SizeChange { input: PathBuf::from("b.png"), before: 1000, after: 1400 }]);
+        assert_eq!(comparison.grew[0].percent(), 40.0);
+        assert!(comparison.shrank.is_empty());
+        let inputs = |changes: &[StatusChange]| changes.iter().map(|change| change.input.clone()).collect::<Vec<_>>();
+        assert_eq!(inputs(&comparison.newly_failed), [PathBuf::from("c.png")]);
+        assert_eq!(inputs(&comparison.fixed), [PathBuf::from("d.png")]);
+        assert_eq!(comparison.status_changed[0].after, FileStatus::Skipped);
+        assert_eq!((comparison.new_files, comparison.missing_files), (1, 1));
+    }
+}
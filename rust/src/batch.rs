@@ -0,0 +1,94 @@
+//! Lazy, per-file batch conversion.
+
+use crate::artifacts::Artifact;
+use crate::collision::OutputPlan;
+use crate::converter::{self, WebPConverter};
+use crate::dedupe::SimilarImages;
+use crate::error::WebPError;
+use crate::metrics::QualityMetrics;
+use crate::utils;
+use std::path::{Path, PathBuf};
+
+/// Outcome of converting one file in a batch.
+#[derive(Debug)]
+pub struct FileResult {
+    pub input: PathBuf,
+    /// Output path (a tile directory when tiling), when a file was written.
+    pub output: Option<PathBuf>,
+    /// Time spent on this file, in seconds.
+    pub time_taken: f64,
+    /// Source file size in bytes, if it could be read.
+    pub original_size: Option<u64>,
+    /// Output size in bytes (even if discarded), when the conversion succeeded.
+    pub compressed_size: Option<u64>,
+    /// PSNR and SSIM of the output, when metrics are enabled.
+    pub metrics: Option<QualityMetrics>,
+    /// Likely visible artifacts, when artifact detection is enabled.
+    pub artifacts: Vec<Artifact>,
+    /// The WebP was not written because it was not smaller than the source.
+    pub discarded: bool,
+    /// The WebP was not written because its SSIM fell below the minimum.
+    pub low_quality: bool,
+    /// The source was left alone as already compact enough.
+    pub already_efficient: bool,
+    /// The file was skipped as a near-duplicate of this earlier file.
+    pub duplicate_of: Option<PathBuf>,
+    pub error: Option<WebPError>,
+}
+
+impl FileResult {
+    /// Check whether the file was converted successfully.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Iterator converting one file per `next()` call.
+///
+/// Created by [`WebPConverter::convert_directory_iter`]. WebP inputs are
+/// skipped, and iteration ends early once the converter's cancellation token
+/// is triggered. Near-duplicates are detected among the files visited so far.
+pub struct BatchIter<'a> {
+    converter: &'a WebPConverter,
+    files: std::vec::IntoIter<PathBuf>,
+    output_folder: Option<PathBuf>,
+    plan: OutputPlan,
+    similar: Option<SimilarImages>,
+}
+
+impl<'a> BatchIter<'a> {
+    pub(crate) fn new(converter: &'a WebPConverter, files: Vec<PathBuf>, output_folder: Option<&Path>) -> Self {
+        Self {
+            converter,
+            plan: converter.plan_outputs(&files, |input| converter::output_path_for(input, output_folder)),
+            files: files.into_iter(),
+            output_folder: output_folder.map(Path::to_path_buf),
+            similar: converter.similar_images(),
+        }
+    }
+
+    /// Number of files not yet visited (including ones that will be skipped).
+    pub fn remaining(&self) -> usize {
+        self.files.len()
+    }
+}
+
+impl Iterator for BatchIter<'_> {
+    type Item = FileResult;
+
+    fn next(&mut self) -> Option<FileResult> {
+        loop {
+            if self.converter.is_cancelled() {
+                return None;
+            }
+
+            let input = self.files.next()?;
+            if utils::is_webp_file(&input) {
+                continue;
+            }
+
+            let output_folder = self.output_folder.as_deref();
+            return Some(self.converter.convert_file(input, output_folder, &self.plan, self.similar.as_mut()));
+        }
+    }
+}
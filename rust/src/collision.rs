@@ -0,0 +1,169 @@
+//! Collisions between the outputs of a batch and its inputs, e.g. `photo.png`
+//! converting to a `photo.webp` that is itself part of the batch, or
+//! `photo.png` and `photo.jpg` both converting to `photo.webp`.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::utils;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do with a file whose output would overwrite an input of the
+/// batch, or an output already claimed by another of its files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail the file without writing an output.
+    #[default]
+    Refuse,
+    /// Keep the source extension in the output name (`photo.png.webp`).
+    Rename,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "refuse" => Ok(CollisionPolicy::Refuse),
+            "rename" => Ok(CollisionPolicy::Rename),
+            _ => Err(format!("unknown collision action '{}' (expected refuse or rename)", s)),
+        }
+    }
+}
+
+impl fmt::Display for CollisionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CollisionPolicy::Refuse => "refuse",
+            CollisionPolicy::Rename => "rename",
+        })
+    }
+}
+
+/// Outputs of a batch that do not go to their default path.
+#[derive(Debug, Default)]
+pub(crate) struct OutputPlan {
+    moved: HashMap<PathBuf, Resolution>,
+}
+
+#[derive(Debug)]
+enum Resolution {
+    Renamed(PathBuf),
+    /// Refused for clashing with this input.
+    Refused(PathBuf),
+}
+
+impl OutputPlan {
+    /// Where `input` is written, given its `default` output path.
+    pub(crate) fn output(&self, input: &Path, default: PathBuf) -> WebPResult<PathBuf> {
+        match self.moved.get(input) {
+            None => Ok(default),
+            Some(Resolution::Renamed(renamed)) => Ok(renamed.clone()),
+            Some(Resolution::Refused(other)) => Err(WebPError::OutputCollision { output: default, other: other.clone() }),
+        }
+    }
+}
+
+impl WebPConverter {
+    /// Find the files of a batch whose default output, as given by
+    /// `output_for`, is another input or the output of an earlier file, and
+    /// resolve them by the collision policy. Files are taken in sorted order,
+    /// so the same file keeps the plain name on every run. WebP inputs are
+    /// skipped by batches and only count as inputs.
+    pub(crate) fn plan_outputs(&self, files: &[PathBuf], output_for: impl Fn(&Path) -> WebPResult<PathBuf>) -> OutputPlan {
+        let inputs: HashMap<PathBuf, &Path> = files.iter().map(|input| (path_key(input), input.as_path())).collect();
+        let mut claimed: HashMap<PathBuf, &Path> = HashMap::new();
+        let mut plan = OutputPlan::default();
+
+        let mut sorted: Vec<&PathBuf> = files.iter().filter(|input| !utils::is_webp_file(input)).collect();
+        sorted.sort();
+        let mut seen = HashSet::new();
+        for input in sorted {
+            // A file listed twice is converted twice, to the same output
+            if !seen.insert(path_key(input)) {
+                continue;
+            }
+            // An output path error is reported when the file is converted
+            let Ok(default) = output_for(input) else { continue };
+            let clash = |path: &Path| {
+                let key = path_key(path);
+                inputs.get(&key).or_else(|| claimed.get(&key)).map(|other| other.to_path_buf())
+            };
+            let Some(other) = clash(&default) else {
+                claimed.insert(path_key(&default), input);
+                continue;
+            };
+
+            let renamed = renamed_output(input, &default).filter(|_| self.collision_policy() == CollisionPolicy::Rename);
+            match renamed {
+                Some(renamed) if clash(&renamed).is_none() => {
+                    self.report(Event::OutputRenamed { path: input, output: &default, renamed: &renamed, other: &other });
+                    claimed.insert(path_key(&renamed), input);
+                    plan.moved.insert(input.clone(), Resolution::Renamed(renamed));
+                }
+                _ => {
+                    plan.moved.insert(input.clone(), Resolution::Refused(other));
+                }
+            }
+        }
+        plan
+    }
+}
+
+/// `output` named after the whole file name of `input`: `photo.png` →
+/// `photo.png.webp`.
+fn renamed_output(input: &Path, output: &Path) -> Option<PathBuf> {
+    let mut name = input.file_name()?.to_os_string();
+    name.push(".webp");
+    Some(output.with_file_name(name))
+}
+
+/// Whether two paths name the same file, whether or not it exists yet.
+pub(crate) fn is_same_file(a: &Path, b: &Path) -> bool {
+    path_key(a) == path_key(b)
+}
+
+/// A path with its folder resolved, so `./a.png`, `a.png` and a path through
+/// a symlinked folder compare equal. The file itself need not exist.
+fn path_key(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (absolute.parent().map(fs::canonicalize), absolute.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => absolute,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let files = [path("b.png"), path("b.jpg"), path("c.webp"), path("c.png"), path("d.png")];
+        let output_for = |input: &Path| Ok(input.with_extension("webp"));
+
+        let plan = WebPConverter::builder().build().unwrap().plan_outputs(&files, output_for);
+        assert_eq!(plan.output(&path("b.jpg"), path("b.webp")).unwrap(), path("b.webp"));
+        assert!(matches!(
+            plan.output(&path("b.png"), path("b.webp")),
+            Err(WebPError::OutputCollision { other, .. }) if other == path("b.jpg")
+        ));
+        assert!(matches!(
+            plan.output(&path("c.png"), path("c.webp")),
+            Err(WebPError::OutputCollision { other, .. }) if other == path("c.webp")
+        ));
+        assert_eq!(plan.output(&path("d.png"), path("d.webp")).unwrap(), path("d.webp"));
+
+        let converter = WebPConverter::builder().on_collision(CollisionPolicy::Rename).build().unwrap();
+        let plan = converter.plan_outputs(&files, output_for);
+        assert_eq!(plan.output(&path("b.png"), path("b.webp")).unwrap(), path("b.png.webp"));
+        assert_eq!(plan.output(&path("c.png"), path("c.webp")).unwrap(), path("c.png.webp"));
+        assert!(is_same_file(&path("d.png"), &dir.path().join(".").join("d.png")));
+    }
+}
@@ -0,0 +1,307 @@
+//! Color-space handling: ICC profile extraction and sRGB normalization.
+
+use crate::error::{WebPError, WebPResult};
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+use qcms::{DataType, Intent, Profile, Transform};
+use std::io::{BufRead, Cursor, Seek};
+use std::path::Path;
+
+/// Load an image together with its embedded ICC profile, if any.
+pub fn load_image(path: &Path) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+    decode(ImageReader::open(path)?.with_guessed_format()?)
+}
+
+/// Decode an encoded image held in memory.
+///
+/// The format is detected from the content unless `format` is given.
+pub fn load_image_from_memory(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+    let reader = match format {
+        Some(format) => ImageReader::with_format(Cursor::new(bytes), format),
+        None => ImageReader::new(Cursor::new(bytes)).with_guessed_format()?,
+    };
+    decode(reader)
+}
+
+/// Decode an image and its ICC profile from a reader with a known format.
+fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+    let result = match reader.format() {
+        Some(ImageFormat::Png) => decode_with_profile(PngDecoder::new(reader.into_inner())),
+        Some(ImageFormat::Jpeg) => decode_with_profile(JpegDecoder::new(reader.into_inner())),
+        Some(ImageFormat::Tiff) => decode_with_profile(TiffDecoder::new(reader.into_inner())),
+        _ => reader.decode().map(|img| (img, None)),
+    };
+
+    result.map_err(|e| match e {
+        // Kept as I/O errors, so a read failing on a network share can be retried
+        image::ImageError::IoError(e) if e.kind() != std::io::ErrorKind::UnexpectedEof => WebPError::IoError(e),
+        e => WebPError::ImageProcessingError(format!("Failed to open image: {}", e)),
+    })
+}
+
+/// Decode an image, capturing the ICC profile before the decoder is consumed.
+fn decode_with_profile<'a, D: ImageDecoder<'a>>(
+    decoder: image::ImageResult<D>,
+) -> image::ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let mut decoder = decoder?;
+    let icc = decoder.icc_profile();
+    let img = DynamicImage::from_decoder(decoder)?;
+    Ok((img, icc))
+}
+
+/// sRGB's red, green and blue colorants, adapted to the D50 connection space.
+const SRGB_COLORANTS: [(&[u8; 4], [f64; 3]); 3] = [
+    (b"rXYZ", [0.4361, 0.2225, 0.0139]),
+    (b"gXYZ", [0.3851, 0.7169, 0.0971]),
+    (b"bXYZ", [0.1431, 0.0606, 0.7141]),
+];
+
+/// How far colorants and tone curves may stray from sRGB's, covering the
+/// rounding of the published sRGB profiles.
+const SRGB_TOLERANCE: f64 = 0.005;
+
+/// Big-endian `u32` at `offset`.
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+/// `s15Fixed16Number` at `offset`.
+fn read_fixed(data: &[u8], offset: usize) -> Option<f64> {
+    Some(read_u32(data, offset)? as i32 as f64 / 65536.0)
+}
+
+/// The data of the tag with this signature, from the profile's tag table.
+fn find_tag<'a>(icc: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let tag_count = read_u32(icc, 128)? as usize;
+    let (offset, size) = (0..tag_count).find_map(|i| {
+        let entry = 132 + i * 12;
+        (icc.get(entry..entry + 4)? == signature)
+            .then(|| Some((read_u32(icc, entry + 4)? as usize, read_u32(icc, entry + 8)? as usize)))?
+    })?;
+    icc.get(offset..offset.checked_add(size)?)
+}
+
+/// Check whether an ICC profile describes sRGB, by its colorants and tone
+/// curves rather than its name.
+pub fn is_srgb_profile(icc: &[u8]) -> bool {
+    let colorants_match = SRGB_COLORANTS.iter().all(|(signature, expected)| {
+        let Some(tag) = find_tag(icc, signature).filter(|tag| tag.starts_with(b"XYZ ")) else {
+            return false;
+        };
+        (0..3).all(|i| read_fixed(tag, 8 + i * 4).is_some_and(|value| (value - expected[i]).abs() <= SRGB_TOLERANCE))
+    });
+    colorants_match && [b"rTRC", b"gTRC", b"bTRC"].iter().all(|signature| find_tag(icc, signature).is_some_and(is_srgb_curve))
+}
+
+/// The sRGB transfer function, from encoded to linear values.
+fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Whether a `curv` or `para` tone curve follows the sRGB transfer function.
+fn is_srgb_curve(tag: &[u8]) -> bool {
+    let Some(curve) = tone_curve(tag) else {
+        return false;
+    };
+    (0..=64).all(|i| {
+        let x = i as f64 / 64.0;
+        (curve(x) - srgb_to_linear(x)).abs() <= SRGB_TOLERANCE
+    })
+}
+
+/// Evaluate a `curv` or `para` tag; `None` for other types or malformed data.
+fn tone_curve(tag: &[u8]) -> Option<Box<dyn Fn(f64) -> f64 + '_>> {
+    match tag.get(0..4)? {
+        b"curv" => {
+            let count = read_u32(tag, 8)? as usize;
+            let entries = tag.get(12..12 + count.checked_mul(2)?)?;
+            let entry = move |i: usize| u16::from_be_bytes([entries[i * 2], entries[i * 2 + 1]]) as f64;
+            match count {
+                0 => Some(Box::new(|x| x)),
+                1 => {
+                    let gamma = entry(0) / 256.0;
+                    Some(Box::new(move |x: f64| x.powf(gamma)))
+                }
+                _ => Some(Box::new(move |x: f64| {
+                    // Linear interpolation between the table entries
+                    let position = x * (count - 1) as f64;
+                    let low = position.floor() as usize;
+                    let high = (low + 1).min(count - 1);
+                    let fraction = position - low as f64;
+                    (entry(low) * (1.0 - fraction) + entry(high) * fraction) / 65535.0
+                })),
+            }
+        }
+        b"para" => {
+            let function = u16::from_be_bytes(tag.get(8..10)?.try_into().ok()?);
+            let count = [1, 3, 4, 5, 7].get(function as usize)?;
+            let p: Vec<f64> = (0..*count).map(|i| read_fixed(tag, 12 + i * 4)).collect::<Option<_>>()?;
+            let g = p[0];
+            let (a, b) = (p.get(1).copied().unwrap_or(1.0), p.get(2).copied().unwrap_or(0.0));
+            let power = move |x: f64| (a * x + b).max(0.0).powf(g);
+            Some(match function {
+                0 => Box::new(move |x: f64| x.powf(g)),
+                1 => Box::new(move |x: f64| if x >= -b / a { power(x) } else { 0.0 }),
+                2 => Box::new(move |x: f64| if x >= -b / a { power(x) + p[3] } else { p[3] }),
+                3 => Box::new(move |x: f64| if x >= p[4] { power(x) } else { p[3] * x }),
+                _ => Box::new(move |x: f64| if x >= p[4] { power(x) + p[5] } else { p[3] * x + p[6] }),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Convert an image tagged with the given ICC profile into sRGB.
+///
+/// Images that are already sRGB, or whose profile cannot be used for an RGB
+/// transform (e.g., grayscale or CMYK profiles), are returned unchanged.
+pub fn to_srgb(img: DynamicImage, icc: &[u8]) -> DynamicImage {
+    if is_srgb_profile(icc) {
+        return img;
+    }
+
+    let Some(input) = Profile::new_from_slice(icc, false) else {
+        return img;
+    };
+    let mut output = Profile::new_sRGB();
+    output.precache_output_transform();
+
+    if img.color().has_alpha() {
+        let Some(transform) = Transform::new(&input, &output, DataType::RGBA8, Intent::Perceptual) else {
+            return img;
+        };
+        let mut rgba = img.to_rgba8();
+        transform.apply(&mut rgba);
+        DynamicImage::ImageRgba8(rgba)
+    } else {
+        let Some(transform) = Transform::new(&input, &output, DataType::RGB8, Intent::Perceptual) else {
+            return img;
+        };
+        let mut rgb = img.to_rgb8();
+        transform.apply(&mut rgb);
+        DynamicImage::ImageRgb8(rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a minimal ICC profile holding only the given tags.
+    fn profile(tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut icc = vec![0u8; 128];
+        icc.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut data = Vec::new();
+        let data_start = 132 + tags.len() * 12;
+        for (signature, tag) in tags {
+            icc.extend_from_slice(*signature);
+            icc.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+            icc.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+            data.extend_from_slice(tag);
+        }
+        icc.extend(data);
+        icc
+    }
+
+    fn fixed(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|value| ((value * 65536.0).round() as i32).to_be_bytes()).collect()
+    }
+
+    fn xyz(values: [f64; 3]) -> Vec<u8> {
+        [b"XYZ \0\0\0\0".to_vec(), fixed(&values)].concat()
+    }
+
+    /// A profile with sRGB's colorants and the same tone curve on every channel.
+    fn rgb_profile(colorants: [[f64; 3]; 3], curve: Vec<u8>) -> Vec<u8> {
+        profile(&[
+            (b"rXYZ", xyz(colorants[0])),
+            (b"gXYZ", xyz(colorants[1])),
+            (b"bXYZ", xyz(colorants[2])),
+            (b"rTRC", curve.clone()),
+            (b"gTRC", curve.clone()),
+            (b"bTRC", curve),
+        ])
+    }
+
+    /// A complete RGB display profile that color management can use, with
+    /// the given colorants and the same tone curve on every channel.
+    fn display_profile(colorants: [[f64; 3]; 3], curve: Vec<u8>) -> Vec<u8> {
+        let mut icc = profile(&[
+            (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+            (b"rXYZ", xyz(colorants[0])),
+            (b"gXYZ", xyz(colorants[1])),
+            (b"bXYZ", xyz(colorants[2])),
+            (b"rTRC", curve.clone()),
+            (b"gTRC", curve.clone()),
+            (b"bTRC", curve),
+        ]);
+        let size = icc.len() as u32;
+        icc[0..4].copy_from_slice(&size.to_be_bytes());
+        icc[8..12].copy_from_slice(&[4, 0x30, 0, 0]);
+        icc[12..24].copy_from_slice(b"mntrRGB XYZ ");
+        icc[36..40].copy_from_slice(b"acsp");
+        icc[68..80].copy_from_slice(&fixed(&[0.9642, 1.0, 0.8249]));
+        icc
+    }
+
+    const SRGB: [[f64; 3]; 3] = [[0.43607, 0.22249, 0.01392], [0.38515, 0.71687, 0.09708], [0.14307, 0.06061, 0.7141]];
+    const DISPLAY_P3: [[f64; 3]; 3] = [[0.51512, 0.2412, -0.00105], [0.29198, 0.69225, 0.04189], [0.1571, 0.06657, 0.78407]];
+
+    fn srgb_para() -> Vec<u8> {
+        let parameters = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+        [b"para\0\0\0\0\0\x03\0\0".to_vec(), fixed(&parameters)].concat()
+    }
+
+    #[test]
+    fn test_is_srgb_profile() {
+        assert!(is_srgb_profile(&rgb_profile(SRGB, srgb_para())));
+
+        // A 1024-entry table, as in the widespread HP sRGB profile
+        let mut table = b"curv\0\0\0\0".to_vec();
+        table.extend_from_slice(&1024u32.to_be_bytes());
+        for i in 0..1024 {
+            table.extend_from_slice(&((srgb_to_linear(i as f64 / 1023.0) * 65535.0).round() as u16).to_be_bytes());
+        }
+        assert!(is_srgb_profile(&rgb_profile(SRGB, table)));
+
+        // Linear sRGB shares the colorants but not the curve; Display P3 the reverse
+        let linear = b"curv\0\0\0\0\0\0\0\x01\x01\x00".to_vec();
+        assert!(!is_srgb_profile(&rgb_profile(SRGB, linear)));
+        assert!(!is_srgb_profile(&rgb_profile(DISPLAY_P3, srgb_para())));
+        assert!(!is_srgb_profile(&profile(&[])));
+        assert!(!is_srgb_profile(&[0u8; 16]));
+    }
+
+    #[test]
+    fn test_to_srgb_converts_display_p3() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(2, 2, image::Rgb([100, 150, 200])));
+        let result = to_srgb(img.clone(), &display_profile(DISPLAY_P3, srgb_para())).to_rgb8();
+
+        // Display P3 (100, 150, 200) is about (83, 152, 205) in sRGB
+        let expected = [83, 152, 205];
+        let pixel = result.get_pixel(1, 1).0;
+        assert!(pixel.iter().zip(expected).all(|(&value, expected)| value.abs_diff(expected) <= 3), "got {:?}", pixel);
+
+        // The same profile with sRGB's colorants leaves the pixels alone
+        let result = to_srgb(img.clone(), &display_profile(SRGB, srgb_para()));
+        assert_eq!(result.as_bytes(), img.as_bytes());
+    }
+
+    #[test]
+    fn test_to_srgb_leaves_unusable_profiles() {
+        let img = DynamicImage::new_rgb8(2, 2);
+        let icc = profile(&[(b"rTRC", srgb_para())]);
+        let result = to_srgb(img.clone(), &icc);
+        assert_eq!(result.as_bytes(), img.as_bytes());
+    }
+}
@@ -1,20 +1,176 @@
 //! Main WebP converter module.
 
+use crate::analysis::{ContentKind, ImageAnalysis};
+use crate::artifacts::{self, Artifact};
+use crate::batch::{BatchIter, FileResult};
+use crate::collision::{self, CollisionPolicy, OutputPlan};
+use crate::color;
+use crate::dedupe::{self, PerceptualHash, SimilarImages};
+use crate::encoder::{self, EncoderSettings, PixelLayout, Preset};
 use crate::error::{WebPError, WebPResult};
+use crate::journal::Journal;
+use crate::ladder::{Ladder, LadderOptions, LadderStep};
+use crate::metadata;
+use crate::metrics::{self, LowQualityPolicy, Metric, QualityMetrics};
+use crate::montage::{self, MontageOptions};
+use crate::overrides::FormatOverride;
+use crate::pipeline::{Operation, Pipeline};
+use crate::processor::{Processor, Processors};
+use crate::progress::{CancellationToken, FileOutcome, NoProgress, ProgressHandler};
+use crate::report::{Event, Reporter, SharedReporter, SheetKind};
+use crate::retry;
+use crate::sandbox::SandboxLimits;
+use crate::spritesheet;
+use crate::tiling::{self, TileIndex};
+use crate::transform::Dimensions;
 use crate::utils;
+use image::ImageFormat;
+use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 use std::fs;
 use walkdir::WalkDir;
 
+/// A file that could not be converted, and why.
+#[derive(Debug)]
+pub struct FailedFile {
+    pub path: PathBuf,
+    pub error: WebPError,
+}
+
+/// A converted file, its size before and after, and how long it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedFile {
+    pub path: PathBuf,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// Seconds spent converting.
+    pub time_taken: f64,
+}
+
+impl ConvertedFile {
+    /// Bytes saved; negative when the WebP is larger than its source.
+    pub fn saved(&self) -> i64 {
+        self.original_size as i64 - self.compressed_size as i64
+    }
+}
+
+/// Totals of the files under one top-level subdirectory of a batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirectoryRollup {
+    /// Subdirectory relative to the batch root; `.` for the files directly in it.
+    pub directory: PathBuf,
+    pub converted: usize,
+    pub failed: usize,
+    pub original_size: u64,
+    pub compressed_size: u64,
+}
+
+impl DirectoryRollup {
+    /// Bytes saved; negative when the outputs are larger than their sources.
+    pub fn saved(&self) -> i64 {
+        self.original_size as i64 - self.compressed_size as i64
+    }
+}
+
+/// Totals of the files of one source format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatBreakdown {
+    /// Format name such as `JPEG` or `PNG`, or the upper-cased extension of
+    /// a file the image crate does not recognize.
+    pub format: String,
+    pub converted: usize,
+    pub failed: usize,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// Seconds spent converting.
+    pub total_time: f64,
+}
+
+impl FormatBreakdown {
+    /// Compressed size divided by original size over the converted files;
+    /// `None` when nothing was converted.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.original_size > 0).then(|| self.compressed_size as f64 / self.original_size as f64)
+    }
+
+    /// Mean seconds per converted file; `None` when nothing was converted.
+    pub fn average_time(&self) -> Option<f64> {
+        (self.converted > 0).then(|| self.total_time / self.converted as f64)
+    }
+}
+
+/// Display name of the source format of `path`.
+fn format_name(path: &Path) -> String {
+    match utils::image_format(path) {
+        Some(format) => utils::format_name(format),
+        None => path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_uppercase())
+            .unwrap_or_else(|| "(none)".to_string()),
+    }
+}
+
 /// Statistics for conversion operations.
 #[derive(Debug, Default)]
 pub struct ConversionStats {
     pub success_count: usize,
     pub failed_count: usize,
+    /// Every failed file with its error, in processing order.
+    pub failures: Vec<FailedFile>,
+    /// Files left alone because their WebP was not smaller.
+    pub not_smaller_count: usize,
+    /// Files skipped as near-duplicates of an earlier image in the batch.
+    pub similar_count: usize,
+    /// Files left alone because they were already compact enough.
+    pub efficient_count: usize,
+    /// Converted files likely to show visible compression artifacts.
+    pub artifact_count: usize,
+    /// Files left alone because their WebP fell below the minimum SSIM.
+    pub low_quality_count: usize,
     pub total_time: f64,
     pub total_original_size: Option<u64>,
     pub total_compressed_size: Option<u64>,
+    /// Set when the batch stopped early because it was cancelled.
+    pub cancelled: bool,
+    /// Quality of each converted file, when metrics are enabled.
+    pub quality_metrics: Vec<QualityMetrics>,
+    /// Every converted file with its sizes and time, in processing order.
+    pub converted_files: Vec<ConvertedFile>,
+}
+
+/// Upper bounds of the [`RatioDistribution::histogram`] buckets; the last
+/// bucket holds the outputs larger than their source.
+pub const RATIO_BUCKETS: [f64; 5] = [0.2, 0.4, 0.6, 0.8, 1.0];
+
+/// Spread of the per-file compression ratios (compressed size divided by
+/// original size), which an overall figure can hide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatioDistribution {
+    pub min: f64,
+    pub median: f64,
+    /// 90% of the files compressed to this ratio or better.
+    pub p90: f64,
+    pub max: f64,
+    /// Files per bucket of [`RATIO_BUCKETS`], plus one for ratios above 1.
+    pub histogram: [usize; RATIO_BUCKETS.len() + 1],
+}
+
+/// Per-file conversion times in seconds, which a few slow files cannot skew
+/// the way they skew an average.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingPercentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Nearest-rank percentile `p` (0 to 1) of sorted, non-empty `values`.
+fn percentile(values: &[f64], p: f64) -> f64 {
+    values[((p * values.len() as f64).ceil() as usize).saturating_sub(1)]
 }
 
 impl ConversionStats {
@@ -22,33 +178,752 @@ impl ConversionStats {
         Self::default()
     }
 
-    pub fn add_success(&mut self, time_taken: f64, original_size: u64, compressed_size: u64) {
+    pub fn add_success(&mut self, path: PathBuf, time_taken: f64, original_size: u64, compressed_size: u64) {
         self.success_count += 1;
         self.total_time += time_taken;
         self.total_original_size = Some(self.total_original_size.unwrap_or(0) + original_size);
         self.total_compressed_size = Some(self.total_compressed_size.unwrap_or(0) + compressed_size);
+        self.converted_files.push(ConvertedFile { path, original_size, compressed_size, time_taken });
     }
 
-    pub fn add_failure(&mut self) {
+    pub fn add_failure(&mut self, path: PathBuf, error: WebPError) {
         self.failed_count += 1;
+        self.failures.push(FailedFile { path, error });
+    }
+
+    pub fn add_not_smaller(&mut self) {
+        self.not_smaller_count += 1;
+    }
+
+    pub fn add_similar(&mut self) {
+        self.similar_count += 1;
+    }
+
+    pub fn add_efficient(&mut self) {
+        self.efficient_count += 1;
+    }
+
+    pub fn add_artifact_warning(&mut self) {
+        self.artifact_count += 1;
+    }
+
+    pub fn add_low_quality(&mut self) {
+        self.low_quality_count += 1;
+    }
+
+    pub fn add_metrics(&mut self, metrics: QualityMetrics) {
+        self.quality_metrics.push(metrics);
+    }
+
+    /// Add the counts of another run, e.g. one pass of a repeated sync.
+    pub fn merge(&mut self, other: ConversionStats) {
+        let add = |total: Option<u64>, more: Option<u64>| match (total, more) {
+            (Some(total), Some(more)) => Some(total + more),
+            (total, more) => total.or(more),
+        };
+        self.success_count += other.success_count;
+        self.failed_count += other.failed_count;
+        self.failures.extend(other.failures);
+        self.not_smaller_count += other.not_smaller_count;
+        self.similar_count += other.similar_count;
+        self.efficient_count += other.efficient_count;
+        self.artifact_count += other.artifact_count;
+        self.low_quality_count += other.low_quality_count;
+        self.total_time += other.total_time;
+        self.total_original_size = add(self.total_original_size, other.total_original_size);
+        self.total_compressed_size = add(self.total_compressed_size, other.total_compressed_size);
+        self.cancelled |= other.cancelled;
+        self.quality_metrics.extend(other.quality_metrics);
+        self.converted_files.extend(other.converted_files);
+    }
+
+    /// Distribution of the compression ratios, percentiles by the nearest
+    /// rank; `None` when nothing was converted.
+    pub fn ratio_distribution(&self) -> Option<RatioDistribution> {
+        let mut ratios: Vec<f64> = self
+            .converted_files
+            .iter()
+            .filter(|file| file.original_size > 0)
+            .map(|file| file.compressed_size as f64 / file.original_size as f64)
+            .collect();
+        ratios.sort_by(f64::total_cmp);
+        if ratios.is_empty() {
+            return None;
+        }
+        let mut histogram = [0; RATIO_BUCKETS.len() + 1];
+        for ratio in &ratios {
+            histogram[RATIO_BUCKETS.iter().position(|&bound| *ratio <= bound).unwrap_or(RATIO_BUCKETS.len())] += 1;
+        }
+        Some(RatioDistribution {
+            min: ratios[0],
+            median: percentile(&ratios, 0.5),
+            p90: percentile(&ratios, 0.9),
+            max: ratios[ratios.len() - 1],
+            histogram,
+        })
+    }
+
+    /// Percentiles of the per-file conversion times, by the nearest rank;
+    /// `None` when nothing was converted.
+    pub fn timing_percentiles(&self) -> Option<TimingPercentiles> {
+        let mut times: Vec<f64> = self.converted_files.iter().map(|file| file.time_taken).collect();
+        times.sort_by(f64::total_cmp);
+        if times.is_empty() {
+            return None;
+        }
+        Some(TimingPercentiles {
+            p50: percentile(&times, 0.5),
+            p90: percentile(&times, 0.9),
+            p99: percentile(&times, 0.99),
+            max: times[times.len() - 1],
+        })
+    }
+
+    /// Up to `n` converted files that saved the most bytes, largest first.
+    pub fn top_savings(&self, n: usize) -> Vec<&ConvertedFile> {
+        let mut files: Vec<&ConvertedFile> = self.converted_files.iter().filter(|file| file.saved() > 0).collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.saved()));
+        files.truncate(n);
+        files
+    }
+
+    /// Up to `n` converted files whose WebP came out larger than the source,
+    /// the largest growth first.
+    pub fn regressions(&self, n: usize) -> Vec<&ConvertedFile> {
+        let mut files: Vec<&ConvertedFile> = self.converted_files.iter().filter(|file| file.saved() < 0).collect();
+        files.sort_by_key(|file| file.saved());
+        files.truncate(n);
+        files
+    }
+
+    /// Mean PSNR and SSIM over the measured files.
+    pub fn average_metrics(&self) -> Option<QualityMetrics> {
+        QualityMetrics::average(&self.quality_metrics)
+    }
+
+    /// Paths of the failed files, e.g. to retry exactly those.
+    pub fn failed_paths(&self) -> impl Iterator<Item = &Path> {
+        self.failures.iter().map(|failure| failure.path.as_path())
+    }
+
+    /// Converted and failed files totalled per top-level subdirectory of
+    /// `root`, the largest sources first.
+    pub fn rollup_by_directory(&self, root: &Path) -> Vec<DirectoryRollup> {
+        // Watch mode reports absolute paths for a relative root
+        let canonical_root = root.canonicalize().ok();
+        let top_level = |path: &Path| {
+            let relative = path
+                .strip_prefix(root)
+                .ok()
+                .or_else(|| canonical_root.as_deref().and_then(|root| path.strip_prefix(root).ok()));
+            let mut components = relative.into_iter().flat_map(Path::components);
+            match (components.next(), components.next()) {
+                (Some(directory), Some(_)) => PathBuf::from(directory.as_os_str()),
+                _ => PathBuf::from("."),
+            }
+        };
+
+        let mut rollups: std::collections::BTreeMap<PathBuf, DirectoryRollup> = Default::default();
+        let converted = self.converted_files.iter().map(|file| (file.path.as_path(), Some(file)));
+        for (path, converted) in converted.chain(self.failed_paths().map(|path| (path, None))) {
+            let directory = top_level(path);
+            let rollup = rollups.entry(directory.clone()).or_insert_with(|| DirectoryRollup { directory, ..Default::default() });
+            match converted {
+                Some(file) => {
+                    rollup.converted += 1;
+                    rollup.original_size += file.original_size;
+                    rollup.compressed_size += file.compressed_size;
+                }
+                None => rollup.failed += 1,
+            }
+        }
+        let mut rollups: Vec<DirectoryRollup> = rollups.into_values().collect();
+        rollups.sort_by_key(|rollup| std::cmp::Reverse(rollup.original_size));
+        rollups
+    }
+
+    /// Converted and failed files totalled per source format, the most
+    /// files first.
+    pub fn breakdown_by_format(&self) -> Vec<FormatBreakdown> {
+        let mut breakdowns: std::collections::BTreeMap<String, FormatBreakdown> = Default::default();
+        let converted = self.converted_files.iter().map(|file| (file.path.as_path(), Some(file)));
+        for (path, converted) in converted.chain(self.failed_paths().map(|path| (path, None))) {
+            let format = format_name(path);
+            let breakdown = breakdowns.entry(format.clone()).or_insert_with(|| FormatBreakdown { format, ..Default::default() });
+            match converted {
+                Some(file) => {
+                    breakdown.converted += 1;
+                    breakdown.original_size += file.original_size;
+                    breakdown.compressed_size += file.compressed_size;
+                    breakdown.total_time += file.time_taken;
+                }
+                None => breakdown.failed += 1,
+            }
+        }
+        let mut breakdowns: Vec<FormatBreakdown> = breakdowns.into_values().collect();
+        breakdowns.sort_by_key(|breakdown| std::cmp::Reverse(breakdown.converted + breakdown.failed));
+        breakdowns
+    }
+}
+
+/// Timing, sizes and quality of one converted file.
+struct Converted {
+    time_taken: f64,
+    original_size: u64,
+    compressed_size: u64,
+    metrics: Option<QualityMetrics>,
+    /// Likely visible artifacts, when artifact detection is enabled.
+    artifacts: Vec<Artifact>,
+    /// The output was not written because it was not smaller than the source.
+    discarded: bool,
+    /// The output was not written because its SSIM fell below the minimum.
+    low_quality: bool,
+    /// The image was skipped as a near-duplicate of this earlier file.
+    duplicate_of: Option<PathBuf>,
+    /// The source was skipped as already compact enough.
+    already_efficient: bool,
+}
+
+impl Converted {
+    /// Whether the image was encoded at all, so the compressed size is known.
+    fn encoded(&self) -> bool {
+        self.duplicate_of.is_none() && !self.already_efficient
+    }
+
+    /// Whether an output was written.
+    fn written(&self) -> bool {
+        self.encoded() && !self.discarded && !self.low_quality
     }
 }
 
+/// Quality decrease between re-encodes that try to meet a size budget.
+const BUDGET_QUALITY_STEP: u8 = 5;
+
 /// Main WebP converter.
+///
+/// Create one with [`WebPConverter::builder`]. The settings are fixed once
+/// built and every conversion method takes `&self`, so the converter is
+/// `Send + Sync`: a single instance (e.g. in an `Arc`) can serve many threads
+/// or request handlers at once.
+#[derive(Debug, Clone)]
 pub struct WebPConverter {
     quality: u8,
     lossless: bool,
     method: u8,
+    keep_alpha: bool,
+    pipeline: Pipeline,
+    keep_profile: bool,
+    dpi: Option<u32>,
+    tile_size: Option<u32>,
+    metrics: bool,
+    metric: Metric,
+    detect_artifacts: bool,
+    diff_output: Option<PathBuf>,
+    only_if_smaller: bool,
+    skip_efficient: Option<f64>,
+    dedupe_similar: Option<u32>,
+    report_similar_only: bool,
+    auto_mode: bool,
+    preset: Preset,
+    auto_preset: bool,
+    target_ssim: Option<f64>,
+    max_output_size: Option<u64>,
+    min_quality: u8,
+    min_ssim: Option<f64>,
+    on_low_quality: LowQualityPolicy,
+    format_overrides: Vec<FormatOverride>,
+    retries: u32,
+    salvage: bool,
+    verify: bool,
+    fallback_output: Option<PathBuf>,
+    sandbox: Option<SandboxLimits>,
+    journal: Option<Arc<Journal>>,
+    on_collision: CollisionPolicy,
+    cancellation: Option<CancellationToken>,
+    reporter: SharedReporter,
+    processors: Processors,
 }
 
-impl WebPConverter {
-    /// Create a new WebP converter with specified settings.
-    pub fn new(quality: u8, lossless: bool, method: u8) -> Self {
+// Keep the converter shareable across threads; this fails to compile if a
+// field ever loses `Send` or `Sync`.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<WebPConverter>();
+};
+
+/// Builder for [`WebPConverter`] settings.
+///
+/// Every setting has a default, so only the ones that differ need to be set:
+/// quality 80, lossy, method 4, alpha kept, colors normalized to sRGB, no
+/// density metadata and no transforms.
+#[derive(Debug, Clone)]
+pub struct WebPConverterBuilder {
+    quality: u8,
+    lossless: bool,
+    method: u8,
+    keep_alpha: bool,
+    pipeline: Pipeline,
+    resize: Option<Dimensions>,
+    keep_profile: bool,
+    dpi: Option<u32>,
+    tile_size: Option<u32>,
+    metrics: bool,
+    metric: Metric,
+    detect_artifacts: bool,
+    diff_output: Option<PathBuf>,
+    only_if_smaller: bool,
+    skip_efficient: Option<f64>,
+    dedupe_similar: Option<u32>,
+    report_similar_only: bool,
+    auto_mode: bool,
+    preset: Preset,
+    auto_preset: bool,
+    target_ssim: Option<f64>,
+    max_output_size: Option<u64>,
+    min_quality: u8,
+    min_ssim: Option<f64>,
+    on_low_quality: LowQualityPolicy,
+    format_overrides: Vec<FormatOverride>,
+    retries: u32,
+    salvage: bool,
+    verify: bool,
+    fallback_output: Option<PathBuf>,
+    sandbox: Option<SandboxLimits>,
+    journal: Option<Arc<Journal>>,
+    on_collision: CollisionPolicy,
+    cancellation: Option<CancellationToken>,
+    reporter: SharedReporter,
+    processors: Processors,
+}
+
+impl Default for WebPConverterBuilder {
+    fn default() -> Self {
         Self {
-            quality,
-            lossless,
-            method,
+            quality: 80,
+            lossless: false,
+            method: 4,
+            keep_alpha: true,
+            pipeline: Pipeline::default(),
+            resize: None,
+            keep_profile: false,
+            dpi: None,
+            tile_size: None,
+            metrics: false,
+            metric: Metric::default(),
+            detect_artifacts: false,
+            diff_output: None,
+            only_if_smaller: false,
+            skip_efficient: None,
+            dedupe_similar: None,
+            report_similar_only: false,
+            auto_mode: false,
+            preset: Preset::default(),
+            auto_preset: false,
+            target_ssim: None,
+            max_output_size: None,
+            min_quality: 40,
+            min_ssim: None,
+            on_low_quality: LowQualityPolicy::default(),
+            format_overrides: Vec::new(),
+            retries: 0,
+            salvage: false,
+            verify: false,
+            fallback_output: None,
+            sandbox: None,
+            journal: None,
+            on_collision: CollisionPolicy::default(),
+            cancellation: None,
+            reporter: SharedReporter::default(),
+            processors: Processors::default(),
+        }
+    }
+}
+
+impl WebPConverterBuilder {
+    /// Lossy quality from 1 (lowest) to 100 (highest).
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Use lossless compression.
+    pub fn lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        self
+    }
+
+    /// Compression method from 0 (fastest) to 6 (best compression).
+    pub fn method(mut self, method: u8) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Encode the alpha channel of images that have one (the default).
+    ///
+    /// When disabled the channel is dropped; add a flatten operation to the
+    /// pipeline to composite onto a background color instead.
+    pub fn keep_alpha(mut self, keep_alpha: bool) -> Self {
+        self.keep_alpha = keep_alpha;
+        self
+    }
+
+    /// Transform pipeline applied to each image before encoding.
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Fit images inside the given size after the pipeline, never enlarging.
+    pub fn resize(mut self, max_width: u32, max_height: u32) -> Self {
+        self.resize = Some(Dimensions { width: max_width, height: max_height });
+        self
+    }
+
+    /// Keep the source ICC profile instead of converting colors to sRGB.
+    pub fn keep_profile(mut self, keep_profile: bool) -> Self {
+        self.keep_profile = keep_profile;
+        self
+    }
+
+    /// Record a physical density (dots per inch) in the output metadata.
+    pub fn dpi(mut self, dpi: u32) -> Self {
+        self.dpi = Some(dpi);
+        self
+    }
+
+    /// Split each image into a grid of square tiles of the given size.
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = Some(tile_size);
+        self
+    }
+
+    /// Decode each written file again and measure its PSNR and SSIM against
+    /// the transformed source (tile grids are not measured).
+    pub fn metrics(mut self, metrics: bool) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Extra perceptual metric computed when metrics are enabled.
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Scan each lossy output for likely visible banding or blocking in the
+    /// source's smooth regions and report them as [`Event::ArtifactsFound`].
+    pub fn detect_artifacts(mut self, detect_artifacts: bool) -> Self {
+        self.detect_artifacts = detect_artifacts;
+        self
+    }
+
+    /// Write an amplified difference heatmap of each output against its
+    /// source into this folder, as `<name>.diff.png`. Tiled outputs are skipped.
+    pub fn diff_output(mut self, folder: impl Into<PathBuf>) -> Self {
+        self.diff_output = Some(folder.into());
+        self
+    }
+
+    /// Discard outputs that are not smaller than their source, keeping the
+    /// original instead (tile grids are always written).
+    pub fn only_if_smaller(mut self, only_if_smaller: bool) -> Self {
+        self.only_if_smaller = only_if_smaller;
+        self
+    }
+
+    /// Leave sources alone whose file size is already below this many bytes
+    /// per pixel (e.g. 0.1 for heavily optimized JPEGs), where WebP would
+    /// save little. Only the image header is read for such files.
+    pub fn skip_efficient(mut self, max_bytes_per_pixel: f64) -> Self {
+        self.skip_efficient = Some(max_bytes_per_pixel);
+        self
+    }
+
+    /// Skip images in a directory batch whose perceptual hash is within
+    /// `max_distance` bits (0-64) of an earlier image, e.g. burst shots.
+    /// Around 5 catches near-identical frames; higher values match looser.
+    pub fn dedupe_similar(mut self, max_distance: u32) -> Self {
+        self.dedupe_similar = Some(max_distance);
+        self
+    }
+
+    /// Only report near-duplicates found with
+    /// [`dedupe_similar`](Self::dedupe_similar) but convert them anyway.
+    pub fn report_similar_only(mut self, report_only: bool) -> Self {
+        self.report_similar_only = report_only;
+        self
+    }
+
+    /// Analyze each image and encode graphics (few colors, flat areas,
+    /// cut-outs) lossless and photos lossy, overriding [`lossless`](Self::lossless).
+    pub fn auto_mode(mut self, auto_mode: bool) -> Self {
+        self.auto_mode = auto_mode;
+        self
+    }
+
+    /// libwebp tuning preset for every image.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    /// Analyze each image and use the preset matching its content (photo,
+    /// drawing or text), overriding [`preset`](Self::preset).
+    pub fn auto_preset(mut self, auto_preset: bool) -> Self {
+        self.auto_preset = auto_preset;
+        self
+    }
+
+    /// Pick the lowest quality per image whose output reaches this SSIM
+    /// (0-1], instead of one fixed quality. Ignored for lossless encoding.
+    pub fn target_ssim(mut self, target_ssim: f64) -> Self {
+        self.target_ssim = Some(target_ssim);
+        self
+    }
+
+    /// Size budget in bytes for each output file (each tile when tiling).
+    ///
+    /// Lossy outputs over budget are re-encoded at lower qualities, down to
+    /// [`min_quality`](Self::min_quality); an output that still does not fit
+    /// fails with [`WebPError::OverBudget`] and is not written.
+    pub fn max_output_size(mut self, bytes: u64) -> Self {
+        self.max_output_size = Some(bytes);
+        self
+    }
+
+    /// Lowest quality tried when re-encoding to fit
+    /// [`max_output_size`](Self::max_output_size) (default 40).
+    pub fn min_quality(mut self, min_quality: u8) -> Self {
+        self.min_quality = min_quality;
+        self
+    }
+
+    /// Reject lossy file outputs whose SSIM against the transformed source is
+    /// below this value (0-1], handling them as set by
+    /// [`on_low_quality`](Self::on_low_quality). Tile grids and in-memory
+    /// conversions are not checked.
+    pub fn min_ssim(mut self, min_ssim: f64) -> Self {
+        self.min_ssim = Some(min_ssim);
+        self
+    }
+
+    /// What to do with outputs below [`min_ssim`](Self::min_ssim): fail the
+    /// file (the default), keep the original or retry lossless.
+    pub fn on_low_quality(mut self, policy: LowQualityPolicy) -> Self {
+        self.on_low_quality = policy;
+        self
+    }
+
+    /// Use different encoder settings for sources of one format, e.g.
+    /// lossless for PNG and quality 78 for JPEG within the same batch.
+    ///
+    /// At most one override per format is allowed.
+    pub fn format_override(mut self, format_override: FormatOverride) -> Self {
+        self.format_overrides.push(format_override);
+        self
+    }
+
+    /// Retry files, and remote transfers, that fail with a
+    /// [transient](WebPError::is_transient) error up to `retries` times,
+    /// waiting 0.5 s before the first retry and twice as long before each
+    /// further one. Files that still fail count as failed.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Recover what can be decoded from damaged JPEG and PNG files instead of
+    /// failing them: a truncated JPEG is decoded with its missing blocks
+    /// filled in, and a PNG keeps the rows decoded before the damage. Each
+    /// recovered file is reported with [`Event::Salvaged`].
+    pub fn salvage(mut self, salvage: bool) -> Self {
+        self.salvage = salvage;
+        self
+    }
+
+    /// Read every written WebP back and decode it before counting the file as
+    /// converted: its dimensions must match, and a lossless output must hold
+    /// exactly the encoded pixels. An output that fails is removed and the
+    /// file fails with [`WebPError::VerificationFailed`]. Tiled outputs are
+    /// not verified.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Write outputs that cannot be written where they belong, because of
+    /// missing permissions, a read-only file system or a full volume, into
+    /// `folder` instead of failing the file. Each redirection is reported with
+    /// [`Event::OutputRedirected`]. Tiled outputs are not redirected.
+    pub fn fallback_output(mut self, folder: impl Into<PathBuf>) -> Self {
+        self.fallback_output = Some(folder.into());
+        self
+    }
+
+    /// Decode inputs in a child process held to `limits`, so a malicious
+    /// image can crash or exhaust only that process. On Linux the child also
+    /// cannot open sockets or run programs. The child is this executable
+    /// started again, which must call [`sandbox::serve_if_child`] at the top
+    /// of `main`. A decoder that is killed fails the file with
+    /// [`WebPError::SandboxFailed`].
+    ///
+    /// [`sandbox::serve_if_child`]: crate::sandbox::serve_if_child
+    pub fn sandbox(mut self, limits: SandboxLimits) -> Self {
+        self.sandbox = Some(limits);
+        self
+    }
+
+    /// Journal the `.part` files outputs are written through, so the next run
+    /// opening the journal removes those a crash or kill left behind. Outputs
+    /// are written through part files with or without a journal. The journal
+    /// of a locked folder is [`RunLock::journal`].
+    ///
+    /// [`RunLock::journal`]: crate::RunLock::journal
+    pub fn journal(mut self, journal: Arc<Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// What to do with a file of a batch whose output would overwrite another
+    /// input, or the output of another file (`photo.png` and `photo.jpg`
+    /// both make `photo.webp`). Files refused fail with
+    /// [`WebPError::OutputCollision`]; renamed ones are reported as
+    /// [`Event::OutputRenamed`]. A single file is never converted onto itself.
+    pub fn on_collision(mut self, policy: CollisionPolicy) -> Self {
+        self.on_collision = policy;
+        self
+    }
+
+    /// Token that stops directory conversions from starting new files.
+    ///
+    /// Accepts a [`CancellationToken`] or a shared `Arc<AtomicBool>`.
+    pub fn cancellation(mut self, token: impl Into<CancellationToken>) -> Self {
+        self.cancellation = Some(token.into());
+        self
+    }
+
+    /// Receiver for conversion events; events are discarded by default.
+    pub fn reporter(mut self, reporter: impl Reporter + 'static) -> Self {
+        self.reporter = SharedReporter(Arc::new(reporter));
+        self
+    }
+
+    /// Register hooks run before and after each image is encoded.
+    ///
+    /// Processors run in the order they were added.
+    pub fn processor(mut self, processor: impl Processor + 'static) -> Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Validate the settings and create the converter.
+    pub fn build(self) -> WebPResult<WebPConverter> {
+        let invalid = |message: String| Err(WebPError::InvalidSettings(message));
+        if !(1..=100).contains(&self.quality) {
+            return invalid(format!("quality must be between 1 and 100, got {}", self.quality));
+        }
+        if self.method > 6 {
+            return invalid(format!("method must be between 0 and 6, got {}", self.method));
+        }
+        if !(1..=100).contains(&self.min_quality) {
+            return invalid(format!("minimum quality must be between 1 and 100, got {}", self.min_quality));
+        }
+        if matches!(self.max_output_size, Some(0)) {
+            return invalid("size budget must be positive".to_string());
+        }
+        if let Some(size) = self.resize {
+            if size.width == 0 || size.height == 0 {
+                return invalid(format!("resize dimensions must be positive, got {}", size));
+            }
+        }
+        if matches!(self.dpi, Some(0)) || matches!(self.tile_size, Some(0)) {
+            return invalid("dpi and tile size must be positive".to_string());
+        }
+        if !self.metric.is_available() {
+            return invalid(format!("the {} metric requires building with --features {}", self.metric, self.metric));
+        }
+        if matches!(self.skip_efficient, Some(bpp) if !(bpp > 0.0 && bpp.is_finite())) {
+            return invalid("bytes per pixel threshold must be positive".to_string());
+        }
+        if matches!(self.dedupe_similar, Some(distance) if distance > dedupe::MAX_DISTANCE) {
+            return invalid(format!("similarity distance must be at most {}", dedupe::MAX_DISTANCE));
+        }
+        if self.retries > retry::MAX_RETRIES {
+            return invalid(format!("retries must be at most {}", retry::MAX_RETRIES));
+        }
+        if let Some(target) = self.target_ssim {
+            if !(target > 0.0 && target <= 1.0) {
+                return invalid(format!("target SSIM must be in (0, 1], got {}", target));
+            }
+        }
+        if let Some(min_ssim) = self.min_ssim {
+            if !(min_ssim > 0.0 && min_ssim <= 1.0) {
+                return invalid(format!("minimum SSIM must be in (0, 1], got {}", min_ssim));
+            }
+        }
+
+        for (index, format_override) in self.format_overrides.iter().enumerate() {
+            let extension = format_override.extension();
+            if matches!(format_override.quality, Some(q) if !(1..=100).contains(&q)) {
+                return invalid(format!("{} quality must be between 1 and 100", extension));
+            }
+            if matches!(format_override.method, Some(m) if m > 6) {
+                return invalid(format!("{} method must be between 0 and 6", extension));
+            }
+            if self.format_overrides[..index].iter().any(|o| o.format == format_override.format) {
+                return invalid(format!("more than one override for {}", extension));
+            }
         }
+
+        let mut pipeline = self.pipeline;
+        if let Some(size) = self.resize {
+            pipeline.push(Operation::Resize(size));
+        }
+
+        Ok(WebPConverter {
+            quality: self.quality,
+            lossless: self.lossless,
+            method: self.method,
+            keep_alpha: self.keep_alpha,
+            pipeline,
+            keep_profile: self.keep_profile,
+            dpi: self.dpi,
+            tile_size: self.tile_size,
+            metrics: self.metrics,
+            metric: self.metric,
+            detect_artifacts: self.detect_artifacts,
+            diff_output: self.diff_output,
+            only_if_smaller: self.only_if_smaller,
+            skip_efficient: self.skip_efficient,
+            dedupe_similar: self.dedupe_similar,
+            report_similar_only: self.report_similar_only,
+            auto_mode: self.auto_mode,
+            preset: self.preset,
+            auto_preset: self.auto_preset,
+            target_ssim: self.target_ssim,
+            max_output_size: self.max_output_size,
+            min_quality: self.min_quality,
+            min_ssim: self.min_ssim,
+            on_low_quality: self.on_low_quality,
+            format_overrides: self.format_overrides,
+            retries: self.retries,
+            salvage: self.salvage,
+            verify: self.verify,
+            fallback_output: self.fallback_output,
+            sandbox: self.sandbox,
+            journal: self.journal,
+            on_collision: self.on_collision,
+            cancellation: self.cancellation,
+            reporter: self.reporter,
+            processors: self.processors,
+        })
+    }
+}
+
+impl WebPConverter {
+    /// Start configuring a converter with default settings.
+    pub fn builder() -> WebPConverterBuilder {
+        WebPConverterBuilder::default()
     }
 
     /// Convert a single image file to WebP.
@@ -58,37 +933,51 @@ impl WebPConverter {
         output_path: Option<&Path>,
         output_folder: Option<&Path>,
     ) -> WebPResult<ConversionStats> {
-        // Validate input file
-        if !utils::is_valid_image(input_path) {
+        // Validate input file; a damaged one may still be salvaged, and an
+        // untrusted one is only decoded in the sandbox
+        if !self.salvage && self.sandbox.is_none() && !utils::is_valid_image(input_path) {
             return Err(WebPError::InvalidImage(input_path.to_path_buf()));
         }
 
         // Generate output path if not provided
         let output_path = if let Some(output_path) = output_path {
             output_path.to_path_buf()
-        } else if let Some(output_folder) = output_folder {
-            // Create output folder if it doesn't exist
-            std::fs::create_dir_all(output_folder)
-                .map_err(|e| WebPError::IoError(e))?;
-
-            let file_name = input_path
-                .file_stem()
-                .ok_or_else(|| WebPError::InvalidFileName(input_path.to_path_buf()))?
-                .to_str()
-                .ok_or_else(|| WebPError::InvalidFileName(input_path.to_path_buf()))?;
-
-            output_folder.join(format!("{}.webp", file_name))
         } else {
-            utils::generate_output_path(input_path)
+            // Create output folder if it doesn't exist
+            if let Some(output_folder) = output_folder {
+                std::fs::create_dir_all(output_folder)
+                    .map_err(WebPError::IoError)?;
+            }
+            output_path_for(input_path, output_folder)?
         };
+        if collision::is_same_file(input_path, &output_path) {
+            return Err(WebPError::OutputCollision { output: output_path, other: input_path.to_path_buf() });
+        }
 
         // Perform conversion
-        let (time_taken, original_size, compressed_size) =
-            self.convert_image_to_webp(input_path, &output_path)?;
+        let converted = self.retrying(input_path, || self.convert_image_to_webp(input_path, &output_path, None))?;
 
         // Create and return stats
         let mut stats = ConversionStats::new();
-        stats.add_success(time_taken, original_size, compressed_size);
+        if converted.already_efficient {
+            stats.add_efficient();
+            return Ok(stats);
+        }
+        if converted.discarded {
+            stats.add_not_smaller();
+            return Ok(stats);
+        }
+        if converted.low_quality {
+            stats.add_low_quality();
+            return Ok(stats);
+        }
+        stats.add_success(input_path.to_path_buf(), converted.time_taken, converted.original_size, converted.compressed_size);
+        if let Some(metrics) = converted.metrics {
+            stats.add_metrics(metrics);
+        }
+        if !converted.artifacts.is_empty() {
+            stats.add_artifact_warning();
+        }
 
         Ok(stats)
     }
@@ -99,6 +988,17 @@ impl WebPConverter {
         directory: &Path,
         recursive: bool,
         output_folder: Option<&Path>,
+    ) -> WebPResult<ConversionStats> {
+        self.convert_directory_with_progress(directory, recursive, output_folder, &NoProgress)
+    }
+
+    /// Convert all images in a directory to WebP, reporting each file to `progress`.
+    pub fn convert_directory_with_progress(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+        progress: &dyn ProgressHandler,
     ) -> WebPResult<ConversionStats> {
         if !directory.exists() {
             return Err(WebPError::InputNotFound(directory.to_path_buf()));
@@ -106,195 +1006,1923 @@ impl WebPConverter {
 
         // Find all image files
         let image_files = self.find_image_files(directory, recursive)?;
+        self.convert_files_with_progress(&image_files, output_folder, progress)
+    }
+
+    /// Convert a list of image files to WebP, e.g. the ones changed in git.
+    pub fn convert_files(&self, image_files: &[PathBuf], output_folder: Option<&Path>) -> WebPResult<ConversionStats> {
+        self.convert_files_with_progress(image_files, output_folder, &NoProgress)
+    }
 
+    /// Convert a list of image files to WebP, reporting each file to `progress`.
+    pub fn convert_files_with_progress(
+        &self,
+        image_files: &[PathBuf],
+        output_folder: Option<&Path>,
+        progress: &dyn ProgressHandler,
+    ) -> WebPResult<ConversionStats> {
         if image_files.is_empty() {
             return Err(WebPError::NoImagesFound);
         }
 
-        println!("🔍 Found {} image(s) to convert...", image_files.len());
-        println!("{}", "=".repeat(60));
+        self.report(Event::BatchStarted { total: image_files.len() });
 
         let mut stats = ConversionStats::new();
+        let total = image_files.len();
+        let mut similar = self.similar_images();
+        let plan = self.plan_outputs(image_files, |input| output_path_for(input, output_folder));
+
+        for (index, img_file) in image_files.iter().enumerate() {
+            if self.is_cancelled() {
+                self.report(Event::Cancelled { completed: index, total });
+                stats.cancelled = true;
+                break;
+            }
 
-        for img_file in &image_files {
             // Skip if already WebP
             if utils::is_webp_file(img_file) {
-                println!("⏭️  Skipping {} (already WebP)", img_file.file_name().unwrap_or_default().to_string_lossy());
+                self.report(Event::FileSkipped { path: img_file, reason: "already WebP" });
+                progress.on_file_done(index, total, img_file, &FileOutcome::Skipped);
                 continue;
             }
 
-            // Convert the image
-            let output_path = if let Some(output_folder) = output_folder {
-                // Create output folder if it doesn't exist
-                std::fs::create_dir_all(output_folder)
-                    .map_err(|e| WebPError::IoError(e))?;
-
-                let file_name = img_file
-                    .file_stem()
-                    .ok_or_else(|| WebPError::InvalidFileName(img_file.to_path_buf()))?
-                    .to_str()
-                    .ok_or_else(|| WebPError::InvalidFileName(img_file.to_path_buf()))?;
-
-                output_folder.join(format!("{}.webp", file_name))
-            } else {
-                utils::generate_output_path(img_file)
-            };
+            progress.on_file_start(index, total, img_file);
 
-            match self.convert_image_to_webp(img_file, &output_path) {
-                Ok((time_taken, original_size, compressed_size)) => {
-                    stats.add_success(time_taken, original_size, compressed_size);
+            // Convert the image
+            let result = self.convert_file(img_file.clone(), output_folder, &plan, similar.as_mut());
+            match result.error {
+                None if result.discarded => {
+                    stats.add_not_smaller();
+                    progress.on_file_done(index, total, img_file, &FileOutcome::Skipped);
+                }
+                None if result.low_quality => {
+                    stats.add_low_quality();
+                    progress.on_file_done(index, total, img_file, &FileOutcome::Skipped);
+                }
+                None if result.duplicate_of.is_some() => {
+                    stats.add_similar();
+                    progress.on_file_done(index, total, img_file, &FileOutcome::Skipped);
                 }
-                Err(e) => {
-                    eprintln!("❌ Error converting {}: {}", img_file.display(), e);
-                    stats.add_failure();
+                None if result.already_efficient => {
+                    stats.add_efficient();
+                    progress.on_file_done(index, total, img_file, &FileOutcome::Skipped);
+                }
+                None => {
+                    // Both sizes are always known for a successful conversion
+                    let original_size = result.original_size.unwrap_or(0);
+                    let compressed_size = result.compressed_size.unwrap_or(0);
+                    stats.add_success(img_file.clone(), result.time_taken, original_size, compressed_size);
+                    if let Some(metrics) = result.metrics {
+                        stats.add_metrics(metrics);
+                    }
+                    if !result.artifacts.is_empty() {
+                        stats.add_artifact_warning();
+                    }
+                    let outcome = FileOutcome::Converted {
+                        time_taken: result.time_taken,
+                        original_size,
+                        compressed_size,
+                    };
+                    progress.on_file_done(index, total, img_file, &outcome);
+                }
+                Some(error) => {
+                    self.report(Event::FileFailed { path: img_file, error: &error });
+                    progress.on_file_done(index, total, img_file, &FileOutcome::Failed(&error));
+                    stats.add_failure(result.input, error);
                 }
             }
         }
 
+        progress.on_batch_done(&stats);
         Ok(stats)
     }
 
-    /// Convert an image to WebP format.
-    fn convert_image_to_webp(
+    /// Convert all images in a directory lazily, one file per iteration step.
+    ///
+    /// Unlike [`convert_directory`](Self::convert_directory), each file's
+    /// result is handed to the caller as soon as it is done. Files are found
+    /// up front, so discovery errors are returned immediately.
+    pub fn convert_directory_iter(
         &self,
-        input_path: &Path,
-        output_path: &Path,
-    ) -> WebPResult<(f64, u64, u64)> {
-        let start_time = Instant::now();
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+    ) -> WebPResult<BatchIter<'_>> {
+        if !directory.exists() {
+            return Err(WebPError::InputNotFound(directory.to_path_buf()));
+        }
 
-        // Load the image
-        let img = image::open(input_path)
-            .map_err(|e| WebPError::ImageProcessingError(format!("Failed to open image: {}", e)))?;
+        let mut image_files = self.find_image_files(directory, recursive)?;
+        image_files.sort();
+        Ok(BatchIter::new(self, image_files, output_folder))
+    }
 
-        // Convert to RGB if necessary
-        let rgb_img = img.to_rgb8();
+    /// Near-duplicate index for a new batch, when deduplication is enabled.
+    pub(crate) fn similar_images(&self) -> Option<SimilarImages> {
+        self.dedupe_similar.map(SimilarImages::new)
+    }
 
-        // Encode to WebP
-        let webp_data = self.encode_to_webp(&rgb_img)?;
+    /// Convert one file of a batch into `output_folder` (or next to it), or
+    /// where `plan` moved its output, capturing any error.
+    pub(crate) fn convert_file(
+        &self,
+        input: PathBuf,
+        output_folder: Option<&Path>,
+        plan: &OutputPlan,
+        mut similar: Option<&mut SimilarImages>,
+    ) -> FileResult {
+        let start_time = Instant::now();
+        let result = output_path_for(&input, output_folder).and_then(|output| plan.output(&input, output)).and_then(|output| {
+            if let Some(output_folder) = output_folder {
+                // Create output folder if it doesn't exist
+                fs::create_dir_all(output_folder)?;
+            }
+            let converted = self.retrying(&input, || self.convert_image_to_webp(&input, &output, similar.as_deref_mut()))?;
+            Ok((output, converted))
+        });
 
-        // Write to file
-        fs::write(output_path, webp_data)
-            .map_err(|e| WebPError::IoError(e))?;
+        match result {
+            Ok((output, converted)) => FileResult {
+                input,
+                output: converted.written().then_some(output),
+                time_taken: converted.time_taken,
+                original_size: Some(converted.original_size),
+                compressed_size: converted.encoded().then_some(converted.compressed_size),
+                metrics: converted.metrics,
+                artifacts: converted.artifacts,
+                discarded: converted.discarded,
+                low_quality: converted.low_quality,
+                already_efficient: converted.already_efficient,
+                duplicate_of: converted.duplicate_of,
+                error: None,
+            },
+            Err(error) => FileResult {
+                original_size: fs::metadata(&input).ok().map(|metadata| metadata.len()),
+                input,
+                output: None,
+                time_taken: start_time.elapsed().as_secs_f64(),
+                compressed_size: None,
+                metrics: None,
+                artifacts: Vec::new(),
+                discarded: false,
+                low_quality: false,
+                already_efficient: false,
+                duplicate_of: None,
+                error: Some(error),
+            },
+        }
+    }
 
-        // Calculate timing
-        let time_taken = start_time.elapsed().as_secs_f64();
+    /// Convert an encoded image held in memory to WebP bytes.
+    ///
+    /// The input format is detected from its content. The transform pipeline
+    /// and metadata settings apply as for files; tiling does not, since the
+    /// result is a single image.
+    pub fn convert_bytes(&self, input: &[u8]) -> WebPResult<Vec<u8>> {
+        self.convert_encoded(input, None)
+    }
 
-        // Get file sizes
-        let original_size = fs::metadata(input_path)?.len();
-        let compressed_size = fs::metadata(output_path)?.len();
-        let compression_ratio = (1.0 - compressed_size as f64 / original_size as f64) * 100.0;
+    /// Convert an image read from any source (network stream, database blob).
+    ///
+    /// The whole input is buffered before decoding. `hint` names the input
+    /// format when it is known; otherwise it is detected from the content.
+    pub fn convert_from_reader(&self, mut reader: impl Read, hint: Option<ImageFormat>) -> WebPResult<Vec<u8>> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        self.convert_encoded(&input, hint)
+    }
 
-        // Print conversion results
-        self.print_conversion_result(
-            input_path,
-            output_path,
-            original_size,
+    /// Decode, process and encode an in-memory image.
+    fn convert_encoded(&self, input: &[u8], format: Option<ImageFormat>) -> WebPResult<Vec<u8>> {
+        if let Some(converter) = self.for_format(format.or_else(|| image::guess_format(input).ok())) {
+            return converter.convert_encoded(input, format);
+        }
+
+        let (img, icc_profile) = self.decode_bytes(input, format)?;
+        let img = self.process(img, icc_profile.as_deref(), || metadata::read_orientation_from_bytes(input))?;
+        self.encode_with_metadata(&img, icc_profile.as_deref(), self.detect_content(&img))
+    }
+
+    /// Convert an image file and stream the encoded WebP into any writer.
+    ///
+    /// Useful for HTTP responses, sockets or archive entries. Returns the
+    /// number of bytes written. Tiling does not apply.
+    pub fn convert_to_writer(&self, input_path: &Path, out: &mut impl Write) -> WebPResult<u64> {
+        if let Some(converter) = self.for_format(utils::image_format(input_path)) {
+            return converter.convert_to_writer(input_path, out);
+        }
+
+        let (img, icc_profile) = self.load_transformed(input_path)?;
+        let webp_data = self.encode_with_metadata(&img, icc_profile.as_deref(), self.detect_content(&img))?;
+        out.write_all(&webp_data)?;
+        Ok(webp_data.len() as u64)
+    }
+
+    /// Encode an already decoded or generated image to WebP bytes.
+    ///
+    /// The image is assumed to be sRGB. The transform pipeline still applies
+    /// (the image is only copied when there is something to transform), but
+    /// auto-rotation has no EXIF orientation to read.
+    pub fn convert_image(&self, img: &image::DynamicImage) -> WebPResult<Vec<u8>> {
+        if self.pipeline.is_empty() && self.processors.is_empty() {
+            return self.encode_with_metadata(img, None, self.detect_content(img));
+        }
+
+        let mut img = self.pipeline.apply(img.clone(), None)?;
+        self.processors.before_encode(&mut img)?;
+        self.encode_with_metadata(&img, None, self.detect_content(&img))
+    }
+
+    /// Pack every image in a directory into one WebP sprite sheet.
+    ///
+    /// The sheet is written to `output_path` (default: `<directory>.webp`) with
+    /// a JSON coordinate map and a CSS file of the same name next to it. Two
+    /// images that would get the same sprite name fail with
+    /// [`WebPError::DuplicateSprite`].
+    pub fn create_spritesheet(
+        &self,
+        directory: &Path,
+        output_path: Option<&Path>,
+        padding: u32,
+        recursive: bool,
+    ) -> WebPResult<ConversionStats> {
+        let start_time = Instant::now();
+
+        let output_path = match output_path {
+            Some(path) => path.to_path_buf(),
+            None => directory.canonicalize()?.with_extension("webp"),
+        };
+        let image_name =
+            utils::escape_path(Path::new(output_path.file_name().ok_or_else(|| WebPError::InvalidFileName(output_path.clone()))?));
+
+        // Sprites are looked up by name, so no two files may share one
+        let files = self.folder_files(directory, recursive, utils::is_supported_extension)?;
+        let mut names = HashMap::new();
+        for path in &files {
+            let name = spritesheet::css_identifier(&frame_name(directory, path));
+            if let Some(first) = names.insert(name.clone(), path) {
+                return Err(WebPError::DuplicateSprite { name, first: first.clone(), second: path.clone() });
+            }
+        }
+
+        let (images, original_size) = self.load_folder(directory, &files)?;
+        self.report(Event::SheetStarted { kind: SheetKind::SpriteSheet, count: images.len() });
+
+        let (sheet, mut map) = spritesheet::build(&images, padding, &image_name);
+        map.dpi = self.dpi;
+        let sheet = image::DynamicImage::ImageRgba8(sheet);
+        self.write_file(&output_path, &self.encode_with_metadata(&sheet, None, self.detect_content(&sheet))?)?;
+
+        let json = serde_json::to_string_pretty(&map)
+            .map_err(|e| WebPError::EncodingError(format!("Failed to write sprite map: {}", e)))?;
+        self.write_file(&output_path.with_extension("json"), json.as_bytes())?;
+        self.write_file(&output_path.with_extension("css"), map.to_css().as_bytes())?;
+
+        let time_taken = start_time.elapsed().as_secs_f64();
+        let compressed_size = fs::metadata(&output_path)?.len();
+        self.report(Event::SheetWritten {
+            kind: SheetKind::SpriteSheet,
+            output: &output_path,
+            width: map.width,
+            height: map.height,
+            compressed_size,
+        });
+
+        let mut stats = ConversionStats::new();
+        stats.add_success(output_path.clone(), time_taken, original_size, compressed_size);
+        Ok(stats)
+    }
+
+    /// Render every image in a directory into one labeled WebP contact sheet.
+    ///
+    /// The sheet is written to `output_path` (default: `<directory>_montage.webp`).
+    pub fn create_montage(
+        &self,
+        directory: &Path,
+        output_path: Option<&Path>,
+        options: &MontageOptions,
+        recursive: bool,
+    ) -> WebPResult<ConversionStats> {
+        let start_time = Instant::now();
+
+        let output_path = match output_path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let directory = directory.canonicalize()?;
+                let mut name = directory.file_name().unwrap_or_default().to_os_string();
+                name.push("_montage.webp");
+                directory.with_file_name(name)
+            }
+        };
+
+        // A sheet for reviewing a converted batch takes its WebP files too
+        let files = self.folder_files(directory, recursive, |path| {
+            utils::is_supported_extension(path) || utils::is_webp_file(path)
+        })?;
+        let (thumbnails, original_size) = self.load_thumbnails(directory, &files, options)?;
+        self.report(Event::SheetStarted { kind: SheetKind::ContactSheet, count: thumbnails.len() });
+
+        let sheet = image::DynamicImage::ImageRgba8(montage::build(&thumbnails, options));
+        self.write_file(&output_path, &self.encode_with_metadata(&sheet, None, self.detect_content(&sheet))?)?;
+
+        let time_taken = start_time.elapsed().as_secs_f64();
+        let compressed_size = fs::metadata(&output_path)?.len();
+        self.report(Event::SheetWritten {
+            kind: SheetKind::ContactSheet,
+            output: &output_path,
+            width: sheet.width(),
+            height: sheet.height(),
+            compressed_size,
+        });
+
+        let mut stats = ConversionStats::new();
+        stats.add_success(output_path.clone(), time_taken, original_size, compressed_size);
+        Ok(stats)
+    }
+
+    /// Encode an image at every quality of a ladder and measure each result.
+    ///
+    /// Rungs are always lossy and use a fixed quality, so lossless, automatic
+    /// mode and target SSIM settings do not apply; tiling does not either.
+    /// Nothing is written unless `options.keep` is set, in which case each
+    /// rung is saved as `<name>.q<quality>.webp` in `output_folder` (or next
+    /// to the source).
+    pub fn quality_ladder(
+        &self,
+        input_path: &Path,
+        output_folder: Option<&Path>,
+        options: &LadderOptions,
+    ) -> WebPResult<Ladder> {
+        if options.qualities.is_empty() {
+            return Err(WebPError::InvalidSettings("a quality ladder needs at least one quality".to_string()));
+        }
+        if let Some(&quality) = options.qualities.iter().find(|q| !(1..=100).contains(*q)) {
+            return Err(WebPError::InvalidSettings(format!("quality must be between 1 and 100, got {}", quality)));
+        }
+        if let Some(converter) = self.for_format(utils::image_format(input_path)) {
+            return converter.quality_ladder(input_path, output_folder, options);
+        }
+
+        let (img, icc_profile) = self.load_transformed(input_path)?;
+        let original_size = fs::metadata(input_path)?.len();
+        let content = self.detect_content(&img);
+        if options.keep {
+            if let Some(output_folder) = output_folder {
+                fs::create_dir_all(output_folder)?;
+            }
+        }
+
+        let mut steps = Vec::with_capacity(options.qualities.len());
+        for &quality in &options.qualities {
+            let rung = WebPConverter {
+                quality,
+                lossless: false,
+                auto_mode: false,
+                target_ssim: None,
+                max_output_size: None,
+                ..self.clone()
+            };
+            let webp_data = rung.encode_with_metadata(&img, icc_profile.as_deref(), content)?;
+            let metrics = QualityMetrics::measure(&img, &webp_data, self.metric)?;
+            if options.keep {
+                let output_path = output_path_for(input_path, output_folder)?.with_extension(format!("q{}.webp", quality));
+                self.write_file(&output_path, &webp_data)?;
+            }
+            steps.push(LadderStep { quality, compressed_size: webp_data.len() as u64, metrics });
+        }
+
+        Ok(Ladder { input: input_path.to_path_buf(), original_size, steps })
+    }
+
+    /// Build a quality ladder for every image in a directory, sorted by path.
+    ///
+    /// Files that fail are reported as [`Event::FileFailed`] and left out;
+    /// use [`Ladder::combine`] for a batch-wide table.
+    pub fn quality_ladder_directory(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+        options: &LadderOptions,
+    ) -> WebPResult<Vec<Ladder>> {
+        if !directory.exists() {
+            return Err(WebPError::InputNotFound(directory.to_path_buf()));
+        }
+
+        let mut image_files = self.find_image_files(directory, recursive)?;
+        if image_files.is_empty() {
+            return Err(WebPError::NoImagesFound);
+        }
+        image_files.sort();
+
+        let mut ladders = Vec::with_capacity(image_files.len());
+        for path in &image_files {
+            if self.is_cancelled() {
+                break;
+            }
+            match self.quality_ladder(path, output_folder, options) {
+                Ok(ladder) => ladders.push(ladder),
+                Err(error @ WebPError::InvalidSettings(_)) => return Err(error),
+                Err(error) => self.report(Event::FileFailed { path, error: &error }),
+            }
+        }
+        Ok(ladders)
+    }
+
+    /// The images of a directory going into a sheet, sorted by path.
+    fn folder_files(&self, directory: &Path, recursive: bool, matches: fn(&Path) -> bool) -> WebPResult<Vec<PathBuf>> {
+        let mut image_files = find_files(directory, recursive, matches)?;
+        if image_files.is_empty() {
+            return Err(WebPError::NoImagesFound);
+        }
+        image_files.sort();
+        Ok(image_files)
+    }
+
+    /// Load and transform the images of a directory, named by [`frame_name`];
+    /// the total size of the source files is returned alongside.
+    fn load_folder(&self, directory: &Path, files: &[PathBuf]) -> WebPResult<(Vec<(String, image::DynamicImage)>, u64)> {
+        let mut images = Vec::with_capacity(files.len());
+        let mut original_size = 0;
+        for path in files {
+            if self.is_cancelled() {
+                return Err(WebPError::Cancelled);
+            }
+            let (img, _) = self.load_transformed(path)?;
+            original_size += fs::metadata(path)?.len();
+            images.push((frame_name(directory, path), img));
+        }
+
+        Ok((images, original_size))
+    }
+
+    /// Load the images of a directory as contact sheet thumbnails, named by
+    /// [`frame_name`], scaling each one down as soon as it is decoded. Files
+    /// that fail to load are reported and left out; the total size of the
+    /// loaded source files is returned alongside.
+    fn load_thumbnails(
+        &self,
+        directory: &Path,
+        files: &[PathBuf],
+        options: &MontageOptions,
+    ) -> WebPResult<(Vec<(String, image::RgbaImage)>, u64)> {
+        let mut thumbnails = Vec::with_capacity(files.len());
+        let mut original_size = 0;
+        for path in files {
+            if self.is_cancelled() {
+                return Err(WebPError::Cancelled);
+            }
+            match self.load_transformed(path).and_then(|(img, _)| Ok((img, fs::metadata(path)?.len()))) {
+                Ok((img, size)) => {
+                    thumbnails.push((frame_name(directory, path), montage::thumbnail(&img, options)));
+                    original_size += size;
+                }
+                Err(error) => self.report(Event::FileFailed { path, error: &error }),
+            }
+        }
+        if thumbnails.is_empty() {
+            return Err(WebPError::NoImagesFound);
+        }
+
+        Ok((thumbnails, original_size))
+    }
+
+    /// Convert an image to WebP format.
+    ///
+    /// With a `similar` index, near-duplicates of earlier images are reported
+    /// and, unless only reporting, skipped before encoding.
+    fn convert_image_to_webp(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        similar: Option<&mut SimilarImages>,
+    ) -> WebPResult<Converted> {
+        let format = utils::image_format(input_path);
+        if let Some(converter) = self.for_format(format) {
+            return converter.convert_image_to_webp(input_path, output_path, similar);
+        }
+
+        // Decoding goes by the content, so a misnamed file only needs a warning
+        if let (Some(format), Ok(named)) = (format, ImageFormat::from_path(input_path)) {
+            if format != named {
+                self.report(Event::FormatMismatch { path: input_path, format });
+            }
+        }
+
+        let start_time = Instant::now();
+        let original_size = fs::metadata(input_path)?.len();
+
+        // Judge compactness from the header alone, before decoding
+        if let Some(max_bytes_per_pixel) = self.skip_efficient {
+            let (width, height) = utils::image_dimensions(input_path)
+                .map_err(|_| WebPError::InvalidImage(input_path.to_path_buf()))?;
+            let bytes_per_pixel = original_size as f64 / (f64::from(width) * f64::from(height)).max(1.0);
+            if bytes_per_pixel < max_bytes_per_pixel {
+                let reason = format!("already efficient at {:.3} bytes/pixel", bytes_per_pixel);
+                self.report(Event::FileSkipped { path: input_path, reason: &reason });
+                return Ok(Converted {
+                    time_taken: start_time.elapsed().as_secs_f64(),
+                    original_size,
+                    compressed_size: 0,
+                    metrics: None,
+                    artifacts: Vec::new(),
+                    discarded: false,
+                    low_quality: false,
+                    duplicate_of: None,
+                    already_efficient: true,
+                });
+            }
+        }
+
+        let (img, icc_profile) = self.load_transformed(input_path)?;
+
+        if let Some(similar) = similar {
+            if let Some((original, distance)) = similar.check(input_path, PerceptualHash::of(&img)) {
+                let skipped = !self.report_similar_only;
+                self.report(Event::SimilarFound { path: input_path, original: &original, distance, skipped });
+                if skipped {
+                    return Ok(Converted {
+                        time_taken: start_time.elapsed().as_secs_f64(),
+                        original_size,
+                        compressed_size: 0,
+                        metrics: None,
+                        artifacts: Vec::new(),
+                        discarded: false,
+                        low_quality: false,
+                        duplicate_of: Some(original),
+                        already_efficient: false,
+                    });
+                }
+            }
+        }
+
+        let content = self.detect_content(&img);
+
+        // Encode and write either a single file or a tile grid
+        let (output_path, webp_data, tiles_size) = match self.tile_size {
+            Some(tile_size) => {
+                let (dir, size) = self.write_tiles(input_path, output_path, &img, icc_profile.as_deref(), content, tile_size)?;
+                (dir, None, Some(size))
+            }
+            None => {
+                let mut webp_data = self.encode_with_metadata(&img, icc_profile.as_deref(), content)?;
+
+                // Measure lossy outputs against the minimum SSIM before writing
+                if let Some(min_ssim) = self.min_ssim.filter(|_| !self.is_lossless(content)) {
+                    let ssim = metrics::ssim(&img, &metrics::decode_matching(&img, &webp_data)?);
+                    if ssim < min_ssim {
+                        let action = self.on_low_quality;
+                        if action == LowQualityPolicy::Fail {
+                            return Err(WebPError::LowQuality { ssim, min_ssim });
+                        }
+                        self.report(Event::LowQuality { path: input_path, ssim, min_ssim, action });
+                        if action == LowQualityPolicy::KeepOriginal {
+                            return Ok(Converted {
+                                time_taken: start_time.elapsed().as_secs_f64(),
+                                original_size,
+                                compressed_size: webp_data.len() as u64,
+                                metrics: None,
+                                artifacts: Vec::new(),
+                                discarded: false,
+                                low_quality: true,
+                                duplicate_of: None,
+                                already_efficient: false,
+                            });
+                        }
+                        webp_data = self.lossless_copy().encode_with_metadata(&img, icc_profile.as_deref(), content)?;
+                    }
+                }
+
+                // Keep only the source when WebP would not save anything
+                if self.only_if_smaller && webp_data.len() as u64 >= original_size {
+                    self.report(Event::FileSkipped { path: input_path, reason: "WebP not smaller than source" });
+                    return Ok(Converted {
+                        time_taken: start_time.elapsed().as_secs_f64(),
+                        original_size,
+                        compressed_size: webp_data.len() as u64,
+                        metrics: None,
+                        artifacts: Vec::new(),
+                        discarded: true,
+                        low_quality: false,
+                        duplicate_of: None,
+                        already_efficient: false,
+                    });
+                }
+
+                let output_path = self.write_output(input_path, output_path, &webp_data)?;
+                if self.verify {
+                    self.verify_output(&output_path, &img, self.is_lossless(content))?;
+                }
+                (output_path, Some(webp_data), None)
+            }
+        };
+        let output_path = output_path.as_path();
+
+        // Calculate timing
+        let time_taken = start_time.elapsed().as_secs_f64();
+
+        // Decode the output again to compare it with the transformed source
+        let check_artifacts = self.detect_artifacts && !self.is_lossless(content);
+        let encoded = match webp_data {
+            Some(webp_data) if self.metrics || self.diff_output.is_some() || check_artifacts => {
+                Some(metrics::decode_matching(&img, &webp_data)?)
+            }
+            _ => None,
+        };
+        let artifacts = match &encoded {
+            Some(encoded) if check_artifacts => artifacts::detect(&img, encoded),
+            _ => Vec::new(),
+        };
+        if !artifacts.is_empty() {
+            self.report(Event::ArtifactsFound { path: input_path, artifacts: &artifacts });
+        }
+        let metrics = match &encoded {
+            Some(encoded) if self.metrics => Some(QualityMetrics::compare(&img, encoded, self.metric)?),
+            _ => None,
+        };
+        if let (Some(diff_folder), Some(encoded)) = (&self.diff_output, &encoded) {
+            fs::create_dir_all(diff_folder)?;
+            let diff_path = output_path_for(input_path, Some(diff_folder))?.with_extension("diff.png");
+            metrics::diff_heatmap(&img, encoded)
+                .save(&diff_path)
+                .map_err(|e| WebPError::ImageProcessingError(format!("Failed to write {}: {}", diff_path.display(), e)))?;
+        }
+
+        // Get output size; a tile folder may hold tiles of earlier runs, so
+        // only what this run wrote counts
+        let compressed_size = match tiles_size {
+            Some(size) => size,
+            None => fs::metadata(output_path)?.len(),
+        };
+
+        self.report(Event::FileConverted {
+            input: input_path,
+            output: output_path,
+            original_size,
             compressed_size,
-            compression_ratio,
             time_taken,
-        );
+            metrics,
+            content,
+        });
 
-        Ok((time_taken, original_size, compressed_size))
+        Ok(Converted {
+            time_taken,
+            original_size,
+            compressed_size,
+            metrics,
+            artifacts,
+            discarded: false,
+            low_quality: false,
+            duplicate_of: None,
+            already_efficient: false,
+        })
     }
 
-    /// Encode RGB image to WebP format.
-    fn encode_to_webp(&self, img: &image::RgbImage) -> WebPResult<Vec<u8>> {
-        use webp::Encoder;
+    /// A copy of this converter with the override for a source `format`
+    /// applied, or `None` when that format has no override.
+    fn for_format(&self, format: Option<ImageFormat>) -> Option<WebPConverter> {
+        let format_override = *self.format_overrides.iter().find(|o| Some(o.format) == format)?;
 
-        // Convert image to RGB bytes
-        let (width, height) = img.dimensions();
-        let rgb_data = img.as_raw();
+        let mut converter = self.clone();
+        converter.format_overrides.clear();
+        // Explicit per-format settings win over automatic selection
+        if let Some(quality) = format_override.quality {
+            converter.quality = quality;
+            converter.target_ssim = None;
+        }
+        if let Some(lossless) = format_override.lossless {
+            converter.lossless = lossless;
+            converter.auto_mode = false;
+        }
+        if let Some(method) = format_override.method {
+            converter.method = method;
+        }
+        if let Some(preset) = format_override.preset {
+            converter.preset = preset;
+            converter.auto_preset = false;
+        }
+        Some(converter)
+    }
+
+    /// A copy of this converter that always encodes lossless, for outputs
+    /// rejected as too lossy.
+    fn lossless_copy(&self) -> WebPConverter {
+        let mut converter = self.clone();
+        converter.lossless = true;
+        converter.auto_mode = false;
+        converter
+    }
+
+    /// A copy of this converter encoding lossy outputs at `quality`, e.g.
+    /// for a per-request override.
+    pub(crate) fn with_quality(&self, quality: u8) -> WebPConverter {
+        let mut converter = self.clone();
+        converter.quality = quality;
+        converter.target_ssim = None;
+        converter
+    }
+
+    /// A copy of this converter that also fits images inside `size` after
+    /// the configured transforms, never enlarging.
+    #[cfg(feature = "server")]
+    pub(crate) fn with_resize(&self, size: Dimensions) -> WebPConverter {
+        let mut converter = self.clone();
+        converter.pipeline.push(Operation::Resize(size));
+        converter
+    }
 
-        // Create WebP encoder with quality settings
-        let encoder = Encoder::from_rgb(
-            rgb_data,
-            width as u32,
-            height as u32,
-        );
+    /// Folder receiving difference heatmaps, if any.
+    #[cfg(feature = "watch")]
+    pub(crate) fn diff_output_folder(&self) -> Option<&Path> {
+        self.diff_output.as_deref()
+    }
 
-        // Set quality based on settings
-        let quality = if self.lossless { 100.0 } else { self.quality as f32 };
+    /// How often transient failures are retried.
+    pub(crate) fn retries(&self) -> u32 {
+        self.retries
+    }
 
-        // Encode to WebP
-        let webp_data = encoder.encode(quality);
+    /// Whether damaged images are salvaged rather than failed.
+    pub(crate) fn salvages(&self) -> bool {
+        self.salvage
+    }
 
-        // Check if encoding was successful by trying to access the data
-        if webp_data.len() > 0 {
-            Ok(webp_data.to_vec())
-        } else {
-            Err(WebPError::EncodingError("Failed to encode WebP - empty result".to_string()))
+    /// Folder for outputs that cannot be written where they belong.
+    pub(crate) fn fallback_folder(&self) -> Option<&Path> {
+        self.fallback_output.as_deref()
+    }
+
+    /// Limits of the sandboxed decoder, when inputs are decoded in one.
+    pub(crate) fn sandbox_limits(&self) -> Option<SandboxLimits> {
+        self.sandbox
+    }
+
+    /// Journal of the part files being written, if any.
+    pub(crate) fn journal(&self) -> Option<&Journal> {
+        self.journal.as_deref()
+    }
+
+    /// What to do with outputs that collide within a batch.
+    pub(crate) fn collision_policy(&self) -> CollisionPolicy {
+        self.on_collision
+    }
+
+    /// Whether alpha channels are kept in the output.
+    pub(crate) fn keeps_alpha(&self) -> bool {
+        self.keep_alpha
+    }
+
+    /// Check whether the configured cancellation token has been triggered.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Load an image, normalize its colors and run the transform pipeline.
+    ///
+    /// Returns the processed image together with its original ICC profile.
+    fn load_transformed(&self, input_path: &Path) -> WebPResult<(image::DynamicImage, Option<Vec<u8>>)> {
+        // Load the image along with its color profile
+        let (img, icc_profile) = self.load_image(input_path)?;
+        let img = self.process(img, icc_profile.as_deref(), || metadata::read_orientation(input_path))?;
+        Ok((img, icc_profile))
+    }
+
+    /// Normalize colors to sRGB, apply the pre-encode transforms and run the
+    /// processors' `before_encode` hooks.
+    ///
+    /// The EXIF orientation is only looked up when the pipeline needs it.
+    fn process(
+        &self,
+        img: image::DynamicImage,
+        icc_profile: Option<&[u8]>,
+        orientation: impl FnOnce() -> Option<u32>,
+    ) -> WebPResult<image::DynamicImage> {
+        // Normalize colors to sRGB unless the profile should be kept
+        let img = match icc_profile {
+            Some(icc) if !self.keep_profile => color::to_srgb(img, icc),
+            _ => img,
+        };
+
+        // Apply pre-encode transforms
+        let orientation = if self.pipeline.needs_orientation() { orientation() } else { None };
+        let mut img = self.pipeline.apply(img, orientation)?;
+
+        self.processors.before_encode(&mut img)?;
+        Ok(img)
+    }
+
+    /// Classify an image's content when the mode or preset is chosen automatically.
+    fn detect_content(&self, img: &image::DynamicImage) -> Option<ContentKind> {
+        (self.auto_mode || self.auto_preset).then(|| ImageAnalysis::of(img).content())
+    }
+
+    /// Encode an image, embed the configured metadata chunks and run the
+    /// processors' `after_encode` hooks.
+    ///
+    /// A detected `content` kind overrides the configured lossless setting
+    /// and preset where automatic selection is enabled. With a size budget,
+    /// lossy outputs are re-encoded at stepped-down qualities until they fit,
+    /// starting below the quality the first encode used.
+    fn encode_with_metadata(
+        &self,
+        img: &image::DynamicImage,
+        icc_profile: Option<&[u8]>,
+        content: Option<ContentKind>,
+    ) -> WebPResult<Vec<u8>> {
+        let (mut webp_data, mut quality) = self.encode_embedded(img, icc_profile, content, None)?;
+
+        if let Some(budget) = self.max_output_size {
+            while webp_data.len() as u64 > budget && !self.is_lossless(content) && quality > self.min_quality {
+                quality = quality.saturating_sub(BUDGET_QUALITY_STEP).max(self.min_quality);
+                (webp_data, quality) = self.encode_embedded(img, icc_profile, content, Some(quality))?;
+            }
+            if webp_data.len() as u64 > budget {
+                return Err(WebPError::OverBudget { size: webp_data.len() as u64, budget, quality });
+            }
         }
+
+        self.processors.after_encode(&webp_data)?;
+        Ok(webp_data)
     }
 
-    /// Print formatted conversion results.
-    fn print_conversion_result(
+    /// Encode an image and embed the configured metadata chunks.
+    ///
+    /// `quality` replaces the configured quality (and any SSIM target) when
+    /// given. Returns the data with the quality it was encoded at.
+    fn encode_embedded(
+        &self,
+        img: &image::DynamicImage,
+        icc_profile: Option<&[u8]>,
+        content: Option<ContentKind>,
+        quality: Option<u8>,
+    ) -> WebPResult<(Vec<u8>, u8)> {
+        let (mut webp_data, quality) = self.encode_to_webp(img, content, quality)?;
+
+        // Embed the original profile when keeping it, and the density if requested
+        let density = self.dpi.map(metadata::density_exif);
+        let embedded = metadata::EmbeddedMetadata {
+            icc_profile: icc_profile.filter(|_| self.keep_profile),
+            exif: density.as_deref(),
+        };
+        if !embedded.is_empty() {
+            webp_data = metadata::embed_metadata(&webp_data, img.width(), img.height(), embedded)?;
+        }
+        Ok((webp_data, quality))
+    }
+
+    /// Whether an image with this detected content is encoded lossless.
+    fn is_lossless(&self, content: Option<ContentKind>) -> bool {
+        match content {
+            Some(content) if self.auto_mode => content.prefers_lossless(),
+            _ => self.lossless,
+        }
+    }
+
+    /// Split an image into tiles next to `output_path`, returning the tile directory.
+    fn write_tiles(
         &self,
         input_path: &Path,
         output_path: &Path,
-        original_size: u64,
-        compressed_size: u64,
-        compression_ratio: f64,
-        time_taken: f64,
-    ) {
-        println!("✅ Converted: {}", input_path.file_name().unwrap_or_default().to_string_lossy());
-        println!("   📁 Output: {}", output_path.file_name().unwrap_or_default().to_string_lossy());
-        println!("   📊 Original: {}", utils::format_size(original_size));
-        println!("   🗜️  Compressed: {}", utils::format_size(compressed_size));
-        println!("   📈 Compression: {:.1}%", compression_ratio);
-        println!("   ⏱️  Time taken: {:.2}s", time_taken);
-        println!();
+        img: &image::DynamicImage,
+        icc_profile: Option<&[u8]>,
+        content: Option<ContentKind>,
+        tile_size: u32,
+    ) -> WebPResult<(PathBuf, u64)> {
+        let dir = tiling::tiles_dir(output_path);
+        fs::create_dir_all(&dir)?;
+
+        let index = TileIndex { dpi: self.dpi, ..TileIndex::new(input_path, img.width(), img.height(), tile_size) };
+        let mut size = 0;
+        for tile in &index.tiles {
+            let tile_img = img.crop_imm(tile.x, tile.y, tile.width, tile.height);
+            let webp_data = self.encode_with_metadata(&tile_img, icc_profile, content)?;
+            self.write_file(&dir.join(&tile.file), &webp_data)?;
+            size += webp_data.len() as u64;
+        }
+
+        let json = serde_json::to_string_pretty(&index)
+            .map_err(|e| WebPError::EncodingError(format!("Failed to write tile index: {}", e)))?;
+        self.write_file(&dir.join("index.json"), json.as_bytes())?;
+        size += json.len() as u64;
+
+        Ok((dir, size))
+    }
+
+    /// Encode an image to WebP format, keeping the alpha channel if present.
+    ///
+    /// Returns the data with the quality it was encoded at, which an SSIM
+    /// target chooses per image.
+    fn encode_to_webp(
+        &self,
+        img: &image::DynamicImage,
+        content: Option<ContentKind>,
+        quality: Option<u8>,
+    ) -> WebPResult<(Vec<u8>, u8)> {
+        // Convert image to RGB or RGBA bytes
+        let has_alpha = self.keep_alpha && img.color().has_alpha();
+        let (pixels, layout) = if has_alpha {
+            (img.to_rgba8().into_raw(), PixelLayout::Rgba)
+        } else {
+            (img.to_rgb8().into_raw(), PixelLayout::Rgb)
+        };
+
+        let lossless = self.is_lossless(content);
+        let preset = match content {
+            Some(content) if self.auto_preset => content.preset(),
+            _ => self.preset,
+        };
+        let settings = EncoderSettings {
+            quality: quality.unwrap_or(self.quality),
+            lossless,
+            method: self.method,
+            preset,
+            layout,
+        };
+        match self.target_ssim {
+            Some(target) if !lossless && quality.is_none() => self.encode_for_ssim(img, &pixels, settings, target),
+            _ => Ok((encoder::encode_webp(&pixels, img.width(), img.height(), &settings)?, settings.quality)),
+        }
+    }
+
+    /// Binary-search the lowest quality whose output reaches `target` SSIM.
+    ///
+    /// Falls back to quality 100 when no quality reaches the target.
+    fn encode_for_ssim(
+        &self,
+        img: &image::DynamicImage,
+        pixels: &[u8],
+        settings: EncoderSettings,
+        target: f64,
+    ) -> WebPResult<(Vec<u8>, u8)> {
+        let encode = |quality| encoder::encode_webp(pixels, img.width(), img.height(), &EncoderSettings { quality, ..settings });
+
+        let (mut low, mut high) = (1u8, 100u8);
+        let mut best = None;
+        while low <= high {
+            let quality = low + (high - low) / 2;
+            let webp_data = encode(quality)?;
+            if metrics::ssim(img, &metrics::decode(&webp_data)?) >= target {
+                best = Some((webp_data, quality));
+                high = quality - 1;
+            } else {
+                low = quality + 1;
+            }
+        }
+
+        match best {
+            Some(best) => Ok(best),
+            None => Ok((encode(100)?, 100)),
+        }
+    }
+
+    /// Send an event to the configured reporter.
+    pub(crate) fn report(&self, event: Event<'_>) {
+        self.reporter.0.report(&event);
     }
 
     /// Find all image files in directory.
-    fn find_image_files(&self, directory: &Path, recursive: bool) -> WebPResult<Vec<PathBuf>> {
-        let mut image_files = Vec::new();
-
-        if recursive {
-            for entry in WalkDir::new(directory) {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() && utils::is_supported_extension(path) {
-                    image_files.push(path.to_path_buf());
+    pub(crate) fn find_image_files(&self, directory: &Path, recursive: bool) -> WebPResult<Vec<PathBuf>> {
+        find_files(directory, recursive, utils::is_supported_extension)
+    }
+}
+
+/// Files in directory whose path `matches`.
+fn find_files(directory: &Path, recursive: bool, matches: fn(&Path) -> bool) -> WebPResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    if recursive {
+        for entry in WalkDir::new(directory) {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_file() && matches(path) {
+                files.push(path.to_path_buf());
+            }
+        }
+    } else {
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_file() && matches(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Name of an image in a sheet: its path relative to the directory, without
+/// the extension and with dashes for separators (`icons/arrow.png` → `icons-arrow`).
+fn frame_name(directory: &Path, path: &Path) -> String {
+    utils::escape_path(&path.strip_prefix(directory).unwrap_or(path).with_extension("")).replace(std::path::MAIN_SEPARATOR, "-")
+}
+
+/// Default output path for an input image: next to it, or inside `output_folder`.
+pub(crate) fn output_path_for(input_path: &Path, output_folder: Option<&Path>) -> WebPResult<PathBuf> {
+    let Some(output_folder) = output_folder else {
+        return Ok(utils::generate_output_path(input_path));
+    };
+
+    // Built from the OS string, so names that are not UTF-8 keep their bytes
+    let mut file_name = input_path.file_stem().ok_or_else(|| WebPError::InvalidFileName(input_path.to_path_buf()))?.to_os_string();
+    file_name.push(".webp");
+
+    Ok(output_folder.join(file_name))
+}
+
+#[cfg(feature = "async")]
+impl WebPConverter {
+    /// Async variant of [`convert_single_file`](Self::convert_single_file).
+    ///
+    /// File I/O uses `tokio::fs` and decoding/encoding runs on the blocking
+    /// thread pool, so the caller's runtime is never blocked. Tiling and the
+    /// minimum SSIM check are not supported here; the image is always
+    /// written as a single file.
+    pub async fn convert_single_file_async(
+        &self,
+        input_path: &Path,
+        output_path: Option<&Path>,
+        output_folder: Option<&Path>,
+    ) -> WebPResult<ConversionStats> {
+        let start_time = Instant::now();
+
+        let output_path = match output_path {
+            Some(output_path) => output_path.to_path_buf(),
+            None => {
+                if let Some(output_folder) = output_folder {
+                    tokio::fs::create_dir_all(output_folder).await?;
                 }
+                output_path_for(input_path, output_folder)?
             }
-        } else {
-            for entry in fs::read_dir(directory)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() && utils::is_supported_extension(&path) {
-                    image_files.push(path);
+        };
+        if collision::is_same_file(input_path, &output_path) {
+            return Err(WebPError::OutputCollision { output: output_path, other: input_path.to_path_buf() });
+        }
+
+        let input = tokio::fs::read(input_path).await?;
+        let original_size = input.len() as u64;
+
+        let converter = self.clone();
+        let webp_data = tokio::task::spawn_blocking(move || converter.convert_bytes(&input))
+            .await
+            .map_err(|e| WebPError::EncodingError(format!("Encoding task failed: {}", e)))??;
+
+        let mut stats = ConversionStats::new();
+        if self.only_if_smaller && webp_data.len() as u64 >= original_size {
+            stats.add_not_smaller();
+            return Ok(stats);
+        }
+        // Through the part file, like blocking writes
+        let part = crate::journal::part_path(&output_path);
+        if let Some(journal) = &self.journal {
+            journal.started(&part)?;
+        }
+        let result = match tokio::fs::write(&part, &webp_data).await {
+            Ok(()) => tokio::fs::rename(&part, &output_path).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&part).await;
+        }
+        if let Some(journal) = &self.journal {
+            journal.finished(&part)?;
+        }
+        result?;
+        stats.add_success(input_path.to_path_buf(), start_time.elapsed().as_secs_f64(), original_size, webp_data.len() as u64);
+        Ok(stats)
+    }
+
+    /// Async variant of [`convert_directory`](Self::convert_directory).
+    ///
+    /// Files are converted one after another; failures are counted in the
+    /// returned stats rather than aborting the batch.
+    pub async fn convert_directory_async(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+    ) -> WebPResult<ConversionStats> {
+        if !tokio::fs::try_exists(directory).await? {
+            return Err(WebPError::InputNotFound(directory.to_path_buf()));
+        }
+
+        // Directory walking is blocking, so run it off the async threads
+        let converter = self.clone();
+        let root = directory.to_path_buf();
+        let image_files = tokio::task::spawn_blocking(move || converter.find_image_files(&root, recursive))
+            .await
+            .map_err(|e| WebPError::ImageProcessingError(format!("Directory scan failed: {}", e)))??;
+
+        if image_files.is_empty() {
+            return Err(WebPError::NoImagesFound);
+        }
+
+        if let Some(output_folder) = output_folder {
+            tokio::fs::create_dir_all(output_folder).await?;
+        }
+        let plan = self.plan_outputs(&image_files, |input| output_path_for(input, output_folder));
+
+        let mut stats = ConversionStats::new();
+        for img_file in image_files.iter().filter(|path| !utils::is_webp_file(path)) {
+            if self.is_cancelled() {
+                stats.cancelled = true;
+                break;
+            }
+
+            let output = match output_path_for(img_file, output_folder).and_then(|output| plan.output(img_file, output)) {
+                Ok(output) => output,
+                Err(error) => {
+                    stats.add_failure(img_file.clone(), error);
+                    continue;
                 }
+            };
+            match self.convert_single_file_async(img_file, Some(&output), None).await {
+                Ok(file_stats) if file_stats.not_smaller_count > 0 => stats.add_not_smaller(),
+                Ok(file_stats) => stats.merge(file_stats),
+                Err(error) => stats.add_failure(img_file.clone(), error),
             }
         }
 
-        Ok(image_files)
+        Ok(stats)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempdir;
 
     #[test]
     fn test_converter_creation() {
-        let converter = WebPConverter::new(80, false, 4);
+        let converter = WebPConverter::builder().build().unwrap();
         assert_eq!(converter.quality, 80);
         assert!(!converter.lossless);
         assert_eq!(converter.method, 4);
+        assert!(converter.keep_alpha);
+        assert!(converter.pipeline.is_empty());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_lossless_encoding() {
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, ((x * y) % 256) as u8]));
+        let converter = WebPConverter::builder().lossless(true).build().unwrap();
+        let (webp_data, _) = converter.encode_to_webp(&image::DynamicImage::ImageRgb8(img.clone()), None, None).unwrap();
+        let decoded = webp::Decoder::new(&webp_data).decode().unwrap();
+        assert_eq!(&*decoded, img.as_raw().as_slice());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_method_encoding() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, (x * 4) as u8, (y * 4) as u8])
+        }));
+        let encode = |method| WebPConverter::builder().method(method).build().unwrap().encode_to_webp(&img, None, None).unwrap().0;
+        assert_ne!(encode(0), encode(6));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_shared_across_threads() {
+        let converter = Arc::new(WebPConverter::builder().lossless(true).build().unwrap());
+        let handles: Vec<_> = (0..4u8)
+            .map(|i| {
+                let converter = Arc::clone(&converter);
+                std::thread::spawn(move || {
+                    let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, image::Rgb([i, 0, 0])));
+                    converter.convert_image(&img).unwrap()
+                })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let decoded = webp::Decoder::new(&handle.join().unwrap()).decode().unwrap().to_image();
+            assert_eq!(decoded.to_rgb8().get_pixel(0, 0).0, [i as u8, 0, 0]);
+        }
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_processor_hooks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Invert(Arc<AtomicUsize>);
+
+        impl Processor for Invert {
+            fn before_encode(&self, img: &mut image::DynamicImage) -> WebPResult<()> {
+                img.invert();
+                Ok(())
+            }
+
+            fn after_encode(&self, webp: &[u8]) -> WebPResult<()> {
+                assert_eq!(&webp[..4], b"RIFF");
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        struct Reject;
+
+        impl Processor for Reject {
+            fn after_encode(&self, _webp: &[u8]) -> WebPResult<()> {
+                Err(WebPError::EncodingError("rejected".to_string()))
+            }
+        }
+
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, image::Rgb([0, 0, 0])));
+        let encoded = Arc::new(AtomicUsize::new(0));
+        let converter = WebPConverter::builder()
+            .lossless(true)
+            .processor(Invert(Arc::clone(&encoded)))
+            .build()
+            .unwrap();
+        let webp_data = converter.convert_image(&img).unwrap();
+        assert_eq!(&webp::Decoder::new(&webp_data).decode().unwrap()[..3], &[255, 255, 255]);
+        assert_eq!(encoded.load(Ordering::SeqCst), 1);
+
+        let rejecting = WebPConverter::builder().processor(Reject).build().unwrap();
+        assert!(matches!(rejecting.convert_image(&img), Err(WebPError::EncodingError(_))));
+    }
+
+    #[test]
+    fn test_builder_settings() {
+        let converter = WebPConverter::builder()
+            .quality(90)
+            .lossless(true)
+            .method(6)
+            .keep_alpha(false)
+            .resize(800, 600)
+            .dpi(300)
+            .build()
+            .unwrap();
+        assert_eq!((converter.quality, converter.lossless, converter.method), (90, true, 6));
+        assert!(!converter.keep_alpha);
+        assert_eq!(converter.pipeline.describe(), "resize");
+        assert_eq!(converter.dpi, Some(300));
+
+        assert!(WebPConverter::builder().quality(0).build().is_err());
+        assert!(WebPConverter::builder().method(7).build().is_err());
+        assert!(WebPConverter::builder().resize(0, 100).build().is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_bytes() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(6, 4)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let converter = WebPConverter::builder().resize(3, 3).build().unwrap();
+        let webp_data = converter.convert_bytes(&png).unwrap();
+        let decoded = webp::Decoder::new(&webp_data).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+
+        assert!(converter.convert_bytes(b"definitely not an image").is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_image() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, image::Rgb([0, 128, 255])));
+
+        let converter = WebPConverter::builder().lossless(true).build().unwrap();
+        let webp_data = converter.convert_image(&img).unwrap();
+        let decoded = webp::Decoder::new(&webp_data).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (8, 8));
+        assert_eq!(&decoded[..3], &[0, 128, 255]);
+
+        let resized = WebPConverter::builder().resize(4, 4).build().unwrap();
+        let webp_data = resized.convert_image(&img).unwrap();
+        assert_eq!(webp::Decoder::new(&webp_data).decode().unwrap().width(), 4);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_convert_directory_async() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.png", "b.png"] {
+            image::RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50]))
+                .save(dir.path().join(name))
+                .unwrap();
+        }
+        std::fs::write(dir.path().join("broken.jpg"), b"not a jpeg").unwrap();
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let stats = converter.convert_directory_async(dir.path(), false, None).await.unwrap();
+        assert_eq!((stats.success_count, stats.failed_count), (2, 1));
+        assert!(stats.failures[0].path.ends_with("broken.jpg"));
+        assert!(dir.path().join("a.webp").exists());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_progress_events() {
+        use std::cell::RefCell;
+
+        #[derive(Default)]
+        struct Recorder(RefCell<Vec<String>>);
+
+        impl ProgressHandler for Recorder {
+            fn on_file_start(&self, index: usize, total: usize, _path: &Path) {
+                self.0.borrow_mut().push(format!("start {}/{}", index, total));
+            }
+
+            fn on_file_done(&self, index: usize, _total: usize, _path: &Path, outcome: &FileOutcome<'_>) {
+                let kind = match outcome {
+                    FileOutcome::Converted { .. } => "converted",
+                    FileOutcome::Skipped => "skipped",
+                    FileOutcome::Failed(_) => "failed",
+                };
+                self.0.borrow_mut().push(format!("done {} {}", index, kind));
+            }
+
+            fn on_batch_done(&self, stats: &ConversionStats) {
+                self.0.borrow_mut().push(format!("batch {}/{}", stats.success_count, stats.failed_count));
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        image::RgbImage::new(4, 4).save(dir.path().join("a.png")).unwrap();
+        std::fs::write(dir.path().join("b.jpg"), b"not a jpeg").unwrap();
+
+        let recorder = Recorder::default();
+        let converter = WebPConverter::builder().build().unwrap();
+        converter
+            .convert_directory_with_progress(dir.path(), false, None, &recorder)
+            .unwrap();
+        // Directory order is not guaranteed, so only check the shape of the events
+        let mut events = recorder.0.into_inner();
+        assert_eq!(events.pop().unwrap(), "batch 1/1");
+        assert_eq!(events.len(), 4);
+        assert!(events[0].starts_with("start 0/2") && events[1].starts_with("done 0"));
+        assert!(events.iter().any(|e| e.ends_with("converted")));
+        assert!(events.iter().any(|e| e.ends_with("failed")));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_cancelled_batch_returns_partial_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.png", "b.png"] {
+            image::RgbImage::new(4, 4).save(dir.path().join(name)).unwrap();
+        }
+
+        // Cancel as soon as the first file is done
+        struct CancelAfterFirst(CancellationToken);
+        impl ProgressHandler for CancelAfterFirst {
+            fn on_file_done(&self, _index: usize, _total: usize, _path: &Path, _outcome: &FileOutcome<'_>) {
+                self.0.cancel();
+            }
+        }
+
+        let token = CancellationToken::new();
+        let converter = WebPConverter::builder().cancellation(token.clone()).build().unwrap();
+        let stats = converter
+            .convert_directory_with_progress(dir.path(), false, None, &CancelAfterFirst(token))
+            .unwrap();
+        assert!(stats.cancelled);
+        assert_eq!(stats.success_count, 1);
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_to_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.png");
+        image::RgbImage::new(5, 3).save(&input).unwrap();
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let mut out = Vec::new();
+        let written = converter.convert_to_writer(&input, &mut out).unwrap();
+        assert_eq!(written, out.len() as u64);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert!(!dir.path().join("input.webp").exists());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_from_reader() {
+        let mut bmp = Vec::new();
+        image::DynamicImage::new_rgb8(3, 3)
+            .write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageOutputFormat::Bmp)
+            .unwrap();
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let detected = converter.convert_from_reader(bmp.as_slice(), None).unwrap();
+        let hinted = converter.convert_from_reader(bmp.as_slice(), Some(ImageFormat::Bmp)).unwrap();
+        assert_eq!(detected, hinted);
+
+        // A wrong hint is an error rather than a silent fallback
+        assert!(converter.convert_from_reader(bmp.as_slice(), Some(ImageFormat::Png)).is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("gradient.png");
+        image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 90]))
+            .save(&input)
+            .unwrap();
+
+        let lossless = WebPConverter::builder().lossless(true).metrics(true).build().unwrap();
+        let stats = lossless.convert_single_file(&input, None, None).unwrap();
+        assert!(stats.average_metrics().unwrap().psnr.is_infinite());
+
+        let lossy = WebPConverter::builder().quality(10).metrics(true).build().unwrap();
+        let result = lossy.convert_directory_iter(dir.path(), false, None).unwrap().next().unwrap();
+        let metrics = result.metrics.unwrap();
+        assert!(metrics.psnr.is_finite() && metrics.ssim < 1.0);
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_diff_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 60]))
+            .save(&input)
+            .unwrap();
+
+        let diff_folder = dir.path().join("diffs");
+        let converter = WebPConverter::builder().quality(10).diff_output(&diff_folder).build().unwrap();
+        converter.convert_single_file(&input, None, None).unwrap();
+
+        let heatmap = image::open(diff_folder.join("photo.diff.png")).unwrap();
+        assert_eq!((heatmap.width(), heatmap.height()), (16, 16));
+        assert!(heatmap.to_rgb8().pixels().any(|p| p[0] > 0));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_detect_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("sky.png");
+        // A slightly grainy radial gradient, like a vignetted sky
+        image::RgbImage::from_fn(256, 128, |x, y| {
+            let distance = (f64::from(x) - 128.0).hypot(f64::from(y) - 64.0) as u32;
+            let value = (220u32.saturating_sub(distance) + (x * 7919 + y * 104729) % 7) as u8;
+            image::Rgb([value, value / 2 + 60, 180])
+        })
+        .save(&input)
+        .unwrap();
+
+        let converter = WebPConverter::builder().quality(1).detect_artifacts(true).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!(stats.artifact_count, 1);
+
+        let converter = WebPConverter::builder().quality(100).detect_artifacts(true).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!(stats.artifact_count, 0);
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_min_ssim() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("noise.png");
+        let output = dir.path().join("noise.webp");
+        image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, ((x * 31 + y * 17) % 256) as u8, 60])
+        })
+        .save(&input)
+        .unwrap();
+        let builder = WebPConverter::builder().quality(5).min_ssim(0.99);
+
+        let converter = builder.clone().build().unwrap();
+        let result = converter.convert_single_file(&input, None, None);
+        assert!(matches!(result, Err(WebPError::LowQuality { min_ssim, .. }) if min_ssim == 0.99));
+        assert!(!output.exists());
+
+        let converter = builder.clone().on_low_quality(LowQualityPolicy::KeepOriginal).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!((stats.success_count, stats.low_quality_count), (0, 1));
+        assert!(!output.exists());
+
+        let converter = builder.on_low_quality(LowQualityPolicy::RetryLossless).metrics(true).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!(stats.success_count, 1);
+        assert!(stats.quality_metrics[0].psnr.is_infinite());
+    }
+
+    #[test]
+    fn test_spritesheet_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let icons = dir.path().join("icons");
+        fs::create_dir_all(icons.join("arrows")).unwrap();
+        let icon = image::RgbaImage::from_pixel(8, 8, image::Rgba([200, 40, 40, 255]));
+        icon.save(icons.join("home.png")).unwrap();
+        icon.save(icons.join("arrows/left.png")).unwrap();
+        icon.save(icons.join("arrows-left.jpg")).unwrap();
+
+        let converter = WebPConverter::builder().lossless(true).build().unwrap();
+        let output = dir.path().join("icons.webp");
+        converter.create_spritesheet(&icons, Some(&output), 0, false).unwrap();
+        assert!(fs::read_to_string(output.with_extension("css")).unwrap().contains(".sprite-arrows-left {"));
+
+        // Subfolders put arrows/left.png on the name of arrows-left.jpg
+        assert!(matches!(
+            converter.create_spritesheet(&icons, Some(&output), 0, true),
+            Err(WebPError::DuplicateSprite { name, .. }) if name == "arrows-left"
+        ));
+    }
+
+    #[test]
+    fn test_montage_of_webp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = dir.path().join("batch");
+        fs::create_dir_all(&batch).unwrap();
+        let converter = WebPConverter::builder().lossless(true).build().unwrap();
+        for (name, color) in [("red", [220, 30, 30]), ("blue", [30, 30, 220])] {
+            let source = dir.path().join(format!("{}.png", name));
+            image::RgbImage::from_pixel(40, 20, image::Rgb(color)).save(&source).unwrap();
+            converter.convert_single_file(&source, None, Some(&batch)).unwrap();
+        }
+        fs::write(batch.join("broken.webp"), b"not a webp").unwrap();
+
+        let options = MontageOptions { columns: Some(2), thumb_size: 32, background: crate::transform::Color::BLACK, font: None };
+        let output = dir.path().join("sheet.webp");
+        let stats = converter.create_montage(&batch, Some(&output), &options, false).unwrap();
+        assert_eq!(stats.success_count, 1);
+
+        // The broken file is left out, so the two WebP files fill one row
+        let sheet = image::open(&output).unwrap().to_rgb8();
+        assert_eq!((sheet.width(), sheet.height()), (2 * 40 + 8, 40 + 8));
+        assert_eq!(sheet.get_pixel(8 + 16, 8 + 16), &image::Rgb([30, 30, 220]));
+        assert_eq!(sheet.get_pixel(48 + 16, 8 + 16), &image::Rgb([220, 30, 30]));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_dpi_in_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let icons = dir.path().join("icons");
+        fs::create_dir_all(&icons).unwrap();
+        image::RgbImage::from_pixel(24, 20, image::Rgb([90, 140, 30])).save(icons.join("leaf.png")).unwrap();
+        let read_json = |path: &Path| serde_json::from_slice::<serde_json::Value>(&fs::read(path).unwrap()).unwrap();
+
+        let converter = WebPConverter::builder().dpi(300).tile_size(16).build().unwrap();
+        converter.convert_single_file(&icons.join("leaf.png"), None, None).unwrap();
+        assert_eq!(read_json(&icons.join("leaf_tiles/index.json"))["dpi"], 300);
+
+        let sheet = dir.path().join("icons.webp");
+        converter.create_spritesheet(&icons, Some(&sheet), 0, false).unwrap();
+        assert_eq!(read_json(&sheet.with_extension("json"))["dpi"], 300);
+
+        // Left out without a density
+        WebPConverter::builder().build().unwrap().create_spritesheet(&icons, Some(&sheet), 0, false).unwrap();
+        assert!(read_json(&sheet.with_extension("json")).get("dpi").is_none());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_tiles_count_only_this_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("map.png");
+        image::RgbImage::from_fn(32, 24, |x, y| image::Rgb([(x * 8) as u8, (y * 10) as u8, 50])).save(&input).unwrap();
+        // Left over from an earlier run with a smaller tile size
+        let tiles = dir.path().join("map_tiles");
+        fs::create_dir_all(&tiles).unwrap();
+        fs::write(tiles.join("stale_9_9.webp"), vec![0; 100_000]).unwrap();
+
+        let converter = WebPConverter::builder().tile_size(16).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        let written: u64 = fs::read_dir(&tiles)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !path.ends_with("stale_9_9.webp"))
+            .map(|path| fs::metadata(path).unwrap().len())
+            .sum();
+        assert_eq!(stats.total_compressed_size, Some(written));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_max_output_size() {
+        let noise = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, ((x * 31 + y * 17) % 256) as u8, 60])
+        }));
+        let unlimited = WebPConverter::builder().quality(95).build().unwrap().convert_image(&noise).unwrap();
+        let floor = WebPConverter::builder().quality(40).build().unwrap().convert_image(&noise).unwrap();
+        let budget = (floor.len() + unlimited.len()) as u64 / 2;
+
+        let converter = WebPConverter::builder().quality(95).max_output_size(budget).build().unwrap();
+        let webp_data = converter.convert_image(&noise).unwrap();
+        assert!(webp_data.len() as u64 <= budget);
+
+        let converter = WebPConverter::builder().quality(95).max_output_size(floor.len() as u64 - 1).build().unwrap();
+        assert!(matches!(
+            converter.convert_image(&noise),
+            Err(WebPError::OverBudget { quality: 40, .. })
+        ));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_skip_efficient() {
+        let dir = tempfile::tempdir().unwrap();
+        // A flat PNG compresses to a tiny fraction of a byte per pixel
+        let flat = dir.path().join("flat.png");
+        image::RgbImage::from_pixel(256, 256, image::Rgb([40, 90, 200])).save(&flat).unwrap();
+        let noisy = dir.path().join("noisy.png");
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([((x * 7919 + y * 104729) % 251) as u8, (x * 4) as u8, 9]))
+            .save(&noisy)
+            .unwrap();
+
+        let converter = WebPConverter::builder().skip_efficient(0.1).build().unwrap();
+        let stats = converter.convert_single_file(&flat, None, None).unwrap();
+        assert_eq!((stats.success_count, stats.efficient_count), (0, 1));
+        assert!(!dir.path().join("flat.webp").exists());
+
+        let stats = converter.convert_directory(dir.path(), false, None).unwrap();
+        assert_eq!((stats.success_count, stats.efficient_count), (1, 1));
+        assert!(dir.path().join("noisy.webp").exists());
+        assert!(WebPConverter::builder().skip_efficient(0.0).build().is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_dedupe_similar() {
+        let dir = tempfile::tempdir().unwrap();
+        let scene = |brightness: u8| {
+            image::RgbImage::from_fn(48, 32, |x, y| {
+                image::Rgb([((x * 5 + y * 3) % 200) as u8 + brightness, (y * 6) as u8, 80])
+            })
+        };
+        scene(0).save(dir.path().join("burst1.png")).unwrap();
+        scene(8).save(dir.path().join("burst2.png")).unwrap();
+        image::RgbImage::from_fn(48, 32, |x, y| image::Rgb([(255 - x * 5) as u8, ((x * y) % 256) as u8, (y * 7) as u8]))
+            .save(dir.path().join("other.png"))
+            .unwrap();
+
+        let converter = WebPConverter::builder().dedupe_similar(5).build().unwrap();
+        let results: Vec<FileResult> = converter.convert_directory_iter(dir.path(), false, None).unwrap().collect();
+        assert!(results[0].output.is_some());
+        assert_eq!(results[1].duplicate_of.as_deref(), Some(dir.path().join("burst1.png").as_path()));
+        assert_eq!(results[1].output, None);
+        assert!(results[2].output.is_some());
+
+        let stats = converter.convert_directory(dir.path(), false, None).unwrap();
+        assert_eq!((stats.success_count, stats.similar_count), (2, 1));
+
+        let report_only = WebPConverter::builder().dedupe_similar(5).report_similar_only(true).build().unwrap();
+        let stats = report_only.convert_directory(dir.path(), false, None).unwrap();
+        assert_eq!((stats.success_count, stats.similar_count), (3, 0));
+        assert!(WebPConverter::builder().dedupe_similar(65).build().is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_target_ssim() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(48, 48, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, (x * 5) as u8, (y * 5) as u8])
+        }));
+
+        let strict = WebPConverter::builder().target_ssim(0.95).build().unwrap();
+        let strict_data = strict.convert_image(&img).unwrap();
+        assert!(metrics::ssim(&img, &metrics::decode(&strict_data).unwrap()) >= 0.95);
+
+        let loose = WebPConverter::builder().target_ssim(0.5).build().unwrap();
+        assert!(loose.convert_image(&img).unwrap().len() < strict_data.len());
+
+        // An unreachable target falls back to the highest quality
+        let unreachable = WebPConverter::builder().target_ssim(1.0).build().unwrap();
+        let best = WebPConverter::builder().quality(100).build().unwrap();
+        assert_eq!(unreachable.convert_image(&img).unwrap(), best.convert_image(&img).unwrap());
+
+        assert!(WebPConverter::builder().target_ssim(1.5).build().is_err());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_target_ssim_with_budget() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(48, 48, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, (x * 5) as u8, (y * 5) as u8])
+        }));
+        let at_quality = |quality| WebPConverter::builder().quality(quality).build().unwrap().convert_image(&img).unwrap();
+        let searched = (1..=100).find(|&quality| metrics::ssim(&img, &metrics::decode(&at_quality(quality)).unwrap()) >= 0.9).unwrap();
+        assert!(searched > BUDGET_QUALITY_STEP && searched != WebPConverter::builder().build().unwrap().quality);
+
+        // Missing the budget steps down from the searched quality, not the configured one
+        let budget = at_quality(searched).len() as u64 - 1;
+        let converter = WebPConverter::builder().target_ssim(0.9).max_output_size(budget).min_quality(1).build().unwrap();
+        assert_eq!(converter.convert_image(&img).unwrap(), at_quality(searched - BUDGET_QUALITY_STEP));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_only_if_smaller() {
+        let dir = tempfile::tempdir().unwrap();
+        // A 1x1 PNG is smaller than a WebP carrying density metadata
+        let input = dir.path().join("icon.png");
+        image::GrayImage::new(1, 1).save(&input).unwrap();
+
+        let converter = WebPConverter::builder().dpi(300).only_if_smaller(true).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!((stats.success_count, stats.not_smaller_count), (0, 1));
+        assert!(!dir.path().join("icon.webp").exists());
+
+        let result = converter.convert_directory_iter(dir.path(), false, None).unwrap().next().unwrap();
+        assert!(result.is_ok() && result.discarded);
+        assert_eq!(result.output, None);
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_auto_mode() {
+        let graphic = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(32, 32, |x, _| {
+            if x < 16 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) }
+        }));
+        let photo = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(32, 32, |x, y| {
+            image::Rgb([(x * 8 + y % 3) as u8, (y * 8 + x % 5) as u8, ((x * y) % 256) as u8])
+        }));
+
+        let converter = WebPConverter::builder().auto_mode(true).build().unwrap();
+        let is_lossless = |webp_data: &[u8]| &webp_data[12..16] == b"VP8L";
+        assert!(is_lossless(&converter.convert_image(&graphic).unwrap()));
+        assert!(!is_lossless(&converter.convert_image(&photo).unwrap()));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_format_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 90]));
+        img.save(dir.path().join("a.png")).unwrap();
+        img.save(dir.path().join("b.jpg")).unwrap();
+
+        let converter = WebPConverter::builder()
+            .format_override("png lossless".parse().unwrap())
+            .format_override("jpg q=40".parse().unwrap())
+            .build()
+            .unwrap();
+        converter.convert_directory(dir.path(), false, None).unwrap();
+        let fourcc = |name: &str| std::fs::read(dir.path().join(name)).unwrap()[12..16].to_vec();
+        assert_eq!(fourcc("a.webp"), b"VP8L");
+        assert_eq!(fourcc("b.webp"), b"VP8 ");
+
+        let duplicate = WebPConverter::builder()
+            .format_override("png lossless".parse().unwrap())
+            .format_override("png q=90".parse().unwrap())
+            .build();
+        assert!(matches!(duplicate, Err(WebPError::InvalidSettings(_))));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_quality_ladder() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, ((x * y) % 256) as u8]))
+            .save(&input)
+            .unwrap();
+
+        let converter = WebPConverter::builder().lossless(true).build().unwrap();
+        let options = LadderOptions { qualities: vec![20, 90], keep: false };
+        let ladder = converter.quality_ladder(&input, None, &options).unwrap();
+        assert_eq!(ladder.steps.iter().map(|step| step.quality).collect::<Vec<_>>(), vec![20, 90]);
+        assert!(ladder.steps[0].compressed_size < ladder.steps[1].compressed_size);
+        assert!(ladder.steps[0].metrics.psnr < ladder.steps[1].metrics.psnr);
+        assert!(!dir.path().join("photo.q20.webp").exists());
+
+        let options = LadderOptions { keep: true, ..options };
+        converter.quality_ladder(&input, None, &options).unwrap();
+        assert!(dir.path().join("photo.q20.webp").exists());
+        assert!(dir.path().join("photo.q90.webp").exists());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_directory_iter() {
+        let dir = tempfile::tempdir().unwrap();
+        image::RgbImage::new(4, 4).save(dir.path().join("a.png")).unwrap();
+        std::fs::write(dir.path().join("b.jpg"), b"not a jpeg").unwrap();
+        std::fs::write(dir.path().join("c.webp"), b"skipped").unwrap();
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let results: Vec<FileResult> = converter.convert_directory_iter(dir.path(), false, None).unwrap().collect();
+        assert_eq!(results.len(), 2);
+
+        assert!(results[0].is_ok());
+        assert_eq!(results[0].output.as_deref(), Some(dir.path().join("a.webp").as_path()));
+        assert!(results[0].compressed_size.is_some());
+
+        assert!(!results[1].is_ok());
+        assert!(results[1].input.ends_with("b.jpg"));
+        assert_eq!(results[1].original_size, Some(10));
+        assert!(results[1].output.is_none());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_convert_directory_in_place_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        image::RgbImage::new(4, 4).save(dir.path().join("one.png")).unwrap();
+        image::RgbImage::new(4, 4).save(dir.path().join("two.jpg")).unwrap();
+
+        // The outputs of the first run are not inputs of the later ones
+        for policy in [CollisionPolicy::Refuse, CollisionPolicy::Refuse, CollisionPolicy::Rename] {
+            let converter = WebPConverter::builder().on_collision(policy).build().unwrap();
+            let stats = converter.convert_directory(dir.path(), false, None).unwrap();
+            assert_eq!((stats.success_count, stats.failed_count), (2, 0));
+        }
+        assert!(dir.path().join("one.webp").is_file() && !dir.path().join("one.png.webp").exists());
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join(utils::path_from_bytes(b"caf\xe9.png"));
+        image::RgbImage::new(4, 4).save_with_format(&input, ImageFormat::Png).unwrap();
+
+        let output_folder = dir.path().join("out");
+        let converter = WebPConverter::builder().build().unwrap();
+        let stats = converter.convert_directory(dir.path(), false, Some(&output_folder)).unwrap();
+        assert_eq!(stats.success_count, 1);
+        assert!(output_folder.join(utils::path_from_bytes(b"caf\xe9.webp")).is_file());
     }
 
     #[test]
     fn test_conversion_stats() {
         let mut stats = ConversionStats::new();
-        stats.add_success(1.5, 1000, 200);
-        stats.add_success(2.0, 1500, 300);
-        stats.add_failure();
+        stats.add_success(PathBuf::from("a.jpg"), 1.5, 1000, 200);
+        stats.add_success(PathBuf::from("b.jpg"), 2.0, 1500, 300);
+        stats.add_failure(PathBuf::from("broken.jpg"), WebPError::InvalidImage(PathBuf::from("broken.jpg")));
 
         assert_eq!(stats.success_count, 2);
         assert_eq!(stats.failed_count, 1);
+        assert_eq!(stats.failed_paths().collect::<Vec<_>>(), vec![Path::new("broken.jpg")]);
         assert_eq!(stats.total_time, 3.5);
         assert_eq!(stats.total_original_size, Some(2500));
         assert_eq!(stats.total_compressed_size, Some(500));
     }
+
+    #[test]
+    fn test_ratio_distribution() {
+        let mut stats = ConversionStats::new();
+        assert_eq!(stats.ratio_distribution(), None);
+        for compressed in [900, 150, 300, 1300, 350, 100, 500, 250, 200, 450] {
+            stats.add_success(PathBuf::from(format!("{}.png", compressed)), 0.1, 1000, compressed);
+        }
+        let distribution = stats.ratio_distribution().unwrap();
+        assert_eq!((distribution.min, distribution.median, distribution.p90, distribution.max), (0.1, 0.3, 0.9, 1.3));
+        assert_eq!(distribution.histogram, [3, 3, 2, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_timing_percentiles() {
+        let mut stats = ConversionStats::new();
+        assert_eq!(stats.timing_percentiles(), None);
+        for index in 1..=100 {
+            // One pathological file among quick ones
+            let time_taken = if index == 37 { 42.0 } else { index as f64 / 100.0 };
+            stats.add_success(PathBuf::from(format!("{}.tiff", index)), time_taken, 1000, 500);
+        }
+        let timing = stats.timing_percentiles().unwrap();
+        assert_eq!((timing.p50, timing.p90, timing.p99, timing.max), (0.51, 0.91, 1.0, 42.0));
+    }
+
+    #[test]
+    fn test_rollup_by_directory() {
+        let mut stats = ConversionStats::new();
+        for (path, original, compressed) in [("assets/web/a.png", 1000, 400), ("assets/game/x/b.png", 5000, 1000), ("assets/c.png", 300, 450), ("assets/web/d.png", 800, 700)] {
+            stats.add_success(PathBuf::from(path), 0.1, original, compressed);
+        }
+        stats.add_failure(PathBuf::from("assets/web/e.png"), WebPError::NoImagesFound);
+
+        let rollups = stats.rollup_by_directory(Path::new("assets"));
+        let summary: Vec<_> = rollups.iter().map(|r| (r.directory.to_str().unwrap(), r.converted, r.failed, r.saved())).collect();
+        assert_eq!(summary, [("game", 1, 0, 4000), ("web", 2, 1, 700), (".", 1, 0, -150)]);
+    }
+
+    #[test]
+    fn test_breakdown_by_format() {
+        let mut stats = ConversionStats::new();
+        for (path, time, original, compressed) in [("a.jpg", 0.2, 1000, 800), ("b.JPEG", 0.4, 3000, 2000), ("c.png", 0.5, 4000, 1000), ("d.xyz", 0.1, 10, 5)] {
+            stats.add_success(PathBuf::from(path), time, original, compressed);
+        }
+        stats.add_failure(PathBuf::from("e.tiff"), WebPError::NoImagesFound);
+
+        let breakdowns = stats.breakdown_by_format();
+        let summary: Vec<_> = breakdowns.iter().map(|b| (b.format.as_str(), b.converted, b.failed)).collect();
+        assert_eq!(summary, [("JPEG", 2, 0), ("PNG", 1, 0), ("TIFF", 0, 1), ("XYZ", 1, 0)]);
+        assert_eq!(breakdowns[0].ratio(), Some(0.7));
+        assert!((breakdowns[0].average_time().unwrap() - 0.3).abs() < 1e-9);
+        assert_eq!((breakdowns[2].ratio(), breakdowns[2].average_time()), (None, None));
+    }
+
+    #[test]
+    fn test_top_savings_and_regressions() {
+        let mut stats = ConversionStats::new();
+        for (name, original, compressed) in [("a", 1000, 400), ("b", 5000, 1000), ("c", 300, 450), ("d", 800, 700), ("e", 200, 900)] {
+            stats.add_success(PathBuf::from(name), 0.1, original, compressed);
+        }
+        let names = |files: Vec<&ConvertedFile>| files.iter().map(|file| file.path.to_string_lossy().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(stats.top_savings(2)), ["b", "a"]);
+        assert_eq!(names(stats.regressions(5)), ["e", "c"]);
+        assert_eq!(stats.regressions(1)[0].saved(), -700);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,114 @@
+//! Perceptual hashing to spot near-duplicate images (burst shots, re-exports)
+//! within a batch.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Largest meaningful Hamming distance between two hashes.
+pub const MAX_DISTANCE: u32 = 64;
+
+/// 64-bit difference hash (dHash) of an image.
+///
+/// Each bit tells whether a cell of a downscaled 9x8 grayscale copy is darker
+/// than its right neighbour, so the hash survives rescaling, recompression
+/// and small tone changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(pub u64);
+
+impl PerceptualHash {
+    /// Hash an image.
+    pub fn of(img: &DynamicImage) -> Self {
+        let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+        let mut hash = 0u64;
+        for y in 0..8 {
+            for x in 0..8 {
+                hash <<= 1;
+                if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                    hash |= 1;
+                }
+            }
+        }
+        Self(hash)
+    }
+
+    /// Number of differing bits (0 for visually identical images, up to 64).
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Hashes of the images seen so far in one batch.
+#[derive(Debug)]
+pub(crate) struct SimilarImages {
+    max_distance: u32,
+    seen: Vec<(PathBuf, PerceptualHash)>,
+}
+
+impl SimilarImages {
+    pub fn new(max_distance: u32) -> Self {
+        Self { max_distance, seen: Vec::new() }
+    }
+
+    /// Find an earlier image within the distance threshold, returning its
+    /// path and distance; otherwise remember this one. An image checked again,
+    /// as when its conversion is retried, is not its own duplicate.
+    pub fn check(&mut self, path: &Path, hash: PerceptualHash) -> Option<(PathBuf, u32)> {
+        let closest = self
+            .seen
+            .iter()
+            .filter(|(seen_path, _)| seen_path != path)
+            .map(|(seen_path, seen_hash)| (seen_path, hash.distance(*seen_hash)))
+            .filter(|&(_, distance)| distance <= self.max_distance)
+            .min_by_key(|&(_, distance)| distance);
+
+        match closest {
+            Some((seen_path, distance)) => Some((seen_path.clone(), distance)),
+            None => {
+                self.seen.push((path.to_path_buf(), hash));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(shift: u32, brightness: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 48, |x, y| {
+            let value = (((x + shift) * 3 + y * 2) % 200) as u8;
+            image::Rgb([value.saturating_add(brightness), value, 90])
+        }))
+    }
+
+    #[test]
+    fn test_similar_images_hash_close() {
+        let original = PerceptualHash::of(&scene(0, 0));
+        assert_eq!(original.distance(PerceptualHash::of(&scene(0, 0))), 0);
+        assert!(original.distance(PerceptualHash::of(&scene(0, 12))) <= 4);
+
+        let other = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 48, |x, y| {
+            image::Rgb([((x * y) % 256) as u8, (255 - x * 4) as u8, (y * 5) as u8])
+        }));
+        assert!(original.distance(PerceptualHash::of(&other)) > 10);
+    }
+
+    #[test]
+    fn test_similar_images_index() {
+        let mut index = SimilarImages::new(4);
+        let hash = PerceptualHash(0b1111);
+        assert_eq!(index.check(Path::new("a.jpg"), hash), None);
+        assert_eq!(index.check(Path::new("b.jpg"), PerceptualHash(0b0111)), Some((PathBuf::from("a.jpg"), 1)));
+        assert_eq!(index.check(Path::new("c.jpg"), PerceptualHash(u64::MAX)), None);
+        assert_eq!(hash.to_string(), "000000000000000f");
+    }
+}
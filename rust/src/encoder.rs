@@ -0,0 +1,236 @@
+//! Low-level WebP encoding of raw frame buffers.
+//!
+//! Encoding goes through libwebp (`libwebp` feature, on by default). Without
+//! it, e.g. in wasm builds lacking a C toolchain for the target, only
+//! lossless encoding is available, through the `image` crate.
+
+use crate::error::{WebPError, WebPResult};
+#[cfg(feature = "libwebp")]
+use libwebp_sys::WebPPreset;
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "libwebp")]
+use webp::{Encoder, WebPConfig};
+
+/// Channel layout of a raw, tightly packed 8-bit pixel buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelLayout {
+    Rgb,
+    Rgba,
+}
+
+impl PixelLayout {
+    /// Bytes per pixel.
+    pub fn channels(self) -> usize {
+        match self {
+            PixelLayout::Rgb => 3,
+            PixelLayout::Rgba => 4,
+        }
+    }
+}
+
+/// libwebp tuning preset, adjusting filtering and noise shaping to the content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preset {
+    #[default]
+    Default,
+    /// Digital pictures, like portraits or indoor shots
+    Picture,
+    /// Outdoor photographs with natural lighting
+    Photo,
+    /// Hand or line drawings with high-contrast details
+    Drawing,
+    /// Small-sized colorful images
+    Icon,
+    /// Text-like content
+    Text,
+}
+
+#[cfg(feature = "libwebp")]
+impl Preset {
+    fn to_libwebp(self) -> WebPPreset {
+        match self {
+            Preset::Default => WebPPreset::WEBP_PRESET_DEFAULT,
+            Preset::Picture => WebPPreset::WEBP_PRESET_PICTURE,
+            Preset::Photo => WebPPreset::WEBP_PRESET_PHOTO,
+            Preset::Drawing => WebPPreset::WEBP_PRESET_DRAWING,
+            Preset::Icon => WebPPreset::WEBP_PRESET_ICON,
+            Preset::Text => WebPPreset::WEBP_PRESET_TEXT,
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(Preset::Default),
+            "picture" => Ok(Preset::Picture),
+            "photo" => Ok(Preset::Photo),
+            "drawing" => Ok(Preset::Drawing),
+            "icon" => Ok(Preset::Icon),
+            "text" => Ok(Preset::Text),
+            _ => Err(format!(
+                "unknown preset '{}' (expected default, picture, photo, drawing, icon or text)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Preset::Default => "default",
+            Preset::Picture => "picture",
+            Preset::Photo => "photo",
+            Preset::Drawing => "drawing",
+            Preset::Icon => "icon",
+            Preset::Text => "text",
+        })
+    }
+}
+
+crate::transform::serde_as_string!(Preset);
+
+/// Encoder configuration for [`encode_webp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderSettings {
+    /// Lossy quality from 1 (lowest) to 100 (highest); ignored when lossless.
+    pub quality: u8,
+    pub lossless: bool,
+    /// Compression method from 0 (fastest) to 6 (best compression).
+    pub method: u8,
+    pub preset: Preset,
+    pub layout: PixelLayout,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            lossless: false,
+            method: 4,
+            preset: Preset::Default,
+            layout: PixelLayout::Rgba,
+        }
+    }
+}
+
+/// Encode a raw RGB or RGBA frame buffer straight to WebP bytes.
+///
+/// `pixels` must hold exactly `width * height` pixels in `settings.layout`,
+/// row by row without padding. No metadata is embedded.
+pub fn encode_webp(pixels: &[u8], width: u32, height: u32, settings: &EncoderSettings) -> WebPResult<Vec<u8>> {
+    let invalid = |message: String| Err(WebPError::InvalidSettings(message));
+    if !(1..=100).contains(&settings.quality) {
+        return invalid(format!("quality must be between 1 and 100, got {}", settings.quality));
+    }
+    if settings.method > 6 {
+        return invalid(format!("method must be between 0 and 6, got {}", settings.method));
+    }
+    if width == 0 || height == 0 {
+        return invalid(format!("image dimensions must be positive, got {}x{}", width, height));
+    }
+    let expected = width as usize * height as usize * settings.layout.channels();
+    if pixels.len() != expected {
+        return invalid(format!(
+            "pixel buffer holds {} bytes, expected {} for {}x{} {:?}",
+            pixels.len(),
+            expected,
+            width,
+            height,
+            settings.layout
+        ));
+    }
+
+    encode(pixels, width, height, settings)
+}
+
+#[cfg(feature = "libwebp")]
+fn encode(pixels: &[u8], width: u32, height: u32, settings: &EncoderSettings) -> WebPResult<Vec<u8>> {
+    // Create WebP encoder for the buffer layout
+    let encoder = match settings.layout {
+        PixelLayout::Rgb => Encoder::from_rgb(pixels, width, height),
+        PixelLayout::Rgba => Encoder::from_rgba(pixels, width, height),
+    };
+
+    // Configure preset, quality and compression method based on settings
+    let quality = if settings.lossless { 100.0 } else { settings.quality as f32 };
+    let mut config = WebPConfig::new_with_preset(settings.preset.to_libwebp(), quality)
+        .map_err(|_| WebPError::EncodingError("Failed to initialize WebP config".to_string()))?;
+    config.lossless = settings.lossless as i32;
+    config.quality = quality;
+    config.method = settings.method as i32;
+
+    // Encode to WebP
+    let webp_data = encoder
+        .encode_advanced(&config)
+        .map_err(|e| WebPError::EncodingError(format!("{:?}", e)))?;
+
+    // Check if encoding was successful by trying to access the data
+    if !webp_data.is_empty() {
+        Ok(webp_data.to_vec())
+    } else {
+        Err(WebPError::EncodingError("Failed to encode WebP - empty result".to_string()))
+    }
+}
+
+/// Lossless-only encoding without libwebp; method and preset do not apply.
+#[cfg(not(feature = "libwebp"))]
+fn encode(pixels: &[u8], width: u32, height: u32, settings: &EncoderSettings) -> WebPResult<Vec<u8>> {
+    use image::codecs::webp::WebPEncoder;
+
+    if !settings.lossless {
+        return Err(WebPError::EncodingError(
+            "lossy encoding requires building with --features libwebp".to_string(),
+        ));
+    }
+    let color = match settings.layout {
+        PixelLayout::Rgb => image::ColorType::Rgb8,
+        PixelLayout::Rgba => image::ColorType::Rgba8,
+    };
+    let mut webp_data = Vec::new();
+    WebPEncoder::new_lossless(&mut webp_data)
+        .encode(pixels, width, height, color)
+        .map_err(|e| WebPError::EncodingError(e.to_string()))?;
+    Ok(webp_data)
+}
+
+// These encode lossily or decode through libwebp, so they need it
+#[cfg(all(test, feature = "libwebp"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_rgba_frame() {
+        let pixels: Vec<u8> = [10, 20, 30, 128].repeat(6);
+        let settings = EncoderSettings { lossless: true, ..Default::default() };
+
+        let webp_data = encode_webp(&pixels, 3, 2, &settings).unwrap();
+        let decoded = webp::Decoder::new(&webp_data).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+        assert_eq!(&decoded[..4], &[10, 20, 30, 128]);
+    }
+
+    #[test]
+    fn test_rejects_mismatched_buffer() {
+        let settings = EncoderSettings { layout: PixelLayout::Rgb, ..Default::default() };
+        assert!(matches!(
+            encode_webp(&[0; 10], 2, 2, &settings),
+            Err(WebPError::InvalidSettings(_))
+        ));
+        assert!(encode_webp(&[0; 12], 2, 2, &settings).is_ok());
+    }
+
+    #[test]
+    fn test_preset_parsing() {
+        assert_eq!("Text".parse::<Preset>().unwrap(), Preset::Text);
+        assert_eq!(Preset::Drawing.to_string(), "drawing");
+        assert!("poster".parse::<Preset>().is_err());
+
+        let settings = EncoderSettings { preset: Preset::Photo, layout: PixelLayout::Rgb, ..Default::default() };
+        assert!(encode_webp(&[90; 48], 4, 4, &settings).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 //! Error handling module for the WebP converter.
 
 use thiserror::Error;
+use std::io;
 use std::path::PathBuf;
 
 /// Custom error types for the WebP converter.
@@ -30,8 +31,163 @@ pub enum WebPError {
     #[error("Directory traversal error: {0}")]
     WalkDirError(#[from] walkdir::Error),
 
+    #[cfg(feature = "watch")]
+    #[error("File watching failed: {0}")]
+    WatchError(#[from] notify::Error),
+
+    #[error("HTTP server error: {0}")]
+    ServerError(String),
+
+    #[error("Remote transfer failed: {0}")]
+    TransferError(String),
+
+    /// The server refused for now, e.g. an FTP 4xx reply or an HTTP 503.
+    #[error("Remote server unavailable: {0}")]
+    ServerUnavailable(String),
+
+    #[error("Webhook failed: {0}")]
+    WebhookError(String),
+
+    #[error("Notification failed: {0}")]
+    NotificationError(String),
+
+    #[error("Git failed: {0}")]
+    GitError(String),
+
+    #[error("Message queue error: {0}")]
+    QueueError(String),
+
+    #[error("Desktop integration failed: {0}")]
+    IntegrationError(String),
+
+    #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+    #[error("Object storage error: {0}")]
+    StorageError(#[from] object_store::Error),
+
+    #[cfg(feature = "sqlite")]
+    #[error("Stats database error: {0}")]
+    HistoryError(#[from] rusqlite::Error),
+
+    #[error(
+        "Output is {} even at quality {quality}, over the {} budget",
+        crate::utils::format_size(*.size),
+        crate::utils::format_size(*.budget)
+    )]
+    OverBudget { size: u64, budget: u64, quality: u8 },
+
+    #[error("Output SSIM {ssim:.4} is below the minimum of {min_ssim}")]
+    LowQuality { ssim: f64, min_ssim: f64 },
+
+    /// Another run holds the lock on a folder; `pid` is its process id when
+    /// the lock file names one.
+    #[error(
+        "{} is in use by another run{}",
+        .path.display(),
+        .pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default()
+    )]
+    Locked { path: PathBuf, pid: Option<u32> },
+
+    /// A written WebP did not read back as the image that was encoded.
+    #[error("Output {} failed verification: {reason}", .path.display())]
+    VerificationFailed { path: PathBuf, reason: String },
+
+    /// An output would overwrite `other`, another input of the batch, or
+    /// the output of `other`; or a file would be converted onto itself.
+    #[error("Output {} clashes with {}", .output.display(), .other.display())]
+    OutputCollision { output: PathBuf, other: PathBuf },
+
+    /// Two images of a sprite sheet would get the same sprite name.
+    #[error("Sprites {} and {} would both be named '{name}'", .first.display(), .second.display())]
+    DuplicateSprite { name: String, first: PathBuf, second: PathBuf },
+
+    /// The sandboxed decoder was killed or could not run.
+    #[error("Sandboxed decoding failed: {0}")]
+    SandboxFailed(String),
+
+    #[error("Invalid converter settings: {0}")]
+    InvalidSettings(String),
+
+    #[error("Invalid --ops pipeline: {0}")]
+    InvalidPipeline(String),
+
     #[error("No supported image files found in directory")]
     NoImagesFound,
+
+    #[error("Operation cancelled")]
+    Cancelled,
+}
+
+impl WebPError {
+    /// Whether the failure may clear up by itself, so the operation is worth
+    /// retrying: busy or stale files, interrupted or timed-out I/O, dropped
+    /// connections, servers that are unavailable for now and outputs that
+    /// failed verification, which are written anew. Object storage
+    /// errors count when a request timed out, could not connect or got a
+    /// 408, 429 or 5xx reply, not when an object is missing, access is denied or
+    /// the request was rejected.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            WebPError::IoError(e) => is_transient_io(e),
+            WebPError::ServerUnavailable(_) | WebPError::VerificationFailed { .. } => true,
+            #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+            WebPError::StorageError(e) => is_transient_storage(e),
+            _ => false,
+        }
+    }
+}
+
+fn is_transient_io(error: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    if matches!(
+        error.kind(),
+        Interrupted
+            | WouldBlock
+            | TimedOut
+            | ResourceBusy
+            | StaleNetworkFileHandle
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+    ) {
+        return true;
+    }
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION: another process,
+    // often a virus scanner, has the file open
+    cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33))
+}
+
+/// Object stores wrap request failures in `Generic` errors. Connection
+/// problems keep their kind; reply statuses are only exposed in the
+/// message, `Server returned non-2xx status code: 503 Service Unavailable`,
+/// and a 200 carrying an error body, which stores retry like a 5xx, as
+/// `Server returned error response`.
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+fn is_transient_storage(error: &object_store::Error) -> bool {
+    use object_store::client::{HttpError, HttpErrorKind};
+
+    let object_store::Error::Generic { source, .. } = error else {
+        return false;
+    };
+    let mut next: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+    while let Some(error) = next {
+        if let Some(error) = error.downcast_ref::<HttpError>() {
+            return matches!(
+                error.kind(),
+                HttpErrorKind::Connect | HttpErrorKind::Request | HttpErrorKind::Timeout | HttpErrorKind::Interrupted
+            );
+        }
+        let message = error.to_string();
+        if let Some((_, status)) = message.split_once("status code: ") {
+            let status: u16 = status.get(..3).and_then(|status| status.parse().ok()).unwrap_or(0);
+            return matches!(status, 408 | 429 | 500..=599);
+        }
+        if message.contains("Server returned error response") {
+            return true;
+        }
+        next = error.source();
+    }
+    false
 }
 
 /// Type alias for Result with our custom error type.
@@ -0,0 +1,147 @@
+//! Batch estimates: project the output size and conversion time of a batch
+//! from its image headers and a sample encoded in memory, for capacity
+//! planning before a migration. Nothing is written.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::utils;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+/// What a batch would produce, projected from a sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Estimate {
+    /// Images the batch would convert.
+    pub files: usize,
+    /// Images whose header could not be read; left out of the figures below.
+    pub unreadable: usize,
+    pub original_size: u64,
+    /// Source pixels over all readable images.
+    pub pixels: u64,
+    /// Images encoded for the sample.
+    pub sampled: usize,
+    pub sample_pixels: u64,
+    pub sample_compressed_size: u64,
+    /// Seconds spent encoding the sample.
+    pub sample_time: f64,
+}
+
+impl Estimate {
+    /// Scale a sample figure by source pixels; `None` without a sample.
+    fn project(&self, sample_value: f64) -> Option<f64> {
+        (self.sample_pixels > 0).then(|| sample_value / self.sample_pixels as f64 * self.pixels as f64)
+    }
+
+    /// Projected total size of the outputs.
+    pub fn projected_size(&self) -> Option<u64> {
+        self.project(self.sample_compressed_size as f64).map(|size| size.round() as u64)
+    }
+
+    /// Projected seconds of conversion for the whole batch.
+    pub fn projected_time(&self) -> Option<f64> {
+        self.project(self.sample_time)
+    }
+}
+
+/// Every `items.len() / count`-th item, so a sample spreads over the
+/// folders of a sorted batch.
+fn spread<T>(items: &[T], count: usize) -> Vec<&T> {
+    let count = count.min(items.len());
+    (0..count).map(|index| &items[index * items.len() / count]).collect()
+}
+
+impl WebPConverter {
+    /// Estimate converting `input`, a file or a directory, at the current
+    /// settings without writing anything.
+    ///
+    /// Every image's header is read for its dimensions, and up to `sample`
+    /// images spread over the batch are encoded in memory. Sizes and times
+    /// are projected by source pixels, so a sample of small images does not
+    /// understate a batch of large ones. WebP inputs are left out, as in a
+    /// batch; sample images that fail are reported and not counted.
+    pub fn estimate(&self, input: &Path, recursive: bool, sample: usize) -> WebPResult<Estimate> {
+        let mut files = if input.is_dir() {
+            self.find_image_files(input, recursive)?
+        } else if input.is_file() {
+            vec![input.to_path_buf()]
+        } else {
+            return Err(WebPError::InputNotFound(input.to_path_buf()));
+        };
+        files.retain(|path| !utils::is_webp_file(path));
+        if files.is_empty() {
+            return Err(WebPError::NoImagesFound);
+        }
+        files.sort();
+
+        let mut estimate = Estimate::default();
+        let mut readable = Vec::with_capacity(files.len());
+        for path in files {
+            estimate.files += 1;
+            match (utils::image_dimensions(&path), fs::metadata(&path)) {
+                (Ok((width, height)), Ok(metadata)) => {
+                    estimate.pixels += width as u64 * height as u64;
+                    estimate.original_size += metadata.len();
+                    readable.push((path, width as u64 * height as u64));
+                }
+                _ => estimate.unreadable += 1,
+            }
+        }
+
+        for (path, pixels) in spread(&readable, sample) {
+            if self.is_cancelled() {
+                break;
+            }
+            let start_time = Instant::now();
+            match self.convert_to_writer(path, &mut io::sink()) {
+                Ok(compressed_size) => {
+                    estimate.sampled += 1;
+                    estimate.sample_time += start_time.elapsed().as_secs_f64();
+                    estimate.sample_compressed_size += compressed_size;
+                    estimate.sample_pixels += pixels;
+                }
+                Err(error) => self.report(Event::FileFailed { path, error: &error }),
+            }
+        }
+        Ok(estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_estimate() {
+        let dir = tempfile::tempdir().unwrap();
+        for (index, size) in [16, 32, 48, 64].into_iter().enumerate() {
+            let img = image::RgbImage::from_fn(size, size, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 90]));
+            img.save(dir.path().join(format!("{}.png", index))).unwrap();
+        }
+        fs::write(dir.path().join("broken.png"), b"not an image").unwrap();
+        fs::write(dir.path().join("done.webp"), b"RIFF").unwrap();
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let estimate = converter.estimate(dir.path(), false, 2).unwrap();
+        assert_eq!((estimate.files, estimate.unreadable, estimate.sampled), (5, 1, 2));
+        assert_eq!(estimate.pixels, 16 * 16 + 32 * 32 + 48 * 48 + 64 * 64);
+        // 0.png and 2.png are sampled
+        assert_eq!(estimate.sample_pixels, 16 * 16 + 48 * 48);
+        let projected = estimate.projected_size().unwrap();
+        assert!(projected > estimate.sample_compressed_size);
+        assert!(estimate.projected_time().is_some());
+        assert!(fs::read_dir(dir.path()).unwrap().all(|entry| !entry.unwrap().path().ends_with("0.webp")));
+    }
+
+    #[test]
+    fn test_spread() {
+        let files: Vec<PathBuf> = (0..10).map(|index| PathBuf::from(index.to_string())).collect();
+        let names = |sample: Vec<&PathBuf>| sample.iter().map(|path| path.to_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(spread(&files, 3)), ["0", "3", "6"]);
+        assert_eq!(spread(&files, 50).len(), 10);
+    }
+}
@@ -0,0 +1,66 @@
+//! Fallback output folder for outputs that cannot be written where they
+//! belong, e.g. on a read-only mount or a full volume.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+impl WebPConverter {
+    /// Write `data` to `output_path`, or into the fallback folder when the
+    /// output cannot be written there. Returns the path written.
+    pub(crate) fn write_output(&self, input_path: &Path, output_path: &Path, data: &[u8]) -> WebPResult<PathBuf> {
+        let error = match self.write_file(output_path, data) {
+            Ok(()) => return Ok(output_path.to_path_buf()),
+            Err(error) => error,
+        };
+        let Some(folder) = self.fallback_folder().filter(|_| is_unwritable(&error)) else {
+            return Err(error.into());
+        };
+
+        let file_name = output_path.file_name().ok_or_else(|| WebPError::InvalidFileName(output_path.to_path_buf()))?;
+        let fallback = folder.join(file_name);
+        fs::create_dir_all(folder)?;
+        self.write_file(&fallback, data)?;
+        self.report(Event::OutputRedirected { path: input_path, output: output_path, fallback: &fallback, error: &WebPError::from(error) });
+        Ok(fallback)
+    }
+}
+
+/// Whether a write failed because of where it went rather than what was
+/// written: no permission, a read-only file system or no space left.
+fn is_unwritable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_output_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let fallback = dir.path().join("fallback");
+        let converter = WebPConverter::builder().fallback_output(&fallback).build().unwrap();
+
+        let output = dir.path().join("a.webp");
+        assert_eq!(converter.write_output(Path::new("a.png"), &output, b"webp").unwrap(), output);
+
+        // Other failures, such as a missing folder, are not redirected
+        let missing = dir.path().join("missing").join("b.webp");
+        assert!(converter.write_output(Path::new("b.png"), &missing, b"webp").is_err());
+        assert!(!fallback.exists());
+
+        assert!(is_unwritable(&io::Error::from(io::ErrorKind::StorageFull)));
+        assert!(is_unwritable(&io::Error::from(io::ErrorKind::PermissionDenied)));
+        assert!(!is_unwritable(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+}
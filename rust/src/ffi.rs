@@ -0,0 +1,232 @@
+//! C interface for linking the converter from C/C++ (`ffi` feature).
+//!
+//! The matching header is `include/webp_converter.h`, generated with
+//! `cbindgen --config cbindgen.toml --output include/webp_converter.h`.
+//! The shared and static libraries are built on request, see the README.
+//!
+//! Panics never unwind into C: every entry point catches them and reports
+//! `WEBPCONV_PANIC`.
+
+use crate::converter::WebPConverter;
+use crate::error::WebPResult;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+/// Success.
+pub const WEBPCONV_OK: c_int = 0;
+/// A required pointer was null or a string was not valid UTF-8.
+pub const WEBPCONV_INVALID_ARGUMENT: c_int = -1;
+/// The conversion failed; see `webpconv_last_error`.
+pub const WEBPCONV_CONVERSION_FAILED: c_int = -2;
+/// An internal error (a panic) was caught; see `webpconv_last_error`.
+pub const WEBPCONV_PANIC: c_int = -3;
+
+/// Encoder settings passed from C. A null pointer selects the defaults.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WebpconvOptions {
+    /// Lossy quality from 1 to 100 (default 80).
+    pub quality: u8,
+    /// Non-zero for lossless compression.
+    pub lossless: u8,
+    /// Compression method from 0 (fastest) to 6 (best compression, default 4).
+    pub method: u8,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember an error message for `webpconv_last_error` and return `code`.
+fn fail(code: c_int, message: impl ToString) -> c_int {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
+
+/// Run an entry point's body, turning a panic into `WEBPCONV_PANIC`.
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        fail(WEBPCONV_PANIC, format!("internal error: {}", message))
+    })
+}
+
+/// Build a converter from optional C options.
+///
+/// # Safety
+///
+/// `options` must be null or point to a valid `WebpconvOptions`.
+unsafe fn converter(options: *const WebpconvOptions) -> WebPResult<WebPConverter> {
+    let builder = WebPConverter::builder();
+    match options.as_ref() {
+        Some(options) => builder
+            .quality(options.quality)
+            .lossless(options.lossless != 0)
+            .method(options.method)
+            .build(),
+        None => builder.build(),
+    }
+}
+
+/// Borrow a C string as a path. On Unix its bytes are used as they are, so
+/// names that are not UTF-8 work too.
+///
+/// # Safety
+///
+/// `value` must be null or a valid NUL-terminated string.
+unsafe fn path_arg<'a>(value: *const c_char) -> Option<&'a Path> {
+    if value.is_null() {
+        return None;
+    }
+    let bytes = CStr::from_ptr(value).to_bytes();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(Path::new(std::ffi::OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(bytes).ok().map(Path::new)
+    }
+}
+
+/// Convert an image file to WebP.
+///
+/// `output_path` may be null to write `<input>.webp` next to the input.
+/// Returns `WEBPCONV_OK` or a negative error code.
+///
+/// # Safety
+///
+/// `input_path` must be a valid NUL-terminated string, `output_path` null or a
+/// valid NUL-terminated string, and `options` null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn webpconv_convert_file(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    options: *const WebpconvOptions,
+) -> c_int {
+    guard(|| {
+        let Some(input) = path_arg(input_path) else {
+            return fail(WEBPCONV_INVALID_ARGUMENT, "input_path must be a valid UTF-8 string");
+        };
+        let output = path_arg(output_path);
+        if !output_path.is_null() && output.is_none() {
+            return fail(WEBPCONV_INVALID_ARGUMENT, "output_path must be a valid UTF-8 string");
+        }
+
+        let result = converter(options).and_then(|converter| converter.convert_single_file(input, output, None));
+        match result {
+            Ok(_) => WEBPCONV_OK,
+            Err(e) => fail(WEBPCONV_CONVERSION_FAILED, e),
+        }
+    })
+}
+
+/// Convert an encoded image held in memory to WebP.
+///
+/// On success `*output` receives a buffer of `*output_len` bytes that must be
+/// released with `webpconv_free_buffer`.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, `output` and
+/// `output_len` must be valid for writes, and `options` null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn webpconv_convert_buffer(
+    input: *const u8,
+    input_len: usize,
+    options: *const WebpconvOptions,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    if input.is_null() || output.is_null() || output_len.is_null() {
+        return fail(WEBPCONV_INVALID_ARGUMENT, "input, output and output_len must not be null");
+    }
+    *output = ptr::null_mut();
+    *output_len = 0;
+
+    guard(|| {
+        let bytes = std::slice::from_raw_parts(input, input_len);
+        match converter(options).and_then(|converter| converter.convert_bytes(bytes)) {
+            Ok(webp_data) => {
+                let webp_data = webp_data.into_boxed_slice();
+                *output_len = webp_data.len();
+                *output = Box::into_raw(webp_data).cast::<u8>();
+                WEBPCONV_OK
+            }
+            Err(e) => fail(WEBPCONV_CONVERSION_FAILED, e),
+        }
+    })
+}
+
+/// Release a buffer returned by `webpconv_convert_buffer`.
+///
+/// # Safety
+///
+/// `buffer` and `len` must come from one successful `webpconv_convert_buffer`
+/// call, and the buffer must not be freed twice. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn webpconv_free_buffer(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        let _ = panic::catch_unwind(|| drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len))));
+    }
+}
+
+/// Message of the last error on the calling thread, or null.
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn webpconv_last_error() -> *const c_char {
+    let last_error = || LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()));
+    panic::catch_unwind(last_error).unwrap_or(ptr::null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_buffer_roundtrip() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(4, 4)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let options = WebpconvOptions { quality: 90, lossless: 0, method: 4 };
+        let mut output = ptr::null_mut();
+        let mut output_len = 0;
+        let code = unsafe { webpconv_convert_buffer(png.as_ptr(), png.len(), &options, &mut output, &mut output_len) };
+        assert_eq!(code, WEBPCONV_OK);
+        let webp_data = unsafe { std::slice::from_raw_parts(output, output_len) };
+        assert_eq!(&webp_data[0..4], b"RIFF");
+        unsafe { webpconv_free_buffer(output, output_len) };
+    }
+
+    #[test]
+    fn test_errors_are_reported() {
+        let garbage = b"not an image";
+        let mut output = ptr::null_mut();
+        let mut output_len = 0;
+        let code =
+            unsafe { webpconv_convert_buffer(garbage.as_ptr(), garbage.len(), ptr::null(), &mut output, &mut output_len) };
+        assert_eq!(code, WEBPCONV_CONVERSION_FAILED);
+        assert!(output.is_null());
+        let message = unsafe { CStr::from_ptr(webpconv_last_error()) };
+        assert!(!message.to_bytes().is_empty());
+
+        let code = unsafe { webpconv_convert_file(ptr::null(), ptr::null(), ptr::null()) };
+        assert_eq!(code, WEBPCONV_INVALID_ARGUMENT);
+
+        assert_eq!(guard(|| panic!("encoder state")), WEBPCONV_PANIC);
+        let message = unsafe { CStr::from_ptr(webpconv_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "internal error: encoder state");
+    }
+}
@@ -0,0 +1,103 @@
+//! Git-aware file selection: convert only the images git reports as staged
+//! or changed, for pre-commit hooks and PR pipelines.
+
+use webp_converter::error::{WebPError, WebPResult};
+use webp_converter::utils;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Which changes to pick images from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitChanges {
+    /// Files staged for the next commit.
+    Staged,
+    /// Files changed in the working tree since a ref, e.g. `HEAD~3` or
+    /// `origin/main...HEAD`.
+    Since(String),
+}
+
+/// Added, copied, modified or renamed images below `directory`, as reported
+/// by the `git` executable. Deleted files are left out, as are files in
+/// subdirectories unless `recursive` is set.
+pub fn changed_images(directory: &Path, changes: &GitChanges, recursive: bool) -> WebPResult<Vec<PathBuf>> {
+    // Outside a repository `git diff` would compare files instead
+    git_output(Command::new("git").arg("-C").arg(directory).args(["rev-parse", "--is-inside-work-tree"]))?;
+
+    let mut command = Command::new("git");
+    // --relative limits the diff to `directory` and prints paths relative to it
+    command.arg("-C").arg(directory).args(["diff", "--name-only", "-z", "--relative", "--diff-filter=ACMR"]);
+    match changes {
+        GitChanges::Staged => {
+            command.arg("--cached");
+        }
+        GitChanges::Since(reference) if reference.starts_with('-') => {
+            return Err(WebPError::GitError(format!("invalid ref '{}'", reference)));
+        }
+        GitChanges::Since(reference) => {
+            command.arg(reference);
+        }
+    }
+
+    let output = git_output(command.arg("--"))?;
+    // git quotes nothing with -z, so names arrive as their raw bytes
+    let mut images: Vec<PathBuf> = output
+        .split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty() && (recursive || !path.contains(&b'/')))
+        .map(|path| directory.join(utils::path_from_bytes(path)))
+        .filter(|path| utils::is_supported_extension(path) && !utils::is_webp_file(path) && path.is_file())
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+/// Standard output of a git command, or its error message.
+fn git_output(command: &mut Command) -> WebPResult<Vec<u8>> {
+    let output = command.output().map_err(|e| WebPError::GitError(format!("cannot run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(WebPError::GitError(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn git(directory: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_changed_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        git(repo, &["init", "-q"]);
+        fs::create_dir(repo.join("assets")).unwrap();
+        for name in ["old.png", "assets/logo.png", "notes.txt"] {
+            fs::write(repo.join(name), "v1").unwrap();
+        }
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-qm", "initial"]);
+
+        fs::write(repo.join("new.png"), "v1").unwrap();
+        fs::write(repo.join("assets/logo.png"), "v2").unwrap();
+        fs::write(repo.join("notes.txt"), "v2").unwrap();
+        git(repo, &["add", "new.png"]);
+
+        assert_eq!(changed_images(repo, &GitChanges::Staged, true).unwrap(), vec![repo.join("new.png")]);
+        let since_head = changed_images(repo, &GitChanges::Since("HEAD".into()), true).unwrap();
+        assert_eq!(since_head, vec![repo.join("assets/logo.png"), repo.join("new.png")]);
+        let not_recursive = changed_images(repo, &GitChanges::Since("HEAD".into()), false).unwrap();
+        assert_eq!(not_recursive, vec![repo.join("new.png")]);
+        assert!(changed_images(repo, &GitChanges::Since("--output=x".into()), true).is_err());
+    }
+}
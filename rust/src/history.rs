@@ -0,0 +1,273 @@
+//! Persistent run history: every finished run and the files it converted are
+//! recorded in a SQLite database, so totals can be queried across runs
+//! ("bytes saved this month") and later runs can look up earlier results.
+
+use webp_converter::converter::ConversionStats;
+use webp_converter::error::WebPResult;
+use webp_converter::utils;
+use crate::webhook::RunKind;
+use rusqlite::{params, Connection};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        finished_at INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        arguments TEXT NOT NULL,
+        converted INTEGER NOT NULL,
+        failed INTEGER NOT NULL,
+        skipped INTEGER NOT NULL,
+        original_size INTEGER NOT NULL,
+        compressed_size INTEGER NOT NULL,
+        total_time REAL NOT NULL,
+        cancelled INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS files (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        path TEXT NOT NULL,
+        status TEXT NOT NULL,
+        original_size INTEGER,
+        compressed_size INTEGER,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS runs_finished_at ON runs(finished_at);
+    CREATE INDEX IF NOT EXISTS files_path ON files(path);
+";
+
+/// The runs a query covers, written `all`, `today`, `month`, `year` or a
+/// duration such as `7d` for the most recent days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    All,
+    /// Since local midnight.
+    Today,
+    /// Since the first of the current month, local time.
+    Month,
+    /// Since the first of January, local time.
+    Year,
+    /// The given time up to now.
+    Last(Duration),
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "today" => Ok(Self::Today),
+            "month" => Ok(Self::Month),
+            "year" => Ok(Self::Year),
+            _ => webp_converter::utils::parse_duration(s)
+                .map(Self::Last)
+                .map_err(|_| format!("expected all, today, month, year or a duration such as 7d, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("all time"),
+            Self::Today => f.write_str("today"),
+            Self::Month => f.write_str("this month"),
+            Self::Year => f.write_str("this year"),
+            Self::Last(duration) => write!(f, "the last {}", webp_converter::utils::format_duration(duration.as_secs_f64())),
+        }
+    }
+}
+
+impl Period {
+    /// SQL expression for the earliest `finished_at` in the period.
+    fn start(&self) -> String {
+        let start_of = |unit: &str| format!("CAST(strftime('%s', 'now', 'localtime', 'start of {}', 'utc') AS INTEGER)", unit);
+        match self {
+            Self::All => "0".to_string(),
+            Self::Today => start_of("day"),
+            Self::Month => start_of("month"),
+            Self::Year => start_of("year"),
+            Self::Last(duration) => format!("CAST(strftime('%s', 'now') AS INTEGER) - {}", duration.as_secs()),
+        }
+    }
+}
+
+/// Figures summed over the runs of a [`Period`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryTotals {
+    pub runs: u64,
+    pub converted: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// Seconds spent converting.
+    pub total_time: f64,
+}
+
+impl HistoryTotals {
+    pub fn bytes_saved(&self) -> u64 {
+        self.original_size.saturating_sub(self.compressed_size)
+    }
+}
+
+/// The most recent conversion of a file, as recorded.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHistory {
+    /// Seconds since the Unix epoch.
+    pub finished_at: i64,
+    pub original_size: u64,
+    pub compressed_size: u64,
+}
+
+/// An open stats database.
+pub struct History {
+    connection: Connection,
+}
+
+impl History {
+    /// Open the database at `path`, creating it and its folder on first use.
+    pub fn open(path: &Path) -> WebPResult<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        // Runs finishing at the same time wait for each other instead of failing
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Record a finished run with its converted and failed files; returns the
+    /// run's id.
+    pub fn record_run(&mut self, kind: RunKind, arguments: &[String], stats: &ConversionStats) -> WebPResult<i64> {
+        let finished_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        let kind = match kind {
+            RunKind::Batch => "batch",
+            RunKind::Watch => "watch",
+        };
+        let arguments = serde_json::Value::from(arguments.to_vec()).to_string();
+        let skipped = stats.not_smaller_count + stats.similar_count + stats.efficient_count + stats.low_quality_count;
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (finished_at, kind, arguments, converted, failed, skipped, original_size, compressed_size, total_time, cancelled)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                finished_at,
+                kind,
+                arguments,
+                stats.success_count as i64,
+                stats.failed_count as i64,
+                skipped as i64,
+                stats.total_original_size.unwrap_or(0) as i64,
+                stats.total_compressed_size.unwrap_or(0) as i64,
+                stats.total_time,
+                stats.cancelled,
+            ],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO files (run_id, path, status, original_size, compressed_size, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for file in &stats.converted_files {
+                let path = utils::escape_path(&file.path);
+                let sizes = (file.original_size as i64, file.compressed_size as i64);
+                insert.execute(params![run_id, path, "converted", sizes.0, sizes.1, None::<String>])?;
+            }
+            for failure in &stats.failures {
+                let path = utils::escape_path(&failure.path);
+                insert.execute(params![run_id, path, "failed", None::<i64>, None::<i64>, failure.error.to_string()])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// Sum the runs that finished within `period`.
+    pub fn totals(&self, period: Period) -> WebPResult<HistoryTotals> {
+        let query = format!(
+            "SELECT COUNT(*), SUM(converted), SUM(failed), SUM(skipped), SUM(original_size), SUM(compressed_size), SUM(total_time)
+             FROM runs WHERE finished_at >= {}",
+            period.start()
+        );
+        let totals = self.connection.query_row(&query, [], |row| {
+            let sum = |index: usize| row.get::<_, Option<i64>>(index).map(|value| value.unwrap_or(0) as u64);
+            Ok(HistoryTotals {
+                runs: sum(0)?,
+                converted: sum(1)?,
+                failed: sum(2)?,
+                skipped: sum(3)?,
+                original_size: sum(4)?,
+                compressed_size: sum(5)?,
+                total_time: row.get::<_, Option<f64>>(6)?.unwrap_or(0.0),
+            })
+        })?;
+        Ok(totals)
+    }
+
+    /// The most recent successful conversion of `path`, if any.
+    #[cfg(test)]
+    fn last_conversion(&self, path: &Path) -> WebPResult<Option<FileHistory>> {
+        let mut statement = self.connection.prepare(
+            "SELECT runs.finished_at, files.original_size, files.compressed_size FROM files JOIN runs ON runs.id = files.run_id
+             WHERE files.path = ?1 AND files.status = 'converted' ORDER BY runs.id DESC LIMIT 1",
+        )?;
+        let mut rows = statement.query(params![utils::escape_path(path)])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        Ok(Some(FileHistory {
+            finished_at: row.get(0)?,
+            original_size: row.get::<_, i64>(1)? as u64,
+            compressed_size: row.get::<_, i64>(2)? as u64,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webp_converter::error::WebPError;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache/stats.db");
+        let mut stats = ConversionStats::new();
+        stats.add_success(PathBuf::from("a.png"), 0.5, 1000, 400);
+        stats.add_success(PathBuf::from("b.png"), 0.25, 3000, 1000);
+        stats.add_failure(PathBuf::from("c.png"), WebPError::NoImagesFound);
+        stats.add_similar();
+
+        let mut history = History::open(&path).unwrap();
+        assert_eq!(history.totals(Period::All).unwrap(), HistoryTotals::default());
+        history.record_run(RunKind::Batch, &["photos/".to_string()], &stats).unwrap();
+        history.record_run(RunKind::Watch, &[], &stats).unwrap();
+
+        // Reopening keeps what was recorded
+        let history = History::open(&path).unwrap();
+        let totals = history.totals(Period::Month).unwrap();
+        assert_eq!((totals.runs, totals.converted, totals.failed, totals.skipped), (2, 4, 2, 2));
+        assert_eq!(totals.bytes_saved(), 5200);
+        assert_eq!(totals.total_time, 1.5);
+        assert_eq!(history.totals(Period::Last(Duration::from_secs(3600))).unwrap(), totals);
+
+        let last = history.last_conversion(Path::new("b.png")).unwrap().unwrap();
+        assert_eq!((last.original_size, last.compressed_size), (3000, 1000));
+        assert_eq!(history.last_conversion(Path::new("c.png")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!("month".parse(), Ok(Period::Month));
+        assert_eq!("7d".parse(), Ok(Period::Last(Duration::from_secs(7 * 86400))));
+        assert!("fortnight".parse::<Period>().is_err());
+    }
+}
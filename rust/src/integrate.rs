@@ -0,0 +1,410 @@
+//! Desktop integration: a "Convert to WebP" entry in the file manager's
+//! context menu — Windows Explorer, a Nautilus script or a Finder Quick
+//! Action — that converts the selected images or folder with a saved
+//! settings file.
+
+use webp_converter::error::{WebPError, WebPResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Menu entry label.
+pub const LABEL: &str = "Convert to WebP";
+
+/// Where `integrate` keeps the settings used by the menu entry:
+/// `%APPDATA%\webp-converter\settings.json` on Windows,
+/// `~/Library/Application Support/webp-converter/settings.json` on macOS and
+/// `$XDG_CONFIG_HOME/webp-converter/settings.json` (default `~/.config`)
+/// elsewhere. `None` when the base folder is unknown.
+pub fn default_settings_path() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join("webp-converter").join("settings.json"))
+}
+
+/// Install the menu entry for this platform, running `executable` with
+/// `--settings <settings>` on each selected item. Installing again replaces
+/// the entry. Returns a description of each installed entry.
+pub fn install(executable: &Path, settings: &Path) -> WebPResult<Vec<String>> {
+    platform::install(executable, settings)
+}
+
+/// Remove what [`install`] added; entries that are not installed are
+/// skipped. Returns a description of each removed entry.
+pub fn uninstall() -> WebPResult<Vec<String>> {
+    platform::uninstall()
+}
+
+/// `value` in single quotes for `sh`.
+fn shell_quote(value: &Path) -> String {
+    format!("'{}'", value.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Shell script converting each path given as an argument, or the current
+/// folder when there is none.
+fn shell_script(executable: &Path, settings: &Path) -> String {
+    format!(
+        r#"#!/bin/sh
+# {label}: installed by `webp-converter integrate`, removed by `webp-converter integrate --uninstall`
+[ $# -eq 0 ] && set -- "$PWD"
+status=0
+for path in "$@"; do
+    {executable} --settings {settings} "$path" || status=1
+done
+if [ $status -ne 0 ] && command -v notify-send >/dev/null 2>&1; then
+    notify-send "{label}" "Some images could not be converted"
+fi
+exit $status
+"#,
+        label = LABEL,
+        executable = shell_quote(executable),
+        settings = shell_quote(settings),
+    )
+}
+
+/// Command line of a Windows Explorer verb; `target` is `%1` or `%V`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn explorer_command(executable: &Path, settings: &Path, target: &str) -> String {
+    format!("\"{}\" --settings \"{}\" \"{}\"", executable.display(), settings.display(), target)
+}
+
+/// Write `contents` to `path`, creating its folder, with `mode` on Unix.
+#[cfg_attr(windows, allow(dead_code))]
+fn write_file(path: &Path, contents: &str, mode: u32) -> WebPResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// The user's home folder.
+#[cfg_attr(windows, allow(dead_code))]
+fn home() -> WebPResult<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| WebPError::IntegrationError("HOME is not set".to_string()))
+}
+
+/// Nautilus (GNOME Files) lists executables in its scripts folder under
+/// "Scripts" in the context menu.
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use super::*;
+
+    /// `$XDG_DATA_HOME`, by default `~/.local/share`.
+    fn data_home() -> WebPResult<PathBuf> {
+        std::env::var_os("XDG_DATA_HOME")
+            .filter(|data| !data.is_empty())
+            .map(PathBuf::from)
+            .map_or_else(|| home().map(|home| home.join(".local/share")), Ok)
+    }
+
+    fn script_path(data_home: &Path) -> PathBuf {
+        data_home.join("nautilus/scripts").join(LABEL)
+    }
+
+    pub fn install(executable: &Path, settings: &Path) -> WebPResult<Vec<String>> {
+        install_in(&data_home()?, executable, settings)
+    }
+
+    pub fn uninstall() -> WebPResult<Vec<String>> {
+        uninstall_in(&data_home()?)
+    }
+
+    /// [`install`] into the scripts folder under `data_home`.
+    pub(super) fn install_in(data_home: &Path, executable: &Path, settings: &Path) -> WebPResult<Vec<String>> {
+        let path = script_path(data_home);
+        write_file(&path, &shell_script(executable, settings), 0o755)?;
+        Ok(vec![format!("Nautilus script {}", path.display())])
+    }
+
+    /// [`uninstall`] from the scripts folder under `data_home`.
+    pub(super) fn uninstall_in(data_home: &Path) -> WebPResult<Vec<String>> {
+        let path = script_path(data_home);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::remove_file(&path)?;
+        Ok(vec![format!("Nautilus script {}", path.display())])
+    }
+}
+
+/// A Finder Quick Action: an Automator service workflow running the shell
+/// script on the selected files and folders.
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::*;
+
+    fn workflow_path() -> WebPResult<PathBuf> {
+        Ok(home()?.join("Library/Services").join(format!("{}.workflow", LABEL)))
+    }
+
+    pub fn install(executable: &Path, settings: &Path) -> WebPResult<Vec<String>> {
+        let path = workflow_path()?;
+        let contents = path.join("Contents");
+        write_file(&contents.join("Info.plist"), &info_plist(), 0o644)?;
+        write_file(&contents.join("document.wflow"), &workflow(&shell_script(executable, settings)), 0o644)?;
+        refresh_services();
+        Ok(vec![format!("Finder Quick Action {}", path.display())])
+    }
+
+    pub fn uninstall() -> WebPResult<Vec<String>> {
+        let path = workflow_path()?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::remove_dir_all(&path)?;
+        refresh_services();
+        Ok(vec![format!("Finder Quick Action {}", path.display())])
+    }
+
+    /// Ask the services menu to pick up the change; it also does on next login.
+    fn refresh_services() {
+        let _ = std::process::Command::new("/System/Library/CoreServices/pbs").arg("-update").status();
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    fn info_plist() -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>NSServices</key>
+	<array>
+		<dict>
+			<key>NSMenuItem</key>
+			<dict>
+				<key>default</key>
+				<string>{label}</string>
+			</dict>
+			<key>NSMessage</key>
+			<string>runWorkflowAsService</string>
+			<key>NSRequiredContext</key>
+			<dict>
+				<key>NSApplicationIdentifier</key>
+				<string>com.apple.finder</string>
+			</dict>
+			<key>NSSendFileTypes</key>
+			<array>
+				<string>public.image</string>
+				<string>public.folder</string>
+			</array>
+		</dict>
+	</array>
+</dict>
+</plist>
+"#,
+            label = escape(LABEL)
+        )
+    }
+
+    fn workflow(script: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AMApplicationBuild</key>
+	<string>523</string>
+	<key>AMApplicationVersion</key>
+	<string>2.10</string>
+	<key>AMDocumentVersion</key>
+	<string>2</string>
+	<key>actions</key>
+	<array>
+		<dict>
+			<key>action</key>
+			<dict>
+				<key>AMAccepts</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Optional</key>
+					<true/>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>AMActionVersion</key>
+				<string>2.0.3</string>
+				<key>AMApplication</key>
+				<array>
+					<string>Automator</string>
+				</array>
+				<key>AMProvides</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>ActionBundlePath</key>
+				<string>/System/Library/Automator/Run Shell Script.action</string>
+				<key>ActionName</key>
+				<string>Run Shell Script</string>
+				<key>ActionParameters</key>
+				<dict>
+					<key>COMMAND_STRING</key>
+					<string>{script}</string>
+					<key>CheckedForUserDefaultShell</key>
+					<true/>
+					<key>inputMethod</key>
+					<integer>1</integer>
+					<key>shell</key>
+					<string>/bin/sh</string>
+					<key>source</key>
+					<string></string>
+				</dict>
+				<key>BundleIdentifier</key>
+				<string>com.apple.RunShellScript</string>
+				<key>CFBundleVersion</key>
+				<string>2.0.3</string>
+				<key>CanShowSelectedItemsWhenRun</key>
+				<false/>
+				<key>CanShowWhenRun</key>
+				<true/>
+				<key>Class Name</key>
+				<string>RunShellScriptAction</string>
+				<key>InputUUID</key>
+				<string>7A1B6C2E-3D4F-4A5B-8C6D-9E0F1A2B3C4D</string>
+				<key>OutputUUID</key>
+				<string>8B2C7D3F-4E5A-4B6C-9D7E-0F1A2B3C4D5E</string>
+				<key>UUID</key>
+				<string>9C3D8E4A-5F6B-4C7D-8E9F-1A2B3C4D5E6F</string>
+			</dict>
+		</dict>
+	</array>
+	<key>connectors</key>
+	<dict/>
+	<key>workflowMetaData</key>
+	<dict>
+		<key>serviceApplicationBundleID</key>
+		<string>com.apple.finder</string>
+		<key>serviceInputTypeIdentifier</key>
+		<string>com.apple.Automator.fileSystemObject</string>
+		<key>serviceOutputTypeIdentifier</key>
+		<string>com.apple.Automator.nothing</string>
+		<key>workflowTypeIdentifier</key>
+		<string>com.apple.Automator.servicesMenu</string>
+	</dict>
+</dict>
+</plist>
+"#,
+            script = escape(script)
+        )
+    }
+}
+
+/// Explorer verbs in the per-user registry (`HKCU\Software\Classes`), added
+/// with `reg.exe`: one per supported image extension, one on folders and one
+/// on a folder's background ("here").
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use webp_converter::utils;
+    use std::process::{Command, Stdio};
+
+    /// Registry key name of the verb.
+    const VERB: &str = "WebPConverter";
+
+    /// Keys holding the verb, with the placeholder Explorer replaces by the target.
+    fn verb_keys() -> Vec<(String, &'static str)> {
+        let classes = r"HKCU\Software\Classes";
+        let mut keys: Vec<(String, &'static str)> = utils::supported_extensions()
+            .iter()
+            .filter(|extension| !extension.eq_ignore_ascii_case("webp"))
+            .map(|extension| (format!(r"{}\SystemFileAssociations\.{}\shell\{}", classes, extension, VERB), "%1"))
+            .collect();
+        keys.push((format!(r"{}\Directory\shell\{}", classes, VERB), "%1"));
+        keys.push((format!(r"{}\Directory\Background\shell\{}", classes, VERB), "%V"));
+        keys
+    }
+
+    fn reg(args: &[&str]) -> WebPResult<bool> {
+        let status = Command::new("reg")
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| WebPError::IntegrationError(format!("cannot run reg.exe: {}", e)))?;
+        Ok(status.success())
+    }
+
+    pub fn install(executable: &Path, settings: &Path) -> WebPResult<Vec<String>> {
+        let mut installed = Vec::new();
+        for (key, target) in verb_keys() {
+            let command = explorer_command(executable, settings, target);
+            let icon = executable.display().to_string();
+            let command_key = format!(r"{}\command", key);
+            let added = reg(&["add", &key, "/ve", "/d", LABEL, "/f"])?
+                && reg(&["add", &key, "/v", "Icon", "/d", &icon, "/f"])?
+                && reg(&["add", &command_key, "/ve", "/d", &command, "/f"])?;
+            if !added {
+                return Err(WebPError::IntegrationError(format!("cannot write registry key {}", key)));
+            }
+            installed.push(format!("Explorer menu entry {}", key));
+        }
+        Ok(installed)
+    }
+
+    pub fn uninstall() -> WebPResult<Vec<String>> {
+        let mut removed = Vec::new();
+        for (key, _) in verb_keys() {
+            // Fails when the key does not exist
+            if reg(&["delete", &key, "/f"])? {
+                removed.push(format!("Explorer menu entry {}", key));
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu_commands() {
+        let script = shell_script(Path::new("/opt/webp tools/webp-converter"), Path::new("/home/o'neil/settings.json"));
+        assert!(script.contains(r#"'/opt/webp tools/webp-converter' --settings '/home/o'\''neil/settings.json' "$path""#));
+        assert!(script.starts_with("#!/bin/sh\n"));
+
+        let command = explorer_command(Path::new(r"C:\Tools\webp-converter.exe"), Path::new(r"C:\cfg.json"), "%V");
+        assert_eq!(command, r#""C:\Tools\webp-converter.exe" --settings "C:\cfg.json" "%V""#);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_nautilus_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let installed = platform::install_in(dir.path(), Path::new("/usr/bin/webp-converter"), Path::new("/tmp/settings.json"));
+        let script = dir.path().join("nautilus/scripts").join(LABEL);
+        assert_eq!(installed.unwrap().len(), 1);
+        assert!(script.exists());
+
+        assert_eq!(platform::uninstall_in(dir.path()).unwrap().len(), 1);
+        assert!(!script.exists());
+        assert!(platform::uninstall_in(dir.path()).unwrap().is_empty());
+    }
+}
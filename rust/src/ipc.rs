@@ -0,0 +1,168 @@
+//! Local IPC: a resident converter takes jobs on a Unix domain socket, so
+//! editors, asset managers and desktop apps get results in milliseconds
+//! instead of starting a process per image.
+//!
+//! Frames are the newline-terminated JSON lines of [`crate::jobs`]: the
+//! client writes one job per frame and reads one result frame per job, in
+//! order. A connection may stay open for any number of jobs, and connections
+//! are served concurrently.
+//!
+//! Unix only: there is no Windows named pipe transport.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// How often idle connections and the listener check for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Removes the socket file when the server stops.
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Bind `socket`, replacing a stale socket file left by a process that
+/// died, but never one another process is still listening on.
+fn bind(socket: &Path) -> WebPResult<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if !metadata.file_type().is_socket() {
+            return Err(WebPError::ServerError(format!("{} exists and is not a socket", socket.display())));
+        }
+        if UnixStream::connect(socket).is_ok() {
+            return Err(WebPError::ServerError(format!("{} is in use by another process", socket.display())));
+        }
+        fs::remove_file(socket)?;
+    }
+    let error = |e: io::Error| WebPError::ServerError(format!("cannot listen on {}: {}", socket.display(), e));
+    // Jobs read and write any file the user can, so only the user may
+    // connect. The socket is bound in a private folder and restricted before
+    // it is moved into place, so no one can connect in between.
+    let parent = socket.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let private = tempfile::Builder::new().prefix(".webp-converter-").tempdir_in(parent).map_err(error)?;
+    fs::set_permissions(private.path(), fs::Permissions::from_mode(0o700))?;
+    let bound = private.path().join("socket");
+    let listener = UnixListener::bind(&bound).map_err(error)?;
+    fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&bound, socket).map_err(error)?;
+    Ok(listener)
+}
+
+impl WebPConverter {
+    /// Take jobs on the Unix domain socket `socket` until the converter's
+    /// cancellation token is triggered, then remove the socket file.
+    ///
+    /// Each connection is served on its own thread; malformed frames and
+    /// failed conversions produce `failed` results, and a client hanging up
+    /// only ends its own connection. Returns the number of jobs processed.
+    pub fn serve_ipc(&self, socket: &Path) -> WebPResult<usize> {
+        let listener = bind(socket)?;
+        let _socket_file = SocketFile(socket.to_path_buf());
+        listener.set_nonblocking(true)?;
+        self.report(Event::Ready);
+
+        let count = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            while !self.is_cancelled() {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let count = &count;
+                        scope.spawn(move || count.fetch_add(self.serve_connection(stream), Ordering::Relaxed));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                    Err(e) => return Err(WebPError::IoError(e)),
+                }
+            }
+            Ok(())
+        })?;
+        Ok(count.into_inner())
+    }
+
+    /// Answer the job frames of one connection until the client hangs up or
+    /// the converter is cancelled. Returns the number of jobs processed.
+    fn serve_connection(&self, stream: UnixStream) -> usize {
+        // Timed reads let an idle connection notice cancellation
+        if stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(POLL_INTERVAL))).is_err() {
+            return 0;
+        }
+        let Ok(mut writer) = stream.try_clone() else {
+            return 0;
+        };
+        let mut reader = BufReader::new(stream);
+        let (mut frame, mut count) = (Vec::new(), 0);
+        while !self.is_cancelled() {
+            // A timeout keeps the partial frame read so far in `frame`
+            let complete = match reader.read_until(b'\n', &mut frame) {
+                Ok(0) => break,
+                Ok(_) => frame.ends_with(b"\n"),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+                Err(_) => break,
+            };
+            if !complete {
+                // The client hung up mid-frame
+                break;
+            }
+            let line = String::from_utf8_lossy(&frame).trim().to_string();
+            frame.clear();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Ok(result) = self.run_job_line(&line).to_json() else { break };
+            count += 1;
+            if writeln!(writer, "{}", result).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+        count
+    }
+}
+
+// Jobs are encoded lossily, which needs libwebp
+#[cfg(all(test, feature = "libwebp"))]
+mod tests {
+    use super::*;
+    use crate::progress::CancellationToken;
+
+    #[test]
+    fn test_serve_ipc() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_pixel(16, 16, image::Rgb([20, 90, 200])).save(&input).unwrap();
+        let socket = dir.path().join("webp.sock");
+
+        let token = CancellationToken::new();
+        let converter = WebPConverter::builder().cancellation(token.clone()).build().unwrap();
+        let server = std::thread::spawn({
+            let socket = socket.clone();
+            move || converter.serve_ipc(&socket)
+        });
+        let stream = (0..50)
+            .find_map(|_| UnixStream::connect(&socket).map_err(|_| std::thread::sleep(POLL_INTERVAL)).ok())
+            .expect("server did not start");
+        assert!(bind(&socket).is_err());
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let mut writer = stream.try_clone().unwrap();
+        let jobs = format!("{}\n\nnot json\n", serde_json::json!({ "id": "1", "input": input, "quality": 70 }));
+        writer.write_all(jobs.as_bytes()).unwrap();
+        let results: Vec<String> = BufReader::new(stream).lines().take(2).map(Result::unwrap).collect();
+        assert!(results[0].contains("\"id\":\"1\"") && results[0].contains("\"status\":\"converted\""));
+        assert!(results[1].contains("invalid job"));
+
+        token.cancel();
+        assert_eq!(server.join().unwrap().unwrap(), 2);
+        assert!(!socket.exists());
+        assert!(dir.path().join("photo.webp").exists());
+    }
+}
@@ -0,0 +1,193 @@
+//! Line-delimited JSON job protocol: one job per input line, one result per
+//! output line, so an orchestrator can drive a single long-running process.
+//!
+//! ```text
+//! → {"id": "42", "input": "uploads/cat.jpg", "output": "web/cat.webp", "quality": 75}
+//! ← {"id":"42","input":"uploads/cat.jpg","output":"web/cat.webp","status":"converted","original_size":48213,"compressed_size":9120,"time_taken":0.084}
+//! ```
+
+use crate::converter::{ConversionStats, WebPConverter};
+use crate::error::{WebPError, WebPResult};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// One conversion requested on a job line.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Echoed back in the result, to match results to jobs.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(with = "crate::path_serde")]
+    pub input: PathBuf,
+    /// Output file; defaults to the input with a `.webp` extension.
+    #[serde(default, with = "crate::path_serde::option")]
+    pub output: Option<PathBuf>,
+    /// Quality 1-100 for this job instead of the process default.
+    #[serde(default)]
+    pub quality: Option<u8>,
+}
+
+/// Outcome of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobStatus {
+    Converted,
+    /// The WebP was not smaller than the source and was discarded.
+    NotSmaller,
+    /// The source was already compact enough to leave alone.
+    Efficient,
+    /// The WebP fell below the minimum SSIM and the source was kept.
+    LowQuality,
+    Failed,
+}
+
+/// Result line written for each job.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The job's input; absent when the line could not be parsed.
+    #[serde(with = "crate::path_serde::option", skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+    #[serde(with = "crate::path_serde::option", skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<u64>,
+    /// Seconds spent on the job.
+    pub time_taken: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JobResult {
+    fn failed(id: Option<String>, input: Option<PathBuf>, start_time: Instant, error: String) -> Self {
+        Self {
+            id,
+            input,
+            output: None,
+            status: JobStatus::Failed,
+            original_size: None,
+            compressed_size: None,
+            time_taken: start_time.elapsed().as_secs_f64(),
+            error: Some(error),
+        }
+    }
+
+    /// The result as one JSON line, without the newline.
+    pub(crate) fn to_json(&self) -> WebPResult<String> {
+        serde_json::to_string(self).map_err(|e| WebPError::EncodingError(format!("Failed to write job result: {}", e)))
+    }
+}
+
+impl WebPConverter {
+    /// Read jobs from `jobs`, one JSON object per line, and write one JSON
+    /// result line to `results` as each finishes, until end of input or
+    /// cancellation. Blank lines are ignored; malformed lines and failed
+    /// conversions produce `failed` results without stopping the loop.
+    ///
+    /// Returns the number of jobs processed.
+    pub fn run_jobs(&self, jobs: impl BufRead, mut results: impl Write) -> WebPResult<usize> {
+        let mut count = 0;
+        for line in jobs.lines() {
+            if self.is_cancelled() {
+                break;
+            }
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            writeln!(results, "{}", self.run_job_line(&line).to_json()?)?;
+            results.flush()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Parse and run one job line; a malformed line gives a `failed` result.
+    pub(crate) fn run_job_line(&self, line: &str) -> JobResult {
+        match serde_json::from_str::<Job>(line) {
+            Ok(job) => self.run_job(job),
+            Err(e) => JobResult::failed(None, None, Instant::now(), format!("invalid job: {}", e)),
+        }
+    }
+
+    fn run_job(&self, job: Job) -> JobResult {
+        let start_time = Instant::now();
+        let converter = match job.quality {
+            Some(quality) if !(1..=100).contains(&quality) => {
+                let error = format!("quality must be between 1 and 100, got {}", quality);
+                return JobResult::failed(job.id, Some(job.input), start_time, error);
+            }
+            Some(quality) => self.with_quality(quality),
+            None => self.clone(),
+        };
+
+        let output = job.output.clone().unwrap_or_else(|| utils::generate_output_path(&job.input));
+        match converter.convert_single_file(&job.input, Some(&output), None) {
+            Ok(stats) => JobResult {
+                status: status_of(&stats),
+                output: (stats.success_count > 0).then_some(output),
+                original_size: stats.total_original_size.or_else(|| std::fs::metadata(&job.input).ok().map(|m| m.len())),
+                compressed_size: stats.total_compressed_size.filter(|_| stats.success_count > 0),
+                time_taken: start_time.elapsed().as_secs_f64(),
+                error: None,
+                id: job.id,
+                input: Some(job.input),
+            },
+            Err(error) => JobResult::failed(job.id, Some(job.input), start_time, error.to_string()),
+        }
+    }
+}
+
+/// Status of a single-file conversion from its statistics.
+fn status_of(stats: &ConversionStats) -> JobStatus {
+    if stats.success_count > 0 {
+        JobStatus::Converted
+    } else if stats.not_smaller_count > 0 {
+        JobStatus::NotSmaller
+    } else if stats.efficient_count > 0 {
+        JobStatus::Efficient
+    } else {
+        JobStatus::LowQuality
+    }
+}
+
+// Jobs are encoded lossily, which needs libwebp
+#[cfg(all(test, feature = "libwebp"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_pixel(16, 16, image::Rgb([20, 90, 200])).save(&input).unwrap();
+        let output = dir.path().join("out.webp");
+        let jobs = format!(
+            "{}\n\n{}\n{}\nnot json\n",
+            serde_json::json!({ "id": "1", "input": input, "output": output, "quality": 70 }),
+            serde_json::json!({ "id": "2", "input": dir.path().join("missing.png") }),
+            serde_json::json!({ "id": "3", "input": input, "quality": 0 }),
+        );
+
+        let converter = WebPConverter::builder().build().unwrap();
+        let mut results = Vec::new();
+        assert_eq!(converter.run_jobs(jobs.as_bytes(), &mut results).unwrap(), 4);
+
+        let lines: Vec<serde_json::Value> =
+            String::from_utf8(results).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!((lines[0]["id"].as_str(), lines[0]["status"].as_str()), (Some("1"), Some("converted")));
+        assert!(output.exists());
+        assert_eq!(lines[1]["status"], "failed");
+        assert!(lines[2]["error"].as_str().unwrap().contains("quality"));
+        assert!(lines[3]["error"].as_str().unwrap().starts_with("invalid job"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Journal of outputs being written, so a run that crashed or was killed
+//! leaves no partial files behind once the next run, or `cleanup`, opens it.
+//!
+//! Outputs are written to a `.part` file next to their destination and
+//! renamed into place when complete, so a destination never holds a partial
+//! output. Each part file is journaled while it exists.
+
+use crate::converter::WebPConverter;
+use crate::error::WebPResult;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the journal inside a locked folder.
+pub const JOURNAL_FILE: &str = ".webp-converter.journal";
+
+/// Extension appended to outputs while they are written.
+pub const PART_EXTENSION: &str = "part";
+
+/// One line of the journal.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Entry {
+    Started(#[serde(with = "crate::path_serde")] PathBuf),
+    Finished(#[serde(with = "crate::path_serde")] PathBuf),
+}
+
+/// Journal of the part files a run is writing.
+///
+/// Opening a journal first removes the part files an interrupted run left,
+/// so it must only be opened by the holder of the folder's
+/// [`RunLock`](crate::RunLock). The lock deletes it when released with no
+/// writes in progress.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    state: Mutex<State>,
+    recovered: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct State {
+    file: File,
+    /// Part files started and not yet finished.
+    pending: usize,
+}
+
+impl Journal {
+    /// Open the journal of `dir`, removing the part files of an earlier run
+    /// that did not finish them.
+    pub fn open(dir: &Path) -> WebPResult<Journal> {
+        let path = dir.join(JOURNAL_FILE);
+        let recovered = recover(&path)?;
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(&path)?;
+        Ok(Journal { path, state: Mutex::new(State { file, pending: 0 }), recovered })
+    }
+
+    /// Part files removed when the journal was opened.
+    pub fn recovered(&self) -> &[PathBuf] {
+        &self.recovered
+    }
+
+    /// Path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record that `part` is about to be written. Paths are journaled as
+    /// absolute, as the next run may start from another folder.
+    pub(crate) fn started(&self, part: &Path) -> io::Result<()> {
+        self.append(&Entry::Started(std::path::absolute(part)?), |pending| *pending += 1)
+    }
+
+    /// Record that `part` was renamed into place or removed.
+    pub(crate) fn finished(&self, part: &Path) -> io::Result<()> {
+        self.append(&Entry::Finished(std::path::absolute(part)?), |pending| *pending -= 1)
+    }
+
+    /// Delete the journal file unless writes are still in progress. Only the
+    /// lock holder does this, before releasing the folder, so the journal of
+    /// the run that takes the lock next is never removed.
+    pub(crate) fn remove_if_idle(&self) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.pending == 0 {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn append(&self, entry: &Entry, pending: impl FnOnce(&mut usize)) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // One write per line, so a kill cannot interleave two entries
+        state.file.write_all(&line)?;
+        pending(&mut state.pending);
+        Ok(())
+    }
+}
+
+
+/// Remove the part files `journal` lists as started but not finished, and
+/// return those that existed.
+fn recover(journal: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = match fs::read(journal) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut unfinished = Vec::new();
+    // A line cut short by a kill is skipped; its part file was never created
+    for entry in contents.split(|&byte| byte == b'\n').filter_map(|line| serde_json::from_slice(line).ok()) {
+        match entry {
+            Entry::Started(part) => unfinished.push(part),
+            Entry::Finished(part) => unfinished.retain(|started| *started != part),
+        }
+    }
+
+    let mut removed = Vec::new();
+    for part in unfinished {
+        match fs::remove_file(&part) {
+            Ok(()) => removed.push(part),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(removed)
+}
+
+/// The part file an output is written to first (`photo.webp` →
+/// `photo.webp.part`).
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(PART_EXTENSION);
+    path.with_file_name(name)
+}
+
+impl WebPConverter {
+    /// Write `data` to `path` through its part file, journaled while it
+    /// exists. `path` is either left as it was or holds all of `data`.
+    pub(crate) fn write_file(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let part = part_path(path);
+        let journal = self.journal();
+        if let Some(journal) = journal {
+            journal.started(&part)?;
+        }
+        let result = fs::write(&part, data).and_then(|()| fs::rename(&part, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&part);
+        }
+        if let Some(journal) = journal {
+            journal.finished(&part)?;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = std::sync::Arc::new(Journal::open(dir.path()).unwrap());
+        assert!(journal.recovered().is_empty());
+
+        let converter = WebPConverter::builder().journal(journal.clone()).build().unwrap();
+        let output = dir.path().join("a.webp");
+        converter.write_file(&output, b"webp").unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"webp");
+        assert!(!part_path(&output).exists());
+
+        // A run killed while writing b.webp
+        let part = part_path(&dir.path().join("b.webp"));
+        journal.started(&part).unwrap();
+        fs::write(&part, b"we").unwrap();
+        drop(converter);
+        std::mem::forget(std::sync::Arc::into_inner(journal).unwrap());
+
+        let journal = Journal::open(dir.path()).unwrap();
+        assert_eq!(journal.recovered(), std::slice::from_ref(&part));
+        assert!(!part.exists() && output.exists());
+        journal.remove_if_idle();
+        assert!(!journal.path().exists());
+    }
+}
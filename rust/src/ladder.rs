@@ -0,0 +1,92 @@
+//! Quality ladders: the same image encoded at several qualities, to compare
+//! size against fidelity before settling on a quality standard.
+
+use crate::metrics::QualityMetrics;
+use std::path::PathBuf;
+
+/// Which qualities a ladder encodes, and whether the encodings are kept.
+#[derive(Debug, Clone, Default)]
+pub struct LadderOptions {
+    /// Lossy qualities (1-100), in table order
+    pub qualities: Vec<u8>,
+    /// Write every rung as `<name>.q<quality>.webp` instead of only measuring it
+    pub keep: bool,
+}
+
+/// Size and quality of one rung of a ladder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LadderStep {
+    pub quality: u8,
+    pub compressed_size: u64,
+    pub metrics: QualityMetrics,
+}
+
+/// One image (or a whole batch) measured at every quality of a ladder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ladder {
+    /// The source image, or the directory for a combined ladder.
+    pub input: PathBuf,
+    pub original_size: u64,
+    pub steps: Vec<LadderStep>,
+}
+
+impl Ladder {
+    /// Combine the ladders of several images encoded at the same qualities:
+    /// sizes are summed and metrics averaged per quality.
+    ///
+    /// Returns `None` when there are no ladders to combine.
+    pub fn combine(input: PathBuf, ladders: &[Ladder]) -> Option<Ladder> {
+        let first = ladders.first()?;
+        let steps = first
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let rungs: Vec<&LadderStep> = ladders.iter().filter_map(|ladder| ladder.steps.get(index)).collect();
+                let metrics: Vec<QualityMetrics> = rungs.iter().map(|rung| rung.metrics).collect();
+                LadderStep {
+                    quality: step.quality,
+                    compressed_size: rungs.iter().map(|rung| rung.compressed_size).sum(),
+                    metrics: QualityMetrics::average(&metrics).unwrap_or(step.metrics),
+                }
+            })
+            .collect();
+
+        Some(Ladder {
+            input,
+            original_size: ladders.iter().map(|ladder| ladder.original_size).sum(),
+            steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ladder(original_size: u64, sizes: &[u64], ssim: f64) -> Ladder {
+        let steps = sizes
+            .iter()
+            .zip([60, 80])
+            .map(|(&compressed_size, quality)| LadderStep {
+                quality,
+                compressed_size,
+                metrics: QualityMetrics { psnr: 40.0, ssim, butteraugli: None },
+            })
+            .collect();
+        Ladder { input: PathBuf::from("a.png"), original_size, steps }
+    }
+
+    #[test]
+    fn test_combine_ladders() {
+        assert_eq!(Ladder::combine(PathBuf::from("dir"), &[]), None);
+
+        let total = Ladder::combine(PathBuf::from("dir"), &[ladder(1000, &[100, 200], 0.9), ladder(3000, &[300, 400], 0.8)])
+            .unwrap();
+        assert_eq!(total.original_size, 4000);
+        assert_eq!(total.steps.len(), 2);
+        assert_eq!((total.steps[0].quality, total.steps[0].compressed_size), (60, 400));
+        assert_eq!((total.steps[1].quality, total.steps[1].compressed_size), (80, 600));
+        assert!((total.steps[1].metrics.ssim - 0.85).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,97 @@
+//! 🦀 WebP Image Converter library
+//!
+//! Conversion logic behind the `webp-converter` CLI, usable directly from Rust
+//! code without shelling out to the binary.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use webp_converter::WebPConverter;
+//!
+//! let converter = WebPConverter::builder().quality(85).resize(1600, 1600).build()?;
+//! let stats = converter.convert_single_file(Path::new("photo.jpg"), None, None)?;
+//! assert_eq!(stats.success_count, 1);
+//! # Ok::<(), webp_converter::WebPError>(())
+//! ```
+//!
+//! Images can be transformed before encoding by attaching a [`Pipeline`],
+//! either parsed from an `--ops` style spec or built from [`transform::Transforms`].
+
+pub mod analysis;
+pub mod artifacts;
+pub mod batch;
+pub mod collision;
+pub mod converter;
+pub mod dedupe;
+pub mod encoder;
+pub mod error;
+pub mod estimate;
+pub mod jobs;
+pub mod journal;
+pub mod ladder;
+pub mod lock;
+pub mod metrics;
+pub mod montage;
+pub mod overrides;
+pub mod path_serde;
+pub mod pipeline;
+pub mod processor;
+pub mod progress;
+pub mod remote;
+pub mod report;
+pub mod retry;
+pub mod sandbox;
+pub mod settings;
+pub mod sync;
+pub mod transform;
+pub mod utils;
+pub mod watermark;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "watch")]
+pub mod watch;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+pub mod storage;
+
+#[cfg(any(feature = "redis", feature = "amqp"))]
+pub mod queue;
+
+#[cfg(unix)]
+pub mod ipc;
+
+mod color;
+mod fallback;
+mod metadata;
+mod salvage;
+mod smart_crop;
+mod spritesheet;
+mod tiling;
+mod verify;
+
+pub use analysis::{ContentKind, ImageAnalysis};
+pub use batch::{BatchIter, FileResult};
+pub use collision::CollisionPolicy;
+pub use converter::{ConversionStats, ConvertedFile, DirectoryRollup, FailedFile, FormatBreakdown, RatioDistribution, TimingPercentiles, WebPConverter, WebPConverterBuilder, RATIO_BUCKETS};
+pub use encoder::{encode_webp, EncoderSettings, PixelLayout, Preset};
+pub use error::{WebPError, WebPResult};
+pub use image::ImageFormat;
+pub use estimate::Estimate;
+pub use journal::Journal;
+pub use ladder::{Ladder, LadderOptions, LadderStep};
+pub use lock::RunLock;
+pub use metrics::{FileComparison, LowQualityPolicy, Metric, QualityMetrics};
+pub use overrides::FormatOverride;
+pub use pipeline::{Operation, Pipeline, PipelineOptions};
+pub use processor::Processor;
+pub use progress::{CancellationToken, FileOutcome, NoProgress, ProgressHandler, ProgressWriter};
+pub use report::{Event, Reporter, SilentReporter};
+pub use sandbox::SandboxLimits;
+pub use settings::ConversionSettings;
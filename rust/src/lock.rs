@@ -0,0 +1,142 @@
+//! Lock files keeping two runs, e.g. overlapping cron jobs, from writing
+//! the same output folder at once.
+
+use crate::error::{WebPError, WebPResult};
+use crate::journal::Journal;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the lock file inside a locked folder.
+pub const LOCK_FILE: &str = ".webp-converter.lock";
+
+/// Exclusive lock on a folder, held until dropped.
+///
+/// The lock is an OS file lock on [`LOCK_FILE`], so it is released even
+/// when the process is killed. The file holds the process id of the run
+/// that took it and is removed when the lock is dropped; only a killed run
+/// leaves it behind, unlocked.
+///
+/// Taking the lock also opens the folder's [`Journal`], cleaning up after a
+/// run that was interrupted there.
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+    path: PathBuf,
+    journal: Arc<Journal>,
+}
+
+impl RunLock {
+    /// Lock `dir`, creating it if needed. Fails with [`WebPError::Locked`]
+    /// when another run holds the lock, without waiting for it.
+    pub fn acquire(dir: &Path) -> WebPResult<RunLock> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE);
+        let mut file = loop {
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let mut holder = String::new();
+                    let _ = file.read_to_string(&mut holder);
+                    return Err(WebPError::Locked { path: dir.to_path_buf(), pid: holder.trim().parse().ok() });
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+            // The run we waited on may have removed the file after we opened
+            // it; locking that file would not keep out a run opening a new one
+            if is_current(&file, &path)? {
+                break file;
+            }
+        };
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        let journal = Arc::new(Journal::open(dir)?);
+        Ok(RunLock { _file: file, path, journal })
+    }
+
+    /// Path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Journal of the outputs written into the locked folder.
+    pub fn journal(&self) -> &Arc<Journal> {
+        &self.journal
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // Removed while still locked, before the file is closed; a converter
+        // holding the journal past this point must not write through it
+        self.journal.remove_if_idle();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether `file` is still the file at `path`.
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let named = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let open = file.metadata()?;
+    Ok((open.dev(), open.ino()) == (named.dev(), named.ino()))
+}
+
+/// Whether `file` is still the file at `path`. Windows keeps a removed file
+/// in place, and refuses to open it, until every handle to it is closed, so
+/// it always is.
+#[cfg(not(unix))]
+fn is_current(_file: &File, _path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+
+        let lock = RunLock::acquire(&output).unwrap();
+        assert_eq!(lock.path(), output.join(LOCK_FILE));
+        match RunLock::acquire(&output) {
+            Err(WebPError::Locked { path, pid }) => {
+                assert_eq!(path, output);
+                assert_eq!(pid, Some(std::process::id()));
+            }
+            other => panic!("expected a lock error, got {:?}", other),
+        }
+
+        drop(lock);
+        assert!(!output.join(LOCK_FILE).exists());
+        assert!(RunLock::acquire(&output).is_ok());
+    }
+
+    #[test]
+    fn test_journal_removed_with_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = RunLock::acquire(dir.path()).unwrap();
+        let journal = Arc::clone(lock.journal());
+        let journal_path = journal.path().to_path_buf();
+        drop(lock);
+        assert!(!journal_path.exists());
+
+        // The next run's journal survives the last clone of the first one
+        let next = RunLock::acquire(dir.path()).unwrap();
+        drop(journal);
+        assert!(journal_path.exists());
+        drop(next);
+        assert!(!journal_path.exists());
+    }
+}
@@ -0,0 +1,246 @@
+//! Image metadata: reading source EXIF tags and embedding WebP metadata chunks.
+
+use crate::error::{WebPError, WebPResult};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::path::Path;
+
+const VP8X_FLAG_ICC: u8 = 0x20;
+const VP8X_FLAG_ALPHA: u8 = 0x10;
+const VP8X_FLAG_EXIF: u8 = 0x08;
+
+/// Read the EXIF orientation tag (1-8) of an image file, if present.
+pub fn read_orientation(path: &Path) -> Option<u32> {
+    let file = File::open(path).ok()?;
+    orientation_from(&mut BufReader::new(file))
+}
+
+/// Read the EXIF orientation tag (1-8) of an encoded image held in memory.
+pub fn read_orientation_from_bytes(bytes: &[u8]) -> Option<u32> {
+    orientation_from(&mut Cursor::new(bytes))
+}
+
+/// Read the orientation tag from any EXIF-carrying container.
+fn orientation_from<R: BufRead + Seek>(reader: &mut R) -> Option<u32> {
+    let exif = exif::Reader::new().read_from_container(reader).ok()?;
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+        .value
+        .get_uint(0)
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// A single RIFF chunk inside a WebP file.
+struct Chunk<'a> {
+    fourcc: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Split a WebP file into its top-level chunks.
+fn parse_chunks(webp: &[u8]) -> WebPResult<Vec<Chunk<'_>>> {
+    if webp.len() < 12 || &webp[0..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        return Err(WebPError::EncodingError("Invalid WebP container".to_string()));
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= webp.len() {
+        let fourcc = [webp[offset], webp[offset + 1], webp[offset + 2], webp[offset + 3]];
+        let size = u32::from_le_bytes([
+            webp[offset + 4],
+            webp[offset + 5],
+            webp[offset + 6],
+            webp[offset + 7],
+        ]) as usize;
+        let start = offset + 8;
+        let data = webp
+            .get(start..start + size)
+            .ok_or_else(|| WebPError::EncodingError("Truncated WebP chunk".to_string()))?;
+        chunks.push(Chunk { fourcc, data });
+        offset = start + size + (size & 1);
+    }
+
+    Ok(chunks)
+}
+
+/// Serialize chunks into a complete WebP file.
+fn write_chunks(chunks: &[Chunk<'_>]) -> Vec<u8> {
+    let mut body = b"WEBP".to_vec();
+    for chunk in chunks {
+        body.extend_from_slice(&chunk.fourcc);
+        body.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(chunk.data);
+        if chunk.data.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+
+    let mut webp = b"RIFF".to_vec();
+    webp.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    webp.extend_from_slice(&body);
+    webp
+}
+
+/// Check whether the image bitstream carries alpha.
+fn has_alpha(chunks: &[Chunk<'_>]) -> bool {
+    chunks.iter().any(|chunk| match &chunk.fourcc {
+        b"ALPH" => true,
+        // VP8L header: signature byte, then 14+14 bits of size and the alpha hint bit
+        b"VP8L" => chunk.data.len() >= 5 && chunk.data[4] & 0x10 != 0,
+        b"VP8X" => chunk.data.first().is_some_and(|flags| flags & VP8X_FLAG_ALPHA != 0),
+        _ => false,
+    })
+}
+
+/// Build a VP8X header chunk payload.
+fn vp8x_header(flags: u8, width: u32, height: u32) -> [u8; 10] {
+    let w = (width - 1).to_le_bytes();
+    let h = (height - 1).to_le_bytes();
+    [flags, 0, 0, 0, w[0], w[1], w[2], h[0], h[1], h[2]]
+}
+
+/// Build a minimal EXIF (TIFF) block declaring the image density in dots per inch.
+pub fn density_exif(dpi: u32) -> Vec<u8> {
+    const TYPE_SHORT: u16 = 3;
+    const TYPE_RATIONAL: u16 = 5;
+    const RESOLUTION_UNIT_INCH: u32 = 2;
+
+    // Little-endian TIFF header followed by IFD0 at offset 8
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+
+    // IFD0: entry count, 3 entries, next-IFD offset; rationals follow at offset 50
+    let values_offset: u32 = 8 + 2 + 3 * 12 + 4;
+    let entries: [(u16, u16, u32); 3] = [
+        (0x011A, TYPE_RATIONAL, values_offset),     // XResolution
+        (0x011B, TYPE_RATIONAL, values_offset + 8), // YResolution
+        (0x0128, TYPE_SHORT, RESOLUTION_UNIT_INCH), // ResolutionUnit
+    ];
+    exif.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, value) in entries {
+        exif.extend_from_slice(&tag.to_le_bytes());
+        exif.extend_from_slice(&kind.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&value.to_le_bytes());
+    }
+    exif.extend_from_slice(&0u32.to_le_bytes());
+
+    for _ in 0..2 {
+        exif.extend_from_slice(&dpi.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+    }
+
+    exif
+}
+
+/// Metadata chunks to embed into an encoded WebP image.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmbeddedMetadata<'a> {
+    pub icc_profile: Option<&'a [u8]>,
+    pub exif: Option<&'a [u8]>,
+}
+
+impl EmbeddedMetadata<'_> {
+    /// Check whether there is anything to embed.
+    pub fn is_empty(&self) -> bool {
+        self.icc_profile.is_none() && self.exif.is_none()
+    }
+}
+
+/// Embed ICC profile and EXIF chunks into an encoded WebP image.
+///
+/// Simple (VP8/VP8L) files are promoted to the extended format with a VP8X
+/// header; existing chunks of the same kind are replaced.
+pub fn embed_metadata(webp: &[u8], width: u32, height: u32, metadata: EmbeddedMetadata<'_>) -> WebPResult<Vec<u8>> {
+    let chunks = parse_chunks(webp)?;
+
+    let mut flags = chunks
+        .iter()
+        .find(|c| &c.fourcc == b"VP8X")
+        .and_then(|vp8x| vp8x.data.first().copied())
+        .unwrap_or(0);
+    if has_alpha(&chunks) {
+        flags |= VP8X_FLAG_ALPHA;
+    }
+    if metadata.icc_profile.is_some() {
+        flags |= VP8X_FLAG_ICC;
+    }
+    if metadata.exif.is_some() {
+        flags |= VP8X_FLAG_EXIF;
+    }
+    let header = vp8x_header(flags, width, height);
+
+    let replaced = |c: &Chunk<'_>| {
+        &c.fourcc == b"VP8X"
+            || (&c.fourcc == b"ICCP" && metadata.icc_profile.is_some())
+            || (&c.fourcc == b"EXIF" && metadata.exif.is_some())
+    };
+
+    // Chunk order: VP8X, ICCP, image data, EXIF
+    let mut output = vec![Chunk { fourcc: *b"VP8X", data: &header }];
+    if let Some(icc) = metadata.icc_profile {
+        output.push(Chunk { fourcc: *b"ICCP", data: icc });
+    }
+    output.extend(chunks.into_iter().filter(|c| !replaced(c)));
+    if let Some(exif) = metadata.exif {
+        output.push(Chunk { fourcc: *b"EXIF", data: exif });
+    }
+
+    Ok(write_chunks(&output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "libwebp")]
+    fn encode_sample() -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(3, 2, image::Rgb([10, 20, 30]));
+        webp::Encoder::from_rgb(img.as_raw(), 3, 2).encode(80.0).to_vec()
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_embed_icc_profile() {
+        let webp = encode_sample();
+        let metadata = EmbeddedMetadata {
+            icc_profile: Some(b"fake-profile"),
+            ..Default::default()
+        };
+
+        let with_icc = embed_metadata(&webp, 3, 2, metadata).unwrap();
+        let chunks = parse_chunks(&with_icc).unwrap();
+        let names: Vec<&[u8; 4]> = chunks.iter().map(|c| &c.fourcc).collect();
+        assert_eq!(names, vec![b"VP8X", b"ICCP", b"VP8 "]);
+        assert_eq!(chunks[0].data[0], VP8X_FLAG_ICC);
+        assert_eq!(chunks[1].data, b"fake-profile");
+
+        // The result must still decode
+        let decoded = webp::Decoder::new(&with_icc).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_embed_density_exif() {
+        let exif = density_exif(300);
+        let metadata = EmbeddedMetadata {
+            exif: Some(&exif),
+            ..Default::default()
+        };
+        let output = embed_metadata(&encode_sample(), 3, 2, metadata).unwrap();
+        let chunks = parse_chunks(&output).unwrap();
+        assert_eq!(&chunks.last().unwrap().fourcc, b"EXIF");
+        assert_eq!(chunks[0].data[0], VP8X_FLAG_EXIF);
+
+        let parsed = exif::Reader::new().read_raw(exif).unwrap();
+        let x_resolution = parsed.get_field(exif::Tag::XResolution, exif::In::PRIMARY).unwrap();
+        assert_eq!(x_resolution.display_value().to_string(), "300");
+        let unit = parsed.get_field(exif::Tag::ResolutionUnit, exif::In::PRIMARY).unwrap();
+        assert_eq!(unit.value.get_uint(0), Some(2));
+    }
+
+    #[test]
+    fn test_rejects_non_webp() {
+        assert!(embed_metadata(b"not a webp file", 1, 1, EmbeddedMetadata::default()).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Objective quality metrics comparing an encoded image with its source.
+
+use crate::error::{WebPError, WebPResult};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Side length of the SSIM windows.
+const SSIM_WINDOW: u32 = 8;
+/// Distance between neighbouring SSIM windows.
+const SSIM_STEP: u32 = 4;
+/// Factor applied to pixel errors in difference heatmaps, so that small
+/// compression errors stand out.
+const DIFF_GAIN: u32 = 8;
+/// Largest value of an 8-bit channel, for PSNR.
+const PEAK: f64 = 255.0;
+
+/// Which metrics to compute besides PSNR and SSIM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// PSNR and SSIM only.
+    #[default]
+    Ssim,
+    /// Also compute the Butteraugli distance (requires the `butteraugli` feature).
+    Butteraugli,
+}
+
+impl Metric {
+    /// Check whether this build can compute the metric.
+    pub fn is_available(self) -> bool {
+        match self {
+            Metric::Ssim => true,
+            Metric::Butteraugli => cfg!(feature = "butteraugli"),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ssim" | "psnr" => Ok(Metric::Ssim),
+            "butteraugli" => Ok(Metric::Butteraugli),
+            _ => Err(format!("unknown metric '{}' (expected ssim or butteraugli)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Ssim => "ssim",
+            Metric::Butteraugli => "butteraugli",
+        })
+    }
+}
+
+crate::transform::serde_as_string!(Metric);
+
+/// What to do with a lossy output whose SSIM falls below the minimum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LowQualityPolicy {
+    /// Fail the file without writing an output.
+    #[default]
+    Fail,
+    /// Keep only the source, like an output that is not smaller.
+    KeepOriginal,
+    /// Encode the image again, lossless.
+    RetryLossless,
+}
+
+impl FromStr for LowQualityPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(LowQualityPolicy::Fail),
+            "keep-original" => Ok(LowQualityPolicy::KeepOriginal),
+            "retry-lossless" => Ok(LowQualityPolicy::RetryLossless),
+            _ => Err(format!(
+                "unknown low-quality action '{}' (expected fail, keep-original or retry-lossless)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LowQualityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LowQualityPolicy::Fail => "fail",
+            LowQualityPolicy::KeepOriginal => "keep-original",
+            LowQualityPolicy::RetryLossless => "retry-lossless",
+        })
+    }
+}
+
+crate::transform::serde_as_string!(LowQualityPolicy);
+
+/// PSNR and SSIM of an encoded image against its source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityMetrics {
+    /// Peak signal-to-noise ratio over the RGB channels, in dB (infinite when identical).
+    pub psnr: f64,
+    /// Mean structural similarity of the luma channel, from 0 to 1.
+    pub ssim: f64,
+    /// Butteraugli distance (below 1 looks identical, above 2 visibly differs),
+    /// when requested and the image is at least 8x8.
+    pub butteraugli: Option<f64>,
+}
+
+impl QualityMetrics {
+    /// Compare two images of the same size.
+    pub fn compare(reference: &DynamicImage, encoded: &DynamicImage, metric: Metric) -> WebPResult<Self> {
+        let butteraugli = match metric {
+            Metric::Ssim => None,
+            Metric::Butteraugli => butteraugli(reference, encoded)?,
+        };
+        Ok(Self {
+            psnr: psnr(reference, encoded),
+            ssim: ssim(reference, encoded),
+            butteraugli,
+        })
+    }
+
+    /// Decode WebP bytes and compare them with the image they were encoded from.
+    pub fn measure(reference: &DynamicImage, webp: &[u8], metric: Metric) -> WebPResult<Self> {
+        Self::compare(reference, &decode_matching(reference, webp)?, metric)
+    }
+
+    /// Mean of several measurements. PSNR is that of the mean squared error,
+    /// so identical images count as no error rather than making the average
+    /// infinite; Butteraugli is averaged over the measurements that have it,
+    /// and `None` when none has. Returns `None` for an empty slice.
+    pub fn average(measurements: &[QualityMetrics]) -> Option<Self> {
+        if measurements.is_empty() {
+            return None;
+        }
+        let count = measurements.len() as f64;
+        let scores: Vec<f64> = measurements.iter().filter_map(|m| m.butteraugli).collect();
+        let mse = measurements.iter().map(|m| PEAK * PEAK / 10f64.powf(m.psnr / 10.0)).sum::<f64>() / count;
+        Some(Self {
+            psnr: psnr_of_mse(mse),
+            ssim: measurements.iter().map(|m| m.ssim).sum::<f64>() / count,
+            butteraugli: (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+        })
+    }
+}
+
+/// Two image files compared for a regression check, e.g. a source and its
+/// committed WebP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileComparison {
+    pub reference_size: u64,
+    pub candidate_size: u64,
+    pub reference_dimensions: (u32, u32),
+    pub candidate_dimensions: (u32, u32),
+    /// Quality of the candidate against the reference; `None` when the
+    /// dimensions differ and the images cannot be compared.
+    pub metrics: Option<QualityMetrics>,
+}
+
+impl FileComparison {
+    /// Load and compare two image files of any supported format.
+    pub fn of(reference: &Path, candidate: &Path, metric: Metric) -> WebPResult<Self> {
+        let (reference_img, _) = crate::color::load_image(reference)?;
+        let (candidate_img, _) = crate::color::load_image(candidate)?;
+        let reference_dimensions = (reference_img.width(), reference_img.height());
+        let candidate_dimensions = (candidate_img.width(), candidate_img.height());
+        let metrics = if reference_dimensions == candidate_dimensions {
+            Some(QualityMetrics::compare(&reference_img, &candidate_img, metric)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            reference_size: fs::metadata(reference)?.len(),
+            candidate_size: fs::metadata(candidate)?.len(),
+            reference_dimensions,
+            candidate_dimensions,
+            metrics,
+        })
+    }
+
+    /// Check the candidate against minimum SSIM and, optionally, PSNR
+    /// thresholds. Images of different sizes never pass.
+    pub fn passes(&self, min_ssim: f64, min_psnr: Option<f64>) -> bool {
+        self.metrics
+            .is_some_and(|metrics| metrics.ssim >= min_ssim && min_psnr.is_none_or(|min| metrics.psnr >= min))
+    }
+}
+
+/// Decode WebP bytes for comparison.
+#[cfg(feature = "libwebp")]
+pub(crate) fn decode(webp: &[u8]) -> WebPResult<DynamicImage> {
+    let failed = || WebPError::ImageProcessingError("Failed to decode the encoded image".to_string());
+    let decoded = webp::Decoder::new(webp).decode().ok_or_else(failed)?;
+    let (width, height, pixels) = (decoded.width(), decoded.height(), decoded.to_vec());
+    let img = if decoded.is_alpha() {
+        image::RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        image::RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+    };
+    img.ok_or_else(failed)
+}
+
+/// Decode WebP bytes for comparison.
+#[cfg(not(feature = "libwebp"))]
+pub(crate) fn decode(webp: &[u8]) -> WebPResult<DynamicImage> {
+    image::load_from_memory_with_format(webp, image::ImageFormat::WebP)
+        .map_err(|_| WebPError::ImageProcessingError("Failed to decode the encoded image".to_string()))
+}
+
+/// Decode WebP bytes encoded from `reference`, checking that the sizes match.
+pub(crate) fn decode_matching(reference: &DynamicImage, webp: &[u8]) -> WebPResult<DynamicImage> {
+    let encoded = decode(webp)?;
+    if (encoded.width(), encoded.height()) != (reference.width(), reference.height()) {
+        return Err(WebPError::ImageProcessingError(
+            "Encoded image size differs from its source".to_string(),
+        ));
+    }
+    Ok(encoded)
+}
+
+/// Heatmap of the per-pixel error between two images of the same size.
+///
+/// The largest channel difference (alpha included) is amplified and mapped
+/// from black (identical) through red and yellow to white (largest errors).
+pub fn diff_heatmap(a: &DynamicImage, b: &DynamicImage) -> RgbImage {
+    let (a, b) = (a.to_rgba8(), b.to_rgba8());
+    RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let error = pa.0.iter().zip(pb.0).map(|(&ca, cb)| ca.abs_diff(cb)).max().unwrap_or(0);
+        heat((u32::from(error) * DIFF_GAIN).min(255) as u8)
+    })
+}
+
+/// Black-red-yellow-white color ramp.
+fn heat(level: u8) -> Rgb<u8> {
+    // Each third of the range ramps up one channel
+    let ramp = |start: u32| ((u32::from(level).saturating_sub(start) * 3).min(255)) as u8;
+    Rgb([ramp(0), ramp(85), ramp(170)])
+}
+
+/// Peak signal-to-noise ratio over the RGB channels, in dB.
+pub fn psnr(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    let (a, b) = (a.to_rgb8(), b.to_rgb8());
+    let squared_error: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&x, &y)| (f64::from(x) - f64::from(y)).powi(2))
+        .sum();
+    psnr_of_mse(squared_error / a.as_raw().len().max(1) as f64)
+}
+
+/// PSNR in dB of a mean squared error over 8-bit channels.
+fn psnr_of_mse(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (PEAK * PEAK / mse).log10()
+    }
+}
+
+/// Butteraugli distance between two images; `None` below the 8x8 minimum.
+#[cfg(feature = "butteraugli")]
+pub fn butteraugli(a: &DynamicImage, b: &DynamicImage) -> WebPResult<Option<f64>> {
+    use butteraugli::{ButteraugliParams, Img, RGB8};
+
+    if a.width() < 8 || a.height() < 8 {
+        return Ok(None);
+    }
+    let to_img = |img: &DynamicImage| {
+        let rgb = img.to_rgb8();
+        let pixels: Vec<RGB8> = rgb.pixels().map(|p| RGB8::new(p[0], p[1], p[2])).collect();
+        Img::new(pixels, rgb.width() as usize, rgb.height() as usize)
+    };
+
+    let result = butteraugli::butteraugli(to_img(a).as_ref(), to_img(b).as_ref(), &ButteraugliParams::default())
+        .map_err(|e| WebPError::ImageProcessingError(format!("Butteraugli failed: {}", e)))?;
+    Ok(Some(result.score))
+}
+
+/// Butteraugli distance between two images; unavailable in this build.
+#[cfg(not(feature = "butteraugli"))]
+pub fn butteraugli(_a: &DynamicImage, _b: &DynamicImage) -> WebPResult<Option<f64>> {
+    Err(WebPError::InvalidSettings(
+        "the butteraugli metric requires building with --features butteraugli".to_string(),
+    ))
+}
+
+/// Mean SSIM of the luma channel over overlapping 8x8 windows.
+pub fn ssim(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    let (a, b) = (a.to_luma8(), b.to_luma8());
+    let (width, height) = a.dimensions();
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in window_starts(height, window_height) {
+        for x in window_starts(width, window_width) {
+            total += window_ssim(&a, &b, x, y, window_width, window_height);
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+/// Window offsets along one axis, always covering the last pixels.
+fn window_starts(length: u32, window: u32) -> Vec<u32> {
+    if length == 0 {
+        return Vec::new();
+    }
+    let last = length - window;
+    let mut starts: Vec<u32> = (0..=last).step_by(SSIM_STEP as usize).collect();
+    if starts.last() != Some(&last) {
+        starts.push(last);
+    }
+    starts
+}
+
+/// SSIM of one window.
+fn window_ssim(a: &GrayImage, b: &GrayImage, x0: u32, y0: u32, width: u32, height: u32) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let n = f64::from(width * height);
+    let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for y in y0..y0 + height {
+        for x in x0..x0 + width {
+            let pa = f64::from(a.get_pixel(x, y)[0]);
+            let pb = f64::from(b.get_pixel(x, y)[0]);
+            sum_a += pa;
+            sum_b += pb;
+            sum_aa += pa * pa;
+            sum_bb += pb * pb;
+            sum_ab += pa * pb;
+        }
+    }
+
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+    let var_a = sum_aa / n - mean_a * mean_a;
+    let var_b = sum_bb / n - mean_b * mean_b;
+    let covariance = sum_ab / n - mean_a * mean_b;
+
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(offset: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(20, 12, |x, y| {
+            image::Rgb([(x * 12) as u8 + offset, (y * 20) as u8, 128])
+        }))
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let metrics = QualityMetrics::compare(&gradient(0), &gradient(0), Metric::Ssim).unwrap();
+        assert!(metrics.psnr.is_infinite());
+        assert!((metrics.ssim - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_distorted_images() {
+        let metrics = QualityMetrics::compare(&gradient(0), &gradient(10), Metric::Ssim).unwrap();
+        // A uniform error of 10 in one of three channels
+        let expected_psnr = 10.0 * (255.0f64 * 255.0 / (100.0 / 3.0)).log10();
+        assert!((metrics.psnr - expected_psnr).abs() < 1e-9);
+        assert!(metrics.ssim < 1.0 && metrics.ssim > 0.9);
+
+        let noise = DynamicImage::ImageRgb8(image::RgbImage::from_fn(20, 12, |x, y| {
+            image::Rgb([((x * 7919 + y * 104729) % 251) as u8, 0, 0])
+        }));
+        assert!(ssim(&gradient(0), &noise) < 0.5);
+    }
+
+    #[cfg(feature = "libwebp")]
+    #[test]
+    fn test_measure_encoded() {
+        let img = gradient(0);
+        let webp_data = webp::Encoder::from_rgb(img.as_bytes(), 20, 12).encode_lossless().to_vec();
+        let metrics = QualityMetrics::measure(&img, &webp_data, Metric::Ssim).unwrap();
+        assert!(metrics.psnr.is_infinite());
+        assert_eq!(metrics.butteraugli, None);
+    }
+
+    #[test]
+    fn test_compare_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (reference, candidate, smaller) = (dir.path().join("a.png"), dir.path().join("b.png"), dir.path().join("c.png"));
+        gradient(0).save(&reference).unwrap();
+        gradient(10).save(&candidate).unwrap();
+        gradient(0).thumbnail(10, 6).save(&smaller).unwrap();
+
+        let comparison = FileComparison::of(&reference, &candidate, Metric::Ssim).unwrap();
+        assert_eq!(comparison.candidate_dimensions, (20, 12));
+        assert!(comparison.passes(0.9, Some(20.0)));
+        assert!(!comparison.passes(0.9, Some(40.0)));
+
+        let resized = FileComparison::of(&reference, &smaller, Metric::Ssim).unwrap();
+        assert_eq!((resized.candidate_dimensions, resized.metrics), ((10, 6), None));
+        assert!(!resized.passes(0.0, None));
+    }
+
+    #[test]
+    fn test_diff_heatmap() {
+        let identical = diff_heatmap(&gradient(0), &gradient(0));
+        assert!(identical.pixels().all(|p| p.0 == [0, 0, 0]));
+
+        // An error of 10 is amplified to 80: a dark red
+        let distorted = diff_heatmap(&gradient(0), &gradient(10));
+        assert_eq!(distorted.get_pixel(3, 3).0, [240, 0, 0]);
+        assert_eq!(heat(255).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_average() {
+        let scored = |butteraugli| QualityMetrics { psnr: 40.0, ssim: 0.95, butteraugli };
+        // An image too small for Butteraugli leaves the others' average alone
+        let average = QualityMetrics::average(&[scored(Some(1.0)), scored(None), scored(Some(2.0))]).unwrap();
+        assert_eq!(average.butteraugli, Some(1.5));
+        assert_eq!(QualityMetrics::average(&[scored(None)]).unwrap().butteraugli, None);
+        assert_eq!(QualityMetrics::average(&[]), None);
+
+        // One identical image halves the error instead of making PSNR infinite
+        let identical = QualityMetrics { psnr: f64::INFINITY, ..scored(None) };
+        let average = QualityMetrics::average(&[identical, scored(None)]).unwrap();
+        assert!((average.psnr - (40.0 + 10.0 * 2f64.log10())).abs() < 1e-9);
+        assert!(QualityMetrics::average(&[identical]).unwrap().psnr.is_infinite());
+    }
+
+    #[cfg(feature = "butteraugli")]
+    #[test]
+    fn test_butteraugli() {
+        let identical = QualityMetrics::compare(&gradient(0), &gradient(0), Metric::Butteraugli).unwrap();
+        assert!(identical.butteraugli.unwrap() < 0.01);
+
+        let distorted = QualityMetrics::compare(&gradient(0), &gradient(25), Metric::Butteraugli).unwrap();
+        assert!(distorted.butteraugli.unwrap() > 1.0);
+    }
+}
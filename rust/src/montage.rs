@@ -0,0 +1,128 @@
+//! Contact sheets: a labeled grid of thumbnails rendered into a single image.
+
+use crate::transform::Color;
+use crate::watermark;
+use ab_glyph::FontVec;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Gap between cells and around the sheet, in pixels.
+const SPACING: u32 = 8;
+
+/// Layout options for a contact sheet.
+pub struct MontageOptions {
+    /// Number of columns; a roughly square grid when unset
+    pub columns: Option<u32>,
+    /// Longest edge of each thumbnail
+    pub thumb_size: u32,
+    pub background: Color,
+    /// Font for the file-name labels; labels are omitted without one
+    pub font: Option<FontVec>,
+}
+
+impl MontageOptions {
+    /// Height of the label strip under each thumbnail.
+    fn label_size(&self) -> f32 {
+        (self.thumb_size as f32 / 12.0).clamp(10.0, 24.0)
+    }
+}
+
+/// Number of grid columns for `count` images.
+fn column_count(count: usize, columns: Option<u32>) -> u32 {
+    columns.unwrap_or_else(|| (count as f64).sqrt().ceil() as u32).clamp(1, count.max(1) as u32)
+}
+
+/// Render a label, dropping characters until it fits into `max_width`.
+fn render_label(font: &FontVec, text: &str, size: f32, max_width: u32) -> Option<RgbaImage> {
+    let mut chars: Vec<char> = text.chars().collect();
+    loop {
+        let label = if chars.len() < text.chars().count() {
+            format!("{}…", chars.iter().collect::<String>())
+        } else {
+            text.to_string()
+        };
+        let rendered = watermark::render_text(font, &label, size)?;
+        if rendered.width() <= max_width || chars.is_empty() {
+            return Some(rendered);
+        }
+        chars.pop();
+    }
+}
+
+/// Scale an image down to fit a thumbnail cell of `options`.
+pub fn thumbnail(img: &DynamicImage, options: &MontageOptions) -> RgbaImage {
+    img.resize(options.thumb_size, options.thumb_size, FilterType::Triangle).to_rgba8()
+}
+
+/// Arrange named thumbnails, as made by [`thumbnail`], into a grid with
+/// optional labels.
+pub fn build(thumbnails: &[(String, RgbaImage)], options: &MontageOptions) -> RgbaImage {
+    let columns = column_count(thumbnails.len(), options.columns);
+    let rows = (thumbnails.len() as u32).div_ceil(columns).max(1);
+
+    let thumb = options.thumb_size;
+    let label_height = match options.font {
+        Some(_) => (options.label_size() * 1.5).ceil() as u32,
+        None => 0,
+    };
+    let cell_height = thumb + label_height;
+
+    let width = columns * (thumb + SPACING) + SPACING;
+    let height = rows * (cell_height + SPACING) + SPACING;
+    let mut sheet = RgbaImage::from_pixel(width, height, Rgba(options.background.0));
+
+    for (i, (name, thumbnail)) in thumbnails.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let cell_x = SPACING + column * (thumb + SPACING);
+        let cell_y = SPACING + row * (cell_height + SPACING);
+
+        // Center the thumbnail inside the cell
+        let x = cell_x + thumb.saturating_sub(thumbnail.width()) / 2;
+        let y = cell_y + thumb.saturating_sub(thumbnail.height()) / 2;
+        imageops::overlay(&mut sheet, thumbnail, i64::from(x), i64::from(y));
+
+        if let Some(font) = &options.font {
+            if let Some(label) = render_label(font, name, options.label_size(), thumb) {
+                let x = cell_x + thumb.saturating_sub(label.width()) / 2;
+                let y = cell_y + thumb + label_height.saturating_sub(label.height()) / 2;
+                imageops::overlay(&mut sheet, &label, i64::from(x), i64::from(y));
+            }
+        }
+    }
+
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_count() {
+        assert_eq!(column_count(10, None), 4);
+        assert_eq!(column_count(4, None), 2);
+        assert_eq!(column_count(3, Some(5)), 3);
+        assert_eq!(column_count(0, None), 1);
+    }
+
+    #[test]
+    fn test_build_grid() {
+        let options = MontageOptions {
+            columns: Some(2),
+            thumb_size: 32,
+            background: Color::BLACK,
+            font: None,
+        };
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 20, image::Rgb([255, 0, 0])));
+        let thumbnails: Vec<(String, RgbaImage)> = (0..5).map(|i| (format!("img{}", i), thumbnail(&image, &options))).collect();
+
+        let sheet = build(&thumbnails, &options);
+        assert_eq!((sheet.width(), sheet.height()), (2 * 40 + 8, 3 * 40 + 8));
+
+        // The first thumbnail is scaled to 32x16 and centered vertically in its cell
+        assert_eq!(sheet.get_pixel(8, 8 + 8), &Rgba([255, 0, 0, 255]));
+        assert_eq!(sheet.get_pixel(8, 8), &Rgba([0, 0, 0, 255]));
+        // The last row has a single image; its second cell stays empty
+        assert_eq!(sheet.get_pixel(48 + 16, 88 + 16), &Rgba([0, 0, 0, 255]));
+    }
+}
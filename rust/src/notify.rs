@@ -0,0 +1,188 @@
+//! Completion notifications: a short human-readable summary of a finished
+//! run, posted to Slack or mailed, for long batches nobody is watching.
+
+use webp_converter::converter::ConversionStats;
+use webp_converter::error::{WebPError, WebPResult};
+use webp_converter::utils;
+use crate::webhook::RunKind;
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+#[cfg(feature = "webhook")]
+use std::time::Duration;
+
+/// Timeout for the whole Slack request.
+#[cfg(feature = "webhook")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a summary is sent, written `slack:<webhook url>` or
+/// `email:<address>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyTarget {
+    /// A Slack incoming webhook URL.
+    Slack(String),
+    /// An address mailed through the local `sendmail`.
+    Email(String),
+}
+
+impl FromStr for NotifyTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("slack", url)) if url.starts_with("https://") => Ok(Self::Slack(url.to_string())),
+            Some(("slack", url)) => Err(format!("expected an https:// Slack webhook URL, got '{}'", url)),
+            Some(("email", address)) => {
+                let valid = address.contains('@')
+                    && !address.starts_with('-')
+                    && !address.chars().any(|c| c.is_whitespace() || c.is_control() || c == ',');
+                if valid {
+                    Ok(Self::Email(address.to_string()))
+                } else {
+                    Err(format!("invalid email address '{}'", address))
+                }
+            }
+            _ => Err(format!("expected slack:<webhook url> or email:<address>, got '{}'", s)),
+        }
+    }
+}
+
+/// Shows the kind and address; Slack webhook URLs are secrets and are left out.
+impl fmt::Display for NotifyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Slack(_) => f.write_str("slack"),
+            Self::Email(address) => write!(f, "email:{}", address),
+        }
+    }
+}
+
+/// The figures a notification reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub kind: RunKind,
+    pub converted: usize,
+    pub failed: usize,
+    pub bytes_saved: u64,
+    /// Seconds spent converting.
+    pub duration: f64,
+    pub cancelled: bool,
+}
+
+impl RunSummary {
+    pub fn new(kind: RunKind, stats: &ConversionStats) -> Self {
+        let bytes_saved = match (stats.total_original_size, stats.total_compressed_size) {
+            (Some(original), Some(compressed)) => original.saturating_sub(compressed),
+            _ => 0,
+        };
+        Self {
+            kind,
+            converted: stats.success_count,
+            failed: stats.failed_count,
+            bytes_saved,
+            duration: stats.total_time,
+            cancelled: stats.cancelled,
+        }
+    }
+
+    /// One line, e.g. `webp-converter batch finished: 120 converted, 2 failed`.
+    pub fn subject(&self) -> String {
+        let kind = match self.kind {
+            RunKind::Batch => "batch",
+            RunKind::Watch => "watch session",
+        };
+        let outcome = if self.cancelled { "stopped early" } else { "finished" };
+        format!("webp-converter {} {}: {} converted, {} failed", kind, outcome, self.converted, self.failed)
+    }
+
+    /// Subject followed by one line per figure.
+    pub fn text(&self) -> String {
+        format!(
+            "{}\nConverted: {}\nFailed: {}\nSaved: {}\nDuration: {}\n",
+            self.subject(),
+            self.converted,
+            self.failed,
+            utils::format_size(self.bytes_saved),
+            utils::format_duration(self.duration)
+        )
+    }
+}
+
+impl NotifyTarget {
+    /// Send the summary; delivery failures are returned, not retried.
+    pub fn send(&self, summary: &RunSummary) -> WebPResult<()> {
+        match self {
+            #[cfg(feature = "webhook")]
+            Self::Slack(url) => {
+                let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().into();
+                agent
+                    .post(url)
+                    .send_json(serde_json::json!({ "text": summary.text() }))
+                    .map_err(|e| WebPError::NotificationError(format!("Slack: {}", e)))?;
+                Ok(())
+            }
+            #[cfg(not(feature = "webhook"))]
+            Self::Slack(_) => Err(WebPError::NotificationError(
+                "Slack: notifications require building with --features webhook".to_string(),
+            )),
+            Self::Email(address) => send_mail(address, &summary.subject(), &summary.text())
+                .map_err(|e| WebPError::NotificationError(format!("email to {}: {}", address, e))),
+        }
+    }
+}
+
+/// Hand a plain-text message to `sendmail`, looked up in `PATH` and then in
+/// `/usr/sbin`.
+fn send_mail(address: &str, subject: &str, body: &str) -> io::Result<()> {
+    let spawn = |program: &str| {
+        Command::new(program).args(["-oi", "--", address]).stdin(Stdio::piped()).stdout(Stdio::null()).spawn()
+    };
+    let mut child = match spawn("sendmail") {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => spawn("/usr/sbin/sendmail")?,
+        child => child?,
+    };
+
+    let message = format!(
+        "To: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+        address,
+        subject,
+        body.replace('\n', "\r\n")
+    );
+    let written = child.stdin.take().expect("stdin is piped").write_all(message.as_bytes());
+    let status = child.wait()?;
+    written?;
+    if !status.success() {
+        return Err(io::Error::other(format!("sendmail exited with {}", status)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_target() {
+        let slack: NotifyTarget = "slack:https://hooks.slack.com/services/T0/B0/x".parse().unwrap();
+        assert_eq!(slack.to_string(), "slack");
+        assert_eq!("email:ops@example.com".parse(), Ok(NotifyTarget::Email("ops@example.com".to_string())));
+        for invalid in ["slack:http://example.com", "email:nobody", "email:-f@x", "pager:42"] {
+            assert!(invalid.parse::<NotifyTarget>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_summary_text() {
+        let mut stats = ConversionStats::new();
+        stats.add_success(PathBuf::from("a.png"), 700.0, 3_000_000, 1_000_000);
+        stats.add_success(PathBuf::from("b.png"), 30.5, 1_000_000, 1_200_000);
+        let summary = RunSummary::new(RunKind::Batch, &stats);
+        assert_eq!(summary.bytes_saved, 1_800_000);
+        assert_eq!(
+            summary.text(),
+            "webp-converter batch finished: 2 converted, 0 failed\nConverted: 2\nFailed: 0\nSaved: 1.7 MB\nDuration: 12m 10s\n"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Encoder settings that apply only to sources of one format.
+
+use crate::encoder::Preset;
+use image::ImageFormat;
+use std::fmt;
+use std::str::FromStr;
+
+/// Encoder settings overriding the converter defaults for one source format,
+/// written like `png lossless` or `jpg q=78,preset=photo`.
+///
+/// Settings left unset keep the converter's values. An override that sets
+/// `lossless`/`lossy` or a quality takes precedence over automatic mode and
+/// quality selection for that format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOverride {
+    pub format: ImageFormat,
+    pub quality: Option<u8>,
+    pub lossless: Option<bool>,
+    pub method: Option<u8>,
+    pub preset: Option<Preset>,
+}
+
+impl FormatOverride {
+    /// Parse a source format (file extension) and a comma-separated list of
+    /// settings: `lossless`, `lossy`, `q=N`, `method=N` and `preset=NAME`.
+    pub fn parse(format: &str, settings: &str) -> Result<Self, String> {
+        let format = ImageFormat::from_extension(format.trim())
+            .ok_or_else(|| format!("unknown source format '{}'", format.trim()))?;
+        let mut result = Self { format, quality: None, lossless: None, method: None, preset: None };
+
+        for entry in settings.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, value) = match entry.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (entry, None),
+            };
+            let number = |value: Option<&str>| {
+                value
+                    .and_then(|v| v.parse::<u8>().ok())
+                    .ok_or_else(|| format!("'{}' needs a number, e.g. {}=80", name, name))
+            };
+            match name.to_lowercase().as_str() {
+                "lossless" => result.lossless = Some(true),
+                "lossy" => result.lossless = Some(false),
+                "q" | "quality" => result.quality = Some(number(value)?),
+                "m" | "method" => result.method = Some(number(value)?),
+                "preset" => {
+                    let value = value.ok_or_else(|| "'preset' needs a name, e.g. preset=photo".to_string())?;
+                    result.preset = Some(value.parse()?);
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown setting '{}' (expected lossless, lossy, q=N, method=N or preset=NAME)",
+                        name
+                    ))
+                }
+            }
+        }
+
+        if result.is_empty() {
+            return Err(format!("no settings given for {}", result.extension()));
+        }
+        Ok(result)
+    }
+
+    /// Check whether the override changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.quality.is_none() && self.lossless.is_none() && self.method.is_none() && self.preset.is_none()
+    }
+
+    /// Preferred file extension of the source format.
+    pub fn extension(&self) -> &'static str {
+        self.format.extensions_str().first().copied().unwrap_or("?")
+    }
+
+    /// The settings part, as accepted by [`parse`](Self::parse).
+    pub fn describe(&self) -> String {
+        let mut settings = Vec::new();
+        match self.lossless {
+            Some(true) => settings.push("lossless".to_string()),
+            Some(false) => settings.push("lossy".to_string()),
+            None => {}
+        }
+        if let Some(quality) = self.quality {
+            settings.push(format!("q={}", quality));
+        }
+        if let Some(method) = self.method {
+            settings.push(format!("method={}", method));
+        }
+        if let Some(preset) = self.preset {
+            settings.push(format!("preset={}", preset));
+        }
+        settings.join(",")
+    }
+}
+
+/// Parses `FORMAT SETTINGS`, e.g. `jpg q=78`.
+impl FromStr for FormatOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, settings) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected FORMAT SETTINGS (e.g. 'png lossless'), got '{}'", s))?;
+        Self::parse(format, settings)
+    }
+}
+
+impl fmt::Display for FormatOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.extension(), self.describe())
+    }
+}
+
+crate::transform::serde_as_string!(FormatOverride);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_override() {
+        let png = FormatOverride::parse("PNG", "lossless").unwrap();
+        assert_eq!(png.format, ImageFormat::Png);
+        assert_eq!(png.lossless, Some(true));
+        assert_eq!(png.quality, None);
+
+        let jpeg: FormatOverride = "jpeg q=78, preset=photo".parse().unwrap();
+        assert_eq!(jpeg.format, ImageFormat::Jpeg);
+        assert_eq!((jpeg.quality, jpeg.preset), (Some(78), Some(Preset::Photo)));
+        assert_eq!(jpeg.to_string(), "jpg q=78,preset=photo");
+        assert_eq!(jpeg.to_string().parse::<FormatOverride>().unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_parse_override_errors() {
+        assert!(FormatOverride::parse("psd", "lossless").is_err());
+        assert!(FormatOverride::parse("png", "").is_err());
+        assert!(FormatOverride::parse("png", "q=high").is_err());
+        assert!(FormatOverride::parse("png", "sharp").is_err());
+        assert!("png".parse::<FormatOverride>().is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Serde for paths that may not be valid UTF-8, for `#[serde(with)]`.
+//!
+//! UTF-8 paths are plain JSON strings. Other names, common in old archives,
+//! become `{"bytes": [...]}` on Unix and `{"wide": [...]}` on Windows, so
+//! they round-trip exactly instead of failing to serialize.
+
+use crate::utils;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EncodedPath {
+    Text(String),
+    Bytes { bytes: Vec<u8> },
+    Wide { wide: Vec<u16> },
+}
+
+impl EncodedPath {
+    fn new(path: &Path) -> Self {
+        if let Some(text) = path.to_str() {
+            return EncodedPath::Text(text.to_string());
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+            EncodedPath::Wide { wide: path.as_os_str().encode_wide().collect() }
+        }
+        #[cfg(not(windows))]
+        {
+            EncodedPath::Bytes { bytes: utils::path_to_bytes(path).into_owned() }
+        }
+    }
+
+    fn into_path(self) -> PathBuf {
+        match self {
+            EncodedPath::Text(text) => PathBuf::from(text),
+            EncodedPath::Bytes { bytes } => utils::path_from_bytes(&bytes),
+            #[cfg(windows)]
+            EncodedPath::Wide { wide } => {
+                use std::os::windows::ffi::OsStringExt;
+                PathBuf::from(std::ffi::OsString::from_wide(&wide))
+            }
+            #[cfg(not(windows))]
+            EncodedPath::Wide { wide } => PathBuf::from(String::from_utf16_lossy(&wide)),
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    EncodedPath::new(path).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    EncodedPath::deserialize(deserializer).map(EncodedPath::into_path)
+}
+
+/// The same for optional paths; pair with `#[serde(default)]`.
+pub mod option {
+    use super::EncodedPath;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::PathBuf;
+
+    pub fn serialize<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(EncodedPath::new).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<EncodedPath>::deserialize(deserializer)?.map(EncodedPath::into_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "super")]
+        input: PathBuf,
+        #[serde(default, with = "option", skip_serializing_if = "Option::is_none")]
+        output: Option<PathBuf>,
+    }
+
+    #[test]
+    fn test_path_serde() {
+        let record = Record { input: PathBuf::from("photos/café.jpg"), output: None };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(json, r#"{"input":"photos/café.jpg"}"#);
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+
+        #[cfg(unix)]
+        {
+            let record = Record { input: utils::path_from_bytes(b"caf\xe9.jpg"), output: Some(PathBuf::from("out.webp")) };
+            let json = serde_json::to_string(&record).unwrap();
+            assert_eq!(json, r#"{"input":{"bytes":[99,97,102,233,46,106,112,103]},"output":"out.webp"}"#);
+            assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! Ordered transform pipeline built from `--ops` or from individual flags.
+
+use crate::error::{WebPError, WebPResult};
+use crate::smart_crop;
+use crate::transform::{
+    self, Border, Color, Crop, CropRegion, Dimensions, Flip, PadTarget, ResizeFilter, RoundMask,
+    Rotation, Scale, Upscale,
+};
+use crate::watermark::{Position, Stamp, Watermark};
+use image::DynamicImage;
+use std::path::{Path, PathBuf};
+
+/// A single image operation.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// Apply the EXIF orientation of the source image.
+    AutoRotate,
+    Rotate(Rotation),
+    Flip(Flip),
+    Crop(Crop),
+    SmartCrop(Dimensions),
+    Denoise(u8),
+    /// Fit inside the given box, never enlarging.
+    Resize(Dimensions),
+    Scale(Scale),
+    Upscale(Upscale),
+    Sharpen(f32),
+    Blur(f32),
+    Brightness(i32),
+    Contrast(i32),
+    Saturation(i32),
+    Grayscale,
+    Pad(PadTarget, Color),
+    Border(Border),
+    Round(RoundMask),
+    Watermark(Watermark),
+    Stamp(Stamp),
+    Quantize { colors: u16, dither: bool },
+    /// Composite onto an opaque background, dropping alpha.
+    Flatten(Color),
+}
+
+impl Operation {
+    /// Short name of the operation, as used in `--ops`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::AutoRotate => "autorotate",
+            Operation::Rotate(_) => "rotate",
+            Operation::Flip(_) => "flip",
+            Operation::Crop(Crop::Region(_)) => "crop",
+            Operation::Crop(Crop::Center(_)) => "crop-center",
+            Operation::SmartCrop(_) => "smart-crop",
+            Operation::Denoise(_) => "denoise",
+            Operation::Resize(_) => "resize",
+            Operation::Scale(_) => "scale",
+            Operation::Upscale(_) => "upscale",
+            Operation::Sharpen(_) => "sharpen",
+            Operation::Blur(_) => "blur",
+            Operation::Brightness(_) => "brightness",
+            Operation::Contrast(_) => "contrast",
+            Operation::Saturation(_) => "saturation",
+            Operation::Grayscale => "grayscale",
+            Operation::Pad(..) => "pad",
+            Operation::Border(_) => "border",
+            Operation::Round(_) => "round",
+            Operation::Watermark(_) => "watermark",
+            Operation::Stamp(_) => "stamp",
+            Operation::Quantize { .. } => "quantize",
+            Operation::Flatten(_) => "flatten",
+        }
+    }
+}
+
+/// Settings used to fill in details that `--ops` entries do not spell out.
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub filter: ResizeFilter,
+    pub pad_color: Color,
+    pub watermark_position: Position,
+    pub watermark_opacity: f32,
+    pub background: Color,
+    /// Font for `stamp` entries, which fail to parse without one.
+    pub stamp_font: Option<PathBuf>,
+    pub stamp_size: f32,
+    pub stamp_position: Position,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            filter: ResizeFilter::default(),
+            pad_color: Color::WHITE,
+            watermark_position: Position::default(),
+            watermark_opacity: 1.0,
+            background: Color::WHITE,
+            stamp_font: None,
+            stamp_size: 24.0,
+            stamp_position: Position::BottomLeft,
+        }
+    }
+}
+
+/// Ordered list of operations applied to each image before encoding.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    operations: Vec<Operation>,
+    filter: ResizeFilter,
+}
+
+impl Pipeline {
+    /// Create a pipeline from a list of operations.
+    pub fn new(operations: Vec<Operation>, filter: ResizeFilter) -> Self {
+        Self { operations, filter }
+    }
+
+    /// Parse a comma-separated operation list such as
+    /// `autorotate,resize=1600,sharpen=0.5,watermark=logo.png`.
+    pub fn parse(spec: &str, options: &PipelineOptions) -> WebPResult<Self> {
+        let mut operations = Vec::new();
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, value) = match entry.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (entry, None),
+            };
+            operations.push(parse_operation(name, value, options).map_err(WebPError::InvalidPipeline)?);
+        }
+
+        Ok(Self::new(operations, options.filter))
+    }
+
+    /// Append an operation to the end of the pipeline.
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    /// Check whether the pipeline has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Check whether the pipeline needs the source EXIF orientation.
+    pub fn needs_orientation(&self) -> bool {
+        self.operations.iter().any(|op| matches!(op, Operation::AutoRotate))
+    }
+
+    /// Describe the pipeline as a `--ops`-style list of operation names.
+    pub fn describe(&self) -> String {
+        self.operations.iter().map(Operation::name).collect::<Vec<_>>().join(" → ")
+    }
+
+    /// Apply all operations in order.
+    ///
+    /// `orientation` is the EXIF orientation tag of the source (1-8), used by `autorotate`.
+    pub fn apply(&self, img: DynamicImage, orientation: Option<u32>) -> WebPResult<DynamicImage> {
+        let filter = self.filter.filter_type();
+        let mut img = img;
+
+        for operation in &self.operations {
+            img = match operation {
+                Operation::AutoRotate => transform::orient(img, orientation.unwrap_or(1)),
+                Operation::Rotate(rotation) => transform::rotate(img, *rotation),
+                Operation::Flip(Flip::Horizontal) => img.fliph(),
+                Operation::Flip(Flip::Vertical) => img.flipv(),
+                Operation::Crop(crop) => transform::crop(img, *crop)?,
+                Operation::SmartCrop(target) => smart_crop::smart_crop(&img, *target, filter),
+                Operation::Denoise(strength) => transform::denoise(img, *strength),
+                Operation::Resize(size) => {
+                    if img.width() > size.width || img.height() > size.height {
+                        img.resize(size.width, size.height, filter)
+                    } else {
+                        img
+                    }
+                }
+                Operation::Scale(scale) => {
+                    let (width, height) = scale.apply_to(img.width(), img.height());
+                    if (width, height) != (img.width(), img.height()) {
+                        img.resize_exact(width, height, filter)
+                    } else {
+                        img
+                    }
+                }
+                Operation::Upscale(upscale) => {
+                    let (width, height) = upscale.apply_to(img.width(), img.height());
+                    img.resize_exact(width, height, filter)
+                }
+                Operation::Sharpen(amount) => transform::unsharp_mask(img, *amount),
+                Operation::Blur(sigma) => img.blur(*sigma),
+                Operation::Brightness(value) => img.brighten(value * 255 / 100),
+                Operation::Contrast(value) => img.adjust_contrast(*value as f32),
+                Operation::Saturation(value) => transform::adjust_saturation(img, *value),
+                Operation::Grayscale => img.grayscale(),
+                Operation::Pad(target, color) => transform::pad(img, *target, *color, filter),
+                Operation::Border(border) => transform::add_border(img, *border)?,
+                Operation::Round(mask) => transform::round_corners(img, *mask),
+                Operation::Watermark(watermark) => watermark.apply(img),
+                Operation::Stamp(stamp) => stamp.apply(img),
+                Operation::Quantize { colors, dither } => transform::quantize(img, *colors, *dither),
+                Operation::Flatten(background) => transform::flatten(img, *background),
+            };
+        }
+
+        Ok(img)
+    }
+}
+
+/// Parse a single `name[=value]` entry of an `--ops` list.
+fn parse_operation(name: &str, value: Option<&str>, options: &PipelineOptions) -> Result<Operation, String> {
+    let required = || value.ok_or_else(|| format!("operation '{}' requires a value ({}=...)", name, name));
+    let percent = |v: &str| -> Result<i32, String> {
+        v.parse::<i32>()
+            .ok()
+            .filter(|v| (-100..=100).contains(v))
+            .ok_or_else(|| format!("{}: expected a value from -100 to 100, got '{}'", name, v))
+    };
+
+    let operation = match name.to_lowercase().as_str() {
+        "autorotate" => Operation::AutoRotate,
+        "rotate" => Operation::Rotate(required()?.parse()?),
+        "flip" => Operation::Flip(required()?.parse()?),
+        "crop" => Operation::Crop(Crop::Region(required()?.parse::<CropRegion>()?)),
+        "crop-center" => Operation::Crop(Crop::Center(required()?.parse()?)),
+        "smart-crop" => Operation::SmartCrop(required()?.parse()?),
+        "denoise" => {
+            let value = required()?;
+            let strength = value
+                .parse::<u8>()
+                .ok()
+                .filter(|v| (1..=100).contains(v))
+                .ok_or_else(|| format!("denoise: expected a strength from 1 to 100, got '{}'", value))?;
+            Operation::Denoise(strength)
+        }
+        "resize" => {
+            let value = required()?;
+            let size = match value.parse::<u32>() {
+                Ok(edge) if edge > 0 => Dimensions { width: edge, height: edge },
+                _ => value.parse()?,
+            };
+            Operation::Resize(size)
+        }
+        "scale" => Operation::Scale(required()?.parse()?),
+        "upscale" => Operation::Upscale(required()?.parse()?),
+        "sharpen" => Operation::Sharpen(transform::parse_positive(required()?)?),
+        "blur" => Operation::Blur(transform::parse_positive(required()?)?),
+        "brightness" => Operation::Brightness(percent(required()?)?),
+        "contrast" => Operation::Contrast(percent(required()?)?),
+        "saturation" => Operation::Saturation(percent(required()?)?),
+        "grayscale" => Operation::Grayscale,
+        "flatten" => Operation::Flatten(match value {
+            Some(color) => color.parse()?,
+            None => options.background,
+        }),
+        "pad" => Operation::Pad(required()?.parse()?, options.pad_color),
+        "border" => Operation::Border(required()?.parse()?),
+        "round" => Operation::Round(required()?.parse()?),
+        "watermark" => {
+            let watermark = Watermark::load(
+                Path::new(required()?),
+                options.watermark_position,
+                options.watermark_opacity,
+            )
+            .map_err(|e| e.to_string())?;
+            Operation::Watermark(watermark)
+        }
+        "stamp" => {
+            let text = required()?;
+            let font = options.stamp_font.as_deref().ok_or_else(|| "stamp: requires a stamp font (--stamp-font)".to_string())?;
+            let stamp = Stamp::load(text, font, options.stamp_size, options.stamp_position).map_err(|e| e.to_string())?;
+            Operation::Stamp(stamp)
+        }
+        "quantize" => {
+            let value = required()?;
+            let (colors, dither) = match value.split_once(':') {
+                Some((colors, "dither")) => (colors, true),
+                Some(_) => return Err(format!("quantize: expected COLORS or COLORS:dither, got '{}'", value)),
+                None => (value, false),
+            };
+            let colors = colors
+                .parse::<u16>()
+                .ok()
+                .filter(|c| (2..=256).contains(c))
+                .ok_or_else(|| format!("quantize: expected 2 to 256 colors, got '{}'", colors))?;
+            Operation::Quantize { colors, dither }
+        }
+        _ => return Err(format!("unknown operation '{}'", name)),
+    };
+
+    // Operations without arguments must not be given a value
+    if value.is_some() && matches!(operation, Operation::AutoRotate | Operation::Grayscale) {
+        return Err(format!("operation '{}' does not take a value", name));
+    }
+
+    Ok(operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_parse_pipeline() {
+        let pipeline = Pipeline::parse(
+            "autorotate, resize=1600, sharpen=0.5, grayscale, quantize=64:dither",
+            &PipelineOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(pipeline.describe(), "autorotate → resize → sharpen → grayscale → quantize");
+        assert!(pipeline.needs_orientation());
+        assert!(matches!(
+            pipeline.operations[1],
+            Operation::Resize(Dimensions { width: 1600, height: 1600 })
+        ));
+        assert!(matches!(pipeline.operations[4], Operation::Quantize { colors: 64, dither: true }));
+    }
+
+    #[test]
+    fn test_parse_pipeline_errors() {
+        let options = PipelineOptions::default();
+        assert!(Pipeline::parse("explode", &options).is_err());
+        assert!(Pipeline::parse("resize", &options).is_err());
+        assert!(Pipeline::parse("grayscale=1", &options).is_err());
+        assert!(Pipeline::parse("brightness=200", &options).is_err());
+        assert!(Pipeline::parse("watermark=/nonexistent/logo.png", &options).is_err());
+        assert!(Pipeline::parse("stamp=draft", &options).is_err());
+    }
+
+    /// A font with only the tables a font needs to load and no glyphs.
+    fn empty_font() -> Vec<u8> {
+        let mut head = vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x5F, 0x0F, 0x3C, 0xF5, 0, 0, 0x03, 0xE8];
+        head.resize(54, 0);
+        let mut hhea = vec![0, 1, 0, 0];
+        hhea.resize(36, 0);
+        let maxp = vec![0, 0, 0x50, 0, 0, 1];
+
+        // Offset table, then the table records sorted by tag
+        let mut font = vec![0, 1, 0, 0, 0, 3, 0, 32, 0, 1, 0, 16];
+        let mut offset = 12 + 3 * 16;
+        for (tag, table) in [(b"head", &head), (b"hhea", &hhea), (b"maxp", &maxp)] {
+            font.extend_from_slice(tag);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        font.extend(head.into_iter().chain(hhea).chain(maxp));
+        font
+    }
+
+    #[test]
+    fn test_parse_stamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let font = dir.path().join("font.ttf");
+        std::fs::write(&font, empty_font()).unwrap();
+        let options = PipelineOptions { stamp_font: Some(font), stamp_position: Position::TopRight, ..Default::default() };
+
+        let pipeline = Pipeline::parse("resize=800,stamp=© 2025 ACME", &options).unwrap();
+        assert_eq!(pipeline.describe(), "resize → stamp");
+        assert!(matches!(&pipeline.operations[1], Operation::Stamp(stamp) if stamp.position == Position::TopRight));
+        assert!(Pipeline::parse("stamp", &options).is_err());
+    }
+
+    #[test]
+    fn test_order_matters() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([10, 20, 30])));
+        let options = PipelineOptions::default();
+
+        let crop_then_resize = Pipeline::parse("crop=20x20+0+0,resize=10", &options).unwrap();
+        let result = crop_then_resize.apply(img.clone(), None).unwrap();
+        assert_eq!((result.width(), result.height()), (10, 10));
+
+        let resize_then_crop = Pipeline::parse("resize=10,crop=20x20+0+0", &options).unwrap();
+        let result = resize_then_crop.apply(img, None).unwrap();
+        assert_eq!((result.width(), result.height()), (10, 5));
+    }
+
+    #[test]
+    fn test_autorotate() {
+        let pipeline = Pipeline::parse("autorotate", &PipelineOptions::default()).unwrap();
+        let img = DynamicImage::new_rgb8(40, 20);
+        let rotated = pipeline.apply(img.clone(), Some(6)).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (20, 40));
+        let untouched = pipeline.apply(img, None).unwrap();
+        assert_eq!((untouched.width(), untouched.height()), (40, 20));
+    }
+}
@@ -0,0 +1,50 @@
+//! Custom processing hooks run around the encoder.
+
+use crate::error::WebPResult;
+use image::DynamicImage;
+use std::sync::Arc;
+
+/// Hooks into every conversion, for custom watermarking, validation or
+/// telemetry without forking the pipeline.
+///
+/// Both methods default to doing nothing. Returning an error fails the
+/// conversion of that image.
+pub trait Processor: Send + Sync {
+    /// Called with the fully transformed image just before it is encoded.
+    fn before_encode(&self, _img: &mut DynamicImage) -> WebPResult<()> {
+        Ok(())
+    }
+
+    /// Called with the final WebP bytes (metadata included) before they are written.
+    fn after_encode(&self, _webp: &[u8]) -> WebPResult<()> {
+        Ok(())
+    }
+}
+
+/// Registered processors, run in registration order.
+#[derive(Clone, Default)]
+pub(crate) struct Processors(Vec<Arc<dyn Processor>>);
+
+impl Processors {
+    pub fn push(&mut self, processor: Arc<dyn Processor>) {
+        self.0.push(processor);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn before_encode(&self, img: &mut DynamicImage) -> WebPResult<()> {
+        self.0.iter().try_for_each(|processor| processor.before_encode(img))
+    }
+
+    pub fn after_encode(&self, webp: &[u8]) -> WebPResult<()> {
+        self.0.iter().try_for_each(|processor| processor.after_encode(webp))
+    }
+}
+
+impl std::fmt::Debug for Processors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Processors({})", self.0.len())
+    }
+}
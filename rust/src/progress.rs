@@ -0,0 +1,230 @@
+//! Progress callbacks and cancellation for batch conversions.
+
+use crate::converter::ConversionStats;
+use crate::error::WebPError;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Result of processing one file in a batch.
+#[derive(Debug)]
+pub enum FileOutcome<'a> {
+    /// The file was converted; times are in seconds, sizes in bytes.
+    Converted {
+        time_taken: f64,
+        original_size: u64,
+        compressed_size: u64,
+    },
+    /// The file was left alone (e.g. it is already WebP).
+    Skipped,
+    /// The conversion failed; the batch continues with the next file.
+    Failed(&'a WebPError),
+}
+
+/// Receives progress events while a directory is converted.
+///
+/// All methods have empty default implementations, so implementors only
+/// override the events they care about. `index` is zero-based and `total`
+/// counts every file found, including skipped ones.
+pub trait ProgressHandler {
+    /// Called before a file is converted.
+    fn on_file_start(&self, _index: usize, _total: usize, _path: &Path) {}
+
+    /// Called after a file was converted, skipped or failed.
+    fn on_file_done(&self, _index: usize, _total: usize, _path: &Path, _outcome: &FileOutcome<'_>) {}
+
+    /// Called once after the whole batch, with the final statistics.
+    fn on_batch_done(&self, _stats: &ConversionStats) {}
+}
+
+/// Progress handler that ignores every event.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressHandler for NoProgress {}
+
+/// One line written by [`ProgressWriter`].
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum ProgressLine<'a> {
+    /// A file is about to be converted.
+    File {
+        completed: usize,
+        total: usize,
+        percent: f64,
+        #[serde(serialize_with = "crate::path_serde::serialize")]
+        current: &'a Path,
+        elapsed: f64,
+        /// Seconds left, once a file has finished.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta: Option<f64>,
+    },
+    /// The batch finished or was cancelled.
+    Done {
+        completed: usize,
+        total: usize,
+        percent: f64,
+        elapsed: f64,
+        converted: usize,
+        failed: usize,
+        cancelled: bool,
+    },
+}
+
+struct ProgressState<W> {
+    output: W,
+    started: Option<Instant>,
+    completed: usize,
+    total: usize,
+}
+
+/// Progress handler that writes one JSON line per file started and a final
+/// line when the batch is done, for progress dialogs in GUI wrappers:
+///
+/// ```text
+/// {"event":"file","completed":3,"total":10,"percent":30.0,"current":"photos/cat.jpg","elapsed":1.2,"eta":2.8}
+/// {"event":"done","completed":10,"total":10,"percent":100.0,"elapsed":4.1,"converted":9,"failed":1,"cancelled":false}
+/// ```
+///
+/// Times are in seconds from the first file; the ETA assumes the remaining
+/// files take as long as the finished ones on average. Each line is flushed
+/// as it is written, and write errors are ignored so a wrapper that goes
+/// away does not stop the batch.
+pub struct ProgressWriter<W> {
+    state: Mutex<ProgressState<W>>,
+}
+
+impl<W: Write> ProgressWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self { state: Mutex::new(ProgressState { output, started: None, completed: 0, total: 0 }) }
+    }
+
+    /// The output, e.g. to inspect what was written.
+    pub fn into_inner(self) -> W {
+        self.state.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).output
+    }
+
+    fn state(&self) -> MutexGuard<'_, ProgressState<W>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<W: Write> ProgressState<W> {
+    /// Seconds since the first event.
+    fn elapsed(&mut self) -> f64 {
+        round(self.started.get_or_insert_with(Instant::now).elapsed().as_secs_f64())
+    }
+
+    fn write(&mut self, line: &ProgressLine<'_>) {
+        let Ok(json) = serde_json::to_string(line) else { return };
+        let _ = writeln!(self.output, "{}", json).and_then(|_| self.output.flush());
+    }
+}
+
+/// Seconds to the millisecond, keeping lines short.
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// Share of `total` done, to a tenth of a percent.
+fn percent(completed: usize, total: usize) -> f64 {
+    (completed as f64 / total.max(1) as f64 * 1000.0).round() / 10.0
+}
+
+impl<W: Write> ProgressHandler for ProgressWriter<W> {
+    fn on_file_start(&self, _index: usize, total: usize, path: &Path) {
+        let mut state = self.state();
+        let (elapsed, completed) = (state.elapsed(), state.completed);
+        state.total = total;
+        let eta = (completed > 0).then(|| round(elapsed / completed as f64 * total.saturating_sub(completed) as f64));
+        state.write(&ProgressLine::File { completed, total, percent: percent(completed, total), current: path, elapsed, eta });
+    }
+
+    fn on_file_done(&self, _index: usize, total: usize, _path: &Path, _outcome: &FileOutcome<'_>) {
+        let mut state = self.state();
+        state.completed += 1;
+        state.total = total;
+    }
+
+    fn on_batch_done(&self, stats: &ConversionStats) {
+        let mut state = self.state();
+        let elapsed = state.elapsed();
+        let completed = if stats.cancelled { state.completed } else { state.total };
+        let total = state.total;
+        state.write(&ProgressLine::Done {
+            completed,
+            total,
+            percent: percent(completed, total),
+            elapsed,
+            converted: stats.success_count,
+            failed: stats.failed_count,
+            cancelled: stats.cancelled,
+        });
+    }
+}
+
+/// Shared flag that asks a running batch to stop.
+///
+/// Clones share the same flag, so one can be handed to the converter and
+/// another kept by e.g. a cancel button. The file being converted when the
+/// flag is set still finishes; no new files are started.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// The shared flag, for setting it from a signal handler.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_writer() {
+        let writer = ProgressWriter::new(Vec::new());
+        writer.on_file_start(0, 2, Path::new("a.png"));
+        writer.on_file_done(0, 2, Path::new("a.png"), &FileOutcome::Skipped);
+        writer.on_file_start(1, 2, Path::new("b.png"));
+        writer.on_file_done(1, 2, Path::new("b.png"), &FileOutcome::Failed(&WebPError::NoImagesFound));
+        let mut stats = ConversionStats::new();
+        stats.add_failure("b.png".into(), WebPError::NoImagesFound);
+        writer.on_batch_done(&stats);
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!((&lines[0]["event"], &lines[0]["current"], &lines[0]["percent"]), (&"file".into(), &"a.png".into(), &0.0.into()));
+        assert!(lines[0].get("eta").is_none());
+        assert_eq!((&lines[1]["completed"], &lines[1]["percent"]), (&1.into(), &50.0.into()));
+        assert!(lines[1]["eta"].is_number());
+        assert_eq!(lines[2]["event"], "done");
+        assert_eq!((&lines[2]["completed"], &lines[2]["percent"], &lines[2]["failed"]), (&2.into(), &100.0.into(), &1.into()));
+    }
+}
@@ -0,0 +1,239 @@
+//! Prometheus metrics for watch and server mode: conversion counts, bytes
+//! saved, latencies and the watch backlog, served as text on `/metrics`.
+
+use webp_converter::error::{WebPError, WebPResult};
+use webp_converter::report::{Event, Reporter};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tiny_http::{Header, Response, Server};
+
+/// Upper bounds in seconds of the latency histogram buckets (the Prometheus
+/// client defaults).
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Latency histogram with [`BUCKETS`].
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last entry is `+Inf`.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    conversions: u64,
+    failures: u64,
+    skipped: u64,
+    retries: u64,
+    salvaged: u64,
+    redirected: u64,
+    original_bytes: u64,
+    compressed_bytes: u64,
+    bytes_saved: u64,
+    conversion_duration: Histogram,
+    requests: BTreeMap<u16, u64>,
+    request_duration: Histogram,
+    pending: usize,
+}
+
+/// Counters collected from conversion events; feed it through a
+/// [`MetricsReporter`] and publish it with [`Metrics::serve`].
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // Counters stay usable even if a reporting thread panicked
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Update the counters for one event.
+    pub fn record(&self, event: &Event<'_>) {
+        let mut state = self.state();
+        match event {
+            Event::FileConverted { original_size, compressed_size, time_taken, .. } => {
+                state.conversions += 1;
+                state.original_bytes += original_size;
+                state.compressed_bytes += compressed_size;
+                state.bytes_saved += original_size.saturating_sub(*compressed_size);
+                state.conversion_duration.observe(*time_taken);
+            }
+            Event::FileFailed { .. } => state.failures += 1,
+            Event::FileSkipped { .. } => state.skipped += 1,
+            Event::Retrying { .. } => state.retries += 1,
+            Event::Salvaged { .. } => state.salvaged += 1,
+            Event::OutputRedirected { .. } => state.redirected += 1,
+            Event::RequestHandled { status, time_taken, .. } => {
+                *state.requests.entry(*status).or_default() += 1;
+                state.request_duration.observe(*time_taken);
+            }
+            Event::Backlog { pending } => state.pending = *pending,
+            _ => {}
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state();
+        let mut text = String::new();
+        let mut single = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(text, "# HELP webp_converter_{} {}", name, help);
+            let _ = writeln!(text, "# TYPE webp_converter_{} {}", name, kind);
+            let _ = writeln!(text, "webp_converter_{} {}", name, value);
+        };
+        single("conversions_total", "counter", "Images converted.", state.conversions);
+        single("failures_total", "counter", "Images that failed to convert.", state.failures);
+        single("skipped_total", "counter", "Images left alone, e.g. when the WebP was not smaller.", state.skipped);
+        single("retries_total", "counter", "Conversions and transfers retried after a transient failure.", state.retries);
+        single("salvaged_total", "counter", "Damaged images decoded tolerantly.", state.salvaged);
+        single("redirected_total", "counter", "Outputs written to the fallback folder.", state.redirected);
+        single("original_bytes_total", "counter", "Size of the converted sources.", state.original_bytes);
+        single("compressed_bytes_total", "counter", "Size of the WebP outputs.", state.compressed_bytes);
+        single("bytes_saved_total", "counter", "Bytes saved by converting.", state.bytes_saved);
+        single("pending_files", "gauge", "Changed files waiting to be converted in watch mode.", state.pending as u64);
+
+        write_histogram(
+            &mut text,
+            "conversion_duration_seconds",
+            "Time spent converting one image.",
+            &state.conversion_duration,
+        );
+
+        let _ = writeln!(text, "# HELP webp_converter_requests_total HTTP requests answered, by status.");
+        let _ = writeln!(text, "# TYPE webp_converter_requests_total counter");
+        for (status, count) in &state.requests {
+            let _ = writeln!(text, "webp_converter_requests_total{{status=\"{}\"}} {}", status, count);
+        }
+        write_histogram(
+            &mut text,
+            "request_duration_seconds",
+            "Time spent answering one HTTP request.",
+            &state.request_duration,
+        );
+        text
+    }
+
+    /// Answer `GET /metrics` on `listen` (e.g. `0.0.0.0:9090`) from a
+    /// background thread for the rest of the process. Returns the bound
+    /// address, which tells the port when `listen` asked for port 0.
+    pub fn serve(self: &Arc<Self>, listen: &str) -> WebPResult<SocketAddr> {
+        let server = Server::http(listen).map_err(|e| WebPError::ServerError(format!("cannot listen on {}: {}", listen, e)))?;
+        let address = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| WebPError::ServerError(format!("{} is not an IP address", listen)))?;
+
+        let metrics = Arc::clone(self);
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let path = request.url().split('?').next().unwrap_or_default();
+                let response = if path != "/metrics" {
+                    Response::from_string("not found\n").with_status_code(404)
+                } else if request.method() != &tiny_http::Method::Get {
+                    Response::from_string("method not allowed\n").with_status_code(405)
+                } else {
+                    let content_type = Header::from_bytes(&b"Content-Type"[..], CONTENT_TYPE.as_bytes())
+                        .expect("content types are valid header values");
+                    Response::from_string(metrics.render()).with_header(content_type)
+                };
+                // A scraper that went away only loses this scrape
+                let _ = request.respond(response);
+            }
+        });
+        Ok(address)
+    }
+}
+
+fn write_histogram(text: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(text, "# HELP webp_converter_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE webp_converter_{} histogram", name);
+    let mut cumulative = 0;
+    for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+        cumulative += count;
+        let _ = writeln!(text, "webp_converter_{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+    }
+    cumulative += histogram.counts[BUCKETS.len()];
+    let _ = writeln!(text, "webp_converter_{}_bucket{{le=\"+Inf\"}} {}", name, cumulative);
+    let _ = writeln!(text, "webp_converter_{}_sum {}", name, histogram.sum);
+    let _ = writeln!(text, "webp_converter_{}_count {}", name, cumulative);
+}
+
+/// Reporter that records every event in [`Metrics`] before passing it on.
+pub struct MetricsReporter<R> {
+    inner: R,
+    metrics: Arc<Metrics>,
+}
+
+impl<R: Reporter> MetricsReporter<R> {
+    pub fn new(inner: R, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl<R: Reporter> Reporter for MetricsReporter<R> {
+    fn report(&self, event: &Event<'_>) {
+        self.metrics.record(event);
+        self.inner.report(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webp_converter::report::SilentReporter;
+    use std::path::Path;
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        let reporter = MetricsReporter::new(SilentReporter, Arc::clone(&metrics));
+        let (input, output) = (Path::new("a.png"), Path::new("a.webp"));
+        for time_taken in [0.02, 3.0] {
+            reporter.report(&Event::FileConverted {
+                input,
+                output,
+                original_size: 1000,
+                compressed_size: 400,
+                time_taken,
+                metrics: None,
+                content: None,
+            });
+        }
+        reporter.report(&Event::RequestHandled { method: "GET", url: "/convert", status: 404, time_taken: 0.001 });
+        reporter.report(&Event::Backlog { pending: 7 });
+
+        let address = metrics.serve("127.0.0.1:0").unwrap();
+        let text = ureq::get(&format!("http://{}/metrics", address)).call().unwrap().body_mut().read_to_string().unwrap();
+        for line in [
+            "webp_converter_conversions_total 2",
+            "webp_converter_bytes_saved_total 1200",
+            "webp_converter_pending_files 7",
+            "webp_converter_conversion_duration_seconds_bucket{le=\"0.025\"} 1",
+            "webp_converter_conversion_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "webp_converter_requests_total{status=\"404\"} 1",
+            "webp_converter_request_duration_seconds_count 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing '{}' in:\n{}", line, text);
+        }
+        assert!(ureq::get(&format!("http://{}/other", address)).call().is_err());
+    }
+}
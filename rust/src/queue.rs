@@ -0,0 +1,294 @@
+//! Message-queue worker mode: take jobs from Redis or RabbitMQ (AMQP) and
+//! publish results back, so any number of worker processes can share one
+//! queue.
+//!
+//! Jobs and results use the JSON format of [`crate::jobs`]. A job is only
+//! acknowledged once its result is published; a worker that dies mid-job
+//! leaves it to be redelivered (AMQP) or in the `<queue>:processing` list
+//! (Redis).
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use std::time::Duration;
+
+/// How long a worker waits for a job before checking for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Queue names used by a worker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueNames {
+    /// Redis list or AMQP queue jobs are taken from.
+    pub jobs: String,
+    /// Redis list or AMQP queue results are published to.
+    pub results: String,
+}
+
+impl Default for QueueNames {
+    fn default() -> Self {
+        Self { jobs: "webp-jobs".to_string(), results: "webp-results".to_string() }
+    }
+}
+
+/// A job taken from a queue but not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// The job line as sent by the producer.
+    pub payload: Vec<u8>,
+    /// Broker-specific handle used to acknowledge the job.
+    pub tag: u64,
+}
+
+/// One connection to a message broker.
+pub trait JobQueue: Send {
+    /// The next job, or `None` when none arrived within `timeout`.
+    fn receive(&mut self, timeout: Duration) -> WebPResult<Option<Delivery>>;
+
+    /// Publish the result line of a job, then acknowledge the job.
+    fn complete(&mut self, delivery: Delivery, result: &str) -> WebPResult<()>;
+}
+
+/// Connect to the broker at `url`: `redis://` (and `rediss://`) with the
+/// `redis` feature, `amqp://` (and `amqps://`) with the `amqp` feature.
+pub fn connect(url: &str, names: &QueueNames) -> WebPResult<Box<dyn JobQueue>> {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    match scheme {
+        #[cfg(feature = "redis")]
+        "redis" | "rediss" => Ok(Box::new(RedisQueue::connect(url, names)?)),
+        #[cfg(not(feature = "redis"))]
+        "redis" | "rediss" => Err(WebPError::QueueError(
+            "redis:// queues need a build with the `redis` feature".to_string(),
+        )),
+        #[cfg(feature = "amqp")]
+        "amqp" | "amqps" => Ok(Box::new(AmqpQueue::connect(url, names)?)),
+        #[cfg(not(feature = "amqp"))]
+        "amqp" | "amqps" => Err(WebPError::QueueError(
+            "amqp:// queues need a build with the `amqp` feature".to_string(),
+        )),
+        _ => Err(WebPError::QueueError(format!("expected a redis:// or amqp:// URL, got '{}'", url))),
+    }
+}
+
+impl WebPConverter {
+    /// Convert jobs from each queue connection on its own thread until the
+    /// converter's cancellation token is triggered, so the number of
+    /// connections is the number of jobs converted at once.
+    ///
+    /// Failed jobs produce `failed` results and do not stop the worker; a
+    /// broker error does. Returns the number of jobs completed.
+    pub fn run_worker(&self, queues: Vec<Box<dyn JobQueue>>) -> WebPResult<usize> {
+        self.report(Event::Ready);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = queues.into_iter().map(|queue| scope.spawn(|| self.work(queue))).collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| Err(WebPError::QueueError("worker panicked".to_string()))))
+                .sum()
+        })
+    }
+
+    fn work(&self, mut queue: Box<dyn JobQueue>) -> WebPResult<usize> {
+        let mut count = 0;
+        while !self.is_cancelled() {
+            let Some(delivery) = queue.receive(POLL_INTERVAL)? else {
+                continue;
+            };
+            let result = self.run_job_line(&String::from_utf8_lossy(&delivery.payload)).to_json()?;
+            queue.complete(delivery, &result)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Redis lists: producers `LPUSH` jobs, workers move them to
+/// `<jobs>:processing` while converting and `LPUSH` results.
+#[cfg(feature = "redis")]
+pub struct RedisQueue {
+    connection: redis::Connection,
+    jobs: String,
+    processing: String,
+    results: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisQueue {
+    pub fn connect(url: &str, names: &QueueNames) -> WebPResult<Self> {
+        let connection = redis::Client::open(url).and_then(|client| client.get_connection()).map_err(redis_error)?;
+        Ok(Self {
+            connection,
+            jobs: names.jobs.clone(),
+            processing: format!("{}:processing", names.jobs),
+            results: names.results.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl JobQueue for RedisQueue {
+    fn receive(&mut self, timeout: Duration) -> WebPResult<Option<Delivery>> {
+        // Oldest job first; needs Redis 6.2
+        let payload: Option<Vec<u8>> = redis::cmd("BLMOVE")
+            .arg(&self.jobs)
+            .arg(&self.processing)
+            .arg("RIGHT")
+            .arg("LEFT")
+            .arg(timeout.as_secs_f64())
+            .query(&mut self.connection)
+            .map_err(redis_error)?;
+        Ok(payload.map(|payload| Delivery { payload, tag: 0 }))
+    }
+
+    fn complete(&mut self, delivery: Delivery, result: &str) -> WebPResult<()> {
+        redis::pipe()
+            .atomic()
+            .lpush(&self.results, result)
+            .ignore()
+            .lrem(&self.processing, 1, delivery.payload)
+            .ignore()
+            .query::<()>(&mut self.connection)
+            .map_err(redis_error)
+    }
+}
+
+#[cfg(feature = "redis")]
+fn redis_error(error: redis::RedisError) -> WebPError {
+    WebPError::QueueError(error.to_string())
+}
+
+/// AMQP 0.9.1 (RabbitMQ) durable queues; each connection holds at most one
+/// unacknowledged job, so jobs spread evenly over workers.
+#[cfg(feature = "amqp")]
+pub struct AmqpQueue {
+    runtime: tokio::runtime::Runtime,
+    // Kept open for the lifetime of the channel
+    _connection: lapin::Connection,
+    channel: lapin::Channel,
+    consumer: lapin::Consumer,
+    results: String,
+}
+
+#[cfg(feature = "amqp")]
+impl AmqpQueue {
+    pub fn connect(url: &str, names: &QueueNames) -> WebPResult<Self> {
+        use lapin::options::{BasicConsumeOptions, BasicQosOptions, QueueDeclareOptions};
+        use lapin::types::FieldTable;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build()?;
+        let (connection, channel, consumer) = runtime
+            .block_on(async {
+                let connection = lapin::Connection::connect(url, lapin::ConnectionProperties::default()).await?;
+                let channel = connection.create_channel().await?;
+                channel.basic_qos(1, BasicQosOptions::default()).await?;
+                let durable = QueueDeclareOptions { durable: true, ..Default::default() };
+                for queue in [&names.jobs, &names.results] {
+                    channel.queue_declare(queue, durable, FieldTable::default()).await?;
+                }
+                let consumer = channel
+                    .basic_consume(&names.jobs, "", BasicConsumeOptions::default(), FieldTable::default())
+                    .await?;
+                Ok::<_, lapin::Error>((connection, channel, consumer))
+            })
+            .map_err(amqp_error)?;
+        Ok(Self { runtime, _connection: connection, channel, consumer, results: names.results.clone() })
+    }
+}
+
+#[cfg(feature = "amqp")]
+impl JobQueue for AmqpQueue {
+    fn receive(&mut self, timeout: Duration) -> WebPResult<Option<Delivery>> {
+        use futures::StreamExt;
+
+        let consumer = &mut self.consumer;
+        match self.runtime.block_on(async { tokio::time::timeout(timeout, consumer.next()).await }) {
+            Err(_) => Ok(None),
+            Ok(None) => Err(WebPError::QueueError("the broker cancelled the consumer".to_string())),
+            Ok(Some(delivery)) => {
+                let delivery = delivery.map_err(amqp_error)?;
+                Ok(Some(Delivery { payload: delivery.data, tag: delivery.delivery_tag }))
+            }
+        }
+    }
+
+    fn complete(&mut self, delivery: Delivery, result: &str) -> WebPResult<()> {
+        use lapin::options::{BasicAckOptions, BasicPublishOptions};
+        use lapin::BasicProperties;
+
+        let channel = &self.channel;
+        self.runtime
+            .block_on(async {
+                // Persistent, like the queue itself
+                let properties = BasicProperties::default().with_delivery_mode(2);
+                channel
+                    .basic_publish("", &self.results, BasicPublishOptions::default(), result.as_bytes(), properties)
+                    .await?
+                    .await?;
+                channel.basic_ack(delivery.tag, BasicAckOptions::default()).await
+            })
+            .map_err(amqp_error)
+    }
+}
+
+#[cfg(feature = "amqp")]
+fn amqp_error(error: lapin::Error) -> WebPError {
+    WebPError::QueueError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::CancellationToken;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory queue that cancels the worker once it is drained.
+    struct MemoryQueue {
+        jobs: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        results: Arc<Mutex<Vec<String>>>,
+        token: CancellationToken,
+    }
+
+    impl JobQueue for MemoryQueue {
+        fn receive(&mut self, _timeout: Duration) -> WebPResult<Option<Delivery>> {
+            let payload = self.jobs.lock().unwrap().pop_front();
+            if payload.is_none() {
+                self.token.cancel();
+            }
+            Ok(payload.map(|payload| Delivery { payload, tag: 0 }))
+        }
+
+        fn complete(&mut self, _delivery: Delivery, result: &str) -> WebPResult<()> {
+            self.results.lock().unwrap().push(result.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_worker() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut jobs = VecDeque::new();
+        for index in 0..4 {
+            let input = dir.path().join(format!("{}.png", index));
+            image::RgbImage::from_pixel(16, 16, image::Rgb([index * 60, 80, 160])).save(&input).unwrap();
+            jobs.push_back(serde_json::json!({ "id": index.to_string(), "input": input }).to_string().into_bytes());
+        }
+        jobs.push_back(b"not json".to_vec());
+        let (jobs, results) = (Arc::new(Mutex::new(jobs)), Arc::new(Mutex::new(Vec::new())));
+
+        let token = CancellationToken::new();
+        let converter = WebPConverter::builder().cancellation(token.clone()).build().unwrap();
+        let queues = (0..2)
+            .map(|_| {
+                let queue = MemoryQueue { jobs: jobs.clone(), results: results.clone(), token: token.clone() };
+                Box::new(queue) as Box<dyn JobQueue>
+            })
+            .collect();
+        assert_eq!(converter.run_worker(queues).unwrap(), 5);
+
+        let results = results.lock().unwrap();
+        assert_eq!(results.iter().filter(|result| result.contains("\"status\":\"converted\"")).count(), 4);
+        assert!(results.iter().any(|result| result.contains("invalid job")));
+        assert!(dir.path().join("3.webp").exists());
+        assert!(connect("kafka://localhost", &QueueNames::default()).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Reporting of conversion events, so the library never prints on its own.
+
+use crate::analysis::ContentKind;
+use crate::artifacts::Artifact;
+use crate::error::WebPError;
+use crate::metrics::{LowQualityPolicy, QualityMetrics};
+use image::ImageFormat;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Something worth telling the user about while converting.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A directory batch is about to convert `total` files.
+    BatchStarted { total: usize },
+    /// A file was converted; `output` may be a tile directory.
+    FileConverted {
+        input: &'a Path,
+        output: &'a Path,
+        original_size: u64,
+        compressed_size: u64,
+        time_taken: f64,
+        /// PSNR and SSIM of the output, when metrics are enabled.
+        metrics: Option<QualityMetrics>,
+        /// Detected content, when the mode or preset is chosen automatically.
+        content: Option<ContentKind>,
+    },
+    /// A lossy output is likely to show visible artifacts.
+    ArtifactsFound { path: &'a Path, artifacts: &'a [Artifact] },
+    /// A lossy output fell below the minimum SSIM; `action` tells whether the
+    /// source was kept or the image re-encoded lossless.
+    LowQuality {
+        path: &'a Path,
+        ssim: f64,
+        min_ssim: f64,
+        action: LowQualityPolicy,
+    },
+    /// A file was left alone (e.g. it is already WebP).
+    FileSkipped { path: &'a Path, reason: &'a str },
+    /// A file looks like an earlier image of the batch: its perceptual hash
+    /// differs from `original`'s by `distance` bits.
+    SimilarFound {
+        path: &'a Path,
+        original: &'a Path,
+        distance: u32,
+        /// Whether the file was skipped rather than converted anyway.
+        skipped: bool,
+    },
+    /// A file in a batch failed; the batch continues.
+    FileFailed { path: &'a Path, error: &'a WebPError },
+    /// Work on a file or remote object failed with a transient error and is
+    /// retried after `delay`; `attempt` counts from 1 up to `retries`.
+    Retrying {
+        path: &'a Path,
+        attempt: u32,
+        retries: u32,
+        delay: Duration,
+        error: &'a WebPError,
+    },
+    /// A file's extension names another format than its content, e.g. a PNG
+    /// named `.jpg`; it is converted as `format`.
+    FormatMismatch { path: &'a Path, format: ImageFormat },
+    /// An output could not be written to `output`, e.g. on a read-only or
+    /// full volume, and went to `fallback` in the fallback folder instead.
+    OutputRedirected { path: &'a Path, output: &'a Path, fallback: &'a Path, error: &'a WebPError },
+    /// The default `output` of a file clashed with `other`, another input of
+    /// the batch or the file writing that output, so it goes to `renamed`.
+    OutputRenamed { path: &'a Path, output: &'a Path, renamed: &'a Path, other: &'a Path },
+    /// A damaged file was decoded tolerantly with `--salvage`; `error` is why
+    /// the regular decode failed.
+    Salvaged { path: &'a Path, error: &'a WebPError },
+    /// A batch stopped early after `completed` of `total` files.
+    Cancelled { completed: usize, total: usize },
+    /// Sync mode removed a WebP file whose source no longer exists.
+    OrphanDeleted { path: &'a Path },
+    /// A remote image was downloaded for conversion.
+    Downloaded { url: &'a str, size: u64 },
+    /// A WebP file was uploaded to a remote folder.
+    Uploaded { url: &'a str, size: u64 },
+    /// Watch or server mode is set up and waiting for work.
+    Ready,
+    /// The HTTP server answered a request with `status` after `time_taken`
+    /// seconds.
+    RequestHandled {
+        method: &'a str,
+        url: &'a str,
+        status: u16,
+        time_taken: f64,
+    },
+    /// The number of changed files watch mode has yet to convert changed.
+    Backlog { pending: usize },
+    /// A sprite sheet or contact sheet is being assembled from `count` images.
+    SheetStarted { kind: SheetKind, count: usize },
+    /// A sprite sheet or contact sheet was written.
+    SheetWritten {
+        kind: SheetKind,
+        output: &'a Path,
+        width: u32,
+        height: u32,
+        compressed_size: u64,
+    },
+}
+
+/// Kind of combined output image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetKind {
+    SpriteSheet,
+    ContactSheet,
+}
+
+/// Receives conversion events; implement it to route output to a console,
+/// log or UI.
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event<'_>);
+}
+
+impl<R: Reporter + ?Sized> Reporter for Box<R> {
+    fn report(&self, event: &Event<'_>) {
+        (**self).report(event);
+    }
+}
+
+/// Reporter that discards every event (the library default).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn report(&self, _event: &Event<'_>) {}
+}
+
+/// Shared reporter handle stored in the converter.
+#[derive(Clone)]
+pub(crate) struct SharedReporter(pub(crate) Arc<dyn Reporter>);
+
+impl Default for SharedReporter {
+    fn default() -> Self {
+        Self(Arc::new(SilentReporter))
+    }
+}
+
+impl std::fmt::Debug for SharedReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Reporter")
+    }
+}
@@ -0,0 +1,141 @@
+//! Retries of transient failures, such as a file locked by another process,
+//! a network share dropping a read or a server answering 503, with
+//! exponential backoff.
+
+use crate::converter::WebPConverter;
+use crate::error::WebPResult;
+use crate::report::Event;
+use std::path::Path;
+use std::time::Duration;
+
+/// Most retries a converter accepts; the last one waits over four minutes.
+pub const MAX_RETRIES: u32 = 10;
+
+/// Wait before the first retry; each further retry waits twice as long.
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+
+/// Wait before retry `attempt`, counting from 1.
+fn backoff(attempt: u32) -> Duration {
+    FIRST_DELAY * 2u32.pow(attempt.clamp(1, MAX_RETRIES) - 1)
+}
+
+impl WebPConverter {
+    /// Run `operation` on `path`, repeating it after a growing delay while it
+    /// fails with a transient error, up to the converter's retries. Each
+    /// retry is reported; cancellation stops retrying.
+    pub(crate) fn retrying<T>(&self, path: &Path, mut operation: impl FnMut() -> WebPResult<T>) -> WebPResult<T> {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(error) if error.is_transient() && attempt < self.retries() && !self.is_cancelled() => {
+                    attempt += 1;
+                    let delay = backoff(attempt);
+                    self.report(Event::Retrying { path, attempt, retries: self.retries(), delay, error: &error });
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// [`retrying`](Self::retrying) for async operations, waiting without
+    /// blocking the runtime.
+    #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+    pub(crate) async fn retrying_async<T, F>(&self, path: &Path, mut operation: impl FnMut() -> F) -> WebPResult<T>
+    where
+        F: std::future::Future<Output = WebPResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(error) if error.is_transient() && attempt < self.retries() && !self.is_cancelled() => {
+                    attempt += 1;
+                    let delay = backoff(attempt);
+                    self.report(Event::Retrying { path, attempt, retries: self.retries(), delay, error: &error });
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WebPError;
+    use crate::report::Reporter;
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    struct CountRetries(Arc<AtomicU32>);
+
+    impl Reporter for CountRetries {
+        fn report(&self, event: &Event<'_>) {
+            if let Event::Retrying { .. } = event {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_retrying() {
+        let reported = Arc::new(AtomicU32::new(0));
+        let converter = WebPConverter::builder().retries(2).reporter(CountRetries(Arc::clone(&reported))).build().unwrap();
+        let busy = || WebPError::IoError(io::Error::from(io::ErrorKind::ResourceBusy));
+
+        // Succeeds on the second attempt
+        let mut calls = 0;
+        let result = converter.retrying(Path::new("a.png"), || {
+            calls += 1;
+            if calls < 2 { Err(busy()) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(reported.load(Ordering::Relaxed), 1);
+
+        // Gives up after the retries
+        calls = 0;
+        assert!(converter.retrying(Path::new("b.png"), || -> WebPResult<()> { calls += 1; Err(busy()) }).is_err());
+        assert_eq!(calls, 3);
+
+        // Permanent errors are not retried
+        calls = 0;
+        let result = converter.retrying(Path::new("c.png"), || -> WebPResult<()> {
+            calls += 1;
+            Err(WebPError::InvalidImage("c.png".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(WebPError::IoError(io::Error::from(io::ErrorKind::TimedOut)).is_transient());
+        assert!(WebPError::ServerUnavailable("503".to_string()).is_transient());
+        assert!(!WebPError::IoError(io::Error::from(io::ErrorKind::NotFound)).is_transient());
+        assert!(!WebPError::IoError(io::Error::from(io::ErrorKind::UnexpectedEof)).is_transient());
+        assert!(!WebPError::TransferError("authentication failed".to_string()).is_transient());
+        assert_eq!(backoff(1), Duration::from_millis(500));
+        assert_eq!(backoff(3), Duration::from_secs(2));
+    }
+
+    #[test]
+    #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+    fn test_storage_errors_are_transient() {
+        use object_store::client::{HttpError, HttpErrorKind};
+
+        let generic = |source: Box<dyn std::error::Error + Send + Sync>| WebPError::StorageError(object_store::Error::Generic { store: "S3", source });
+        let status = |message: &str| generic(message.into());
+        assert!(generic(Box::new(HttpError::new(HttpErrorKind::Timeout, io::Error::from(io::ErrorKind::TimedOut)))).is_transient());
+        assert!(generic(Box::new(HttpError::new(HttpErrorKind::Connect, io::Error::from(io::ErrorKind::ConnectionRefused)))).is_transient());
+        assert!(!generic(Box::new(HttpError::new(HttpErrorKind::Decode, io::Error::from(io::ErrorKind::InvalidData)))).is_transient());
+        assert!(status("Error performing PUT in 2s - Server returned non-2xx status code: 503 Service Unavailable: ").is_transient());
+        assert!(status("Server returned non-2xx status code: 429 Too Many Requests: SlowDown").is_transient());
+        assert!(status("Server returned error response: <Error><Code>InternalError</Code></Error>").is_transient());
+        assert!(!status("Server returned non-2xx status code: 400 Bad Request: InvalidBucketName").is_transient());
+        assert!(!status("Missing bucket name").is_transient());
+        let missing = object_store::Error::NotFound { path: "a.png".to_string(), source: "404".into() };
+        assert!(!WebPError::StorageError(missing).is_transient());
+    }
+}
@@ -0,0 +1,135 @@
+//! Tolerant decoding of damaged images, so a truncated JPEG or PNG keeps the
+//! region decoded before the damage instead of failing the whole file.
+
+use crate::color;
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use image::codecs::png::PngDecoder;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat, ImageResult};
+use std::io::Cursor;
+use std::path::Path;
+
+/// JPEG end-of-image marker.
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+
+impl WebPConverter {
+    /// Load an image file with its ICC profile. When salvaging is on, a file
+    /// that fails to decode is retried tolerantly and reported as salvaged;
+    /// the original error is kept if nothing can be recovered.
+    pub(crate) fn load_image(&self, path: &Path) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+        if let Some(limits) = self.sandbox_limits() {
+            return self.load_sandboxed(path, limits);
+        }
+        match color::load_image(path) {
+            Err(error @ WebPError::ImageProcessingError(_)) if self.salvages() => match salvage(&std::fs::read(path)?) {
+                Some(loaded) => {
+                    self.report(Event::Salvaged { path, error: &error });
+                    Ok(loaded)
+                }
+                None => Err(error),
+            },
+            result => result,
+        }
+    }
+}
+
+/// Decode what is left of a damaged JPEG or PNG, or `None` for other formats
+/// and files damaged before their image data.
+pub(crate) fn salvage(bytes: &[u8]) -> Option<(DynamicImage, Option<Vec<u8>>)> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Jpeg => salvage_jpeg(bytes),
+        ImageFormat::Png => salvage_png(bytes),
+        _ => None,
+    }
+}
+
+/// The JPEG decoder reads zero bits once it reaches a marker, so closing a
+/// truncated file with an end-of-image marker decodes the missing blocks
+/// from them instead of failing at the end of the data.
+fn salvage_jpeg(bytes: &[u8]) -> Option<(DynamicImage, Option<Vec<u8>>)> {
+    // Drop a marker cut off after its 0xFF
+    let end = bytes.iter().rposition(|&b| b != 0xFF)? + 1;
+    if bytes[..end].ends_with(&JPEG_EOI) {
+        // Complete, so damaged in a way padding cannot fix
+        return None;
+    }
+    let mut closed = bytes[..end].to_vec();
+    closed.extend_from_slice(&JPEG_EOI);
+    color::load_image_from_memory(&closed, Some(ImageFormat::Jpeg)).ok()
+}
+
+fn salvage_png(bytes: &[u8]) -> Option<(DynamicImage, Option<Vec<u8>>)> {
+    let mut decoder = PngDecoder::new(Cursor::new(bytes)).ok()?;
+    let icc = decoder.icc_profile();
+    let img = DynamicImage::from_decoder(PartialPng(decoder)).ok()?;
+    Some((img, icc))
+}
+
+/// PNG decoder that keeps the rows written before a decoding error. Rows
+/// after the damage stay zeroed, i.e. black, or transparent with alpha.
+struct PartialPng<'a>(PngDecoder<Cursor<&'a [u8]>>);
+
+impl<'a> ImageDecoder<'a> for PartialPng<'a> {
+    type Reader = <PngDecoder<Cursor<&'a [u8]>> as ImageDecoder<'a>>::Reader;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.0.color_type()
+    }
+
+    // Required by the trait, though deprecated
+    #[allow(deprecated)]
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        self.0.into_reader()
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        let color_type = self.0.color_type();
+        if self.0.read_image(buf).is_err() && color_type.bytes_per_pixel() / color_type.channel_count() == 2 {
+            // PNG samples are big endian; the decoder only swaps them to
+            // native order after a complete read
+            for sample in buf.chunks_exact_mut(2) {
+                let value = u16::from_be_bytes([sample[0], sample[1]]);
+                sample.copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn encoded(img: &RgbImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        img.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_salvage_truncated() {
+        let img = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+
+        for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+            let bytes = encoded(&img, format);
+            let truncated = &bytes[..bytes.len() * 2 / 3];
+            assert!(color::load_image_from_memory(truncated, None).is_err(), "{:?}", format);
+
+            let (salvaged, _) = salvage(truncated).unwrap_or_else(|| panic!("{:?} not salvaged", format));
+            assert_eq!((salvaged.width(), salvaged.height()), (64, 64));
+            // The top rows decode as they were
+            let top = salvaged.to_rgb8().get_pixel(40, 2).0;
+            assert!(top.iter().zip(img.get_pixel(40, 2).0).all(|(a, b)| a.abs_diff(b) < 16), "{:?}", format);
+        }
+
+        // Damaged before any image data
+        let bytes = encoded(&img, ImageFormat::Png);
+        assert!(salvage(&bytes[..20]).is_none());
+    }
+}
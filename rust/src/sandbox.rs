@@ -0,0 +1,395 @@
+//! Sandboxed decoding: untrusted images are decoded in a child process with
+//! a memory cap, a time limit and, on Linux, no way to open sockets or run
+//! programs, so a malicious upload can only take the child down.
+//!
+//! The child is the current executable started again with [`SANDBOX_ENV`]
+//! set. Programs enabling [`WebPConverterBuilder::sandbox`] must call
+//! [`serve_if_child`] first thing in `main`, as `webp-converter` does.
+//!
+//! [`WebPConverterBuilder::sandbox`]: crate::WebPConverterBuilder::sandbox
+
+use crate::color;
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::salvage;
+use crate::utils;
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable marking a process as a sandboxed decoder.
+pub const SANDBOX_ENV: &str = "WEBP_CONVERTER_SANDBOX";
+
+/// Marks the start of a decoded image on the child's standard output.
+const MAGIC: &[u8; 4] = b"WPSB";
+
+/// Child exit code for an image that does not decode.
+const EXIT_UNDECODABLE: i32 = 2;
+
+/// Limits of a sandboxed decoder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SandboxLimits {
+    /// Address space of the decoder in bytes; allocations beyond it fail.
+    pub memory: u64,
+    /// Wall-clock time a single decode may take before it is killed.
+    pub timeout: Duration,
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self { memory: 1 << 30, timeout: Duration::from_secs(30) }
+    }
+}
+
+/// A decoded image as the child hands it back.
+#[derive(Debug)]
+struct Decoded {
+    image: DynamicImage,
+    icc_profile: Option<Vec<u8>>,
+    /// Why the image needed salvaging, when it did.
+    salvaged: Option<String>,
+}
+
+impl WebPConverter {
+    /// Load an image file through a sandboxed decoder, reporting a salvaged
+    /// image like [`load_image`](Self::load_image) does.
+    pub(crate) fn load_sandboxed(&self, path: &Path, limits: SandboxLimits) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+        let bytes = fs::read(path)?;
+        let decoded = decode(&bytes, utils::image_format(path), self.salvages(), limits)?;
+        if let Some(error) = decoded.salvaged {
+            self.report(Event::Salvaged { path, error: &WebPError::ImageProcessingError(error) });
+        }
+        Ok((decoded.image, decoded.icc_profile))
+    }
+
+    /// Decode an in-memory image, in the sandbox when it is on.
+    pub(crate) fn decode_bytes(&self, bytes: &[u8], format: Option<ImageFormat>) -> WebPResult<(DynamicImage, Option<Vec<u8>>)> {
+        match self.sandbox_limits() {
+            Some(limits) => decode(bytes, format, false, limits).map(|decoded| (decoded.image, decoded.icc_profile)),
+            None => color::load_image_from_memory(bytes, format),
+        }
+    }
+}
+
+/// Decode `bytes` in a child process within `limits`.
+fn decode(bytes: &[u8], format: Option<ImageFormat>, salvage: bool, limits: SandboxLimits) -> WebPResult<Decoded> {
+    let failed = |reason: String| WebPError::SandboxFailed(reason);
+    let program = std::env::current_exe().map_err(|e| failed(format!("cannot find the decoder: {}", e)))?;
+    let mut child = Command::new(program)
+        .env(SANDBOX_ENV, "1")
+        // Keeps a failed allocation to its one-line message
+        .env("RUST_BACKTRACE", "0")
+        .arg(format.and_then(|format| format.extensions_str().first().copied()).unwrap_or("-"))
+        .arg(if salvage { "salvage" } else { "-" })
+        .arg(limits.memory.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("cannot start the decoder: {}", e)))?;
+
+    let (Some(mut stdin), Some(mut stdout), Some(mut stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take()) else {
+        unreachable!("all standard streams are piped");
+    };
+    let (status, output, message) = thread::scope(|scope| {
+        // A child that dies early closes its end; the write error is moot then
+        scope.spawn(move || stdin.write_all(bytes));
+        let output = scope.spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+        let message = scope.spawn(move || {
+            let mut message = String::new();
+            let _ = stderr.read_to_string(&mut message);
+            message
+        });
+        let status = wait(&mut child, limits.timeout);
+        (status, output.join().unwrap_or_else(|_| Ok(Vec::new())), message.join().unwrap_or_default())
+    });
+
+    let message = message.lines().next().unwrap_or_default().to_string();
+    match status? {
+        None => Err(failed(format!("decoding took longer than {}", utils::format_duration(limits.timeout.as_secs_f64())))),
+        Some(status) if status.success() => {
+            read_decoded(&output?).ok_or_else(|| failed("the decoder returned a malformed image".to_string()))
+        }
+        Some(status) if status.code() == Some(EXIT_UNDECODABLE) => Err(WebPError::ImageProcessingError(message)),
+        // An allocation over the memory cap aborts with a message saying so
+        Some(status) if message.is_empty() => Err(failed(format!("decoder {}", status))),
+        Some(_) => Err(failed(message)),
+    }
+}
+
+/// Wait for `child` to exit, killing it after `timeout`; `None` when it was
+/// killed.
+fn wait(child: &mut Child, timeout: Duration) -> WebPResult<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// What the sandbox cannot restrict on this platform, if anything. Linux on
+/// x86-64 and ARM64 gets every restriction; other Unix systems lack the
+/// system call filter and the rest also the memory cap.
+pub fn missing_restrictions() -> Option<&'static str> {
+    if cfg!(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))) {
+        None
+    } else if cfg!(unix) {
+        Some("no system call filter on this platform, so the decoder can still open sockets and run programs")
+    } else {
+        Some("no memory cap or system call filter on this platform, only the time limit")
+    }
+}
+
+/// If this process was started as a sandboxed decoder, decode the image on
+/// standard input, write it to standard output and exit. Returns at once in
+/// any other process.
+pub fn serve_if_child() {
+    if std::env::var_os(SANDBOX_ENV).is_none() {
+        return;
+    }
+    let mut args = std::env::args().skip(1);
+    let format = args.next().and_then(ImageFormat::from_extension);
+    let salvage = args.next().is_some_and(|arg| arg == "salvage");
+    let memory = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(SandboxLimits::default().memory);
+
+    // The pipes to the parent are the child's only way to report back, and
+    // the first line written is the reason it gives
+    std::panic::set_hook(Box::new(|info| {
+        let message = info.payload().downcast_ref::<&str>().copied().or_else(|| info.payload().downcast_ref::<String>().map(String::as_str));
+        let _ = writeln!(io::stderr(), "decoder panicked: {}", message.unwrap_or("unknown cause"));
+    }));
+    let code = match serve(format, salvage, memory) {
+        Ok(()) => 0,
+        Err(WebPError::ImageProcessingError(message)) => {
+            let _ = writeln!(io::stderr(), "{}", message);
+            EXIT_UNDECODABLE
+        }
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "{}", error);
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+fn serve(format: Option<ImageFormat>, salvage: bool, memory: u64) -> WebPResult<()> {
+    // Locked down before the untrusted bytes are even read
+    restrict(memory).map_err(|e| WebPError::SandboxFailed(format!("cannot restrict the decoder: {}", e)))?;
+
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    let decoded = match color::load_image_from_memory(&bytes, format) {
+        Err(WebPError::ImageProcessingError(error)) if salvage => match salvage::salvage(&bytes) {
+            Some((image, icc_profile)) => Decoded { image, icc_profile, salvaged: Some(error) },
+            None => return Err(WebPError::ImageProcessingError(error)),
+        },
+        result => result.map(|(image, icc_profile)| Decoded { image, icc_profile, salvaged: None })?,
+    };
+    // Flushed by hand, as exiting skips the buffer's destructor
+    let mut stdout = io::stdout().lock();
+    write_decoded(&decoded, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Cap the address space and drop core dumps and, on Linux, deny the system
+/// calls that open sockets or run programs.
+#[cfg(unix)]
+fn restrict(memory: u64) -> io::Result<()> {
+    set_limit(libc::RLIMIT_AS, memory)?;
+    set_limit(libc::RLIMIT_CORE, 0)?;
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    deny_syscalls(&[libc::SYS_socket, libc::SYS_execve, libc::SYS_execveat])?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict(_memory: u64) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_limit(resource: Resource, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Install a seccomp filter failing `syscalls` with `EACCES`. System calls
+/// of another architecture, which would bypass the numbers, kill the process.
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn deny_syscalls(syscalls: &[libc::c_long]) -> io::Result<()> {
+    use libc::{sock_filter, BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+    const AUDIT_ARCH: u32 = if cfg!(target_arch = "x86_64") { 0xC000_003E } else { 0xC000_00B7 };
+    // Offsets into struct seccomp_data
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    let load = |offset: u32| sock_filter { code: (BPF_LD | BPF_W | BPF_ABS) as u16, jt: 0, jf: 0, k: offset };
+    let ret = |action: u32| sock_filter { code: (BPF_RET | BPF_K) as u16, jt: 0, jf: 0, k: action };
+    // Skip the next instruction unless the loaded word compares as `test`
+    let unless = |test: u32, value: u32| sock_filter { code: (BPF_JMP | test | BPF_K) as u16, jt: 0, jf: 1, k: value };
+
+    let mut filter = vec![
+        load(ARCH),
+        // Over the kill when the architecture matches
+        sock_filter { jt: 1, jf: 0, ..unless(BPF_JEQ, AUDIT_ARCH) },
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR),
+    ];
+    if cfg!(target_arch = "x86_64") {
+        // x32 system calls share the architecture but set this bit
+        filter.push(unless(BPF_JGE, 0x4000_0000));
+        filter.push(ret(libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    for &syscall in syscalls {
+        filter.push(unless(BPF_JEQ, syscall as u32));
+        filter.push(ret(libc::SECCOMP_RET_ERRNO | libc::EACCES as u32));
+    }
+    filter.push(ret(libc::SECCOMP_RET_ALLOW));
+
+    let program = libc::sock_fprog { len: filter.len() as u16, filter: filter.as_mut_ptr() };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+            || libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const libc::sock_fprog) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Serialize a decoded image: a header of magic, color type, dimensions and
+/// lengths, then the ICC profile, the salvage reason and the raw samples in
+/// native byte order, as both ends run on the same machine.
+fn write_decoded(decoded: &Decoded, output: &mut impl Write) -> io::Result<()> {
+    let (image, icc_profile) = (&decoded.image, decoded.icc_profile.as_deref().unwrap_or_default());
+    let salvaged = decoded.salvaged.as_deref().unwrap_or_default().as_bytes();
+    let color = match image {
+        DynamicImage::ImageLuma8(_) => 0,
+        DynamicImage::ImageLumaA8(_) => 1,
+        DynamicImage::ImageRgb8(_) => 2,
+        DynamicImage::ImageRgba8(_) => 3,
+        DynamicImage::ImageLuma16(_) => 4,
+        DynamicImage::ImageLumaA16(_) => 5,
+        DynamicImage::ImageRgb16(_) => 6,
+        DynamicImage::ImageRgba16(_) => 7,
+        DynamicImage::ImageRgb32F(_) => 8,
+        _ => 9,
+    };
+    // Written straight from the image, which may be most of the memory cap
+    let image = match image {
+        DynamicImage::ImageRgba32F(_) => Cow::Borrowed(image),
+        _ if color == 9 => Cow::Owned(DynamicImage::ImageRgba32F(image.to_rgba32f())),
+        _ => Cow::Borrowed(image),
+    };
+
+    output.write_all(MAGIC)?;
+    output.write_all(&[color])?;
+    for value in [image.width(), image.height(), icc_profile.len() as u32, salvaged.len() as u32] {
+        output.write_all(&value.to_le_bytes())?;
+    }
+    output.write_all(icc_profile)?;
+    output.write_all(salvaged)?;
+    output.write_all(image.as_bytes())
+}
+
+/// The inverse of [`write_decoded`], or `None` for malformed input.
+fn read_decoded(input: &[u8]) -> Option<Decoded> {
+    let input = input.strip_prefix(MAGIC)?;
+    let (&color, input) = input.split_first()?;
+    let (header, input) = input.split_at_checked(16)?;
+    let field = |index: usize| u32::from_le_bytes(header[index * 4..index * 4 + 4].try_into().unwrap());
+    let (width, height) = (field(0), field(1));
+    let (icc_profile, input) = input.split_at_checked(field(2) as usize)?;
+    let (salvaged, samples) = input.split_at_checked(field(3) as usize)?;
+
+    fn samples_of<T, const N: usize>(bytes: &[u8], from: fn([u8; N]) -> T) -> Vec<T> {
+        bytes.chunks_exact(N).map(|chunk| from(chunk.try_into().unwrap())).collect()
+    }
+    let u16s = || samples_of(samples, u16::from_ne_bytes);
+    let f32s = || samples_of(samples, f32::from_ne_bytes);
+    let image = match color {
+        0 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, samples.to_vec())?),
+        1 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, samples.to_vec())?),
+        2 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, samples.to_vec())?),
+        3 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, samples.to_vec())?),
+        4 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, u16s())?),
+        5 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, u16s())?),
+        6 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, u16s())?),
+        7 => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, u16s())?),
+        8 => DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, f32s())?),
+        9 => DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, f32s())?),
+        _ => return None,
+    };
+    Some(Decoded {
+        image,
+        icc_profile: (!icc_profile.is_empty()).then(|| icc_profile.to_vec()),
+        salvaged: (!salvaged.is_empty()).then(|| String::from_utf8_lossy(salvaged).into_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_decoded_round_trip() {
+        let image = DynamicImage::ImageRgba16(ImageBuffer::from_fn(5, 3, |x, y| Rgba([x as u16 * 1000, y as u16 * 300, 65535, 1])));
+        let decoded = Decoded { image, icc_profile: Some(vec![1, 2, 3]), salvaged: Some("truncated".to_string()) };
+        let mut written = Vec::new();
+        write_decoded(&decoded, &mut written).unwrap();
+        let read = read_decoded(&written).unwrap();
+        assert_eq!(read.image, decoded.image);
+        assert_eq!(read.icc_profile, decoded.icc_profile);
+        assert_eq!(read.salvaged, decoded.salvaged);
+
+        let plain = Decoded { image: DynamicImage::ImageRgba8(RgbaImage::new(2, 2)), icc_profile: None, salvaged: None };
+        let mut written = Vec::new();
+        write_decoded(&plain, &mut written).unwrap();
+        let read = read_decoded(&written).unwrap();
+        assert_eq!((read.image, read.icc_profile, read.salvaged), (plain.image, None, None));
+
+        // Output cut short, e.g. by a killed child
+        assert!(read_decoded(&written[..written.len() - 1]).is_none());
+        assert!(read_decoded(b"garbage").is_none());
+    }
+
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[test]
+    fn test_restrictions() {
+        // Seccomp filters apply to the calling thread, leaving the tests alone
+        let socket = thread::spawn(|| {
+            deny_syscalls(&[libc::SYS_socket]).unwrap();
+            std::net::UdpSocket::bind("127.0.0.1:0").map(|_| ())
+        });
+        assert_eq!(socket.join().unwrap().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert!(wait(&mut child, Duration::from_millis(50)).unwrap().is_none());
+    }
+}
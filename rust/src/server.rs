@@ -0,0 +1,359 @@
+//! HTTP server mode: convert images below a root folder on request.
+//!
+//! `GET /convert?src=photos/cat.jpg&q=80` answers with the encoded WebP of a
+//! file below the configured root; paths escaping it are refused.
+//! `POST /convert?q=80&resize=800x600` converts the uploaded image instead,
+//! sent either as the raw body or as a `multipart/form-data` file field.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::transform::Dimensions;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response, Server};
+
+/// How often idle workers check for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Largest accepted upload, in bytes.
+const MAX_UPLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Status, content type and body of an HTTP response.
+#[derive(Debug)]
+pub(crate) struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    fn webp(body: Vec<u8>) -> Self {
+        Self { status: 200, content_type: "image/webp", body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let mut body = message.into().into_bytes();
+        body.push(b'\n');
+        Self { status, content_type: "text/plain; charset=utf-8", body }
+    }
+}
+
+impl WebPConverter {
+    /// Serve conversions of the images below `root` over HTTP on `listen`
+    /// (e.g. `0.0.0.0:8080`), until the converter's cancellation token is
+    /// triggered.
+    ///
+    /// Requests are handled by one worker per CPU core. Each handled request
+    /// is reported as [`Event::RequestHandled`].
+    pub fn serve(&self, listen: &str, root: &Path) -> WebPResult<()> {
+        let root = root.canonicalize()?;
+        if !root.is_dir() {
+            return Err(WebPError::InvalidInputType(root));
+        }
+        let server = Server::http(listen).map_err(|e| WebPError::ServerError(format!("cannot listen on {}: {}", listen, e)))?;
+        let workers = std::thread::available_parallelism().map_or(1, usize::from);
+        self.report(Event::Ready);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers).map(|_| scope.spawn(|| self.serve_requests(&server, &root))).collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| Err(WebPError::ServerError("worker panicked".to_string()))))
+                .collect::<WebPResult<Vec<()>>>()
+        })?;
+        Ok(())
+    }
+
+    /// Answer requests until cancelled.
+    fn serve_requests(&self, server: &Server, root: &Path) -> WebPResult<()> {
+        while !self.is_cancelled() {
+            let Some(mut request) = server.recv_timeout(POLL_INTERVAL)? else {
+                continue;
+            };
+            let start_time = Instant::now();
+            let method = request.method().to_string();
+            let url = request.url().to_string();
+            let reply = match read_body(&mut request) {
+                Ok(body) => {
+                    let content_type = header(&request, "Content-Type");
+                    self.respond(root, &method, &url, content_type.as_deref(), &body)
+                }
+                Err(reply) => reply,
+            };
+
+            let time_taken = start_time.elapsed().as_secs_f64();
+            self.report(Event::RequestHandled { method: &method, url: &url, status: reply.status, time_taken });
+            let content_type = Header::from_bytes(&b"Content-Type"[..], reply.content_type.as_bytes())
+                .expect("content types are valid header values");
+            let response = Response::from_data(reply.body).with_status_code(reply.status).with_header(content_type);
+            // The client may have gone away; that only affects this request
+            let _ = request.respond(response);
+        }
+        Ok(())
+    }
+
+    /// Route one request. `root` must be canonical; `body` is only read for
+    /// uploads.
+    pub(crate) fn respond(&self, root: &Path, method: &str, url: &str, content_type: Option<&str>, body: &[u8]) -> Reply {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        if path != "/convert" {
+            return Reply::error(404, "not found");
+        }
+        if method != "GET" && method != "POST" {
+            return Reply::error(405, "method not allowed");
+        }
+
+        let params = parse_query(query);
+        let converter = match self.request_converter(&params) {
+            Ok(converter) => converter,
+            Err(reply) => return reply,
+        };
+        let result = if method == "GET" {
+            let Some(src) = params.get("src") else {
+                return Reply::error(400, "missing 'src' parameter");
+            };
+            let path = match resolve(root, src) {
+                Ok(path) => path,
+                Err(reply) => return reply,
+            };
+            let mut webp_data = Vec::new();
+            converter.convert_to_writer(&path, &mut webp_data).map(|_| webp_data)
+        } else {
+            let upload = match upload(content_type, body) {
+                Ok(upload) => upload,
+                Err(reply) => return reply,
+            };
+            converter.convert_bytes(upload)
+        };
+
+        match result {
+            Ok(webp_data) => Reply::webp(webp_data),
+            Err(error @ (WebPError::InvalidImage(_) | WebPError::ImageProcessingError(_))) => {
+                Reply::error(415, error.to_string())
+            }
+            Err(error) => Reply::error(500, error.to_string()),
+        }
+    }
+
+    /// The converter for one request, applying the `q` and `resize` parameters.
+    fn request_converter(&self, params: &HashMap<String, String>) -> Result<WebPConverter, Reply> {
+        let mut converter = match params.get("q").map(|q| q.parse::<u8>()) {
+            None => self.clone(),
+            Some(Ok(quality)) if (1..=100).contains(&quality) => self.with_quality(quality),
+            Some(_) => return Err(Reply::error(400, "'q' must be a quality between 1 and 100")),
+        };
+        if let Some(resize) = params.get("resize") {
+            // Like `--ops resize=N`: one number bounds both edges
+            let size = match resize.parse::<u32>() {
+                Ok(edge) if edge > 0 => Dimensions { width: edge, height: edge },
+                _ => resize.parse().map_err(|e: String| Reply::error(400, e))?,
+            };
+            converter = converter.with_resize(size);
+        }
+        Ok(converter)
+    }
+}
+
+/// Read a request body, refusing uploads over [`MAX_UPLOAD_SIZE`].
+fn read_body(request: &mut Request) -> Result<Vec<u8>, Reply> {
+    if request.body_length().is_some_and(|length| length as u64 > MAX_UPLOAD_SIZE) {
+        return Err(Reply::error(413, "upload too large"));
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| Reply::error(400, format!("cannot read request body: {}", e)))?;
+    if body.len() as u64 > MAX_UPLOAD_SIZE {
+        return Err(Reply::error(413, "upload too large"));
+    }
+    Ok(body)
+}
+
+/// Value of a request header, matched case-insensitively.
+fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str().to_string())
+}
+
+/// The uploaded image: the first file of a multipart form, or the raw body.
+fn upload<'a>(content_type: Option<&str>, body: &'a [u8]) -> Result<&'a [u8], Reply> {
+    let boundary = content_type
+        .filter(|content_type| content_type.to_ascii_lowercase().starts_with("multipart/form-data"))
+        .map(|content_type| {
+            content_type
+                .split(';')
+                .find_map(|param| param.trim().strip_prefix("boundary="))
+                .map(|boundary| boundary.trim_matches('"'))
+                .ok_or_else(|| Reply::error(400, "multipart upload without a boundary"))
+        })
+        .transpose()?;
+
+    let upload = match boundary {
+        Some(boundary) => multipart_file(body, boundary).ok_or_else(|| Reply::error(400, "no file in multipart upload"))?,
+        None => body,
+    };
+    if upload.is_empty() {
+        return Err(Reply::error(400, "empty upload"));
+    }
+    Ok(upload)
+}
+
+/// Content of the first part of a multipart body that carries a file name,
+/// falling back to the first part.
+fn multipart_file<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(start) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        // The closing delimiter is followed by "--"
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let end = find(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let part = rest[..end].strip_prefix(b"\r\n").unwrap_or(&rest[..end]);
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        if let Some(split) = find(part, b"\r\n\r\n") {
+            parts.push((&part[..split], &part[split + 4..]));
+        }
+        rest = &rest[end..];
+    }
+
+    let is_file = |headers: &[u8]| String::from_utf8_lossy(headers).to_ascii_lowercase().contains("filename=");
+    parts
+        .iter()
+        .find(|(headers, _)| is_file(headers))
+        .or_else(|| parts.first())
+        .map(|&(_, content)| content)
+}
+
+/// Position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Resolve a requested path inside `root`, refusing anything outside it.
+fn resolve(root: &Path, src: &str) -> Result<PathBuf, Reply> {
+    let path = root
+        .join(src.trim_start_matches('/'))
+        .canonicalize()
+        .map_err(|_| Reply::error(404, format!("'{}' not found", src)))?;
+    if !path.starts_with(root) {
+        return Err(Reply::error(403, format!("'{}' is outside the served folder", src)));
+    }
+    if !path.is_file() {
+        return Err(Reply::error(404, format!("'{}' is not a file", src)));
+    }
+    Ok(path)
+}
+
+/// Split a query string into decoded parameters; later duplicates win.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes and `+` (as a space) in a URL query component.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let params = parse_query("src=my%20photos/cat+1.png&q=80&flag");
+        assert_eq!(params["src"], "my photos/cat 1.png");
+        assert_eq!(params["q"], "80");
+        assert_eq!(params["flag"], "");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_convert_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        std::fs::create_dir(&root).unwrap();
+        image::RgbImage::from_pixel(8, 8, image::Rgb([10, 200, 30])).save(root.join("a b.png")).unwrap();
+        image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0])).save(dir.path().join("secret.png")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let converter = WebPConverter::builder().build().unwrap();
+
+        let reply = converter.respond(&root, "GET", "/convert?src=a%20b.png&q=50", None, &[]);
+        assert_eq!((reply.status, reply.content_type), (200, "image/webp"));
+        assert_eq!(&reply.body[8..12], b"WEBP");
+
+        let status = |url: &str| converter.respond(&root, "GET", url, None, &[]).status;
+        assert_eq!(status("/convert?src=../secret.png"), 403);
+        assert_eq!(status("/convert?src=missing.png"), 404);
+        assert_eq!(status("/convert?src=a%20b.png&q=0"), 400);
+        assert_eq!(status("/convert"), 400);
+        assert_eq!(status("/other"), 404);
+        assert_eq!(status("/convert?src=a%20b.png&resize=big"), 400);
+        assert_eq!(converter.respond(&root, "DELETE", "/convert?src=a%20b.png", None, &[]).status, 405);
+    }
+
+    #[test]
+    fn test_upload_request() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 20, image::Rgb([90, 90, 200])))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let converter = WebPConverter::builder().build().unwrap();
+        let root = Path::new("/");
+        let decoded_size = |reply: Reply| {
+            assert_eq!(reply.status, 200);
+            let decoded = webp::Decoder::new(&reply.body).decode().unwrap();
+            (decoded.width(), decoded.height())
+        };
+
+        let raw = converter.respond(root, "POST", "/convert?q=70&resize=20", Some("image/png"), &png);
+        assert_eq!(decoded_size(raw), (20, 10));
+
+        let mut form = b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n".to_vec();
+        form.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.png\"\r\n");
+        form.extend_from_slice(b"Content-Type: image/png\r\n\r\n");
+        form.extend_from_slice(&png);
+        form.extend_from_slice(b"\r\n--XyZ--\r\n");
+        let multipart = converter.respond(root, "POST", "/convert", Some("multipart/form-data; boundary=XyZ"), &form);
+        assert_eq!(decoded_size(multipart), (40, 20));
+
+        assert_eq!(converter.respond(root, "POST", "/convert", None, b"not an image").status, 415);
+        assert_eq!(converter.respond(root, "POST", "/convert", None, &[]).status, 400);
+    }
+}
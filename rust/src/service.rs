@@ -0,0 +1,282 @@
+//! Running as a system service: readiness notification for systemd,
+//! journald-style log lines and shutdown on SIGTERM.
+
+use webp_converter::progress::CancellationToken;
+use webp_converter::report::{Event, Reporter, SheetKind};
+use webp_converter::utils;
+use std::fmt::{self, Display, Write as _};
+use std::io::{self, Write};
+
+/// Syslog priorities understood by journald as `<N>` line prefixes.
+pub const ERR: u8 = 3;
+pub const WARNING: u8 = 4;
+pub const NOTICE: u8 = 5;
+pub const INFO: u8 = 6;
+
+/// Send a state such as `READY=1` or `STOPPING=1` to the service manager.
+///
+/// Returns `false` without doing anything when not started by systemd with
+/// `Type=notify` (no `NOTIFY_SOCKET` in the environment).
+#[cfg(unix)]
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => notify_socket(&path, state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Send `state` to the notification socket at `path`.
+#[cfg(unix)]
+fn notify_socket(path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    match path.to_str().and_then(|path| path.strip_prefix('@')) {
+        // Abstract socket names start with '@'
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Service managers other than systemd are not notified.
+#[cfg(not(unix))]
+pub fn sd_notify(_state: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Cancel `token` on SIGTERM or SIGINT, so watch and server mode finish the
+/// current file and return. A second signal exits immediately.
+#[cfg(unix)]
+pub fn cancel_on_termination(token: &CancellationToken) -> io::Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::flag;
+
+    for signal in [SIGTERM, SIGINT] {
+        // Registered first, so it only fires once the flag is already set
+        flag::register_conditional_shutdown(signal, 1, token.flag())?;
+        flag::register(signal, token.flag())?;
+    }
+    Ok(())
+}
+
+/// Without Unix signals the process is simply stopped.
+#[cfg(not(unix))]
+pub fn cancel_on_termination(_token: &CancellationToken) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reporter for running under a service manager: one plain `key=value` line
+/// per event on stderr, prefixed with its syslog priority as journald
+/// expects, and `READY=1` sent via [`sd_notify`] once watch or server mode
+/// is waiting for work.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServiceReporter;
+
+impl ServiceReporter {
+    /// Write one log line; also usable for the caller's own messages.
+    pub fn log(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) {
+        let line = format_line(priority, message, fields);
+        // Nothing sensible to do when stderr is gone
+        let _ = writeln!(io::stderr().lock(), "{}", line);
+    }
+}
+
+impl Reporter for ServiceReporter {
+    fn report(&self, event: &Event<'_>) {
+        match event {
+            Event::BatchStarted { total } => Self::log(INFO, "batch started", &[("total", total)]),
+            Event::FileConverted { input, output, original_size, compressed_size, time_taken, metrics, content } => {
+                let (input, output) = (utils::escape_path(input), utils::escape_path(output));
+                let time_taken = format!("{:.3}", time_taken);
+                let mut fields: Vec<(&str, &dyn Display)> = vec![
+                    ("input", &input),
+                    ("output", &output),
+                    ("original_size", original_size),
+                    ("compressed_size", compressed_size),
+                    ("time_taken", &time_taken),
+                ];
+                let psnr = metrics.map(|metrics| format!("{:.2}", metrics.psnr));
+                let ssim = metrics.map(|metrics| format!("{:.4}", metrics.ssim));
+                if let (Some(psnr), Some(ssim)) = (&psnr, &ssim) {
+                    fields.push(("psnr", psnr));
+                    fields.push(("ssim", ssim));
+                }
+                if let Some(content) = content {
+                    fields.push(("content", content));
+                }
+                Self::log(INFO, "converted", &fields);
+            }
+            Event::ArtifactsFound { path, artifacts } => {
+                for artifact in artifacts.iter() {
+                    let share = format!("{:.2}", artifact.share);
+                    Self::log(
+                        WARNING,
+                        "artifacts found",
+                        &[("path", &utils::escape_path(path)), ("kind", &artifact.kind), ("share", &share)],
+                    );
+                }
+            }
+            Event::LowQuality { path, ssim, min_ssim, action } => {
+                let ssim = format!("{:.4}", ssim);
+                Self::log(
+                    WARNING,
+                    "low quality",
+                    &[("path", &utils::escape_path(path)), ("ssim", &ssim), ("min_ssim", min_ssim), ("action", action)],
+                );
+            }
+            Event::FileSkipped { path, reason } => {
+                Self::log(INFO, "skipped", &[("path", &utils::escape_path(path)), ("reason", reason)]);
+            }
+            Event::SimilarFound { path, original, distance, skipped } => Self::log(
+                INFO,
+                "similar image",
+                &[("path", &utils::escape_path(path)), ("original", &utils::escape_path(original)), ("distance", distance), ("skipped", skipped)],
+            ),
+            Event::FileFailed { path, error } => {
+                Self::log(ERR, "failed", &[("path", &utils::escape_path(path)), ("error", error)]);
+            }
+            Event::Retrying { path, attempt, retries, delay, error } => {
+                let delay = format!("{:.1}", delay.as_secs_f64());
+                Self::log(
+                    WARNING,
+                    "retrying",
+                    &[("path", &utils::escape_path(path)), ("attempt", attempt), ("retries", retries), ("delay", &delay), ("error", error)],
+                );
+            }
+            Event::FormatMismatch { path, format } => {
+                let format = utils::format_name(*format);
+                Self::log(WARNING, "extension does not match content", &[("path", &utils::escape_path(path)), ("format", &format)]);
+            }
+            Event::OutputRedirected { path, output, fallback, error } => {
+                Self::log(
+                    WARNING,
+                    "output redirected",
+                    &[("path", &utils::escape_path(path)), ("output", &utils::escape_path(output)), ("fallback", &utils::escape_path(fallback)), ("error", error)],
+                );
+            }
+            Event::OutputRenamed { path, output, renamed, other } => {
+                Self::log(
+                    WARNING,
+                    "output renamed",
+                    &[("path", &utils::escape_path(path)), ("output", &utils::escape_path(output)), ("renamed", &utils::escape_path(renamed)), ("other", &utils::escape_path(other))],
+                );
+            }
+            Event::Salvaged { path, error } => {
+                Self::log(WARNING, "salvaged damaged image", &[("path", &utils::escape_path(path)), ("error", error)]);
+            }
+            Event::Cancelled { completed, total } => {
+                Self::log(NOTICE, "cancelled", &[("completed", completed), ("total", total)]);
+            }
+            Event::OrphanDeleted { path } => Self::log(INFO, "deleted orphan", &[("path", &utils::escape_path(path))]),
+            Event::Downloaded { url, size } => Self::log(INFO, "downloaded", &[("url", url), ("size", size)]),
+            Event::Uploaded { url, size } => Self::log(INFO, "uploaded", &[("url", url), ("size", size)]),
+            Event::RequestHandled { method, url, status, time_taken } => {
+                let priority = if *status < 500 { INFO } else { WARNING };
+                let time_taken = format!("{:.3}", time_taken);
+                Self::log(
+                    priority,
+                    "request",
+                    &[("method", method), ("url", url), ("status", status), ("time_taken", &time_taken)],
+                );
+            }
+            // Exposed as a metric; one line per queued file would flood the journal
+            Event::Backlog { .. } => {}
+            Event::SheetStarted { kind, count } => {
+                Self::log(INFO, "sheet started", &[("kind", &sheet_kind(*kind)), ("count", count)]);
+            }
+            Event::SheetWritten { kind, output, width, height, compressed_size } => Self::log(
+                INFO,
+                "sheet written",
+                &[
+                    ("kind", &sheet_kind(*kind)),
+                    ("output", &utils::escape_path(output)),
+                    ("width", width),
+                    ("height", height),
+                    ("compressed_size", compressed_size),
+                ],
+            ),
+            Event::Ready => {
+                Self::log(NOTICE, "ready", &[]);
+                if let Err(error) = sd_notify("READY=1") {
+                    Self::log(WARNING, "sd_notify failed", &[("error", &error)]);
+                }
+            }
+        }
+    }
+}
+
+fn sheet_kind(kind: SheetKind) -> &'static str {
+    match kind {
+        SheetKind::SpriteSheet => "spritesheet",
+        SheetKind::ContactSheet => "contact-sheet",
+    }
+}
+
+/// `<priority>message key=value ...`, quoting values that contain spaces,
+/// quotes, `=` or control characters.
+fn format_line(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) -> String {
+    let mut line = format!("<{}>{}", priority, message);
+    for (key, value) in fields {
+        let value = value.to_string();
+        let plain = !value.is_empty()
+            && !value.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '=');
+        if plain {
+            let _ = write!(line, " {}={}", key, value);
+        } else {
+            let _ = write!(line, " {}={}", key, Quoted(&value));
+        }
+    }
+    line
+}
+
+/// A value in double quotes, with quotes, backslashes and newlines escaped.
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{}", c)?,
+                '\n' => f.write_str("\\n")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        let line = format_line(INFO, "converted", &[("input", &"a b.png"), ("size", &1200), ("error", &"bad \"data\"\nend")]);
+        assert_eq!(line, r#"<6>converted input="a b.png" size=1200 error="bad \"data\"\nend""#);
+        assert_eq!(format_line(NOTICE, "ready", &[("reason", &"")]), r#"<5>ready reason="""#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.as_os_str(), "READY=1").unwrap();
+        let mut buffer = [0; 64];
+        let length = socket.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"READY=1");
+        assert!(notify_socket(dir.path().join("missing.sock").as_os_str(), "READY=1").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Serializable conversion settings for config files, job queues and APIs.
+
+use crate::converter::{WebPConverter, WebPConverterBuilder};
+use crate::encoder::Preset;
+use crate::error::WebPResult;
+use crate::metrics::{LowQualityPolicy, Metric};
+use crate::overrides::FormatOverride;
+use crate::pipeline::{Pipeline, PipelineOptions};
+use crate::transform::{Color, Dimensions, ResizeFilter};
+use crate::watermark::Position;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Every converter setting in one plain, serializable struct.
+///
+/// Missing fields take the same defaults as [`WebPConverter::builder`], and
+/// transforms use the `--ops` syntax, so a settings file reads like the
+/// equivalent command line:
+///
+/// ```json
+/// { "quality": 85, "resize": "1600x1600", "ops": "autorotate,sharpen=0.5" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConversionSettings {
+    pub quality: u8,
+    pub lossless: bool,
+    pub method: u8,
+    pub keep_alpha: bool,
+    pub keep_profile: bool,
+    pub dpi: Option<u32>,
+    pub tile_size: Option<u32>,
+    /// Measure PSNR/SSIM of each written file.
+    pub metrics: bool,
+    /// Extra perceptual metric computed with `metrics`.
+    pub metric: Metric,
+    /// Warn about likely visible banding or blocking in lossy outputs.
+    pub detect_artifacts: bool,
+    /// Folder for difference heatmaps of each output against its source.
+    #[serde(with = "crate::path_serde::option")]
+    pub diff_output: Option<PathBuf>,
+    /// Discard outputs that are not smaller than their source.
+    pub only_if_smaller: bool,
+    /// Leave sources below this many bytes per pixel alone.
+    pub skip_efficient: Option<f64>,
+    /// Skip near-duplicates whose perceptual hashes differ by at most this many bits.
+    pub dedupe_similar: Option<u32>,
+    /// Only report near-duplicates instead of skipping them.
+    pub report_similar_only: bool,
+    /// Choose lossless or lossy per image from its content.
+    pub auto_mode: bool,
+    pub preset: Preset,
+    /// Choose the preset per image from its content.
+    pub auto_preset: bool,
+    /// Pick the quality per image to reach this SSIM instead of using `quality`.
+    pub target_ssim: Option<f64>,
+    /// Size budget in bytes for each output file.
+    pub max_output_size: Option<u64>,
+    /// Lowest quality tried when re-encoding to fit `max_output_size`.
+    pub min_quality: u8,
+    /// Reject lossy outputs whose SSIM is below this value.
+    pub min_ssim: Option<f64>,
+    /// What to do with outputs below `min_ssim`.
+    pub on_low_quality: LowQualityPolicy,
+    /// Per-source-format settings, e.g. `["png lossless", "jpg q=78"]`.
+    pub format_overrides: Vec<FormatOverride>,
+    /// Fit inside this size after the transforms, never enlarging.
+    pub resize: Option<Dimensions>,
+    /// Transform pipeline in `--ops` syntax.
+    pub ops: Option<String>,
+    pub resize_filter: ResizeFilter,
+    pub pad_color: Color,
+    pub background: Color,
+    pub watermark_position: Position,
+    pub watermark_opacity: f32,
+    /// Font for `stamp` entries of `ops`.
+    #[serde(with = "crate::path_serde::option")]
+    pub stamp_font: Option<PathBuf>,
+    pub stamp_size: f32,
+    pub stamp_position: Position,
+}
+
+impl Default for ConversionSettings {
+    fn default() -> Self {
+        let options = PipelineOptions::default();
+        Self {
+            quality: 80,
+            lossless: false,
+            method: 4,
+            keep_alpha: true,
+            keep_profile: false,
+            dpi: None,
+            tile_size: None,
+            metrics: false,
+            metric: Metric::default(),
+            detect_artifacts: false,
+            diff_output: None,
+            only_if_smaller: false,
+            skip_efficient: None,
+            dedupe_similar: None,
+            report_similar_only: false,
+            auto_mode: false,
+            preset: Preset::default(),
+            auto_preset: false,
+            target_ssim: None,
+            max_output_size: None,
+            min_quality: 40,
+            min_ssim: None,
+            on_low_quality: LowQualityPolicy::default(),
+            format_overrides: Vec::new(),
+            resize: None,
+            ops: None,
+            resize_filter: options.filter,
+            pad_color: options.pad_color,
+            background: options.background,
+            watermark_position: options.watermark_position,
+            watermark_opacity: options.watermark_opacity,
+            stamp_font: options.stamp_font,
+            stamp_size: options.stamp_size,
+            stamp_position: options.stamp_position,
+        }
+    }
+}
+
+impl ConversionSettings {
+    /// Options used to fill in details of the `ops` entries.
+    pub fn pipeline_options(&self) -> PipelineOptions {
+        PipelineOptions {
+            filter: self.resize_filter,
+            pad_color: self.pad_color,
+            watermark_position: self.watermark_position,
+            watermark_opacity: self.watermark_opacity,
+            background: self.background,
+            stamp_font: self.stamp_font.clone(),
+            stamp_size: self.stamp_size,
+            stamp_position: self.stamp_position,
+        }
+    }
+
+    /// Create a converter builder from these settings, parsing `ops`.
+    pub fn to_builder(&self) -> WebPResult<WebPConverterBuilder> {
+        let pipeline = match &self.ops {
+            Some(ops) => Pipeline::parse(ops, &self.pipeline_options())?,
+            None => Pipeline::default(),
+        };
+
+        let mut builder = WebPConverter::builder()
+            .quality(self.quality)
+            .lossless(self.lossless)
+            .method(self.method)
+            .keep_alpha(self.keep_alpha)
+            .keep_profile(self.keep_profile)
+            .metrics(self.metrics)
+            .metric(self.metric)
+            .detect_artifacts(self.detect_artifacts)
+            .only_if_smaller(self.only_if_smaller)
+            .report_similar_only(self.report_similar_only)
+            .min_quality(self.min_quality)
+            .on_low_quality(self.on_low_quality)
+            .auto_mode(self.auto_mode)
+            .preset(self.preset)
+            .auto_preset(self.auto_preset)
+            .pipeline(pipeline);
+        if let Some(size) = self.resize {
+            builder = builder.resize(size.width, size.height);
+        }
+        if let Some(dpi) = self.dpi {
+            builder = builder.dpi(dpi);
+        }
+        if let Some(tile_size) = self.tile_size {
+            builder = builder.tile_size(tile_size);
+        }
+        if let Some(max_bytes_per_pixel) = self.skip_efficient {
+            builder = builder.skip_efficient(max_bytes_per_pixel);
+        }
+        if let Some(max_distance) = self.dedupe_similar {
+            builder = builder.dedupe_similar(max_distance);
+        }
+        if let Some(diff_output) = &self.diff_output {
+            builder = builder.diff_output(diff_output);
+        }
+        if let Some(target_ssim) = self.target_ssim {
+            builder = builder.target_ssim(target_ssim);
+        }
+        if let Some(min_ssim) = self.min_ssim {
+            builder = builder.min_ssim(min_ssim);
+        }
+        if let Some(max_output_size) = self.max_output_size {
+            builder = builder.max_output_size(max_output_size);
+        }
+        for format_override in &self.format_overrides {
+            builder = builder.format_override(*format_override);
+        }
+        Ok(builder)
+    }
+}
+
+impl WebPConverter {
+    /// Create a converter from serialized settings.
+    pub fn from_settings(settings: &ConversionSettings) -> WebPResult<Self> {
+        settings.to_builder()?.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let settings = ConversionSettings {
+            quality: 85,
+            resize: Some(Dimensions { width: 1600, height: 1200 }),
+            ops: Some("autorotate,sharpen=0.5".to_string()),
+            format_overrides: vec!["png lossless".parse().unwrap()],
+            pad_color: Color::BLACK,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&settings).unwrap();
+        assert!(json.contains("\"resize\":\"1600x1200\""));
+        assert!(json.contains("\"format_overrides\":[\"png lossless\"]"));
+        let parsed: ConversionSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, settings);
+    }
+
+    #[test]
+    fn test_settings_defaults_and_validation() {
+        let settings: ConversionSettings = serde_json::from_str(r#"{ "quality": 90 }"#).unwrap();
+        assert_eq!(settings, ConversionSettings { quality: 90, ..Default::default() });
+        assert!(WebPConverter::from_settings(&settings).is_ok());
+
+        assert!(serde_json::from_str::<ConversionSettings>(r#"{ "qualty": 90 }"#).is_err());
+        assert!(serde_json::from_str::<ConversionSettings>(r#"{ "resize": "big" }"#).is_err());
+
+        let bad_ops = ConversionSettings { ops: Some("explode".to_string()), ..Default::default() };
+        assert!(WebPConverter::from_settings(&bad_ops).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Content-aware cropping toward the most detailed region of an image.
+
+use crate::transform::Dimensions;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+
+/// Crop an image to exactly `target`, keeping the most detailed region.
+///
+/// The image is first scaled so it covers the target size, then a window is
+/// slid along the axis with spare pixels and placed where edge energy peaks.
+pub fn smart_crop(img: &DynamicImage, target: Dimensions, filter: FilterType) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let scale = f64::max(
+        target.width as f64 / width as f64,
+        target.height as f64 / height as f64,
+    );
+    let cover_width = ((width as f64 * scale).ceil() as u32).max(target.width);
+    let cover_height = ((height as f64 * scale).ceil() as u32).max(target.height);
+
+    let covered = if (cover_width, cover_height) == (width, height) {
+        img.clone()
+    } else {
+        img.resize_exact(cover_width, cover_height, filter)
+    };
+
+    let energy = edge_energy(&covered.to_luma8());
+    let (x, y) = if cover_width > target.width {
+        let columns = axis_totals(&energy, cover_width, cover_height, true);
+        (best_offset(&columns, target.width as usize) as u32, 0)
+    } else if cover_height > target.height {
+        let rows = axis_totals(&energy, cover_width, cover_height, false);
+        (0, best_offset(&rows, target.height as usize) as u32)
+    } else {
+        (0, 0)
+    };
+
+    covered.crop_imm(x, y, target.width, target.height)
+}
+
+/// Compute a per-pixel edge energy map (absolute horizontal + vertical gradient).
+fn edge_energy(gray: &GrayImage) -> Vec<u32> {
+    let (width, height) = gray.dimensions();
+    let mut energy = vec![0u32; (width * height) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let center = gray.get_pixel(x, y)[0] as i32;
+            let right = gray.get_pixel((x + 1).min(width - 1), y)[0] as i32;
+            let below = gray.get_pixel(x, (y + 1).min(height - 1))[0] as i32;
+            energy[(y * width + x) as usize] =
+                ((right - center).abs() + (below - center).abs()) as u32;
+        }
+    }
+
+    energy
+}
+
+/// Sum the energy map per column (`columns = true`) or per row.
+fn axis_totals(energy: &[u32], width: u32, height: u32, columns: bool) -> Vec<u64> {
+    let len = if columns { width } else { height } as usize;
+    let mut totals = vec![0u64; len];
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = if columns { x } else { y } as usize;
+            totals[index] += energy[(y * width + x) as usize] as u64;
+        }
+    }
+
+    totals
+}
+
+/// Find the start of the window with the highest total score.
+///
+/// Ties are resolved toward the centered position.
+fn best_offset(scores: &[u64], window: usize) -> usize {
+    if window >= scores.len() {
+        return 0;
+    }
+
+    let center = (scores.len() - window) / 2;
+    let mut sum: u64 = scores[..window].iter().sum();
+    let mut best = (sum, usize::MAX - center, 0);
+
+    for start in 0..=scores.len() - window {
+        if start > 0 {
+            sum = sum + scores[start + window - 1] - scores[start - 1];
+        }
+        let candidate = (sum, usize::MAX - start.abs_diff(center), start);
+        if (candidate.0, candidate.1) > (best.0, best.1) {
+            best = candidate;
+        }
+    }
+
+    best.2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_best_offset() {
+        assert_eq!(best_offset(&[0, 0, 0, 5, 5, 0], 2), 3);
+        assert_eq!(best_offset(&[0, 0, 0, 0, 0], 3), 1);
+        assert_eq!(best_offset(&[1, 2, 3], 5), 0);
+    }
+
+    #[test]
+    fn test_smart_crop_finds_detail() {
+        // Flat image with a checkerboard in the right third
+        let img = RgbImage::from_fn(300, 100, |x, y| {
+            if x >= 200 && (x / 5 + y / 5) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let target = Dimensions { width: 100, height: 100 };
+        let cropped = smart_crop(&DynamicImage::ImageRgb8(img), target, FilterType::Nearest);
+
+        assert_eq!((cropped.width(), cropped.height()), (100, 100));
+        // The crop should contain the checkerboard region
+        let white = cropped.to_luma8().pixels().filter(|p| p[0] == 255).count();
+        assert!(white > 2000);
+    }
+
+    #[test]
+    fn test_smart_crop_scales_to_cover() {
+        let img = DynamicImage::new_rgb8(400, 200);
+        let target = Dimensions { width: 100, height: 100 };
+        let cropped = smart_crop(&img, target, FilterType::Triangle);
+        assert_eq!((cropped.width(), cropped.height()), (100, 100));
+    }
+}
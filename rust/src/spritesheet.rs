@@ -0,0 +1,171 @@
+//! Packing many small images into a single sprite sheet (texture atlas).
+
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::Serialize;
+
+/// Location of one sprite inside the sheet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sprite {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Coordinate map of a packed sprite sheet, written alongside the image as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteMap {
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    /// Density written into the sheet, in pixels per inch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<u32>,
+    pub sprites: Vec<Sprite>,
+}
+
+impl SpriteMap {
+    /// Render CSS classes (`.sprite-<name>`) using the sheet as a background image.
+    pub fn to_css(&self) -> String {
+        let mut css = format!(
+            ".sprite {{\n  background-image: url(\"{}\");\n  background-repeat: no-repeat;\n  display: inline-block;\n}}\n",
+            self.image
+        );
+        for sprite in &self.sprites {
+            css.push_str(&format!(
+                "\n.sprite-{} {{\n  width: {}px;\n  height: {}px;\n  background-position: {} {};\n}}\n",
+                css_identifier(&sprite.name),
+                sprite.width,
+                sprite.height,
+                css_offset(sprite.x),
+                css_offset(sprite.y)
+            ));
+        }
+        css
+    }
+}
+
+/// Negative background offset for a sprite coordinate.
+fn css_offset(value: u32) -> String {
+    if value == 0 {
+        "0".to_string()
+    } else {
+        format!("-{}px", value)
+    }
+}
+
+/// Replace characters that are not valid in a CSS class name.
+pub(crate) fn css_identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect()
+}
+
+/// Lay out rectangles on shelves, tallest first, aiming for a roughly square sheet.
+///
+/// Returns the sheet size and the top-left position of each input, in input order.
+fn pack(sizes: &[(u32, u32)], padding: u32) -> (u32, u32, Vec<(u32, u32)>) {
+    let area: u64 = sizes
+        .iter()
+        .map(|&(w, h)| u64::from(w + padding) * u64::from(h + padding))
+        .sum();
+    let widest = sizes.iter().map(|&(w, _)| w).max().unwrap_or(0);
+    let target_width = widest.max((area as f64).sqrt().ceil() as u32);
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    let (mut sheet_width, mut sheet_height) = (0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if x > 0 && x + w > target_width {
+            y += shelf_height + padding;
+            x = 0;
+            shelf_height = 0;
+        }
+        positions[i] = (x, y);
+        sheet_width = sheet_width.max(x + w);
+        sheet_height = sheet_height.max(y + h);
+        shelf_height = shelf_height.max(h);
+        x += w + padding;
+    }
+
+    (sheet_width, sheet_height, positions)
+}
+
+/// Pack named images into one RGBA sheet, returning the sheet and its coordinate map.
+pub fn build(images: &[(String, DynamicImage)], padding: u32, image_name: &str) -> (RgbaImage, SpriteMap) {
+    let sizes: Vec<(u32, u32)> = images.iter().map(|(_, img)| (img.width(), img.height())).collect();
+    let (width, height, positions) = pack(&sizes, padding);
+
+    let mut sheet = RgbaImage::new(width.max(1), height.max(1));
+    let mut sprites = Vec::with_capacity(images.len());
+    for ((name, img), &(x, y)) in images.iter().zip(&positions) {
+        imageops::replace(&mut sheet, &img.to_rgba8(), i64::from(x), i64::from(y));
+        sprites.push(Sprite {
+            name: name.clone(),
+            x,
+            y,
+            width: img.width(),
+            height: img.height(),
+        });
+    }
+
+    let map = SpriteMap {
+        image: image_name.to_string(),
+        width: sheet.width(),
+        height: sheet.height(),
+        dpi: None,
+        sprites,
+    };
+    (sheet, map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &Sprite, b: &Sprite) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn test_build_packs_without_overlap() {
+        let images: Vec<(String, DynamicImage)> = [(16, 16), (32, 8), (8, 24), (16, 16), (40, 4)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(w, h))| (format!("icon{}", i), DynamicImage::new_rgba8(w, h)))
+            .collect();
+
+        let (sheet, map) = build(&images, 2, "icons.webp");
+        assert_eq!((sheet.width(), sheet.height()), (map.width, map.height));
+        assert_eq!(map.sprites.len(), 5);
+        assert_eq!(map.sprites[1].name, "icon1");
+        assert_eq!((map.sprites[1].width, map.sprites[1].height), (32, 8));
+
+        for (i, a) in map.sprites.iter().enumerate() {
+            assert!(a.x + a.width <= map.width && a.y + a.height <= map.height);
+            for b in &map.sprites[i + 1..] {
+                assert!(!overlaps(a, b), "{} overlaps {}", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_css_output() {
+        let map = SpriteMap {
+            image: "icons.webp".to_string(),
+            width: 32,
+            height: 16,
+            dpi: None,
+            sprites: vec![Sprite { name: "arrow left".to_string(), x: 16, y: 0, width: 16, height: 16 }],
+        };
+        let css = map.to_css();
+        assert!(css.contains("url(\"icons.webp\")"));
+        assert!(css.contains(".sprite-arrow-left {"));
+        assert!(css.contains("background-position: -16px 0;"));
+    }
+}
@@ -0,0 +1,401 @@
+//! Cloud storage batch jobs: convert every image under a bucket prefix and
+//! upload the WebP files under a destination prefix.
+//!
+//! Each cloud is a [`StorageBackend`]. Amazon S3 (`s3://`), Google Cloud
+//! Storage (`gs://`) and Azure Blob Storage (`az://`) are built in behind the
+//! `s3`, `gcs` and `azure` features.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::utils;
+use futures::TryStreamExt;
+use image::ImageFormat;
+use object_store::path::Path as ObjectPath;
+use object_store::{Attribute, Attributes, ObjectMeta, ObjectStore, PutOptions, PutPayload};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A cloud storage service holding buckets (or containers) of objects.
+pub trait StorageBackend: Send + Sync {
+    /// URL scheme of locations on this backend, e.g. `s3`.
+    fn scheme(&self) -> &'static str;
+
+    /// Object store for one bucket or container.
+    fn open(&self, bucket: &str) -> WebPResult<Arc<dyn ObjectStore>>;
+}
+
+/// Amazon S3 and compatible services, configured from the `AWS_*`
+/// environment variables (credentials, region, endpoint).
+#[cfg(feature = "s3")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct S3Backend;
+
+#[cfg(feature = "s3")]
+impl StorageBackend for S3Backend {
+    fn scheme(&self) -> &'static str {
+        "s3"
+    }
+
+    fn open(&self, bucket: &str) -> WebPResult<Arc<dyn ObjectStore>> {
+        let store = object_store::aws::AmazonS3Builder::from_env().with_bucket_name(bucket).build()?;
+        Ok(Arc::new(store))
+    }
+}
+
+/// Google Cloud Storage, configured from the `GOOGLE_*` environment
+/// variables (e.g. `GOOGLE_SERVICE_ACCOUNT`).
+#[cfg(feature = "gcs")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcsBackend;
+
+#[cfg(feature = "gcs")]
+impl StorageBackend for GcsBackend {
+    fn scheme(&self) -> &'static str {
+        "gs"
+    }
+
+    fn open(&self, bucket: &str) -> WebPResult<Arc<dyn ObjectStore>> {
+        let store = object_store::gcp::GoogleCloudStorageBuilder::from_env().with_bucket_name(bucket).build()?;
+        Ok(Arc::new(store))
+    }
+}
+
+/// Azure Blob Storage, configured from the `AZURE_*` environment variables
+/// (e.g. `AZURE_STORAGE_ACCOUNT_NAME` and `AZURE_STORAGE_ACCOUNT_KEY`).
+#[cfg(feature = "azure")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AzureBackend;
+
+#[cfg(feature = "azure")]
+impl StorageBackend for AzureBackend {
+    fn scheme(&self) -> &'static str {
+        "az"
+    }
+
+    fn open(&self, container: &str) -> WebPResult<Arc<dyn ObjectStore>> {
+        let store = object_store::azure::MicrosoftAzureBuilder::from_env().with_container_name(container).build()?;
+        Ok(Arc::new(store))
+    }
+}
+
+/// The backends compiled into this build.
+// One push per feature, so any subset can be compiled in
+#[allow(unused_mut, clippy::vec_init_then_push)]
+pub fn builtin_backends() -> Vec<Arc<dyn StorageBackend>> {
+    let mut backends: Vec<Arc<dyn StorageBackend>> = Vec::new();
+    #[cfg(feature = "s3")]
+    backends.push(Arc::new(S3Backend));
+    #[cfg(feature = "gcs")]
+    backends.push(Arc::new(GcsBackend));
+    #[cfg(feature = "azure")]
+    backends.push(Arc::new(AzureBackend));
+    backends
+}
+
+/// A bucket and key prefix on a backend, written like
+/// `s3://bucket/photos/2024` or `gs://bucket/photos`.
+#[derive(Clone)]
+pub struct RemoteLocation {
+    pub backend: Arc<dyn StorageBackend>,
+    pub bucket: String,
+    /// Key prefix without leading or trailing slashes; empty for the whole bucket.
+    pub prefix: String,
+}
+
+impl RemoteLocation {
+    /// A location on any backend, including ones defined outside this crate.
+    pub fn new(backend: Arc<dyn StorageBackend>, bucket: impl Into<String>, prefix: &str) -> Self {
+        Self { backend, bucket: bucket.into(), prefix: prefix.trim_matches('/').to_string() }
+    }
+}
+
+impl fmt::Debug for RemoteLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RemoteLocation({})", self)
+    }
+}
+
+impl PartialEq for RemoteLocation {
+    fn eq(&self, other: &Self) -> bool {
+        self.backend.scheme() == other.backend.scheme() && self.bucket == other.bucket && self.prefix == other.prefix
+    }
+}
+
+/// Parses `SCHEME://BUCKET/PREFIX` against the built-in backends.
+impl FromStr for RemoteLocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .trim()
+            .split_once("://")
+            .ok_or_else(|| format!("expected a location like s3://bucket/prefix, got '{}'", s))?;
+        let backend = builtin_backends().into_iter().find(|backend| backend.scheme() == scheme).ok_or_else(|| {
+            let feature = match scheme {
+                "s3" => "s3",
+                "gs" => "gcs",
+                "az" => "azure",
+                _ => return format!("unknown storage scheme '{}://' (expected s3://, gs:// or az://)", scheme),
+            };
+            format!("{}:// locations require building with --features {}", scheme, feature)
+        })?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("missing bucket name in '{}'", s));
+        }
+        Ok(Self::new(backend, bucket, prefix))
+    }
+}
+
+impl fmt::Display for RemoteLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}/{}", self.backend.scheme(), self.bucket, self.prefix)
+    }
+}
+
+/// What happened to one source object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectStatus {
+    /// A new or updated WebP was uploaded.
+    Converted,
+    /// The destination was already newer than the source.
+    Unchanged,
+    Failed,
+}
+
+/// One source object of a job.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestEntry {
+    pub source: String,
+    pub destination: String,
+    pub status: ObjectStatus,
+    pub original_size: u64,
+    /// Size of the uploaded WebP, when converted.
+    pub compressed_size: Option<u64>,
+    /// Why the conversion failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Every source object of a job and what happened to it, in listing order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Number of entries with the given status.
+    pub fn count(&self, status: ObjectStatus) -> usize {
+        self.entries.iter().filter(|entry| entry.status == status).count()
+    }
+}
+
+impl WebPConverter {
+    /// Convert every image under `source` and upload the results under
+    /// `destination`, keeping the relative key layout (`a/b.jpg` becomes
+    /// `a/b.webp`) and setting `Content-Type: image/webp`. The two locations
+    /// may be on different backends.
+    ///
+    /// Up to `concurrency` objects are downloaded, converted and uploaded at
+    /// once, as the listing streams in. Objects whose destination is already
+    /// newer are left alone, and a failed object does not stop the job.
+    pub async fn convert_remote(
+        &self,
+        source: &RemoteLocation,
+        destination: &RemoteLocation,
+        concurrency: usize,
+    ) -> WebPResult<Manifest> {
+        let source_store = source.backend.open(&source.bucket)?;
+        let destination_store = destination.backend.open(&destination.bucket)?;
+        self.convert_objects(&*source_store, &source.prefix, &*destination_store, &destination.prefix, concurrency)
+            .await
+    }
+
+    /// [`convert_remote`](Self::convert_remote) against any object stores.
+    pub(crate) async fn convert_objects(
+        &self,
+        source: &dyn ObjectStore,
+        source_prefix: &str,
+        destination: &dyn ObjectStore,
+        destination_prefix: &str,
+        concurrency: usize,
+    ) -> WebPResult<Manifest> {
+        let prefix = (!source_prefix.is_empty()).then(|| ObjectPath::from(source_prefix));
+        let objects = source
+            .list(prefix.as_ref())
+            .try_filter(|meta| {
+                let path = Path::new(meta.location.as_ref());
+                futures::future::ready(utils::is_supported_extension(path) && !utils::is_webp_file(path))
+            })
+            .map_err(WebPError::from);
+
+        let mut entries = Vec::new();
+        let mut results = objects
+            .map_ok(|meta| self.convert_object(source, meta, source_prefix, destination, destination_prefix))
+            .try_buffered(concurrency.max(1));
+        while let Some(entry) = results.try_next().await? {
+            entries.push(entry);
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Convert and upload one object, recording failures in its entry.
+    async fn convert_object(
+        &self,
+        source: &dyn ObjectStore,
+        meta: ObjectMeta,
+        source_prefix: &str,
+        destination: &dyn ObjectStore,
+        destination_prefix: &str,
+    ) -> WebPResult<ManifestEntry> {
+        let start_time = Instant::now();
+        let key = meta.location.to_string();
+        let destination_key = destination_key(&key, source_prefix, destination_prefix);
+        let destination_path = ObjectPath::from(destination_key.as_str());
+        let mut entry = ManifestEntry {
+            source: key.clone(),
+            destination: destination_key.clone(),
+            status: ObjectStatus::Unchanged,
+            original_size: meta.size,
+            compressed_size: None,
+            error: None,
+        };
+
+        if let Ok(existing) = destination.head(&destination_path).await {
+            if existing.last_modified >= meta.last_modified {
+                self.report(Event::FileSkipped { path: Path::new(&key), reason: "destination is up to date" });
+                return Ok(entry);
+            }
+        }
+
+        let result = async {
+            let location = &meta.location;
+            let input = self
+                .retrying_async(Path::new(&key), move || async move { Ok(source.get(location).await?.bytes().await?) })
+                .await?;
+            let converter = self.clone();
+            // The content decides, as for local files; the key's extension only fills in
+            let hint = image::guess_format(&input).ok().or_else(|| ImageFormat::from_path(&key).ok());
+            let webp_data = tokio::task::spawn_blocking(move || converter.convert_from_reader(&input[..], hint))
+                .await
+                .map_err(|e| WebPError::EncodingError(format!("Encoding task failed: {}", e)))??;
+
+            let options = PutOptions {
+                attributes: Attributes::from_iter([(Attribute::ContentType, "image/webp")]),
+                ..Default::default()
+            };
+            let size = webp_data.len() as u64;
+            let (payload, options, destination_path) = (&PutPayload::from(webp_data), &options, &destination_path);
+            self.retrying_async(Path::new(&key), move || async move {
+                Ok(destination.put_opts(destination_path, payload.clone(), options.clone()).await?)
+            })
+            .await?;
+            Ok::<_, WebPError>(size)
+        }
+        .await;
+
+        match result {
+            Ok(compressed_size) => {
+                entry.status = ObjectStatus::Converted;
+                entry.compressed_size = Some(compressed_size);
+                self.report(Event::FileConverted {
+                    input: Path::new(&key),
+                    output: Path::new(&destination_key),
+                    original_size: meta.size,
+                    compressed_size,
+                    time_taken: start_time.elapsed().as_secs_f64(),
+                    metrics: None,
+                    content: None,
+                });
+            }
+            Err(error) => {
+                self.report(Event::FileFailed { path: Path::new(&key), error: &error });
+                entry.status = ObjectStatus::Failed;
+                entry.error = Some(error.to_string());
+            }
+        }
+        Ok(entry)
+    }
+}
+
+/// Destination key of a source object: the key relative to the source
+/// prefix, under the destination prefix, with a `.webp` extension.
+fn destination_key(key: &str, source_prefix: &str, destination_prefix: &str) -> String {
+    let relative = key.strip_prefix(source_prefix).unwrap_or(key).trim_start_matches('/');
+    let stem = match relative.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem,
+        _ => relative,
+    };
+    if destination_prefix.is_empty() {
+        format!("{}.webp", stem)
+    } else {
+        format!("{}/{}.webp", destination_prefix, stem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    /// A backend serving the same in-memory store for every bucket.
+    struct MemoryBackend(Arc<InMemory>);
+
+    impl StorageBackend for MemoryBackend {
+        fn scheme(&self) -> &'static str {
+            "mem"
+        }
+
+        fn open(&self, _bucket: &str) -> WebPResult<Arc<dyn ObjectStore>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_parse_location() {
+        #[cfg(feature = "s3")]
+        {
+            let location: RemoteLocation = "s3://media/photos/2024/".parse().unwrap();
+            assert_eq!((location.bucket.as_str(), location.prefix.as_str()), ("media", "photos/2024"));
+            assert_eq!(location.to_string(), "s3://media/photos/2024");
+            assert!("s3:///photos".parse::<RemoteLocation>().is_err());
+        }
+        assert!("media/photos".parse::<RemoteLocation>().is_err());
+        assert!("ftp://host/photos".parse::<RemoteLocation>().is_err());
+
+        assert_eq!(destination_key("in/a/b.jpg", "in", "out"), "out/a/b.webp");
+        assert_eq!(destination_key("b.png", "", ""), "b.webp");
+    }
+
+    #[tokio::test]
+    async fn test_convert_remote() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, image::Rgb([30, 160, 90])))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let store = Arc::new(InMemory::new());
+        store.put(&ObjectPath::from("in/a/one.png"), png.into()).await.unwrap();
+        store.put(&ObjectPath::from("in/broken.jpg"), b"not a jpeg".to_vec().into()).await.unwrap();
+        store.put(&ObjectPath::from("in/notes.txt"), b"skip me".to_vec().into()).await.unwrap();
+        let converter = WebPConverter::builder().build().unwrap();
+        let backend: Arc<dyn StorageBackend> = Arc::new(MemoryBackend(store.clone()));
+        let source = RemoteLocation::new(backend.clone(), "media", "in/");
+        let destination = RemoteLocation::new(backend, "media", "out");
+
+        let manifest = converter.convert_remote(&source, &destination, 2).await.unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!((manifest.count(ObjectStatus::Converted), manifest.count(ObjectStatus::Failed)), (1, 1));
+        let uploaded = store.get(&ObjectPath::from("out/a/one.webp")).await.unwrap();
+        assert_eq!(uploaded.attributes.get(&Attribute::ContentType).map(|v| v.as_ref()), Some("image/webp"));
+
+        // A second run leaves the up-to-date output alone
+        let manifest = converter.convert_remote(&source, &destination, 2).await.unwrap();
+        assert_eq!(manifest.count(ObjectStatus::Unchanged), 1);
+    }
+}
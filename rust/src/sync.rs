@@ -0,0 +1,334 @@
+//! Sync mode: keep a folder as a WebP mirror of another, like rsync for WebP.
+
+use crate::converter::{ConversionStats, WebPConverter};
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// How often a repeated sync checks for cancellation between passes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Name of the manifest of mirrored outputs inside the destination.
+pub const MANIFEST_FILE: &str = ".webp-converter-sync.json";
+
+/// Outputs sync has written or found up to date, so `--delete` only ever
+/// removes those and leaves WebP files it did not make alone.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    outputs: Vec<Mirrored>,
+}
+
+/// One mirrored output, relative to the destination, and its source,
+/// relative to the source folder.
+#[derive(Debug, Serialize, Deserialize)]
+struct Mirrored {
+    #[serde(with = "crate::path_serde")]
+    output: PathBuf,
+    #[serde(with = "crate::path_serde")]
+    source: PathBuf,
+}
+
+/// What a sync changed.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Conversions of new and changed sources.
+    pub stats: ConversionStats,
+    /// Sources whose mirrored WebP was already up to date.
+    pub unchanged: usize,
+    /// Orphaned WebP files removed from the destination.
+    pub deleted: Vec<PathBuf>,
+}
+
+impl WebPConverter {
+    /// Make `destination` a WebP mirror of `source`: `a/b.jpg` becomes
+    /// `a/b.webp`, converted only when missing or older than its source.
+    ///
+    /// The outputs are listed in [`MANIFEST_FILE`] in the destination. With
+    /// `delete_orphans`, listed outputs whose source no longer exists are
+    /// removed; other files, such as WebP files that were there before, are
+    /// never deleted, and nothing is deleted when the sync is cancelled,
+    /// since not every source has been seen. Deleting needs source and
+    /// destination to be separate folders, neither inside the other.
+    pub fn sync(
+        &self,
+        source: &Path,
+        destination: &Path,
+        recursive: bool,
+        delete_orphans: bool,
+    ) -> WebPResult<SyncReport> {
+        if !source.is_dir() {
+            return Err(WebPError::InvalidInputType(source.to_path_buf()));
+        }
+        fs::create_dir_all(destination)?;
+        if delete_orphans && overlaps(source, destination)? {
+            return Err(WebPError::InvalidSettings(format!(
+                "cannot delete orphans when {} and {} are the same folder or one is inside the other",
+                source.display(),
+                destination.display()
+            )));
+        }
+
+        let mut report = SyncReport::default();
+        let mut manifest = read_manifest(destination);
+        let mut expected = HashSet::new();
+        let mut similar = self.similar_images();
+        let sources = find_sources(source, recursive)?;
+        let mirrored = |input: &Path| destination.join(input.strip_prefix(source).unwrap_or(input)).with_extension("webp");
+        let plan = self.plan_outputs(&sources, |input| Ok(mirrored(input)));
+
+        for input in sources {
+            if self.is_cancelled() {
+                report.stats.cancelled = true;
+                break;
+            }
+            let default = mirrored(&input);
+            let relative = input.strip_prefix(source).unwrap_or(&input).to_path_buf();
+            // A refused output fails below, when converting
+            let output = plan.output(&input, default.clone()).ok();
+            if let Some(output) = &output {
+                expected.insert(output.clone());
+                if is_up_to_date(&input, output) {
+                    self.report(Event::FileSkipped { path: &input, reason: "up to date" });
+                    report.unchanged += 1;
+                    record(&mut manifest, destination, output, relative);
+                    continue;
+                }
+            }
+
+            let folder = default.parent().unwrap_or(destination);
+            let result = self.convert_file(input, Some(folder), &plan, similar.as_mut());
+            let stats = &mut report.stats;
+            match result.error {
+                Some(error) => {
+                    self.report(Event::FileFailed { path: &result.input, error: &error });
+                    stats.add_failure(result.input, error);
+                }
+                None if result.discarded => stats.add_not_smaller(),
+                None if result.low_quality => stats.add_low_quality(),
+                None if result.duplicate_of.is_some() => stats.add_similar(),
+                None if result.already_efficient => stats.add_efficient(),
+                None => {
+                    if let Some(output) = &output {
+                        record(&mut manifest, destination, output, relative);
+                    }
+                    stats.add_success(
+                        result.input,
+                        result.time_taken,
+                        result.original_size.unwrap_or(0),
+                        result.compressed_size.unwrap_or(0),
+                    );
+                    if let Some(metrics) = result.metrics {
+                        stats.add_metrics(metrics);
+                    }
+                    if !result.artifacts.is_empty() {
+                        stats.add_artifact_warning();
+                    }
+                }
+            }
+        }
+
+        if delete_orphans && !report.stats.cancelled {
+            // Only outputs this tool made, and only once their source is gone
+            let orphans: Vec<PathBuf> = manifest
+                .iter()
+                .filter(|(output, source_file)| !expected.contains(&destination.join(output)) && !source.join(source_file).exists())
+                .map(|(output, _)| output.clone())
+                .collect();
+            for output in orphans {
+                manifest.remove(&output);
+                let path = destination.join(output);
+                match fs::remove_file(&path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                }
+                self.report(Event::OrphanDeleted { path: &path });
+                report.deleted.push(path);
+            }
+        }
+        manifest.retain(|output, _| destination.join(output).is_file());
+        write_manifest(destination, &manifest)?;
+
+        Ok(report)
+    }
+
+    /// Run [`sync`](Self::sync) every `interval` until the converter's
+    /// cancellation token is triggered, handing each pass's report to
+    /// `on_pass`. Returns the statistics of all passes together.
+    pub fn sync_every(
+        &self,
+        source: &Path,
+        destination: &Path,
+        recursive: bool,
+        delete_orphans: bool,
+        interval: Duration,
+        mut on_pass: impl FnMut(&SyncReport),
+    ) -> WebPResult<ConversionStats> {
+        let mut total = ConversionStats::new();
+        loop {
+            let next_pass = Instant::now() + interval;
+            let report = self.sync(source, destination, recursive, delete_orphans)?;
+            on_pass(&report);
+            total.merge(report.stats);
+
+            while !self.is_cancelled() && Instant::now() < next_pass {
+                std::thread::sleep(POLL_INTERVAL.min(next_pass.saturating_duration_since(Instant::now())));
+            }
+            if self.is_cancelled() {
+                total.cancelled = true;
+                return Ok(total);
+            }
+        }
+    }
+}
+
+/// Convertible images below `source`, in a stable order.
+fn find_sources(source: &Path, recursive: bool) -> WebPResult<Vec<PathBuf>> {
+    let depth = if recursive { usize::MAX } else { 1 };
+    let mut sources = Vec::new();
+    for entry in WalkDir::new(source).max_depth(depth).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type().is_file() && utils::is_supported_extension(path) && !utils::is_webp_file(path) {
+            sources.push(entry.into_path());
+        }
+    }
+    Ok(sources)
+}
+
+/// Whether two folders are the same or one is inside the other.
+fn overlaps(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a, b) = (fs::canonicalize(a)?, fs::canonicalize(b)?);
+    Ok(a.starts_with(&b) || b.starts_with(&a))
+}
+
+/// The manifest of `destination`, by output; empty when there is none yet
+/// or it cannot be read, in which case nothing is deleted.
+fn read_manifest(destination: &Path) -> BTreeMap<PathBuf, PathBuf> {
+    fs::read(destination.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<Manifest>(&contents).ok())
+        .map(|manifest| manifest.outputs.into_iter().map(|mirrored| (mirrored.output, mirrored.source)).collect())
+        .unwrap_or_default()
+}
+
+fn write_manifest(destination: &Path, manifest: &BTreeMap<PathBuf, PathBuf>) -> io::Result<()> {
+    let outputs = manifest.iter().map(|(output, source)| Mirrored { output: output.clone(), source: source.clone() }).collect();
+    let json = serde_json::to_vec_pretty(&Manifest { outputs })?;
+    fs::write(destination.join(MANIFEST_FILE), json)
+}
+
+/// Add `output`, mirrored from `source` (relative to the source folder), to
+/// the manifest.
+fn record(manifest: &mut BTreeMap<PathBuf, PathBuf>, destination: &Path, output: &Path, source: PathBuf) {
+    if let Ok(output) = output.strip_prefix(destination) {
+        manifest.insert(output.to_path_buf(), source);
+    }
+}
+
+/// Whether `output` exists and is at least as new as `input`.
+fn is_up_to_date(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(input), modified(output)) {
+        (Ok(input), Ok(output)) => output >= input,
+        _ => false,
+    }
+}
+
+// Mirrors are encoded lossily, which needs libwebp
+#[cfg(all(test, feature = "libwebp"))]
+mod tests {
+    use crate::progress::CancellationToken;
+    use crate::{CollisionPolicy, WebPConverter};
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+        fs::create_dir_all(source.join("nested")).unwrap();
+        let pixel = image::Rgb([120, 60, 200]);
+        image::RgbImage::from_pixel(16, 16, pixel).save(source.join("a.png")).unwrap();
+        image::RgbImage::from_pixel(16, 16, pixel).save(source.join("nested/b.png")).unwrap();
+        let converter = WebPConverter::builder().build().unwrap();
+
+        let report = converter.sync(&source, &destination, true, true).unwrap();
+        assert_eq!((report.stats.success_count, report.unchanged), (2, 0));
+        assert!(destination.join("nested/b.webp").exists());
+
+        // A second run only deletes the output of the removed source
+        fs::remove_file(source.join("nested/b.png")).unwrap();
+        fs::write(destination.join("stale.txt"), "not ours").unwrap();
+        let report = converter.sync(&source, &destination, true, true).unwrap();
+        assert_eq!((report.stats.success_count, report.unchanged), (0, 1));
+        assert_eq!(report.deleted, vec![destination.join("nested/b.webp")]);
+        assert!(destination.join("stale.txt").exists());
+    }
+
+    #[test]
+    fn test_sync_delete_keeps_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+        fs::create_dir(&source).unwrap();
+        fs::create_dir(&destination).unwrap();
+        let pixel = image::Rgb([120, 60, 200]);
+        image::RgbImage::from_pixel(16, 16, pixel).save(source.join("a.png")).unwrap();
+        image::RgbImage::from_pixel(16, 16, pixel).save(source.join("a.jpg")).unwrap();
+        fs::write(destination.join("original-only.webp"), "not ours").unwrap();
+
+        // a.png goes to a.png.webp, then clashes with a.jpg once refused
+        let renaming = WebPConverter::builder().on_collision(CollisionPolicy::Rename).build().unwrap();
+        renaming.sync(&source, &destination, false, true).unwrap();
+        assert!(destination.join("a.png.webp").exists());
+        let report = WebPConverter::builder().build().unwrap().sync(&source, &destination, false, true).unwrap();
+        assert_eq!(report.stats.failed_count, 1);
+        assert!(report.deleted.is_empty());
+        assert!(destination.join("original-only.webp").exists() && destination.join("a.png.webp").exists());
+
+        // Once its source is gone, the renamed output is an orphan
+        fs::remove_file(source.join("a.png")).unwrap();
+        let report = WebPConverter::builder().build().unwrap().sync(&source, &destination, false, true).unwrap();
+        assert_eq!(report.deleted, vec![destination.join("a.png.webp")]);
+        assert!(destination.join("original-only.webp").exists());
+
+        // A folder cannot be its own mirror when deleting
+        let converter = WebPConverter::builder().build().unwrap();
+        assert!(converter.sync(&source, &source, false, true).is_err());
+        assert!(converter.sync(dir.path(), &destination, false, true).is_err());
+        assert!(destination.join("original-only.webp").exists());
+    }
+
+    #[test]
+    fn test_sync_every() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, destination) = (dir.path().join("src"), dir.path().join("dst"));
+        fs::create_dir(&source).unwrap();
+        image::RgbImage::from_pixel(16, 16, image::Rgb([30, 60, 90])).save(source.join("a.png")).unwrap();
+        let token = CancellationToken::new();
+        let converter = WebPConverter::builder().cancellation(token.clone()).build().unwrap();
+
+        // Add a file after the first pass and stop after the second
+        let mut passes = Vec::new();
+        let stats = converter
+            .sync_every(&source, &destination, false, false, Duration::from_millis(50), |report| {
+                passes.push((report.stats.success_count, report.unchanged));
+                if passes.len() == 1 {
+                    image::RgbImage::from_pixel(16, 16, image::Rgb([90, 60, 30])).save(source.join("b.png")).unwrap();
+                } else {
+                    token.cancel();
+                }
+            })
+            .unwrap();
+        assert_eq!(passes, vec![(1, 0), (1, 1)]);
+        assert_eq!(stats.success_count, 2);
+        assert!(stats.cancelled);
+    }
+}
@@ -0,0 +1,98 @@
+//! Splitting large images into a grid of tiles for deep-zoom and map viewers.
+
+use crate::utils;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Position and size of one tile within the source image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tile {
+    pub row: u32,
+    pub column: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub file: String,
+}
+
+/// JSON index describing a tiled image.
+#[derive(Debug, Clone, Serialize)]
+pub struct TileIndex {
+    pub source: String,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Density written into every tile, in pixels per inch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<u32>,
+    pub tiles: Vec<Tile>,
+}
+
+impl TileIndex {
+    /// Lay out the tile grid for an image; edge tiles may be smaller than `tile_size`.
+    pub fn new(source: &Path, width: u32, height: u32, tile_size: u32) -> Self {
+        let columns = width.div_ceil(tile_size);
+        let rows = height.div_ceil(tile_size);
+
+        let mut tiles = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = column * tile_size;
+                let y = row * tile_size;
+                tiles.push(Tile {
+                    row,
+                    column,
+                    x,
+                    y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                    file: format!("tile_{}_{}.webp", row, column),
+                });
+            }
+        }
+
+        Self {
+            source: utils::escape_path(Path::new(source.file_name().unwrap_or_default())),
+            width,
+            height,
+            tile_size,
+            columns,
+            rows,
+            dpi: None,
+            tiles,
+        }
+    }
+}
+
+/// Directory receiving the tiles for a given output path (`photo.webp` → `photo_tiles/`).
+pub fn tiles_dir(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_stem().unwrap_or_default().to_os_string();
+    name.push("_tiles");
+    output_path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_layout() {
+        let index = TileIndex::new(Path::new("/images/map.png"), 1100, 600, 512);
+        assert_eq!((index.columns, index.rows), (3, 2));
+        assert_eq!(index.tiles.len(), 6);
+        assert_eq!(index.source, "map.png");
+
+        let last = index.tiles.last().unwrap();
+        assert_eq!((last.row, last.column), (1, 2));
+        assert_eq!((last.x, last.y, last.width, last.height), (1024, 512, 76, 88));
+        assert_eq!(last.file, "tile_1_2.webp");
+    }
+
+    #[test]
+    fn test_tiles_dir() {
+        assert_eq!(tiles_dir(Path::new("out/map.webp")), PathBuf::from("out/map_tiles"));
+    }
+}
@@ -0,0 +1,1171 @@
+//! Image transforms applied before WebP encoding.
+
+use crate::error::{WebPError, WebPResult};
+use crate::pipeline::{Operation, Pipeline};
+use crate::watermark::{Stamp, Watermark};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::str::FromStr;
+
+/// Implement serde support for a value type through its `Display`/`FromStr` form,
+/// so settings files use the same spelling as the command line (`800x600`, `#ffffff`).
+macro_rules! serde_as_string {
+    ($($ty:ty),+) => {$(
+        impl serde::Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+                value.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    )+};
+}
+pub(crate) use serde_as_string;
+
+serde_as_string!(Dimensions, Color, ResizeFilter);
+
+/// Proportional scale factor, parsed from values like `50%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale(f64);
+
+impl Scale {
+    /// Scale factor as a fraction (e.g. `0.5` for `50%`).
+    pub fn factor(&self) -> f64 {
+        self.0
+    }
+
+    /// Compute the scaled dimensions, never collapsing below one pixel.
+    pub fn apply_to(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |v: u32| ((v as f64 * self.0).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().trim_end_matches('%');
+        let percent: f64 = value
+            .parse()
+            .map_err(|_| format!("Invalid scale '{}': expected a percentage like 50%", s))?;
+
+        if !(percent > 0.0 && percent <= 100.0) {
+            return Err(format!("Invalid scale '{}': must be between 0% and 100%", s));
+        }
+
+        Ok(Scale(percent / 100.0))
+    }
+}
+
+/// Upscale factor, parsed from values like `2x`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Upscale(f64);
+
+impl Upscale {
+    /// Upscale factor (e.g. `2.0` for `2x`).
+    pub fn factor(&self) -> f64 {
+        self.0
+    }
+
+    /// Compute the enlarged dimensions.
+    pub fn apply_to(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |v: u32| (v as f64 * self.0).round() as u32;
+        (scaled(width), scaled(height))
+    }
+}
+
+impl FromStr for Upscale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().trim_end_matches(['x', 'X']);
+        let factor: f64 = value
+            .parse()
+            .map_err(|_| format!("Invalid upscale '{}': expected a factor like 2x", s))?;
+
+        if !(factor > 1.0 && factor <= 8.0) {
+            return Err(format!("Invalid upscale '{}': must be greater than 1x and at most 8x", s));
+        }
+
+        Ok(Upscale(factor))
+    }
+}
+
+/// Pixel dimensions, parsed from values like `800x600`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Dimensions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid dimensions '{}': expected WxH (e.g., 800x600)", s);
+        let (width, height) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+        let width: u32 = width.parse().map_err(|_| invalid())?;
+        let height: u32 = height.parse().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid dimensions '{}': width and height must be positive", s));
+        }
+
+        Ok(Dimensions { width, height })
+    }
+}
+
+impl std::fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Crop rectangle, parsed from values like `800x600+10+20`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRegion {
+    pub size: Dimensions,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl FromStr for CropRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid crop '{}': expected WxH+X+Y (e.g., 800x600+10+20)", s);
+        let mut parts = s.trim().split('+');
+        let size: Dimensions = parts.next().ok_or_else(invalid)?.parse()?;
+        let x: u32 = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+        let y: u32 = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(CropRegion { size, x, y })
+    }
+}
+
+/// How an image should be cropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crop {
+    /// Crop an explicit region.
+    Region(CropRegion),
+    /// Crop a region of the given size centered in the image.
+    Center(Dimensions),
+}
+
+impl Crop {
+    /// Resolve the crop rectangle `(x, y, width, height)` for an image, clamped to its bounds.
+    pub fn rect_for(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let (x, y, w, h) = match *self {
+            Crop::Region(region) => (region.x, region.y, region.size.width, region.size.height),
+            Crop::Center(size) => {
+                let w = size.width.min(width);
+                let h = size.height.min(height);
+                ((width - w) / 2, (height - h) / 2, w, h)
+            }
+        };
+
+        if x >= width || y >= height {
+            return None;
+        }
+
+        Some((x, y, w.min(width - x), h.min(height - y)))
+    }
+}
+
+/// Clockwise rotation in 90-degree steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "90" => Ok(Rotation::Rotate90),
+            "180" => Ok(Rotation::Rotate180),
+            "270" => Ok(Rotation::Rotate270),
+            _ => Err(format!("Invalid rotation '{}': expected 90, 180 or 270", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Rotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let degrees = match self {
+            Rotation::Rotate90 => 90,
+            Rotation::Rotate180 => 180,
+            Rotation::Rotate270 => 270,
+        };
+        write!(f, "{}°", degrees)
+    }
+}
+
+/// Mirror axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+}
+
+impl FromStr for Flip {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "h" | "horizontal" => Ok(Flip::Horizontal),
+            "v" | "vertical" => Ok(Flip::Vertical),
+            _ => Err(format!("Invalid flip '{}': expected h or v", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Flip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Flip::Horizontal => write!(f, "horizontal"),
+            Flip::Vertical => write!(f, "vertical"),
+        }
+    }
+}
+
+/// RGBA color, parsed from `#rrggbb`, `#rrggbbaa` or a name like `white`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub [u8; 4]);
+
+impl Color {
+    pub const TRANSPARENT: Color = Color([0, 0, 0, 0]);
+    pub const WHITE: Color = Color([255, 255, 255, 255]);
+    pub const BLACK: Color = Color([0, 0, 0, 255]);
+
+    /// Check whether the color is fully opaque.
+    pub fn is_opaque(&self) -> bool {
+        self.0[3] == 255
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_lowercase();
+        match value.as_str() {
+            "transparent" => return Ok(Color::TRANSPARENT),
+            "white" => return Ok(Color::WHITE),
+            "black" => return Ok(Color::BLACK),
+            _ => {}
+        }
+
+        let invalid = || format!("Invalid color '{}': expected #rrggbb, #rrggbbaa, white, black or transparent", s);
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut rgba = [255u8; 4];
+        for (i, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+
+        Ok(Color(rgba))
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b, a] = self.0;
+        if a == 255 {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+/// Target shape for padding, parsed from an aspect ratio (`1:1`) or canvas size (`800x800`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadTarget {
+    Aspect(u32, u32),
+    Canvas(Dimensions),
+}
+
+impl PadTarget {
+    /// Compute the padded canvas size for an image of the given size.
+    ///
+    /// For a fixed canvas, `None` is returned when the image must first be
+    /// shrunk to fit inside it.
+    pub fn canvas_for(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        match *self {
+            PadTarget::Aspect(aw, ah) => {
+                // Grow whichever side is too short for the requested ratio
+                let (w, h) = (width as u64, height as u64);
+                if w * ah as u64 >= h * aw as u64 {
+                    Some((width, (w * ah as u64).div_ceil(aw as u64) as u32))
+                } else {
+                    Some(((h * aw as u64).div_ceil(ah as u64) as u32, height))
+                }
+            }
+            PadTarget::Canvas(size) => {
+                (width <= size.width && height <= size.height).then_some((size.width, size.height))
+            }
+        }
+    }
+}
+
+impl FromStr for PadTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((w, h)) = s.trim().split_once(':') {
+            let invalid = || format!("Invalid aspect ratio '{}': expected W:H (e.g., 1:1 or 16:9)", s);
+            let w: u32 = w.parse().map_err(|_| invalid())?;
+            let h: u32 = h.parse().map_err(|_| invalid())?;
+            if w == 0 || h == 0 {
+                return Err(invalid());
+            }
+            return Ok(PadTarget::Aspect(w, h));
+        }
+
+        s.parse::<Dimensions>()
+            .map(PadTarget::Canvas)
+            .map_err(|_| format!("Invalid pad target '{}': expected W:H or WxH", s))
+    }
+}
+
+impl std::fmt::Display for PadTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PadTarget::Aspect(w, h) => write!(f, "{}:{}", w, h),
+            PadTarget::Canvas(size) => write!(f, "{}", size),
+        }
+    }
+}
+
+/// Widest border accepted, in pixels.
+const MAX_BORDER_WIDTH: u32 = 10_000;
+
+/// Solid frame, parsed from values like `10px:#ffffff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Border {
+    pub width: u32,
+    pub color: Color,
+}
+
+impl FromStr for Border {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, color) = match s.trim().split_once(':') {
+            Some((width, color)) => (width, color.parse()?),
+            None => (s.trim(), Color::WHITE),
+        };
+        let width: u32 = width
+            .trim_end_matches("px")
+            .parse()
+            .map_err(|_| format!("Invalid border '{}': expected WIDTHpx:COLOR (e.g., 10px:#ffffff)", s))?;
+
+        if width == 0 || width > MAX_BORDER_WIDTH {
+            return Err(format!("Invalid border '{}': width must be from 1 to {}px", s, MAX_BORDER_WIDTH));
+        }
+
+        Ok(Border { width, color })
+    }
+}
+
+impl std::fmt::Display for Border {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}px:{}", self.width, self.color)
+    }
+}
+
+/// Rounded-corner alpha mask, parsed from a radius in pixels (`24`) or `circle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundMask {
+    Radius(u32),
+    /// Radius of half the shorter side, producing a circle on square images.
+    Circle,
+}
+
+impl RoundMask {
+    /// Resolve the corner radius for an image of the given size.
+    pub fn radius_for(&self, width: u32, height: u32) -> f32 {
+        let max_radius = width.min(height) as f32 / 2.0;
+        match *self {
+            RoundMask::Radius(radius) => (radius as f32).min(max_radius),
+            RoundMask::Circle => max_radius,
+        }
+    }
+}
+
+impl FromStr for RoundMask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_lowercase();
+        if value == "circle" {
+            return Ok(RoundMask::Circle);
+        }
+
+        value
+            .trim_end_matches("px")
+            .parse()
+            .map(RoundMask::Radius)
+            .map_err(|_| format!("Invalid round mask '{}': expected a radius in pixels or 'circle'", s))
+    }
+}
+
+impl std::fmt::Display for RoundMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoundMask::Radius(radius) => write!(f, "{}px", radius),
+            RoundMask::Circle => write!(f, "circle"),
+        }
+    }
+}
+
+/// Resampling filter used when resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+    #[default]
+    Lanczos3,
+    CatmullRom,
+    Triangle,
+    Nearest,
+}
+
+impl ResizeFilter {
+    /// Map to the corresponding `image` filter type.
+    pub fn filter_type(&self) -> FilterType {
+        match self {
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::Nearest => FilterType::Nearest,
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "lanczos3" | "lanczos" => Ok(ResizeFilter::Lanczos3),
+            "catmullrom" | "catmull-rom" => Ok(ResizeFilter::CatmullRom),
+            "triangle" | "bilinear" => Ok(ResizeFilter::Triangle),
+            "nearest" => Ok(ResizeFilter::Nearest),
+            _ => Err(format!(
+                "Invalid resize filter '{}': expected lanczos3, catmullrom, triangle or nearest",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResizeFilter::Lanczos3 => "lanczos3",
+            ResizeFilter::CatmullRom => "catmullrom",
+            ResizeFilter::Triangle => "triangle",
+            ResizeFilter::Nearest => "nearest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parse a strictly positive number (e.g., a blur sigma or sharpen amount).
+pub fn parse_positive(s: &str) -> Result<f32, String> {
+    let value: f32 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid value '{}': expected a positive number", s))?;
+
+    if !(value > 0.0 && value.is_finite()) {
+        return Err(format!("Invalid value '{}': must be greater than 0", s));
+    }
+
+    Ok(value)
+}
+
+/// Set of transforms applied to every image before encoding.
+#[derive(Debug, Clone, Default)]
+pub struct Transforms {
+    pub rotate: Option<Rotation>,
+    pub flip: Option<Flip>,
+    pub crop: Option<Crop>,
+    pub smart_crop: Option<Dimensions>,
+    pub denoise: Option<u8>,
+    pub scale: Option<Scale>,
+    pub upscale: Option<Upscale>,
+    pub filter: ResizeFilter,
+    pub sharpen: Option<f32>,
+    pub blur: Option<f32>,
+    pub brightness: i32,
+    pub contrast: i32,
+    pub saturation: i32,
+    pub grayscale: bool,
+    pub pad_to: Option<PadTarget>,
+    pub pad_color: Option<Color>,
+    pub border: Option<Border>,
+    pub round: Option<RoundMask>,
+    pub watermark: Option<Watermark>,
+    pub stamp: Option<Stamp>,
+    pub quantize: Option<u16>,
+    pub dither: bool,
+    pub flatten: Option<Color>,
+}
+
+impl Transforms {
+    /// Build the pipeline applying the configured transforms in their implicit order.
+    pub fn to_pipeline(&self) -> Pipeline {
+        let mut operations = Vec::new();
+
+        if let Some(rotation) = self.rotate {
+            operations.push(Operation::Rotate(rotation));
+        }
+        if let Some(flip) = self.flip {
+            operations.push(Operation::Flip(flip));
+        }
+        if let Some(crop) = self.crop {
+            operations.push(Operation::Crop(crop));
+        }
+        if let Some(target) = self.smart_crop {
+            operations.push(Operation::SmartCrop(target));
+        }
+        if let Some(strength) = self.denoise {
+            operations.push(Operation::Denoise(strength));
+        }
+        if let Some(scale) = self.scale {
+            operations.push(Operation::Scale(scale));
+        }
+        if let Some(upscale) = self.upscale {
+            operations.push(Operation::Upscale(upscale));
+        }
+        if let Some(amount) = self.sharpen {
+            operations.push(Operation::Sharpen(amount));
+        }
+        if let Some(sigma) = self.blur {
+            operations.push(Operation::Blur(sigma));
+        }
+        if self.brightness != 0 {
+            operations.push(Operation::Brightness(self.brightness));
+        }
+        if self.contrast != 0 {
+            operations.push(Operation::Contrast(self.contrast));
+        }
+        if self.saturation != 0 {
+            operations.push(Operation::Saturation(self.saturation));
+        }
+        if self.grayscale {
+            operations.push(Operation::Grayscale);
+        }
+        if let Some(target) = self.pad_to {
+            operations.push(Operation::Pad(target, self.pad_color.unwrap_or(Color::WHITE)));
+        }
+        if let Some(border) = self.border {
+            operations.push(Operation::Border(border));
+        }
+        if let Some(mask) = self.round {
+            operations.push(Operation::Round(mask));
+        }
+        if let Some(watermark) = &self.watermark {
+            operations.push(Operation::Watermark(watermark.clone()));
+        }
+        if let Some(stamp) = &self.stamp {
+            operations.push(Operation::Stamp(stamp.clone()));
+        }
+        if let Some(colors) = self.quantize {
+            operations.push(Operation::Quantize {
+                colors,
+                dither: self.dither,
+            });
+        }
+        if let Some(background) = self.flatten {
+            operations.push(Operation::Flatten(background));
+        }
+
+        Pipeline::new(operations, self.filter)
+    }
+}
+
+/// Drop the alpha channel by compositing onto an opaque background.
+pub(crate) fn flatten(img: DynamicImage, background: Color) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return img;
+    }
+
+    let [br, bg, bb, _] = background.0.map(|c| c as f32);
+    let rgba = img.to_rgba8();
+    let rgb = image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as f32 / 255.0;
+        let blend = |c: u8, b: f32| (c as f32 * alpha + b * (1.0 - alpha)).round() as u8;
+        image::Rgb([blend(pixel[0], br), blend(pixel[1], bg), blend(pixel[2], bb)])
+    });
+
+    DynamicImage::ImageRgb8(rgb)
+}
+
+/// Rotate an image clockwise.
+pub(crate) fn rotate(img: DynamicImage, rotation: Rotation) -> DynamicImage {
+    match rotation {
+        Rotation::Rotate90 => img.rotate90(),
+        Rotation::Rotate180 => img.rotate180(),
+        Rotation::Rotate270 => img.rotate270(),
+    }
+}
+
+/// Undo an EXIF orientation (1-8) so the image displays upright.
+pub(crate) fn orient(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Crop an image, failing if the region lies entirely outside it.
+pub(crate) fn crop(img: DynamicImage, crop: Crop) -> WebPResult<DynamicImage> {
+    let (x, y, width, height) = crop.rect_for(img.width(), img.height()).ok_or_else(|| {
+        WebPError::ImageProcessingError(format!(
+            "Crop region lies outside the {}x{} image",
+            img.width(),
+            img.height()
+        ))
+    })?;
+    Ok(img.crop_imm(x, y, width, height))
+}
+
+/// Letterbox an image onto a canvas of the target shape, centered.
+pub(crate) fn pad(img: DynamicImage, target: PadTarget, color: Color, filter: FilterType) -> DynamicImage {
+    let mut img = img;
+    let (canvas_width, canvas_height) = match target.canvas_for(img.width(), img.height()) {
+        Some(size) => size,
+        None => {
+            // Shrink to fit the fixed canvas first
+            let PadTarget::Canvas(size) = target else { unreachable!() };
+            img = img.resize(size.width, size.height, filter);
+            (size.width, size.height)
+        }
+    };
+
+    if (canvas_width, canvas_height) == (img.width(), img.height()) {
+        return img;
+    }
+
+    let mut canvas = image::RgbaImage::from_pixel(canvas_width, canvas_height, image::Rgba(color.0));
+    let x = (canvas_width - img.width()) / 2;
+    let y = (canvas_height - img.height()) / 2;
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), x as i64, y as i64);
+
+    let padded = DynamicImage::ImageRgba8(canvas);
+    if color.is_opaque() && !img.color().has_alpha() {
+        DynamicImage::ImageRgb8(padded.to_rgb8())
+    } else {
+        padded
+    }
+}
+
+/// Surround an image with a solid frame, failing if the framed size does
+/// not fit in 32 bits.
+pub(crate) fn add_border(img: DynamicImage, border: Border) -> WebPResult<DynamicImage> {
+    let framed = |side: u32| border.width.checked_mul(2).and_then(|frame| side.checked_add(frame));
+    let (Some(width), Some(height)) = (framed(img.width()), framed(img.height())) else {
+        return Err(WebPError::ImageProcessingError(format!(
+            "A {}px border around the {}x{} image is too large",
+            border.width,
+            img.width(),
+            img.height()
+        )));
+    };
+    let mut canvas = image::RgbaImage::from_pixel(width, height, image::Rgba(border.color.0));
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), border.width as i64, border.width as i64);
+
+    let framed = DynamicImage::ImageRgba8(canvas);
+    if border.color.is_opaque() && !img.color().has_alpha() {
+        Ok(DynamicImage::ImageRgb8(framed.to_rgb8()))
+    } else {
+        Ok(framed)
+    }
+}
+
+/// Apply an anti-aliased rounded-corner mask to the alpha channel.
+pub(crate) fn round_corners(img: DynamicImage, mask: RoundMask) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let radius = mask.radius_for(width, height);
+    if radius <= 0.0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        // Distance from the nearest corner circle center, measured at the pixel center
+        let px = x as f32 + 0.5;
+        let py = y as f32 + 0.5;
+        let cx = px.clamp(radius, width as f32 - radius);
+        let cy = py.clamp(radius, height as f32 - radius);
+        let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+
+        let coverage = (radius - distance + 0.5).clamp(0.0, 1.0);
+        if coverage < 1.0 {
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Reduce an image to a palette of at most `colors` colors.
+pub(crate) fn quantize(img: DynamicImage, colors: u16, dither: bool) -> DynamicImage {
+    use image::imageops::ColorMap;
+
+    let has_alpha = img.color().has_alpha();
+    let mut rgba = img.to_rgba8();
+    let palette = color_quant::NeuQuant::new(10, colors as usize, rgba.as_raw());
+
+    // Error diffusion needs two rows and columns; image's dither panics without
+    if dither && rgba.width() >= 2 && rgba.height() >= 2 {
+        image::imageops::dither(&mut rgba, &palette);
+    } else {
+        for pixel in rgba.pixels_mut() {
+            palette.map_color(pixel);
+        }
+    }
+
+    let quantized = DynamicImage::ImageRgba8(rgba);
+    if has_alpha {
+        quantized
+    } else {
+        DynamicImage::ImageRgb8(quantized.to_rgb8())
+    }
+}
+
+/// Light edge-preserving noise reduction.
+///
+/// Each channel is replaced by its 3x3 median, blended with the original
+/// according to `strength` (1-100).
+pub(crate) fn denoise(img: DynamicImage, strength: u8) -> DynamicImage {
+    let weight = strength.min(100) as f32 / 100.0;
+    let has_alpha = img.color().has_alpha();
+    let source = img.to_rgba8();
+    let (width, height) = source.dimensions();
+    let mut output = source.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut window = [[0u8; 9]; 3];
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let neighbor = source.get_pixel(nx, ny);
+                    for channel in 0..3 {
+                        window[channel][count] = neighbor[channel];
+                    }
+                    count += 1;
+                }
+            }
+
+            let pixel = output.get_pixel_mut(x, y);
+            for (channel, values) in window.iter_mut().enumerate() {
+                let values = &mut values[..count];
+                values.sort_unstable();
+                let median = values[count / 2] as f32;
+                let original = pixel[channel] as f32;
+                pixel[channel] = (original + (median - original) * weight).round() as u8;
+            }
+        }
+    }
+
+    let denoised = DynamicImage::ImageRgba8(output);
+    if has_alpha {
+        denoised
+    } else {
+        DynamicImage::ImageRgb8(denoised.to_rgb8())
+    }
+}
+
+/// Sharpen with an unsharp mask: `original + amount * (original - blurred)`.
+pub(crate) fn unsharp_mask(img: DynamicImage, amount: f32) -> DynamicImage {
+    const SIGMA: f32 = 1.0;
+
+    let has_alpha = img.color().has_alpha();
+    let mut rgba = img.to_rgba8();
+    let blurred = image::imageops::blur(&rgba, SIGMA);
+
+    for (pixel, soft) in rgba.pixels_mut().zip(blurred.pixels()) {
+        for channel in 0..3 {
+            let original = pixel[channel] as f32;
+            let value = original + amount * (original - soft[channel] as f32);
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    let sharpened = DynamicImage::ImageRgba8(rgba);
+    if has_alpha {
+        sharpened
+    } else {
+        DynamicImage::ImageRgb8(sharpened.to_rgb8())
+    }
+}
+
+/// Scale color saturation by a percentage (-100 removes all color).
+pub(crate) fn adjust_saturation(img: DynamicImage, percent: i32) -> DynamicImage {
+    let factor = 1.0 + percent as f32 / 100.0;
+    let has_alpha = img.color().has_alpha();
+    let mut rgba = img.to_rgba8();
+
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b, _] = pixel.0.map(|c| c as f32);
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        for channel in 0..3 {
+            let value = luma + (pixel[channel] as f32 - luma) * factor;
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    let adjusted = DynamicImage::ImageRgba8(rgba);
+    if has_alpha {
+        adjusted
+    } else {
+        DynamicImage::ImageRgb8(adjusted.to_rgb8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn apply(transforms: &Transforms, img: DynamicImage) -> WebPResult<DynamicImage> {
+        transforms.to_pipeline().apply(img, None)
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!("50%".parse::<Scale>().unwrap().factor(), 0.5);
+        assert_eq!("25".parse::<Scale>().unwrap().factor(), 0.25);
+        assert_eq!("100%".parse::<Scale>().unwrap().factor(), 1.0);
+        assert!("0%".parse::<Scale>().is_err());
+        assert!("150%".parse::<Scale>().is_err());
+        assert!("half".parse::<Scale>().is_err());
+    }
+
+    #[test]
+    fn test_upscale() {
+        assert_eq!("2x".parse::<Upscale>().unwrap().factor(), 2.0);
+        assert_eq!("1.5".parse::<Upscale>().unwrap().factor(), 1.5);
+        assert!("1x".parse::<Upscale>().is_err());
+        assert!("16x".parse::<Upscale>().is_err());
+
+        let transforms = Transforms {
+            upscale: Some("2x".parse().unwrap()),
+            ..Default::default()
+        };
+        let result = apply(&transforms, DynamicImage::new_rgb8(30, 20)).unwrap();
+        assert_eq!((result.width(), result.height()), (60, 40));
+    }
+
+    #[test]
+    fn test_parse_crop() {
+        let dims: Dimensions = "800x600".parse().unwrap();
+        assert_eq!((dims.width, dims.height), (800, 600));
+        assert!("800".parse::<Dimensions>().is_err());
+        assert!("0x600".parse::<Dimensions>().is_err());
+
+        let region: CropRegion = "800x600+10+20".parse().unwrap();
+        assert_eq!((region.x, region.y), (10, 20));
+        assert_eq!(region.size, dims);
+        assert!("800x600+10".parse::<CropRegion>().is_err());
+        assert!("800x600+10+20+30".parse::<CropRegion>().is_err());
+    }
+
+    #[test]
+    fn test_crop_rect() {
+        let region = Crop::Region("50x50+80+10".parse().unwrap());
+        assert_eq!(region.rect_for(100, 100), Some((80, 10, 20, 50)));
+        assert_eq!(region.rect_for(60, 100), None);
+
+        let center = Crop::Center("40x20".parse().unwrap());
+        assert_eq!(center.rect_for(100, 100), Some((30, 40, 40, 20)));
+        assert_eq!(center.rect_for(30, 10), Some((0, 0, 30, 10)));
+    }
+
+    #[test]
+    fn test_rotate_and_flip() {
+        assert!("45".parse::<Rotation>().is_err());
+        assert_eq!("V".parse::<Flip>().unwrap(), Flip::Vertical);
+
+        let mut img = RgbImage::new(4, 2);
+        img.put_pixel(0, 0, Rgb([255, 0, 0]));
+        let transforms = Transforms {
+            rotate: Some("90".parse().unwrap()),
+            flip: Some("h".parse().unwrap()),
+            ..Default::default()
+        };
+        let result = apply(&transforms, DynamicImage::ImageRgb8(img)).unwrap().to_rgb8();
+
+        // Rotating 90° moves the top-left pixel to the top-right; flipping brings it back
+        assert_eq!(result.dimensions(), (2, 4));
+        assert_eq!(result.get_pixel(0, 0), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn test_grayscale() {
+        let img = RgbImage::from_pixel(2, 2, Rgb([255, 0, 0]));
+        let transforms = Transforms {
+            grayscale: true,
+            ..Default::default()
+        };
+        let result = apply(&transforms, DynamicImage::ImageRgb8(img)).unwrap().to_rgb8();
+        let pixel = result.get_pixel(0, 0);
+        assert_eq!(pixel[0], pixel[1]);
+        assert_eq!(pixel[1], pixel[2]);
+    }
+
+    #[test]
+    fn test_color_adjustments() {
+        let img = RgbImage::from_pixel(2, 2, Rgb([100, 150, 200]));
+
+        let brighter = Transforms {
+            brightness: 20,
+            ..Default::default()
+        };
+        let result = apply(&brighter, DynamicImage::ImageRgb8(img.clone())).unwrap().to_rgb8();
+        assert_eq!(result.get_pixel(0, 0), &Rgb([151, 201, 251]));
+
+        let desaturated = Transforms {
+            saturation: -100,
+            ..Default::default()
+        };
+        let result = apply(&desaturated, DynamicImage::ImageRgb8(img.clone())).unwrap().to_rgb8();
+        let pixel = result.get_pixel(0, 0);
+        assert_eq!(pixel[0], pixel[2]);
+
+        let saturated = Transforms {
+            saturation: 50,
+            ..Default::default()
+        };
+        let result = apply(&saturated, DynamicImage::ImageRgb8(img)).unwrap().to_rgb8();
+        let pixel = result.get_pixel(0, 0);
+        assert!(pixel[2] as i32 - pixel[0] as i32 > 100);
+    }
+
+    #[test]
+    fn test_sharpen_and_blur() {
+        assert!(parse_positive("0.5").is_ok());
+        assert!(parse_positive("0").is_err());
+        assert!(parse_positive("-1").is_err());
+
+        // A hard vertical edge: sharpening increases contrast, blurring reduces it
+        let img = RgbImage::from_fn(20, 20, |x, _| {
+            if x < 10 {
+                Rgb([100, 100, 100])
+            } else {
+                Rgb([150, 150, 150])
+            }
+        });
+        let edge_contrast = |img: &RgbImage| {
+            img.get_pixel(10, 10)[0] as i32 - img.get_pixel(9, 10)[0] as i32
+        };
+
+        let sharpen = Transforms {
+            sharpen: Some(1.0),
+            ..Default::default()
+        };
+        let sharpened = apply(&sharpen, DynamicImage::ImageRgb8(img.clone())).unwrap().to_rgb8();
+        assert!(edge_contrast(&sharpened) > 50);
+
+        let blur = Transforms {
+            blur: Some(2.0),
+            ..Default::default()
+        };
+        let blurred = apply(&blur, DynamicImage::ImageRgb8(img)).unwrap().to_rgb8();
+        assert!(edge_contrast(&blurred) < 50);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!("#ff8000".parse::<Color>().unwrap(), Color([255, 128, 0, 255]));
+        assert_eq!("#FF800080".parse::<Color>().unwrap(), Color([255, 128, 0, 128]));
+        assert_eq!("transparent".parse::<Color>().unwrap(), Color::TRANSPARENT);
+        assert!("ff8000".parse::<Color>().is_err());
+        assert!("#ff80".parse::<Color>().is_err());
+        assert_eq!(Color([255, 128, 0, 255]).to_string(), "#ff8000");
+    }
+
+    #[test]
+    fn test_pad_to() {
+        assert_eq!("1:1".parse::<PadTarget>().unwrap(), PadTarget::Aspect(1, 1));
+        assert!("0:1".parse::<PadTarget>().is_err());
+        assert_eq!(PadTarget::Aspect(1, 1).canvas_for(300, 200), Some((300, 300)));
+        assert_eq!(PadTarget::Aspect(16, 9).canvas_for(100, 100), Some((178, 100)));
+        let canvas: PadTarget = "800x800".parse().unwrap();
+        assert_eq!(canvas.canvas_for(300, 200), Some((800, 800)));
+        assert_eq!(canvas.canvas_for(1600, 200), None);
+
+        let transforms = Transforms {
+            pad_to: Some(PadTarget::Aspect(1, 1)),
+            pad_color: Some(Color::TRANSPARENT),
+            ..Default::default()
+        };
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([255, 0, 0])));
+        let result = apply(&transforms, img).unwrap().to_rgba8();
+        assert_eq!(result.dimensions(), (4, 4));
+        assert_eq!(result.get_pixel(0, 0)[3], 0);
+        assert_eq!(result.get_pixel(0, 1), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_border() {
+        assert_eq!(
+            "10px:#ff0000".parse::<Border>().unwrap(),
+            Border { width: 10, color: Color([255, 0, 0, 255]) }
+        );
+        assert_eq!("4".parse::<Border>().unwrap().color, Color::WHITE);
+        assert!("0px:#ffffff".parse::<Border>().is_err());
+        assert!("10001px".parse::<Border>().is_err());
+        let tiny = DynamicImage::ImageRgb8(RgbImage::new(1, 1));
+        assert!(add_border(tiny, Border { width: u32::MAX, color: Color::WHITE }).is_err());
+        assert!("10px:red".parse::<Border>().is_err());
+
+        let transforms = Transforms {
+            border: Some("2px:#ff0000".parse().unwrap()),
+            ..Default::default()
+        };
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 3, Rgb([0, 0, 255])));
+        let result = apply(&transforms, img).unwrap().to_rgb8();
+        assert_eq!(result.dimensions(), (8, 7));
+        assert_eq!(result.get_pixel(1, 1), &Rgb([255, 0, 0]));
+        assert_eq!(result.get_pixel(2, 2), &Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn test_round_corners() {
+        assert_eq!("circle".parse::<RoundMask>().unwrap(), RoundMask::Circle);
+        assert_eq!("12px".parse::<RoundMask>().unwrap(), RoundMask::Radius(12));
+        assert!("round".parse::<RoundMask>().is_err());
+        assert_eq!(RoundMask::Radius(500).radius_for(100, 40), 20.0);
+
+        let transforms = Transforms {
+            round: Some(RoundMask::Circle),
+            ..Default::default()
+        };
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 20, Rgb([0, 0, 255])));
+        let result = apply(&transforms, img).unwrap().to_rgba8();
+        assert_eq!(result.get_pixel(0, 0)[3], 0);
+        assert_eq!(result.get_pixel(19, 19)[3], 0);
+        assert_eq!(result.get_pixel(10, 10)[3], 255);
+        assert!(result.get_pixel(10, 0)[3] > 240);
+    }
+
+    #[test]
+    fn test_denoise() {
+        // A single speckle on a flat background is removed at full strength
+        let mut img = RgbImage::from_pixel(5, 5, Rgb([50, 50, 50]));
+        img.put_pixel(2, 2, Rgb([250, 250, 250]));
+
+        let full = Transforms {
+            denoise: Some(100),
+            ..Default::default()
+        };
+        let result = apply(&full, DynamicImage::ImageRgb8(img.clone())).unwrap().to_rgb8();
+        assert_eq!(result.get_pixel(2, 2), &Rgb([50, 50, 50]));
+
+        let half = Transforms {
+            denoise: Some(50),
+            ..Default::default()
+        };
+        let result = apply(&half, DynamicImage::ImageRgb8(img)).unwrap().to_rgb8();
+        assert_eq!(result.get_pixel(2, 2), &Rgb([150, 150, 150]));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let img = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+        for dither in [false, true] {
+            let transforms = Transforms {
+                quantize: Some(16),
+                dither,
+                ..Default::default()
+            };
+            let result = apply(&transforms, DynamicImage::ImageRgb8(img.clone())).unwrap().to_rgb8();
+            let colors: std::collections::HashSet<_> = result.pixels().collect();
+            assert!(colors.len() <= 16);
+        }
+    }
+
+    #[test]
+    fn test_quantize_single_row_or_column() {
+        for (width, height) in [(1, 1), (1, 10), (10, 1)] {
+            let img = RgbImage::from_fn(width, height, |x, y| Rgb([(x * 20) as u8, (y * 20) as u8, 128]));
+            let result = quantize(DynamicImage::ImageRgb8(img), 4, true);
+            assert_eq!((result.width(), result.height()), (width, height));
+        }
+    }
+
+    #[test]
+    fn test_flatten() {
+        let img = image::RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                image::Rgba([255, 0, 0, 0])
+            } else {
+                image::Rgba([255, 0, 0, 128])
+            }
+        });
+        let transforms = Transforms {
+            flatten: Some(Color::WHITE),
+            ..Default::default()
+        };
+        let result = apply(&transforms, DynamicImage::ImageRgba8(img)).unwrap();
+        assert!(!result.color().has_alpha());
+        let rgb = result.to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0), &Rgb([255, 255, 255]));
+        assert_eq!(rgb.get_pixel(1, 0), &Rgb([255, 127, 127]));
+    }
+
+    #[test]
+    fn test_parse_resize_filter() {
+        assert_eq!("lanczos3".parse::<ResizeFilter>().unwrap(), ResizeFilter::Lanczos3);
+        assert_eq!("CatmullRom".parse::<ResizeFilter>().unwrap(), ResizeFilter::CatmullRom);
+        assert_eq!("triangle".parse::<ResizeFilter>().unwrap(), ResizeFilter::Triangle);
+        assert_eq!("nearest".parse::<ResizeFilter>().unwrap(), ResizeFilter::Nearest);
+        assert!("cubic".parse::<ResizeFilter>().is_err());
+    }
+
+    #[test]
+    fn test_scale_dimensions() {
+        let scale: Scale = "50%".parse().unwrap();
+        assert_eq!(scale.apply_to(2880, 1800), (1440, 900));
+        assert_eq!(scale.apply_to(1, 1), (1, 1));
+    }
+
+    #[test]
+    fn test_apply_scale() {
+        let transforms = Transforms {
+            scale: Some("50%".parse().unwrap()),
+            ..Default::default()
+        };
+        let img = DynamicImage::new_rgb8(100, 40);
+        let scaled = apply(&transforms, img).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (50, 20));
+    }
+}
@@ -1,6 +1,15 @@
 //! Utility functions for the WebP converter.
 
-use std::path::Path;
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageFormat, ImageResult};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Bytes read to recognize an image format; the longest signature is 12.
+const SNIFF_BYTES: u64 = 64;
 
 /// Format file size in human-readable format.
 pub fn format_size(size_bytes: u64) -> String {
@@ -16,9 +25,99 @@ pub fn format_size(size_bytes: u64) -> String {
     format!("{:.1} {}", size, UNITS[unit_index])
 }
 
-/// Check if a file is a valid image format.
+/// Parse a human-readable size such as `200KB`, `1.5 MB` or `4096`.
+///
+/// Units are binary (1 KB = 1024 bytes), matching [`format_size`]; a bare
+/// number is in bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let trimmed = s.trim();
+    let split = trimmed.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size '{}': expected a number with an optional unit, e.g. 200KB", s))?;
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("Invalid size '{}': unknown unit '{}' (expected B, KB, MB or GB)", s, unit.trim())),
+    };
+
+    let bytes = (number * multiplier as f64).round();
+    if bytes < 1.0 {
+        return Err(format!("Invalid size '{}': must be at least 1 byte", s));
+    }
+    Ok(bytes as u64)
+}
+
+/// Parse a duration such as `15m`, `30s`, `2h` or `1d`; a bare number is in
+/// seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let trimmed = s.trim();
+    let split = trimmed.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}': expected a number with an optional unit, e.g. 15m", s))?;
+    let multiplier: f64 = match unit.trim().to_lowercase().as_str() {
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return Err(format!("Invalid duration '{}': unknown unit '{}' (expected s, m, h or d)", s, unit.trim())),
+    };
+
+    let seconds = number * multiplier;
+    if seconds < 1.0 {
+        return Err(format!("Invalid duration '{}': must be at least 1 second", s));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Format seconds as `4.2s`, `12m 03s` or `2h 05m`.
+pub fn format_duration(seconds: f64) -> String {
+    let whole = seconds as u64;
+    match whole {
+        0..=59 => format!("{:.1}s", seconds),
+        60..=3599 => format!("{}m {:02}s", whole / 60, whole % 60),
+        _ => format!("{}h {:02}m", whole / 3600, whole % 3600 / 60),
+    }
+}
+
+/// Check if a file is a valid image format, whatever its extension says.
 pub fn is_valid_image(file_path: &Path) -> bool {
-    image::open(file_path).is_ok()
+    open_image(file_path).is_ok()
+}
+
+/// Open an image, detecting its format from the content before the
+/// extension, so a PNG named `.jpg` still opens.
+pub fn open_image(file_path: &Path) -> ImageResult<DynamicImage> {
+    ImageReader::open(file_path)?.with_guessed_format()?.decode()
+}
+
+/// Read the dimensions of an image from its header, detecting the format
+/// like [`open_image`].
+pub fn image_dimensions(file_path: &Path) -> ImageResult<(u32, u32)> {
+    ImageReader::open(file_path)?.with_guessed_format()?.into_dimensions()
+}
+
+/// Recognize the format of an image from its first bytes alone.
+pub fn sniff_format(file_path: &Path) -> Option<ImageFormat> {
+    let mut head = Vec::new();
+    File::open(file_path).ok()?.take(SNIFF_BYTES).read_to_end(&mut head).ok()?;
+    image::guess_format(&head).ok()
+}
+
+/// Format of an image from its content, or from its extension when the
+/// content is not recognized.
+pub fn image_format(file_path: &Path) -> Option<ImageFormat> {
+    sniff_format(file_path).or_else(|| ImageFormat::from_path(file_path).ok())
+}
+
+/// Display name of an image format, e.g. `JPEG`.
+pub fn format_name(format: ImageFormat) -> String {
+    format!("{:?}", format).to_uppercase()
 }
 
 /// Get supported image extensions.
@@ -44,8 +143,109 @@ pub fn is_webp_file(file_path: &Path) -> bool {
     }
 }
 
+/// Paths listed one per line, e.g. by [`write_path_list`]; blank lines are
+/// skipped and `-` reads standard input. Lines need not be UTF-8.
+pub fn read_path_list(list: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin().read_to_end(&mut bytes)?;
+    } else {
+        bytes = std::fs::read(list)?;
+    }
+    Ok(bytes
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// Write paths one per line, e.g. the files that failed in a run so they can
+/// be converted again with `--files-from`. Paths are written byte for byte,
+/// so [`read_path_list`] gets back names that are not UTF-8.
+pub fn write_path_list<'a>(list: &Path, paths: impl IntoIterator<Item = &'a Path>) -> std::io::Result<()> {
+    let mut bytes = Vec::new();
+    for path in paths {
+        bytes.extend_from_slice(&path_to_bytes(path));
+        bytes.push(b'\n');
+    }
+    std::fs::write(list, bytes)
+}
+
+/// A path from the raw bytes of a file name, as Unix stores them. Elsewhere,
+/// where names are Unicode, invalid UTF-8 is replaced.
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// The raw bytes of a path, the inverse of [`path_from_bytes`].
+pub fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match path.to_string_lossy() {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        }
+    }
+}
+
+/// A path as text for reports and logs: UTF-8 paths unchanged, and bytes
+/// that are not UTF-8 escaped as `\xNN` rather than replaced, so names that
+/// differ only there stay apart.
+pub fn escape_path(path: &Path) -> String {
+    if let Some(text) = path.to_str() {
+        return text.to_string();
+    }
+    let mut text = String::new();
+    for chunk in path_to_bytes(path).utf8_chunks() {
+        text.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    text
+}
+
+/// CPU time, user and system, this process and its finished child processes
+/// (such as sandboxed decoders) have used so far; `None` where it cannot be
+/// read.
+#[cfg(unix)]
+pub fn cpu_time() -> Option<Duration> {
+    let usage = |who: libc::c_int| {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: getrusage fills in the struct it is given, or fails
+        if unsafe { libc::getrusage(who, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let usage = unsafe { usage.assume_init() };
+        let duration = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        Some(duration(usage.ru_utime) + duration(usage.ru_stime))
+    };
+    Some(usage(libc::RUSAGE_SELF)? + usage(libc::RUSAGE_CHILDREN)?)
+}
+
+/// CPU time, user and system, this process and its finished child processes
+/// have used so far; `None` where it cannot be read.
+#[cfg(not(unix))]
+pub fn cpu_time() -> Option<Duration> {
+    None
+}
+
 /// Generate output path for WebP conversion.
-pub fn generate_output_path(input_path: &Path) -> std::path::PathBuf {
+pub fn generate_output_path(input_path: &Path) -> PathBuf {
     input_path.with_extension("webp")
 }
 
@@ -61,6 +261,80 @@ mod tests {
         assert_eq!(format_size(1073741824), "1.0 GB");
     }
 
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("200KB"), Ok(204800));
+        assert_eq!(parse_size("1.5 mb"), Ok(1572864));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("200XB").is_err());
+        assert!(parse_size("0KB").is_err());
+        assert!(parse_size("KB").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("0.5s").is_err());
+        assert!(parse_duration("3w").is_err());
+        assert_eq!(format_duration(730.0), "12m 10s");
+    }
+
+    #[test]
+    fn test_misnamed_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        image::RgbImage::new(6, 4).save_with_format(&path, ImageFormat::Png).unwrap();
+
+        assert_eq!(sniff_format(&path), Some(ImageFormat::Png));
+        assert_eq!(image_format(&path), Some(ImageFormat::Png));
+        assert!(is_valid_image(&path));
+        assert_eq!(image_dimensions(&path).unwrap(), (6, 4));
+        assert_eq!(format_name(ImageFormat::Jpeg), "JPEG");
+
+        // Unrecognized content falls back to the extension
+        std::fs::write(&path, b"not an image").unwrap();
+        assert_eq!(image_format(&path), Some(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn test_path_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("failed-files.txt");
+        write_path_list(&list, [Path::new("photos/a b.jpg"), Path::new("scan.png")]).unwrap();
+        std::fs::write(&list, std::fs::read_to_string(&list).unwrap() + "\r\n  \nlast.tif\r\n").unwrap();
+        let paths = read_path_list(&list).unwrap();
+        assert_eq!(paths, [Path::new("photos/a b.jpg"), Path::new("scan.png"), Path::new("last.tif")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths() {
+        // Latin-1 "café.jpg", as old archives store it
+        let latin1 = path_from_bytes(b"caf\xe9.jpg");
+        assert!(latin1.to_str().is_none());
+        assert_eq!(escape_path(&latin1), "caf\\xE9.jpg");
+        assert_eq!(escape_path(Path::new("café.jpg")), "café.jpg");
+
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("failed-files.txt");
+        write_path_list(&list, [latin1.as_path(), Path::new("b.png")]).unwrap();
+        assert_eq!(read_path_list(&list).unwrap(), [latin1.clone(), PathBuf::from("b.png")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cpu_time() {
+        let before = cpu_time().unwrap();
+        let start = std::time::Instant::now();
+        let mut x = 0u64;
+        while start.elapsed() < Duration::from_millis(50) {
+            x = std::hint::black_box(x.wrapping_mul(31).wrapping_add(7));
+        }
+        assert!(cpu_time().unwrap() > before);
+    }
+
     #[test]
     fn test_is_webp_file() {
         assert!(is_webp_file(Path::new("test.webp")));
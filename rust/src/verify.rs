@@ -0,0 +1,91 @@
+//! Verification of written outputs, catching WebP files the encoder or the
+//! disk silently corrupted before a file counts as converted.
+
+use crate::converter::WebPConverter;
+use crate::error::{WebPError, WebPResult};
+use crate::metrics;
+use image::{DynamicImage, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+impl WebPConverter {
+    /// Read `output_path` back and check it against `img`, the image that was
+    /// encoded: the dimensions must match and, for `lossless` outputs, every
+    /// pixel. The output is removed when it fails.
+    pub(crate) fn verify_output(&self, output_path: &Path, img: &DynamicImage, lossless: bool) -> WebPResult<()> {
+        let result = fs::read(output_path).map_err(|e| e.to_string()).and_then(|written| self.check(&written, img, lossless));
+        if let Err(reason) = result {
+            let _ = fs::remove_file(output_path);
+            return Err(WebPError::VerificationFailed { path: output_path.to_path_buf(), reason });
+        }
+        Ok(())
+    }
+
+    /// Why `written` is not a good encoding of `img`, if it is not.
+    fn check(&self, written: &[u8], img: &DynamicImage, lossless: bool) -> Result<(), String> {
+        let decoded = metrics::decode(written).map_err(|_| "does not decode".to_string())?;
+        if (decoded.width(), decoded.height()) != (img.width(), img.height()) {
+            return Err(format!(
+                "decodes as {}x{} instead of {}x{}",
+                decoded.width(),
+                decoded.height(),
+                img.width(),
+                img.height()
+            ));
+        }
+
+        if lossless {
+            let expected = if self.keeps_alpha() && img.color().has_alpha() {
+                img.to_rgba8()
+            } else {
+                DynamicImage::ImageRgb8(img.to_rgb8()).to_rgba8()
+            };
+            let (decoded, expected) = (visible(decoded.to_rgba8()), visible(expected));
+            if let Some((x, y, _)) = decoded.enumerate_pixels().find(|&(x, y, pixel)| pixel != expected.get_pixel(x, y)) {
+                return Err(format!("lossless pixels differ from the source at {},{}", x, y));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Clear the color of fully transparent pixels, which lossless WebP does not
+/// keep.
+fn visible(mut img: RgbaImage) -> RgbaImage {
+    for pixel in img.pixels_mut().filter(|p| p[3] == 0) {
+        pixel.0 = [0; 4];
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_verify_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.png");
+        let img = RgbaImage::from_fn(16, 12, |x, y| Rgba([(x * 16) as u8, (y * 20) as u8, 40, if x < 4 { 0 } else { 255 }]));
+        img.save(&input).unwrap();
+
+        let converter = WebPConverter::builder().lossless(true).verify(true).build().unwrap();
+        let stats = converter.convert_single_file(&input, None, None).unwrap();
+        assert_eq!(stats.success_count, 1);
+
+        // A lossless output must hold the same pixels
+        let output = dir.path().join("a.webp");
+        let mut other = img.clone();
+        other.put_pixel(10, 5, Rgba([1, 2, 3, 255]));
+        let error = converter.verify_output(&output, &DynamicImage::ImageRgba8(other), true).unwrap_err();
+        assert!(matches!(error, WebPError::VerificationFailed { .. }), "{}", error);
+        assert!(!output.exists());
+
+        // A truncated output does not decode
+        converter.convert_single_file(&input, None, None).unwrap();
+        let written = fs::read(&output).unwrap();
+        fs::write(&output, &written[..written.len() / 2]).unwrap();
+        assert!(converter.verify_output(&output, &DynamicImage::ImageRgba8(img), false).is_err());
+    }
+}
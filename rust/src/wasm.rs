@@ -0,0 +1,37 @@
+//! Browser and Node bindings (`wasm` feature).
+//!
+//! Only bytes-in/bytes-out conversion is exposed; nothing here touches the
+//! filesystem, so the same code runs for client-side pre-upload compression.
+
+use crate::converter::WebPConverter;
+use wasm_bindgen::prelude::*;
+
+/// Convert an encoded image (PNG, JPEG, ...) to WebP bytes.
+///
+/// `max_width`/`max_height` fit the image inside that size without enlarging
+/// it; pass 0 for both to keep the original size.
+#[wasm_bindgen(js_name = convertToWebp)]
+pub fn convert_to_webp(
+    input: &[u8],
+    quality: u8,
+    lossless: bool,
+    max_width: u32,
+    max_height: u32,
+) -> Result<Vec<u8>, JsError> {
+    let mut builder = WebPConverter::builder().quality(quality).lossless(lossless);
+    if max_width > 0 || max_height > 0 {
+        builder = builder.resize(nonzero_or_max(max_width), nonzero_or_max(max_height));
+    }
+
+    let converter = builder.build().map_err(|e| JsError::new(&e.to_string()))?;
+    converter.convert_bytes(input).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Treat 0 as "unbounded" for one side of the resize box.
+fn nonzero_or_max(value: u32) -> u32 {
+    if value == 0 {
+        u32::MAX
+    } else {
+        value
+    }
+}
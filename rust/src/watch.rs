@@ -0,0 +1,237 @@
+//! Watch mode: convert images as they are added to or changed in a folder.
+
+use crate::collision::OutputPlan;
+use crate::converter::{ConversionStats, WebPConverter};
+use crate::error::{WebPError, WebPResult};
+use crate::report::Event;
+use crate::utils;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::WalkDir;
+
+/// How often the watcher wakes up to convert settled files and check for
+/// cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl WebPConverter {
+    /// Watch a directory and convert images as they are created or modified,
+    /// until the converter's cancellation token is triggered.
+    ///
+    /// A file is converted once no new events arrived for it within
+    /// `debounce`, so copies in progress are not picked up half-written.
+    /// Existing files are left alone, as are WebP files and anything written
+    /// inside `output_folder`. Returns the statistics of the whole session.
+    pub fn watch(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+        debounce: Duration,
+    ) -> WebPResult<ConversionStats> {
+        self.watch_folder(directory, recursive, output_folder, debounce, None)
+    }
+
+    /// Like [`watch`](Self::watch), but also rescan the whole directory every
+    /// `rescan` and convert files that are new or modified since the last
+    /// scan, for writers whose changes raise no file system events (e.g. some
+    /// network mounts).
+    pub fn watch_with_rescan(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+        debounce: Duration,
+        rescan: Duration,
+    ) -> WebPResult<ConversionStats> {
+        self.watch_folder(directory, recursive, output_folder, debounce, Some(rescan))
+    }
+
+    fn watch_folder(
+        &self,
+        directory: &Path,
+        recursive: bool,
+        output_folder: Option<&Path>,
+        debounce: Duration,
+        rescan: Option<Duration>,
+    ) -> WebPResult<ConversionStats> {
+        if !directory.is_dir() {
+            return Err(WebPError::InvalidInputType(directory.to_path_buf()));
+        }
+
+        // Outputs may live inside the watched folder; never convert them again.
+        // Event paths are based on the watched path, so compare canonical forms.
+        let mut ignored = Vec::new();
+        for folder in output_folder.into_iter().chain(self.diff_output_folder()) {
+            fs::create_dir_all(folder)?;
+            ignored.push(folder.canonicalize()?);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        let directory = directory.canonicalize()?;
+        watcher.watch(&directory, mode)?;
+
+        let is_candidate = |path: &Path| {
+            utils::is_supported_extension(path)
+                && !utils::is_webp_file(path)
+                && !ignored.iter().any(|folder| path.starts_with(folder))
+        };
+
+        // Existing files are left alone, so rescans only pick up what changed
+        // after this first scan
+        let scan = || scan_candidates(&directory, recursive, &is_candidate);
+        let mut known = if rescan.is_some() { scan() } else { HashMap::new() };
+        let mut next_rescan = rescan.map(|interval| Instant::now() + interval);
+        self.report(Event::Ready);
+
+        let mut stats = ConversionStats::new();
+        let mut similar = self.similar_images();
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        let mut backlog = 0;
+
+        while !self.is_cancelled() {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => {
+                    let event = event?;
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths.into_iter().filter(|path| is_candidate(path)) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            if let (Some(interval), Some(due)) = (rescan, next_rescan) {
+                if Instant::now() >= due {
+                    let scanned = scan();
+                    for (path, modified) in &scanned {
+                        if known.get(path) != Some(modified) {
+                            pending.entry(path.clone()).or_insert_with(Instant::now);
+                        }
+                    }
+                    known = scanned;
+                    next_rescan = Some(Instant::now() + interval);
+                }
+            }
+
+            let settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, last_event)| last_event.elapsed() >= debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+            if pending.len() != backlog {
+                backlog = pending.len();
+                self.report(Event::Backlog { pending: backlog });
+            }
+            for path in settled {
+                pending.remove(&path);
+                // Counted down file by file, not only on the next poll
+                backlog = pending.len();
+                self.report(Event::Backlog { pending: backlog });
+                // Deleted or renamed away before it settled
+                if !path.is_file() {
+                    continue;
+                }
+                // Seen now, so the next rescan does not convert it again
+                if rescan.is_some() {
+                    if let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                        known.insert(path.clone(), modified);
+                    }
+                }
+
+                // Files arrive one at a time, so there is no batch to check for collisions
+                let result = self.convert_file(path, output_folder, &OutputPlan::default(), similar.as_mut());
+                match result.error {
+                    Some(error) => {
+                        self.report(Event::FileFailed { path: &result.input, error: &error });
+                        stats.add_failure(result.input, error);
+                    }
+                    None if result.discarded => stats.add_not_smaller(),
+                    None if result.low_quality => stats.add_low_quality(),
+                    None if result.duplicate_of.is_some() => stats.add_similar(),
+                    None if result.already_efficient => stats.add_efficient(),
+                    None => {
+                        stats.add_success(
+                            result.input,
+                            result.time_taken,
+                            result.original_size.unwrap_or(0),
+                            result.compressed_size.unwrap_or(0),
+                        );
+                        if let Some(metrics) = result.metrics {
+                            stats.add_metrics(metrics);
+                        }
+                        if !result.artifacts.is_empty() {
+                            stats.add_artifact_warning();
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.cancelled = self.is_cancelled();
+        Ok(stats)
+    }
+}
+
+/// Modification times of the candidate files below `directory`. Files that
+/// vanish or cannot be read during the scan are left out.
+fn scan_candidates(
+    directory: &Path,
+    recursive: bool,
+    is_candidate: &dyn Fn(&Path) -> bool,
+) -> HashMap<PathBuf, SystemTime> {
+    let depth = if recursive { usize::MAX } else { 1 };
+    WalkDir::new(directory)
+        .max_depth(depth)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_candidate(entry.path()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((entry.into_path(), modified))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::CancellationToken;
+    use crate::WebPConverter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_watch_converts_new_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_folder = dir.path().join("out");
+        let token = CancellationToken::new();
+        let converter = WebPConverter::builder().cancellation(token.clone()).build().unwrap();
+
+        let (stats, output) = std::thread::scope(|scope| {
+            let watching = scope.spawn(|| converter.watch(dir.path(), false, Some(&output_folder), Duration::from_millis(100)));
+
+            // Give the watcher time to start before adding files
+            std::thread::sleep(Duration::from_millis(300));
+            image::RgbImage::from_pixel(16, 16, image::Rgb([200, 40, 40])).save(dir.path().join("new.png")).unwrap();
+            std::fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+
+            let output = output_folder.join("new.webp");
+            let started = Instant::now();
+            while !output.exists() && started.elapsed() < Duration::from_secs(10) {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            token.cancel();
+            (watching.join().unwrap().unwrap(), output)
+        });
+
+        assert!(output.exists());
+        assert_eq!((stats.success_count, stats.failed_count), (1, 0));
+        assert!(stats.cancelled);
+    }
+}
@@ -0,0 +1,329 @@
+//! Watermark overlays composited onto images before encoding.
+
+use crate::error::{WebPError, WebPResult};
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Anchor position of an overlay on the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl Position {
+    /// Compute the top-left coordinate of an overlay of the given size.
+    pub fn place(&self, canvas: (u32, u32), overlay: (u32, u32), margin: u32) -> (i64, i64) {
+        let (cw, ch) = (canvas.0 as i64, canvas.1 as i64);
+        let (ow, oh) = (overlay.0 as i64, overlay.1 as i64);
+        let margin = margin as i64;
+
+        match self {
+            Position::TopLeft => (margin, margin),
+            Position::TopRight => (cw - ow - margin, margin),
+            Position::BottomLeft => (margin, ch - oh - margin),
+            Position::BottomRight => (cw - ow - margin, ch - oh - margin),
+            Position::Center => ((cw - ow) / 2, (ch - oh) / 2),
+        }
+    }
+}
+
+crate::transform::serde_as_string!(Position);
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "top-left" => Ok(Position::TopLeft),
+            "top-right" => Ok(Position::TopRight),
+            "bottom-left" => Ok(Position::BottomLeft),
+            "bottom-right" => Ok(Position::BottomRight),
+            "center" => Ok(Position::Center),
+            _ => Err(format!(
+                "Invalid position '{}': expected top-left, top-right, bottom-left, bottom-right or center",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Position::TopLeft => "top-left",
+            Position::TopRight => "top-right",
+            Position::BottomLeft => "bottom-left",
+            Position::BottomRight => "bottom-right",
+            Position::Center => "center",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Largest text stamp accepted, in pixels.
+const MAX_STAMP_SIZE: f32 = 1000.0;
+
+/// Parse a text stamp height in pixels, above 0 and at most 1000.
+pub fn parse_stamp_size(s: &str) -> Result<f32, String> {
+    let size: f32 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid stamp size '{}': expected a number of pixels", s))?;
+
+    if !(size > 0.0 && size <= MAX_STAMP_SIZE) {
+        return Err(format!("Invalid stamp size '{}': must be greater than 0 and at most {}", s, MAX_STAMP_SIZE));
+    }
+
+    Ok(size)
+}
+
+/// Parse an opacity value between 0.0 and 1.0.
+pub fn parse_opacity(s: &str) -> Result<f32, String> {
+    let opacity: f32 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid opacity '{}': expected a number between 0.0 and 1.0", s))?;
+
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err(format!("Invalid opacity '{}': must be between 0.0 and 1.0", s));
+    }
+
+    Ok(opacity)
+}
+
+/// Image watermark composited onto every converted image.
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    image: RgbaImage,
+    pub position: Position,
+    pub opacity: f32,
+}
+
+impl Watermark {
+    /// Load a watermark image from disk.
+    pub fn load(path: &Path, position: Position, opacity: f32) -> WebPResult<Self> {
+        let image = image::open(path)
+            .map_err(|e| {
+                WebPError::ImageProcessingError(format!("Failed to open watermark {}: {}", path.display(), e))
+            })?
+            .to_rgba8();
+
+        Ok(Self::from_image(image, position, opacity))
+    }
+
+    /// Create a watermark from an in-memory RGBA image.
+    pub fn from_image(image: RgbaImage, position: Position, opacity: f32) -> Self {
+        Self {
+            image,
+            position,
+            opacity,
+        }
+    }
+
+    /// Composite the watermark onto an image.
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        composite(img, self.image.clone(), self.position, self.opacity)
+    }
+}
+
+/// Text stamp rendered onto every converted image.
+#[derive(Clone)]
+pub struct Stamp {
+    text: String,
+    font: Arc<FontVec>,
+    size: f32,
+    pub position: Position,
+}
+
+impl std::fmt::Debug for Stamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stamp")
+            .field("text", &self.text)
+            .field("size", &self.size)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl Stamp {
+    /// Load the stamp font (TrueType or OpenType) from disk.
+    pub fn load(text: &str, font_path: &Path, size: f32, position: Position) -> WebPResult<Self> {
+        if !(size > 0.0 && size <= MAX_STAMP_SIZE) {
+            return Err(WebPError::InvalidSettings(format!("stamp size must be greater than 0 and at most {}, got {}", MAX_STAMP_SIZE, size)));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            font: Arc::new(load_font(font_path)?),
+            size,
+            position,
+        })
+    }
+
+    /// Render the text and composite it onto an image.
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        match render_text(&self.font, &self.text, self.size) {
+            Some(overlay) => composite(img, overlay, self.position, 1.0),
+            None => img,
+        }
+    }
+}
+
+/// Load a TrueType or OpenType font from disk.
+pub fn load_font(font_path: &Path) -> WebPResult<FontVec> {
+    let data = std::fs::read(font_path)?;
+    FontVec::try_from_vec(data)
+        .map_err(|_| WebPError::ImageProcessingError(format!("Invalid font file: {}", font_path.display())))
+}
+
+/// Render a single line of text as white glyphs with a dark drop shadow.
+pub fn render_text(font: &FontVec, text: &str, size: f32) -> Option<RgbaImage> {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let shadow = (size / 16.0).ceil().max(1.0) as u32;
+
+    // Lay out glyphs on a single line
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(size, point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    let width = caret.ceil() as u32 + shadow;
+    let height = scaled.height().ceil() as u32 + shadow;
+    if width <= shadow || height <= shadow {
+        return None;
+    }
+
+    let mut overlay = RgbaImage::new(width, height);
+    for (offset, color) in [(shadow, [0, 0, 0]), (0, [255, 255, 255])] {
+        for glyph in &glyphs {
+            let Some(outlined) = font.outline_glyph(glyph.clone()) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let x = bounds.min.x as i64 + gx as i64 + offset as i64;
+                let y = bounds.min.y as i64 + gy as i64 + offset as i64;
+                if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                    return;
+                }
+                let pixel = overlay.get_pixel_mut(x as u32, y as u32);
+                *pixel = blend_over(*pixel, color, coverage.clamp(0.0, 1.0));
+            });
+        }
+    }
+
+    Some(overlay)
+}
+
+/// Blend a color with the given coverage over an existing RGBA pixel.
+fn blend_over(below: Rgba<u8>, color: [u8; 3], alpha: f32) -> Rgba<u8> {
+    let below_alpha = below[3] as f32 / 255.0;
+    let out_alpha = alpha + below_alpha * (1.0 - alpha);
+    if out_alpha <= 0.0 {
+        return below;
+    }
+
+    let channel = |i: usize| {
+        let value = (color[i] as f32 * alpha + below[i] as f32 * below_alpha * (1.0 - alpha)) / out_alpha;
+        value.round() as u8
+    };
+    Rgba([channel(0), channel(1), channel(2), (out_alpha * 255.0).round() as u8])
+}
+
+/// Composite an overlay onto an image at the given anchor position.
+///
+/// Overlays larger than the image are shrunk to fit inside its margins.
+fn composite(img: DynamicImage, overlay: RgbaImage, position: Position, opacity: f32) -> DynamicImage {
+    let mut canvas = img.to_rgba8();
+    let (width, height) = canvas.dimensions();
+    let margin = width.min(height) / 50;
+
+    let mut overlay = overlay;
+    let max_width = width.saturating_sub(margin * 2).max(1);
+    let max_height = height.saturating_sub(margin * 2).max(1);
+    if overlay.width() > max_width || overlay.height() > max_height {
+        overlay = DynamicImage::ImageRgba8(overlay)
+            .resize(max_width, max_height, FilterType::Lanczos3)
+            .to_rgba8();
+    }
+
+    if opacity < 1.0 {
+        for pixel in overlay.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+    }
+
+    let (x, y) = position.place((width, height), overlay.dimensions(), margin);
+    imageops::overlay(&mut canvas, &overlay, x, y);
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_parse_position_and_opacity() {
+        assert_eq!("Bottom-Right".parse::<Position>().unwrap(), Position::BottomRight);
+        assert!("middle".parse::<Position>().is_err());
+        assert_eq!(parse_opacity("0.5").unwrap(), 0.5);
+        assert!(parse_opacity("1.5").is_err());
+    }
+
+    #[test]
+    fn test_parse_stamp_size() {
+        assert_eq!(parse_stamp_size("24").unwrap(), 24.0);
+        assert_eq!(parse_stamp_size("12.5").unwrap(), 12.5);
+        for invalid in ["0", "-4", "NaN", "inf", "1001", "big"] {
+            assert!(parse_stamp_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_position_place() {
+        assert_eq!(Position::TopLeft.place((100, 50), (10, 10), 2), (2, 2));
+        assert_eq!(Position::BottomRight.place((100, 50), (10, 10), 2), (88, 38));
+        assert_eq!(Position::Center.place((100, 50), (10, 10), 2), (45, 20));
+    }
+
+    #[test]
+    fn test_apply_watermark() {
+        let logo = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let watermark = Watermark::from_image(logo, Position::TopLeft, 0.5);
+        let img = DynamicImage::new_rgb8(100, 100);
+        let result = watermark.apply(img).to_rgb8();
+
+        // Half-opacity white over black lands near mid-gray
+        let value = result.get_pixel(5, 5)[0];
+        assert!((120..=135).contains(&value));
+        assert_eq!(result.get_pixel(50, 50)[0], 0);
+    }
+
+    #[test]
+    fn test_stamp_rejects_invalid_font() {
+        let dir = tempfile::tempdir().unwrap();
+        let font_path = dir.path().join("font.ttf");
+        std::fs::write(&font_path, b"not a font").unwrap();
+
+        assert!(Stamp::load("draft", &font_path, 24.0, Position::BottomLeft).is_err());
+        assert!(Stamp::load("draft", &dir.path().join("missing.ttf"), 24.0, Position::BottomLeft).is_err());
+    }
+}
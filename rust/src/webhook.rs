@@ -0,0 +1,133 @@
+//! Webhook notifications: POST a JSON summary of a finished batch or watch
+//! session, for deployment bots and dashboards.
+
+use webp_converter::converter::ConversionStats;
+use webp_converter::error::{WebPError, WebPResult};
+use webp_converter::utils;
+use serde::Serialize;
+#[cfg(feature = "webhook")]
+use std::time::Duration;
+
+/// Timeout for the whole webhook request.
+#[cfg(feature = "webhook")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What kind of run finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunKind {
+    /// A single file or a directory.
+    Batch,
+    /// A watch mode session, sent when it stops.
+    Watch,
+}
+
+/// A file that failed, as sent in the payload.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// JSON body of a webhook request.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub kind: RunKind,
+    pub success_count: usize,
+    pub failed_count: usize,
+    pub not_smaller_count: usize,
+    pub similar_count: usize,
+    pub efficient_count: usize,
+    pub artifact_count: usize,
+    pub low_quality_count: usize,
+    /// Seconds spent converting.
+    pub total_time: f64,
+    pub total_original_size: Option<u64>,
+    pub total_compressed_size: Option<u64>,
+    pub cancelled: bool,
+    pub failures: Vec<WebhookFailure>,
+}
+
+impl WebhookPayload {
+    pub fn new(kind: RunKind, stats: &ConversionStats) -> Self {
+        Self {
+            kind,
+            success_count: stats.success_count,
+            failed_count: stats.failed_count,
+            not_smaller_count: stats.not_smaller_count,
+            similar_count: stats.similar_count,
+            efficient_count: stats.efficient_count,
+            artifact_count: stats.artifact_count,
+            low_quality_count: stats.low_quality_count,
+            total_time: stats.total_time,
+            total_original_size: stats.total_original_size,
+            total_compressed_size: stats.total_compressed_size,
+            cancelled: stats.cancelled,
+            failures: stats
+                .failures
+                .iter()
+                .map(|failure| WebhookFailure {
+                    path: utils::escape_path(&failure.path),
+                    error: failure.error.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// POST the payload to `url`; any status other than 2xx is an error.
+    #[cfg(feature = "webhook")]
+    pub fn send(&self, url: &str) -> WebPResult<()> {
+        let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().into();
+        agent
+            .post(url)
+            .send_json(self)
+            .map_err(|e| WebPError::WebhookError(format!("{}: {}", url, e)))?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "webhook"))]
+    pub fn send(&self, url: &str) -> WebPResult<()> {
+        Err(WebPError::WebhookError(format!("{}: webhooks require building with --features webhook", url)))
+    }
+}
+
+/// Check that a webhook URL is `http://` or `https://`.
+pub fn parse_url(s: &str) -> Result<String, String> {
+    if s.starts_with("http://") || s.starts_with("https://") {
+        Ok(s.to_string())
+    } else {
+        Err(format!("expected an http:// or https:// URL, got '{}'", s))
+    }
+}
+
+#[cfg(all(test, feature = "webhook"))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_send_payload() {
+        let mut stats = ConversionStats::new();
+        stats.add_success(PathBuf::from("photo.jpg"), 0.5, 1000, 400);
+        stats.add_failure(PathBuf::from("broken.jpg"), WebPError::NoImagesFound);
+        let payload = WebhookPayload::new(RunKind::Batch, &stats);
+
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let (sent, received) = std::thread::scope(|scope| {
+            let sending = scope.spawn(|| payload.send(&url));
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            request.respond(tiny_http::Response::empty(204)).unwrap();
+            (sending.join().unwrap(), body)
+        });
+
+        sent.unwrap();
+        let json: serde_json::Value = serde_json::from_str(&received).unwrap();
+        assert_eq!(json["kind"], "batch");
+        assert_eq!((json["success_count"].as_u64(), json["failed_count"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(json["failures"][0]["path"], "broken.jpg");
+        assert!(parse_url("ftp://example.com").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! `--sandbox` end to end, through the built binary, as the sandboxed
+//! decoder is the binary itself started again. Its conversions are lossy, so
+//! they need libwebp.
+#![cfg(feature = "libwebp")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn convert_sandboxed(dir: &Path, input: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_webp-converter"))
+        .current_dir(dir)
+        .args(["--sandbox", "--sandbox-memory", "256MB", input])
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+/// CRC-32 of a PNG chunk's type and data.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+    chunk
+}
+
+/// A PNG of under a hundred bytes claiming 10000x10000 RGBA pixels, 400 MB
+/// once decoded.
+fn decompression_bomb() -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&10_000u32.to_be_bytes());
+    header.extend_from_slice(&10_000u32.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    // A zlib stream of one stored block with four zero bytes
+    let data = [0x78, 0x01, 0x01, 0x04, 0x00, 0xFB, 0xFF, 0, 0, 0, 0, 0x00, 0x04, 0x00, 0x01];
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(chunk(b"IHDR", &header));
+    png.extend(chunk(b"IDAT", &data));
+    png.extend(chunk(b"IEND", &[]));
+    png
+}
+
+#[test]
+fn test_sandbox_converts_and_contains_bombs() {
+    let dir = tempfile::tempdir().unwrap();
+    image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128])).save(dir.path().join("photo.png")).unwrap();
+    std::fs::write(dir.path().join("bomb.png"), decompression_bomb()).unwrap();
+
+    let output = convert_sandboxed(dir.path(), "photo.png");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.path().join("photo.webp").is_file());
+
+    let output = convert_sandboxed(dir.path(), "bomb.png");
+    assert!(!output.status.success());
+    let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    assert!(printed.contains("Sandboxed decoding failed"), "{}", printed);
+    assert!(!dir.path().join("bomb.webp").exists());
+}